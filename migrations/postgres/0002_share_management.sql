@@ -0,0 +1,4 @@
+ALTER TABLE share_links ADD COLUMN encrypted_aes_key BYTEA;
+ALTER TABLE share_links ADD COLUMN revoked_at BIGINT;
+
+CREATE UNIQUE INDEX IF NOT EXISTS share_links_file_recipient_idx ON share_links (file_id, recipient_user_id);
@@ -0,0 +1,2 @@
+ALTER TABLE share_links ADD COLUMN declined_at BIGINT;
+ALTER TABLE share_links ADD COLUMN archived_at BIGINT;
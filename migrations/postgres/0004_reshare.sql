@@ -0,0 +1,5 @@
+-- Stored as 0/1 because the Any driver cannot decode SQLite booleans
+ALTER TABLE share_links ADD COLUMN can_reshare BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE share_links ADD COLUMN parent_share_id TEXT;
+
+CREATE INDEX IF NOT EXISTS share_links_parent_share_id_idx ON share_links (parent_share_id);
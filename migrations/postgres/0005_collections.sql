@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS collections (
+    id TEXT PRIMARY KEY NOT NULL,
+    user_id TEXT NOT NULL REFERENCES users (id),
+    name TEXT NOT NULL,
+    created_at BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS collections_user_id_idx ON collections (user_id);
+
+ALTER TABLE files ADD COLUMN collection_id TEXT REFERENCES collections (id);
+
+CREATE INDEX IF NOT EXISTS files_collection_id_idx ON files (collection_id);
@@ -0,0 +1,15 @@
+ALTER TABLE files ADD COLUMN version BIGINT NOT NULL DEFAULT 1;
+
+-- Superseded revisions only; the current one stays in files. file_id has no
+-- foreign key so files can be deleted before their versions are swept
+CREATE TABLE IF NOT EXISTS file_versions (
+    id TEXT PRIMARY KEY NOT NULL,
+    file_id TEXT NOT NULL,
+    version BIGINT NOT NULL,
+    file_name TEXT NOT NULL,
+    file_size BIGINT NOT NULL,
+    encrypted_file BYTEA NOT NULL,
+    iv BYTEA NOT NULL,
+    created_at BIGINT NOT NULL,
+    UNIQUE (file_id, version)
+);
@@ -0,0 +1,2 @@
+ALTER TABLE files ADD COLUMN content_type TEXT;
+ALTER TABLE file_versions ADD COLUMN content_type TEXT;
@@ -0,0 +1 @@
+ALTER TABLE files ADD COLUMN scan_status TEXT NOT NULL DEFAULT 'not_scanned';
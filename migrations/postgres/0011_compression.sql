@@ -0,0 +1,2 @@
+ALTER TABLE files ADD COLUMN compression TEXT NOT NULL DEFAULT 'none';
+ALTER TABLE file_versions ADD COLUMN compression TEXT NOT NULL DEFAULT 'none';
@@ -0,0 +1,7 @@
+-- Access receipts shown to the sender
+ALTER TABLE share_links ADD COLUMN first_opened_at BIGINT;
+ALTER TABLE share_links ADD COLUMN last_opened_at BIGINT;
+ALTER TABLE share_links ADD COLUMN open_count BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE share_links ADD COLUMN failed_password_attempts BIGINT NOT NULL DEFAULT 0;
+
+CREATE INDEX IF NOT EXISTS audit_events_share_id_idx ON audit_events (share_id);
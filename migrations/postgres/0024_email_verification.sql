@@ -0,0 +1,14 @@
+-- Emails are matched lowercased; accounts differing only in case must be
+-- merged or renamed before this runs
+UPDATE users SET email = LOWER(TRIM(email));
+CREATE UNIQUE INDEX IF NOT EXISTS users_email_lower ON users (LOWER(email));
+
+-- email_verification_token holds the SHA-256 of the token that was emailed
+ALTER TABLE users ADD COLUMN email_verified_at BIGINT;
+ALTER TABLE users ADD COLUMN email_verification_token TEXT;
+ALTER TABLE users ADD COLUMN email_verification_expires_at BIGINT;
+
+-- Accounts from before verification existed keep working as they did; only
+-- accounts registered from now on have to verify
+UPDATE users SET email_verified_at = created_at;
+CREATE UNIQUE INDEX IF NOT EXISTS users_email_verification_token ON users (email_verification_token);
@@ -0,0 +1,16 @@
+-- A single row holding the head of the audit chain. Appending an event
+-- updates it first, which locks it until the appending transaction ends, so
+-- server instances sharing the database take turns extending the chain
+CREATE TABLE IF NOT EXISTS audit_head (
+    id INTEGER PRIMARY KEY NOT NULL,
+    sequence BIGINT NOT NULL,
+    hash TEXT NOT NULL
+);
+
+INSERT INTO audit_head (id, sequence, hash)
+VALUES (1, 0, '0000000000000000000000000000000000000000000000000000000000000000');
+
+UPDATE audit_head SET
+    sequence = (SELECT sequence FROM audit_events WHERE sequence IS NOT NULL ORDER BY sequence DESC LIMIT 1),
+    hash = (SELECT hash FROM audit_events WHERE sequence IS NOT NULL ORDER BY sequence DESC LIMIT 1)
+WHERE EXISTS (SELECT 1 FROM audit_events WHERE sequence IS NOT NULL);
@@ -0,0 +1,36 @@
+CREATE TABLE IF NOT EXISTS users (
+    id TEXT PRIMARY KEY NOT NULL,
+    username TEXT NOT NULL,
+    email TEXT NOT NULL UNIQUE,
+    password TEXT NOT NULL,
+    public_key TEXT NOT NULL,
+    created_at BIGINT NOT NULL,
+    updated_at BIGINT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS files (
+    id TEXT PRIMARY KEY NOT NULL,
+    user_id TEXT NOT NULL REFERENCES users (id),
+    file_name TEXT NOT NULL,
+    file_size BIGINT NOT NULL,
+    encrypted_aes_key BLOB NOT NULL,
+    encrypted_file BLOB NOT NULL,
+    iv BLOB NOT NULL,
+    created_at BIGINT NOT NULL,
+    updated_at BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS files_user_id_idx ON files (user_id);
+
+CREATE TABLE IF NOT EXISTS share_links (
+    id TEXT PRIMARY KEY NOT NULL,
+    recipient_user_id TEXT NOT NULL REFERENCES users (id),
+    file_id TEXT NOT NULL REFERENCES files (id),
+    password TEXT NOT NULL,
+    expires_at BIGINT NOT NULL,
+    created_at BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS share_links_file_id_idx ON share_links (file_id);
+CREATE INDEX IF NOT EXISTS share_links_recipient_user_id_idx ON share_links (recipient_user_id);
+CREATE INDEX IF NOT EXISTS share_links_expires_at_idx ON share_links (expires_at);
@@ -0,0 +1,3 @@
+ALTER TABLE files ADD COLUMN sha256 TEXT;
+ALTER TABLE file_versions ADD COLUMN sha256 TEXT;
+ALTER TABLE share_links ADD COLUMN signature BLOB;
@@ -0,0 +1,2 @@
+ALTER TABLE users ADD COLUMN storage_used BIGINT NOT NULL DEFAULT 0;
+UPDATE users SET storage_used = (SELECT COALESCE(SUM(f.file_size), 0) FROM files f WHERE f.user_id = users.id);
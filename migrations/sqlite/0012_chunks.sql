@@ -0,0 +1,12 @@
+-- Deduplicated content shared by files and versions; refcount is the number
+-- of entries in their chunk_ids lists that name the chunk
+CREATE TABLE IF NOT EXISTS chunks (
+    id TEXT PRIMARY KEY NOT NULL,
+    data BLOB NOT NULL,
+    refcount BIGINT NOT NULL,
+    created_at BIGINT NOT NULL
+);
+
+-- Comma-separated chunk ids, in content order
+ALTER TABLE files ADD COLUMN chunk_ids TEXT;
+ALTER TABLE file_versions ADD COLUMN chunk_ids TEXT;
@@ -0,0 +1,19 @@
+-- Append-only; ids and user ids are kept without foreign keys so events
+-- outlive the users, files and shares they mention
+CREATE TABLE IF NOT EXISTS audit_events (
+    id TEXT PRIMARY KEY NOT NULL,
+    action TEXT NOT NULL,
+    actor_id TEXT,
+    ip TEXT,
+    user_agent TEXT,
+    subject_user_id TEXT,
+    file_id TEXT,
+    share_id TEXT,
+    collection_id TEXT,
+    detail TEXT,
+    created_at BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS audit_events_created_at_idx ON audit_events (created_at);
+CREATE INDEX IF NOT EXISTS audit_events_actor_id_idx ON audit_events (actor_id);
+CREATE INDEX IF NOT EXISTS audit_events_subject_user_id_idx ON audit_events (subject_user_id);
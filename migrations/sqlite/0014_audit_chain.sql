@@ -0,0 +1,14 @@
+-- Events recorded before chaining keep a NULL sequence and stay outside the chain
+ALTER TABLE audit_events ADD COLUMN sequence BIGINT;
+ALTER TABLE audit_events ADD COLUMN prev_hash TEXT;
+ALTER TABLE audit_events ADD COLUMN hash TEXT;
+
+CREATE UNIQUE INDEX IF NOT EXISTS audit_events_sequence_idx ON audit_events (sequence);
+
+CREATE TABLE IF NOT EXISTS audit_checkpoints (
+    id TEXT PRIMARY KEY NOT NULL,
+    sequence BIGINT NOT NULL,
+    hash TEXT NOT NULL,
+    signature BLOB NOT NULL,
+    created_at BIGINT NOT NULL
+);
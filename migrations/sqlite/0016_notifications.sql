@@ -0,0 +1,17 @@
+CREATE TABLE IF NOT EXISTS notifications (
+    id TEXT PRIMARY KEY NOT NULL,
+    user_id TEXT NOT NULL REFERENCES users (id),
+    kind TEXT NOT NULL,
+    actor_id TEXT,
+    share_id TEXT,
+    file_id TEXT,
+    collection_id TEXT,
+    name TEXT,
+    read_at BIGINT,
+    created_at BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS notifications_user_id_idx ON notifications (user_id, created_at);
+
+-- When the recipient was last told the share is about to expire
+ALTER TABLE share_links ADD COLUMN expiry_notified_at BIGINT;
@@ -0,0 +1,9 @@
+-- Which notifications each user also receives by email; users without a row
+-- get every email. Flags are 0/1 like share_links.can_reshare
+CREATE TABLE IF NOT EXISTS email_preferences (
+    user_id TEXT PRIMARY KEY NOT NULL REFERENCES users (id),
+    share_received BIGINT NOT NULL DEFAULT 1,
+    share_expiring BIGINT NOT NULL DEFAULT 1,
+    share_downloaded BIGINT NOT NULL DEFAULT 1,
+    share_revoked BIGINT NOT NULL DEFAULT 1
+);
@@ -0,0 +1,20 @@
+-- Shares with email addresses that have no account yet. The file's AES key is
+-- wrapped to the server's invitation key until the invitee registers
+CREATE TABLE IF NOT EXISTS invitations (
+    id TEXT PRIMARY KEY NOT NULL,
+    -- Lowercased
+    email TEXT NOT NULL,
+    file_id TEXT NOT NULL REFERENCES files (id),
+    sender_id TEXT NOT NULL REFERENCES users (id),
+    file_name TEXT NOT NULL,
+    password TEXT NOT NULL,
+    expires_at BIGINT NOT NULL,
+    -- 0/1 like share_links.can_reshare
+    sign BIGINT NOT NULL DEFAULT 0,
+    created_at BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS invitations_email_idx ON invitations (email);
+CREATE INDEX IF NOT EXISTS invitations_sender_id_idx ON invitations (sender_id, created_at);
+CREATE INDEX IF NOT EXISTS invitations_file_id_idx ON invitations (file_id);
+CREATE INDEX IF NOT EXISTS invitations_expires_at_idx ON invitations (expires_at);
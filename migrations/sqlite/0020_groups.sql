@@ -0,0 +1,36 @@
+-- Users shared with together under one name
+CREATE TABLE IF NOT EXISTS user_groups (
+    id TEXT PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL,
+    owner_id TEXT NOT NULL REFERENCES users (id),
+    created_at BIGINT NOT NULL,
+    updated_at BIGINT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS group_members (
+    group_id TEXT NOT NULL REFERENCES user_groups (id),
+    user_id TEXT NOT NULL REFERENCES users (id),
+    -- 0/1 like share_links.can_reshare
+    admin BIGINT NOT NULL DEFAULT 0,
+    created_at BIGINT NOT NULL,
+    PRIMARY KEY (group_id, user_id)
+);
+
+CREATE INDEX IF NOT EXISTS group_members_user_id_idx ON group_members (user_id);
+
+-- Files shared with a group that also go to members who join later. file_id
+-- has no foreign key so files can be deleted before their group shares are swept
+CREATE TABLE IF NOT EXISTS group_shares (
+    id TEXT PRIMARY KEY NOT NULL,
+    group_id TEXT NOT NULL REFERENCES user_groups (id),
+    file_id TEXT NOT NULL,
+    sender_id TEXT NOT NULL REFERENCES users (id),
+    password TEXT NOT NULL,
+    expires_at BIGINT NOT NULL,
+    sign BIGINT NOT NULL DEFAULT 0,
+    created_at BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS group_shares_group_id_idx ON group_shares (group_id, created_at);
+CREATE INDEX IF NOT EXISTS group_shares_file_id_idx ON group_shares (file_id);
+CREATE INDEX IF NOT EXISTS group_shares_expires_at_idx ON group_shares (expires_at);
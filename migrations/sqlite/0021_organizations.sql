@@ -0,0 +1,31 @@
+-- Client companies hosted on the server, each a tenant of its own
+CREATE TABLE IF NOT EXISTS organizations (
+    id TEXT PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL,
+    max_expiry_days BIGINT,
+    storage_quota BIGINT,
+    created_at BIGINT NOT NULL,
+    updated_at BIGINT NOT NULL
+);
+
+-- A domain belongs to one organization at most
+CREATE TABLE IF NOT EXISTS organization_domains (
+    domain TEXT PRIMARY KEY NOT NULL,
+    organization_id TEXT NOT NULL REFERENCES organizations (id)
+);
+
+CREATE INDEX IF NOT EXISTS organization_domains_organization_id_idx ON organization_domains (organization_id);
+
+CREATE TABLE IF NOT EXISTS organization_admins (
+    organization_id TEXT NOT NULL REFERENCES organizations (id),
+    user_id TEXT NOT NULL REFERENCES users (id),
+    PRIMARY KEY (organization_id, user_id)
+);
+
+-- NULL outside every organization
+ALTER TABLE users ADD COLUMN organization_id TEXT REFERENCES organizations (id);
+ALTER TABLE files ADD COLUMN organization_id TEXT;
+ALTER TABLE share_links ADD COLUMN organization_id TEXT;
+
+CREATE INDEX IF NOT EXISTS users_organization_id_idx ON users (organization_id);
+CREATE INDEX IF NOT EXISTS share_links_organization_id_idx ON share_links (recipient_user_id, organization_id);
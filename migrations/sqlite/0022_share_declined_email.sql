@@ -0,0 +1,2 @@
+-- Senders are emailed when a recipient declines their share, unless turned off
+ALTER TABLE email_preferences ADD COLUMN share_declined BIGINT NOT NULL DEFAULT 1;
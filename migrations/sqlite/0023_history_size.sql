@@ -0,0 +1,5 @@
+-- Superseded revisions kept in file_versions count towards their owner's
+-- storage; history_size is their total per file
+ALTER TABLE files ADD COLUMN history_size BIGINT NOT NULL DEFAULT 0;
+UPDATE files SET history_size = (SELECT COALESCE(SUM(v.file_size), 0) FROM file_versions v WHERE v.file_id = files.id);
+UPDATE users SET storage_used = storage_used + (SELECT COALESCE(SUM(f.history_size), 0) FROM files f WHERE f.user_id = users.id);
@@ -0,0 +1,145 @@
+use actix_web::{
+    get, post,
+    web::{self, Data, Json, Query},
+    Error, HttpMessage, HttpRequest,
+};
+use mongodb::bson::{oid::ObjectId, Bson};
+
+use crate::{
+    config::Config,
+    dtos::{
+        audit::audit_events::{AuditEventsResponse, AuditQueryParams},
+        webhook::webhooks::{
+            CreateWebhookDto, CreateWebhookResponse, FilteredWebhook, WebhooksResponse,
+        },
+    },
+    models::{
+        audit_event_model::{AuditAction, AuditEvent},
+        user_model::User,
+    },
+    services::{
+        audit::{self, request_event, AuditVerification},
+        db::Database,
+        webhooks,
+    },
+    utils::keys::load_audit_signing_key,
+};
+
+// Initialize routes
+pub fn init(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_audit_events)
+        .service(verify_audit_chain)
+        .service(create_webhook)
+        .service(get_webhooks);
+}
+
+// Resolves the requesting user, refusing anyone not configured as an admin
+async fn require_admin(
+    req: &HttpRequest,
+    db: &Data<dyn Database>,
+    config: &Config,
+) -> Result<User, Error> {
+    // Extract user_id from request extensions
+    let user_id = match req.extensions().get::<ObjectId>().cloned() {
+        Some(id) => id,
+        None => {
+            return Err(actix_web::error::ErrorUnauthorized("User ID not found"));
+        }
+    };
+
+    let user = db.get_user_by_id(Bson::ObjectId(user_id)).await?;
+    if !config.is_admin(&user) {
+        return Err(actix_web::error::ErrorForbidden("Admin access required"));
+    }
+
+    Ok(user)
+}
+
+#[get("/audit")]
+pub async fn get_audit_events(
+    req: HttpRequest,
+    db: Data<dyn Database>,
+    config: Data<Config>,
+    query: Query<AuditQueryParams>,
+) -> Result<Json<AuditEventsResponse>, Error> {
+    let admin = require_admin(&req, &db, &config).await?;
+    let params = query.into_inner();
+    let user_id = params.requested_user()?;
+    let query = params.into_query(user_id)?;
+
+    let events = db.get_audit_events(query.clone()).await?;
+
+    // Reading the audit log is itself audited
+    audit::record(
+        db.get_ref(),
+        AuditEvent {
+            actor_id: Some(admin._id),
+            subject_user_id: user_id,
+            ..request_event(AuditAction::AdminAuditQuery, &req)
+        },
+    )
+    .await;
+
+    Ok(Json(AuditEventsResponse::new(events, &query)))
+}
+
+#[get("/audit/verify")]
+pub async fn verify_audit_chain(
+    req: HttpRequest,
+    db: Data<dyn Database>,
+    config: Data<Config>,
+) -> Result<Json<AuditVerification>, Error> {
+    let admin = require_admin(&req, &db, &config).await?;
+
+    let verifying_key = load_audit_signing_key()?.verifying_key();
+    let verification = audit::verify_chain(db.get_ref(), &verifying_key).await?;
+
+    audit::record(
+        db.get_ref(),
+        AuditEvent {
+            actor_id: Some(admin._id),
+            detail: Some(format!(
+                "{} events checked, {} problems",
+                verification.events_checked,
+                verification.problems.len()
+            )),
+            ..request_event(AuditAction::AdminAuditVerify, &req)
+        },
+    )
+    .await;
+
+    Ok(Json(verification))
+}
+
+// Admin webhooks are sent every user's events. They are managed through the
+// /webhooks routes like any other, by any admin
+#[post("/webhooks")]
+pub async fn create_webhook(
+    req: HttpRequest,
+    db: Data<dyn Database>,
+    config: Data<Config>,
+    body: Json<CreateWebhookDto>,
+) -> Result<Json<CreateWebhookResponse>, Error> {
+    require_admin(&req, &db, &config).await?;
+
+    let webhook = webhooks::register(db.get_ref(), None, body.url.clone(), body.events()).await?;
+
+    Ok(Json(CreateWebhookResponse {
+        status: 200.to_string(),
+        webhook: FilteredWebhook::filter_webhook(&webhook),
+        secret: webhook.secret,
+    }))
+}
+
+#[get("/webhooks")]
+pub async fn get_webhooks(
+    req: HttpRequest,
+    db: Data<dyn Database>,
+    config: Data<Config>,
+) -> Result<Json<WebhooksResponse>, Error> {
+    require_admin(&req, &db, &config).await?;
+
+    let webhooks = db.get_webhooks(None).await?;
+
+    Ok(Json(WebhooksResponse::new(webhooks)))
+}
@@ -1,8 +1,9 @@
 use actix_web::{
     post,
     web::{self, Data, Json},
+    Error, HttpRequest,
 };
-use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{oid::ObjectId, Bson};
 use validator::Validate;
 
 use crate::{
@@ -11,8 +12,17 @@ use crate::{
         login_user_dto::LoginUserDto,
         refresh_token_dto::RefreshTokenDto,
         register_user_dto::{RegisterUserDto, RegisterUserResponse},
+        verify_email_dto::{VerifyEmailDto, VerifyEmailResponse},
+    },
+    models::audit_event_model::{AuditAction, AuditEvent},
+    services::{
+        audit::{self, request_event},
+        db::Database,
+        invitations,
+        notifications::Notifier,
+        organizations::join_by_domain,
+        verification,
     },
-    services::db::Database,
     utils::{
         keys::generate_key,
         password::{compare, hash},
@@ -22,14 +32,18 @@ use crate::{
 
 // Initialize routes
 pub fn init(cfg: &mut web::ServiceConfig) {
-    cfg.service(register).service(login).service(refresh);
+    cfg.service(register)
+        .service(login)
+        .service(refresh)
+        .service(verify_email);
 }
 
 #[post("/auth/register")]
 pub async fn register(
     body: Json<RegisterUserDto>,
-    db: Data<Database>,
+    db: Data<dyn Database>,
     config: Data<Config>,
+    notifier: Data<Notifier>,
 ) -> Json<RegisterUserResponse> {
     let _ = body
         .validate()
@@ -63,7 +77,7 @@ pub async fn register(
 
     match db.create_user(body.name, body.email, hash_password).await {
         Ok(user) => {
-            let _key_result = match generate_key(db.clone(), user.inserted_id.clone()).await {
+            let _key_result = match generate_key(db.clone(), Bson::ObjectId(user)).await {
                 Ok(_) => {}
                 Err(e) => {
                     return Json(RegisterUserResponse {
@@ -75,8 +89,18 @@ pub async fn register(
                 }
             };
 
+            if let Ok(registered) = db.get_user_by_id(Bson::ObjectId(user)).await {
+                // The account stands without the email; the user can ask for
+                // another one
+                if let Err(e) =
+                    verification::send_verification(db.get_ref(), &notifier, &registered).await
+                {
+                    eprintln!("Failed to send verification email to {}: {}", registered.email, e);
+                }
+            }
+
             let access_token: String = match create_token(
-                &user.inserted_id.to_string(),
+                &user.to_string(),
                 &config.jwt_secret.as_bytes(),
                 config.access_token_maxage,
             )
@@ -93,7 +117,7 @@ pub async fn register(
                 }
             };
             let refresh_token: String = match create_token(
-                &user.inserted_id.to_string(),
+                &user.to_string(),
                 &config.jwt_secret.as_bytes(),
                 config.refresh_token_maxage,
             )
@@ -129,8 +153,9 @@ pub async fn register(
 
 #[post("/auth/login")]
 pub async fn login(
+    req: HttpRequest,
     body: Json<LoginUserDto>,
-    db: Data<Database>,
+    db: Data<dyn Database>,
     config: Data<Config>,
 ) -> Json<RegisterUserResponse> {
     let _ = body
@@ -140,6 +165,11 @@ pub async fn login(
     let user = match db.get_user(body.email.clone()).await {
         Ok(user) => user,
         Err(e) => {
+            let event = AuditEvent {
+                detail: Some(format!("Unknown email: {}", body.email)),
+                ..request_event(AuditAction::LoginFailed, &req)
+            };
+            audit::record(db.get_ref(), event).await;
             return Json(RegisterUserResponse {
                 status_code: 400,
                 access_token: None,
@@ -199,6 +229,12 @@ pub async fn login(
             }
         };
 
+        let event = AuditEvent {
+            actor_id: Some(user._id),
+            ..request_event(AuditAction::Login, &req)
+        };
+        audit::record(db.get_ref(), event).await;
+
         return Json(RegisterUserResponse {
             status_code: 201,
             message: "Login successful".to_string(),
@@ -206,6 +242,12 @@ pub async fn login(
             refresh_token: Some(refresh_token.to_string()),
         });
     } else {
+        let event = AuditEvent {
+            subject_user_id: Some(user._id),
+            ..request_event(AuditAction::LoginFailed, &req)
+        };
+        audit::record(db.get_ref(), event).await;
+
         return Json(RegisterUserResponse {
             status_code: 400,
             access_token: None,
@@ -298,3 +340,27 @@ pub async fn refresh(
         refresh_token: Some(refresh_token.to_string()),
     });
 }
+
+#[post("/auth/verify-email")]
+pub async fn verify_email(
+    req: HttpRequest,
+    body: Json<VerifyEmailDto>,
+    db: Data<dyn Database>,
+    notifier: Data<Notifier>,
+) -> Result<Json<VerifyEmailResponse>, Error> {
+    body.validate()
+        .map_err(|e| actix_web::error::ErrorBadRequest(format!("Validation failed: {}", e)))?;
+
+    let mut user = verification::verify(db.get_ref(), &body.token).await?;
+
+    // Addresses in a domain an organization claims join it, before any
+    // invitation from within it can be accepted
+    join_by_domain(db.get_ref(), &req, &mut user).await;
+    // Shares sent to this email before it was proven to be the user's
+    invitations::accept_pending(db.get_ref(), &notifier, &req, &user).await;
+
+    Ok(Json(VerifyEmailResponse {
+        status: 200,
+        message: "Email verified".to_string(),
+    }))
+}
@@ -0,0 +1,497 @@
+use std::collections::HashSet;
+
+use crate::{
+    config::Config,
+    dtos::collection::{
+        download_collection::DownloadCollectionDto,
+        get_collections::CollectionListResponse,
+        upload_collection::{CollectionUploadDtos, UploadCollectionResponse},
+    },
+    models::{
+        audit_event_model::{AuditAction, AuditEvent},
+        collection_model::Collection,
+        file_model::File,
+        notification_model::{Notification, NotificationKind},
+        share_link_model::ShareLink,
+    },
+    services::{
+        audit::{self, request_event},
+        db::Database,
+        notifications::{self, notification, Notifier},
+        organizations::{check_expiry, ensure_same_organization, load_organization, storage_quota},
+        scanner::{scan_upload, Scanner},
+        verification::ensure_verified,
+    },
+    utils::{
+        file::{
+            archive::ZipStream,
+            compress::compress_upload,
+            content_type::sniff_content_type,
+            decrypt::decrypt_file,
+            dedup::{dedup_upload, ChunkedContent},
+            encrypt::encrypt_file,
+            integrity::{sha256_hex, sign_upload, verify_checksum, verify_signature},
+            upload::read_field_limited,
+        },
+        keys::{load_private_key, load_public_key, load_signing_key, load_verifying_key},
+        password,
+    },
+};
+use actix_multipart::Multipart;
+use actix_web::{
+    get,
+    http::header,
+    post,
+    web::{self, Data, Json, Path},
+    Error, HttpMessage, HttpRequest, HttpResponse,
+};
+use futures_util::stream::{self, StreamExt};
+use mongodb::bson::{oid::ObjectId, Bson, DateTime};
+use validator::Validate;
+
+// Initialize routes
+pub fn init(cfg: &mut web::ServiceConfig) {
+    cfg.service(upload_collection)
+        .service(get_sent_collections)
+        .service(get_received_collections)
+        .service(download_collection);
+}
+
+#[post("/upload")]
+pub async fn upload_collection(
+    mut payload: Multipart,
+    req: HttpRequest,
+    db: Data<dyn Database>,
+    config: Data<Config>,
+    scanner: Data<dyn Scanner>,
+    notifier: Data<Notifier>,
+) -> Result<Json<UploadCollectionResponse>, Error> {
+    // Extract user_id from request extensions
+    let user_id = match req.extensions().get::<ObjectId>().cloned() {
+        Some(id) => id,
+        None => {
+            return Err(actix_web::error::ErrorUnauthorized("User ID not found"));
+        }
+    };
+
+    let mut uploads: Vec<(String, Vec<u8>)> = Vec::new();
+    // The size limit covers the whole bundle rather than each file
+    let mut upload_size: i64 = 0;
+    let mut form_data = CollectionUploadDtos::default();
+
+    // Every "fileUpload" part becomes one file of the collection
+    while let Some(Ok(mut field)) = payload.next().await {
+        let field_name = match field.name() {
+            Some(name) => name.to_string(),
+            None => {
+                return Err(actix_web::error::ErrorBadRequest("Field name not found"));
+            }
+        };
+
+        if field_name == "fileUpload" {
+            let file_name = field
+                .content_disposition()
+                .and_then(|disposition| disposition.get_filename())
+                .unwrap_or("unknown_file")
+                .to_string();
+
+            let file_data =
+                read_field_limited(&mut field, config.max_upload_size - upload_size).await?;
+            upload_size += file_data.len() as i64;
+            uploads.push((file_name, file_data));
+            continue;
+        }
+
+        let mut value = Vec::new();
+        while let Some(chunk) = field.next().await {
+            value.extend_from_slice(&chunk?);
+        }
+        let value = String::from_utf8(value).unwrap_or_default();
+
+        match field_name.as_str() {
+            "collection_name" => form_data.collection_name = value,
+            "recipient_email" => form_data.recipient_email = value,
+            "password" => form_data.password = value,
+            "expiration_date" => form_data.expiration_date = value,
+            "sign" => form_data.sign = value == "true",
+            "skip_compression" => form_data.skip_compression = value == "true",
+            _ => {}
+        }
+    }
+
+    form_data.validate().map_err(|e| {
+        actix_web::error::ErrorBadRequest(format!("Failed to validate the form: {}", e))
+    })?;
+
+    if uploads.is_empty() {
+        return Err(actix_web::error::ErrorBadRequest(
+            "A collection needs at least one file",
+        ));
+    }
+
+    // One rejected file rejects the whole bundle
+    let mut content_types = Vec::with_capacity(uploads.len());
+    for (file_name, file_data) in &uploads {
+        let content_type = sniff_content_type(file_data);
+        config.upload_policy.check(file_name, &content_type)?;
+        content_types.push(content_type);
+    }
+
+    let user = db.get_user_by_id(Bson::ObjectId(user_id)).await?;
+    let organization = load_organization(db.get_ref(), user.organization_id).await?;
+
+    let recipient_user = db
+        .get_user(form_data.recipient_email.clone())
+        .await
+        .map_err(|e| {
+            actix_web::error::ErrorBadRequest(format!("Failed to get reciepient: {}", e))
+        })?;
+    ensure_verified(&recipient_user)?;
+    ensure_same_organization(user.organization_id, &recipient_user)?;
+    let public_key = load_public_key(&recipient_user)?;
+    let signing_key = if form_data.sign {
+        Some(load_signing_key(user_id)?)
+    } else {
+        None
+    };
+
+    let hash_password = password::hash(&form_data.password).map_err(|e| {
+        actix_web::error::ErrorBadRequest(format!("Failed to hash password: {}", e))
+    })?;
+
+    let expires_at = DateTime::parse_rfc3339_str(&form_data.expiration_date).map_err(|e| {
+        actix_web::error::ErrorBadRequest(format!("Failed to parse date time: {}", e))
+    })?;
+    check_expiry(organization.as_ref(), expires_at)?;
+
+    let collection = Collection {
+        _id: ObjectId::new(),
+        user_id,
+        name: form_data.collection_name,
+        created_at: DateTime::now(),
+    };
+
+    // All files in the bundle share one password and expiry; infected ones are
+    // quarantined on their own while the rest are delivered
+    let mut quarantined = Vec::new();
+    let mut files = Vec::with_capacity(uploads.len());
+    let mut share_links = Vec::with_capacity(uploads.len());
+    let mut chunks = Vec::new();
+    for ((file_name, file_data), content_type) in uploads.into_iter().zip(content_types) {
+        let file_size = file_data.len() as i64;
+        let (scan_status, detected) = scan_upload(scanner.as_ref(), &file_name, &file_data).await?;
+        let sha256 = sha256_hex(&file_data);
+        let signature = signing_key
+            .as_ref()
+            .map(|key| sign_upload(key, &sha256, &file_name, recipient_user._id));
+        let (file_data, compression) = compress_upload(file_data, form_data.skip_compression)?;
+        let ChunkedContent {
+            data: file_data,
+            chunk_ids,
+            chunks: file_chunks,
+        } = dedup_upload(config.dedup_key.as_deref(), file_data)?;
+        chunks.extend(file_chunks);
+        let (encrypted_aes_key, encrypted_file, iv) = encrypt_file(file_data, &public_key).await?;
+
+        let file = File {
+            _id: ObjectId::new(),
+            user_id,
+            file_name,
+            file_size,
+            content_type: Some(content_type),
+            encrypted_aes_key,
+            encrypted_file,
+            iv,
+            sha256: Some(sha256),
+            created_at: DateTime::now(),
+            updated_at: DateTime::now(),
+            collection_id: Some(collection._id),
+            version: 1,
+            scan_status,
+            compression,
+            chunk_ids,
+            organization_id: user.organization_id,
+            history_size: 0,
+        };
+
+        share_links.push(ShareLink {
+            _id: ObjectId::new(),
+            recipient_user_id: recipient_user._id,
+            file_id: file._id,
+            password: hash_password.clone(),
+            expires_at,
+            created_at: DateTime::now(),
+            encrypted_aes_key: None,
+            revoked_at: None,
+            declined_at: None,
+            archived_at: None,
+            can_reshare: false,
+            parent_share_id: None,
+            signature,
+            first_opened_at: None,
+            last_opened_at: None,
+            open_count: 0,
+            failed_password_attempts: 0,
+            organization_id: user.organization_id,
+        });
+        if let Some(signature) = detected {
+            quarantined.push((file._id, file.file_name.clone(), signature));
+        }
+        files.push(file);
+    }
+
+    let file_count = files.len();
+    let collection_name = collection.name.clone();
+    db.reserve_storage(
+        user_id,
+        upload_size,
+        storage_quota(&config, organization.as_ref()),
+    )
+    .await?;
+    let collection_id = match db
+        .save_collection(collection, files, share_links, chunks)
+        .await
+    {
+        Ok(collection_id) => collection_id,
+        Err(e) => {
+            db.release_storage(user_id, upload_size).await?;
+            return Err(e);
+        }
+    };
+
+    let event = AuditEvent {
+        actor_id: Some(user_id),
+        subject_user_id: Some(recipient_user._id),
+        collection_id: Some(collection_id),
+        detail: Some(format!(
+            "{} files, {} quarantined",
+            file_count,
+            quarantined.len()
+        )),
+        ..request_event(AuditAction::Upload, &req)
+    };
+    audit::record(db.get_ref(), event).await;
+
+    let notices = quarantined
+        .iter()
+        .map(|(file_id, file_name, _)| Notification {
+            file_id: Some(*file_id),
+            collection_id: Some(collection_id),
+            name: Some(file_name.clone()),
+            ..notification(user_id, NotificationKind::Quarantined)
+        })
+        .collect();
+    notifications::notify(db.get_ref(), &notifier, notices).await;
+
+    // Nothing reaches the recipient when every file was quarantined
+    if quarantined.len() < file_count {
+        let received = Notification {
+            actor_id: Some(user_id),
+            collection_id: Some(collection_id),
+            name: Some(collection_name),
+            ..notification(recipient_user._id, NotificationKind::Received)
+        };
+        notifications::notify(db.get_ref(), &notifier, vec![received]).await;
+    }
+
+    let message = if quarantined.is_empty() {
+        format!("Collection of {} files uploaded successfully", file_count)
+    } else {
+        format!(
+            "Collection of {} files uploaded; quarantined after malware was detected and not delivered: {}",
+            file_count,
+            quarantined
+                .iter()
+                .map(|(_, file_name, signature)| format!("{} ({})", file_name, signature))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    };
+
+    Ok(Json(UploadCollectionResponse {
+        status: 200,
+        message,
+        collection_id: collection_id.to_hex(),
+    }))
+}
+
+#[get("/get-my-collections")]
+pub async fn get_sent_collections(
+    req: HttpRequest,
+    db: Data<dyn Database>,
+) -> Result<Json<CollectionListResponse>, Error> {
+    // Extract user_id from request extensions
+    let user_id = match req.extensions().get::<ObjectId>().cloned() {
+        Some(id) => id,
+        None => {
+            return Err(actix_web::error::ErrorUnauthorized("User ID not found"));
+        }
+    };
+
+    let collections = db.get_sent_collections(user_id).await?;
+
+    Ok(Json(CollectionListResponse::new(collections)))
+}
+
+#[get("/get-received-collections")]
+pub async fn get_received_collections(
+    req: HttpRequest,
+    db: Data<dyn Database>,
+) -> Result<Json<CollectionListResponse>, Error> {
+    // Extract user_id from request extensions
+    let user_id = match req.extensions().get::<ObjectId>().cloned() {
+        Some(id) => id,
+        None => {
+            return Err(actix_web::error::ErrorUnauthorized("User ID not found"));
+        }
+    };
+
+    let collections = db.get_received_collections(user_id).await?;
+
+    Ok(Json(CollectionListResponse::new(collections)))
+}
+
+#[post("/{id}/download")]
+pub async fn download_collection(
+    req: HttpRequest,
+    db: Data<dyn Database>,
+    notifier: Data<Notifier>,
+    path: Path<String>,
+    body: Json<DownloadCollectionDto>,
+) -> Result<HttpResponse, Error> {
+    body.validate().map_err(|e| {
+        actix_web::error::ErrorBadRequest(format!("Failed to validate the form: {}", e))
+    })?;
+
+    // Extract user_id from request extensions
+    let user_id = match req.extensions().get::<ObjectId>().cloned() {
+        Some(id) => id,
+        None => {
+            return Err(actix_web::error::ErrorUnauthorized("User ID not found"));
+        }
+    };
+
+    let collection_id = ObjectId::parse_str(path.as_str()).map_err(|e| {
+        actix_web::error::ErrorBadRequest(format!("Failed to convert to objectid: {}", e))
+    })?;
+
+    let share_links = db.get_collection_shares(collection_id, user_id).await?;
+    if share_links.is_empty() {
+        return Err(actix_web::error::ErrorNotFound("Collection not found"));
+    }
+    let collection = db.get_collection(collection_id).await?;
+
+    // Shares start out with one password, but any of them may have been rotated since
+    let mut checked_passwords = HashSet::new();
+    for share_link in &share_links {
+        if !checked_passwords.insert(share_link.password.as_str()) {
+            continue;
+        }
+        let matched_password =
+            password::compare(&body.password, &share_link.password).map_err(|e| {
+                actix_web::error::ErrorBadRequest(format!("Failed to comapre password: {}", e))
+            })?;
+        if !matched_password {
+            let event = AuditEvent {
+                actor_id: Some(user_id),
+                share_id: Some(share_link._id),
+                collection_id: Some(collection_id),
+                ..request_event(AuditAction::SharePasswordFailed, &req)
+            };
+            audit::record(db.get_ref(), event).await;
+            return Err(actix_web::error::ErrorBadRequest("Password don't match"));
+        }
+    }
+
+    // Recorded as the archive starts streaming
+    let event = AuditEvent {
+        actor_id: Some(user_id),
+        subject_user_id: Some(collection.user_id),
+        collection_id: Some(collection_id),
+        detail: Some(format!("{} files", share_links.len())),
+        ..request_event(AuditAction::Download, &req)
+    };
+    audit::record(db.get_ref(), event).await;
+    let downloaded = Notification {
+        actor_id: Some(user_id),
+        collection_id: Some(collection_id),
+        name: Some(collection.name.clone()),
+        ..notification(collection.user_id, NotificationKind::Downloaded)
+    };
+    notifications::notify(db.get_ref(), &notifier, vec![downloaded]).await;
+
+    let private_key = load_private_key(user_id)?;
+    let verifying_key = if share_links.iter().any(|share| share.signature.is_some()) {
+        Some(load_verifying_key(collection.user_id)?)
+    } else {
+        None
+    };
+
+    // Each step decrypts one file and emits the archive bytes written for it
+    let state = Some((share_links.into_iter(), ZipStream::new()));
+    let archive = stream::unfold(state, move |state| {
+        let db = db.clone();
+        let private_key = private_key.clone();
+        async move {
+            let (mut share_links, mut zip) = state?;
+
+            let share_link = match share_links.next() {
+                Some(share_link) => share_link,
+                None => return Some((zip.finish(), None)),
+            };
+
+            let file = match db.get_file(Bson::ObjectId(share_link.file_id)).await {
+                Ok(file) => file,
+                Err(e) => return Some((Err(e), None)),
+            };
+            let encrypted_aes_key = share_link
+                .encrypted_aes_key
+                .unwrap_or(file.encrypted_aes_key);
+
+            let chunks = match db.get_chunks(&file.chunk_ids).await {
+                Ok(chunks) => chunks,
+                Err(e) => return Some((Err(e), None)),
+            };
+            let chunk = match decrypt_file(
+                encrypted_aes_key,
+                file.encrypted_file,
+                file.iv,
+                file.compression,
+                chunks,
+                &private_key,
+            )
+            .await
+            {
+                Ok(data) => verify_checksum(&data, file.sha256.as_deref())
+                    .and_then(|sha256| match (&share_link.signature, &verifying_key) {
+                        (Some(signature), Some(verifying_key)) => verify_signature(
+                            verifying_key,
+                            signature,
+                            &sha256,
+                            &file.file_name,
+                            share_link.recipient_user_id,
+                        ),
+                        _ => Ok(()),
+                    })
+                    .and_then(|_| zip.add_file(&file.file_name, &data)),
+                Err(e) => Err(e),
+            };
+
+            match chunk {
+                Ok(chunk) => Some((Ok(chunk), Some((share_links, zip)))),
+                Err(e) => Some((Err(e), None)),
+            }
+        }
+    });
+
+    Ok(HttpResponse::Ok()
+        .insert_header((header::CONTENT_TYPE, "application/zip"))
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            format!(
+                "attachment; filename=\"{}.zip\"",
+                collection.name.replace('"', "")
+            ),
+        ))
+        .streaming(archive))
+}
@@ -1,31 +1,71 @@
-use std::{fs, path::PathBuf};
-
 use crate::{
+    config::Config,
     dtos::file::{
         delete_file::DeleteFileQuery,
-        get_files::{FilteredFile, QueryParams},
+        file_versions::{FileVersionListResponse, UploadFileVersionResponse},
+        get_files::{FileListResponse, QueryParams},
+        invitations::InvitationListResponse,
+        manage_share::{
+            ManageShareResponse, ReshareDto, RotateSharePasswordDto, SharePermissionsDto,
+            ShareRecipientDto, UpdateShareExpiryDto,
+        },
         retrieve_file::{RetrieveFileDto, RetrieveFileResponse},
+        share_access::{ShareAccessParams, ShareAccessResponse},
         upload_file::{FileUploadDtos, UploadFileResponse},
     },
-    services::db::Database,
+    models::{
+        audit_event_model::{AuditAction, AuditEvent},
+        file_model::{File, ScanStatus},
+        group_model::GroupShare,
+        invitation_model::Invitation,
+        notification_model::{Notification, NotificationKind},
+        share_link_model::{RevokedShare, ShareLink},
+        webhook_model::WebhookEvent,
+    },
+    services::{
+        audit::{self, request_event},
+        db::Database,
+        groups::{get_member_group, member_share_links, share_recipients},
+        invitations::send_invitation,
+        notifications::{self, notification, Notifier},
+        organizations::{
+            check_expiry, check_invitee, ensure_same_organization, load_organization, storage_quota,
+        },
+        scanner::{scan_upload, Scanner},
+        verification::ensure_verified,
+        webhooks::{self, file_event, FileEvent},
+    },
     utils::{
-        file::{decrypt::decrypt_file, encrypt::encrypt_file},
+        email::normalize_email,
+        file::{
+            compress::compress_upload,
+            content_type::sniff_content_type,
+            decrypt::decrypt_file,
+            dedup::{dedup_upload, ChunkedContent},
+            encrypt::{encrypt_file, encrypt_revision},
+            integrity::{sha256_hex, sign_upload, verify_checksum, verify_signature},
+            rewrap::rewrap_aes_key,
+            upload::read_field_limited,
+        },
+        keys::{
+            load_invitation_key, load_private_key, load_public_key, load_signing_key,
+            load_verifying_key,
+        },
         password,
     },
 };
 use actix_multipart::Multipart;
 use actix_web::{
-    delete, get, post,
-    web::{self, Data, Json, Query},
-    Error, HttpMessage, HttpRequest,
+    delete, get,
+    http::StatusCode,
+    patch, post,
+    web::{self, Data, Json, Path, Query},
+    Error, HttpMessage, HttpRequest, HttpResponse,
 };
 use base64::{engine::general_purpose::STANDARD, Engine};
 use futures_util::stream::StreamExt;
-use mongodb::bson::{self, oid::ObjectId, Bson};
-use rsa::{
-    pkcs1::{DecodeRsaPrivateKey, DecodeRsaPublicKey},
-    RsaPrivateKey, RsaPublicKey,
-};
+use mongodb::bson::{self, oid::ObjectId, Bson, DateTime};
+use rsa::RsaPublicKey;
 use validator::Validate;
 
 // Initialize routes
@@ -34,14 +74,33 @@ pub fn init(cfg: &mut web::ServiceConfig) {
         .service(retrieve_file)
         .service(get_user_files)
         .service(get_recieve_files)
-        .service(delete_file);
+        .service(delete_file)
+        .service(revoke_share)
+        .service(update_share_expiry)
+        .service(rotate_share_password)
+        .service(update_share_permissions)
+        .service(add_share_recipient)
+        .service(remove_share_recipient)
+        .service(decline_received_share)
+        .service(archive_received_share)
+        .service(unarchive_received_share)
+        .service(remove_received_share)
+        .service(reshare_received_file)
+        .service(upload_file_version)
+        .service(get_file_versions)
+        .service(get_share_access)
+        .service(get_sent_invitations)
+        .service(cancel_invitation);
 }
 
 #[post("/upload-file")]
 pub async fn upload_file(
     mut payload: Multipart, // Handle multipart payload
     req: HttpRequest,
-    db: Data<Database>,
+    db: Data<dyn Database>,
+    config: Data<Config>,
+    scanner: Data<dyn Scanner>,
+    notifier: Data<Notifier>,
 ) -> Result<Json<UploadFileResponse>, Error> {
     // Extract user_id from request extensions
     let user_id = req.extensions().get::<ObjectId>().cloned();
@@ -54,7 +113,7 @@ pub async fn upload_file(
         }
     };
 
-    let _user = match db
+    let user = match db
         .get_user_by_id(mongodb::bson::Bson::ObjectId(user_id))
         .await
     {
@@ -67,14 +126,22 @@ pub async fn upload_file(
         }
     };
 
+    let organization = load_organization(db.get_ref(), user.organization_id).await?;
+
     let mut file_data = Vec::new();
     let mut file_name = String::new();
     let mut file_size: i64 = 0;
+    // The size limit covers every file part of the request, not just the one kept
+    let mut upload_size: i64 = 0;
 
     let mut form_data = FileUploadDtos {
         recipient_email: String::new(),
+        recipient_group: None,
+        auto_grant: false,
         password: String::new(),
         expiration_date: String::new(),
+        sign: false,
+        skip_compression: false,
     };
 
     // Process the file upload
@@ -106,12 +173,12 @@ pub async fn upload_file(
                     .to_string();
 
                 // Collect the file bytes into a Vec<u8>
-                while let Some(Ok(chunk)) = field.next().await {
-                    file_data.extend_from_slice(&chunk);
-                }
+                file_data =
+                    read_field_limited(&mut field, config.max_upload_size - upload_size).await?;
 
                 // Get the size of the file in bytes
                 file_size = file_data.len() as i64;
+                upload_size += file_size;
             }
             // Handle other form fields
             "recipient_email" => {
@@ -120,6 +187,17 @@ pub async fn upload_file(
                         String::from_utf8(bytes?.to_vec()).unwrap_or_default();
                 }
             }
+            "recipient_group" => {
+                if let Some(bytes) = field.next().await {
+                    form_data.recipient_group =
+                        Some(String::from_utf8(bytes?.to_vec()).unwrap_or_default());
+                }
+            }
+            "auto_grant" => {
+                if let Some(bytes) = field.next().await {
+                    form_data.auto_grant = bytes?.as_ref() == b"true";
+                }
+            }
             "password" => {
                 if let Some(bytes) = field.next().await {
                     form_data.password = String::from_utf8(bytes?.to_vec()).unwrap_or_default();
@@ -132,6 +210,16 @@ pub async fn upload_file(
                 } else {
                 }
             }
+            "sign" => {
+                if let Some(bytes) = field.next().await {
+                    form_data.sign = bytes?.as_ref() == b"true";
+                }
+            }
+            "skip_compression" => {
+                if let Some(bytes) = field.next().await {
+                    form_data.skip_compression = bytes?.as_ref() == b"true";
+                }
+            }
             _ => {}
         }
     }
@@ -140,30 +228,77 @@ pub async fn upload_file(
         actix_web::error::ErrorBadRequest(format!("Failed to validate the form: {}", e.to_string()))
     })?;
 
-    let recipient_user = db
-        .get_user(form_data.recipient_email.clone())
-        .await
-        .map_err(|e| {
-            actix_web::error::ErrorBadRequest(format!(
-                "Failed to get reciepient: {}",
-                e.to_string()
-            ))
-        })?;
+    let content_type = sniff_content_type(&file_data);
+    config.upload_policy.check(&file_name, &content_type)?;
 
-    let public_key_str = recipient_user.public_key;
+    // A group expands to a share for every member but the sender
+    let group = match &form_data.recipient_group {
+        Some(group_id) => Some(get_member_group(db.get_ref(), group_id, user_id).await?),
+        None => None,
+    };
+    let group_members = match &group {
+        Some(group) => share_recipients(db.get_ref(), group, &user).await?,
+        None => Vec::new(),
+    };
 
-    let public_key_bytes = STANDARD.decode(public_key_str).map_err(|e| {
-        actix_web::error::ErrorBadRequest(format!("Failed to get public key: {}", e.to_string()))
-    })?;
+    // Addresses without a verified account are sent an invitation instead, and
+    // the file is encrypted for the server's invitation key until they verify
+    // it. For a group, the file is encrypted for its first member
+    let recipient_user = match group_members.first() {
+        Some(member) => Some(member.clone()),
+        None => match db.get_user(form_data.recipient_email.clone()).await {
+            Ok(recipient_user) if recipient_user.email_verified_at.is_some() => {
+                ensure_same_organization(user.organization_id, &recipient_user)?;
+                Some(recipient_user)
+            }
+            // Whoever registered the address has not shown it is theirs
+            Ok(_) => {
+                check_invitee(db.get_ref(), &user, &form_data.recipient_email).await?;
+                None
+            }
+            Err(e) if e.as_response_error().status_code() == StatusCode::NOT_FOUND => {
+                check_invitee(db.get_ref(), &user, &form_data.recipient_email).await?;
+                None
+            }
+            Err(e) => {
+                return Err(actix_web::error::ErrorBadRequest(format!(
+                    "Failed to get reciepient: {}",
+                    e
+                )))
+            }
+        },
+    };
 
-    let public_key = String::from_utf8(public_key_bytes).map_err(|e| {
-        actix_web::error::ErrorBadRequest(format!("Failed to get public key: {}", e.to_string()))
-    })?;
+    let public_key_pem = match &recipient_user {
+        Some(recipient_user) => load_public_key(recipient_user)?,
+        None => RsaPublicKey::from(&load_invitation_key()?),
+    };
 
-    let public_key_pem = RsaPublicKey::from_pkcs1_pem(&public_key).map_err(|e| {
-        actix_web::error::ErrorBadRequest(format!("Failed to get public key: {}", e.to_string()))
-    })?;
+    // Runs on the plaintext, so it has to happen before encryption
+    let (scan_status, detected) = scan_upload(scanner.as_ref(), &file_name, &file_data).await?;
+
+    let sha256 = sha256_hex(&file_data);
+    // Invitees are signed for once they register and have an id to sign, and
+    // group members along with their share links
+    let signature = match (&recipient_user, form_data.sign && group.is_none()) {
+        (Some(recipient_user), true) => {
+            let signing_key = load_signing_key(user_id)?;
+            Some(sign_upload(
+                &signing_key,
+                &sha256,
+                &file_name,
+                recipient_user._id,
+            ))
+        }
+        _ => None,
+    };
 
+    let (file_data, compression) = compress_upload(file_data, form_data.skip_compression)?;
+    let ChunkedContent {
+        data: file_data,
+        chunk_ids,
+        chunks,
+    } = dedup_upload(config.dedup_key.as_deref(), file_data)?;
     let (encrypted_aes_key, encrypted_data, iv) = encrypt_file(file_data, &public_key_pem).await?;
 
     let hash_password = password::hash(&form_data.password).map_err(|e| {
@@ -182,22 +317,131 @@ pub async fn upload_file(
         }
     };
 
-    let result = match db
-        .save_file(
-            file_name,
-            file_size,
-            encrypted_data,
-            iv,
-            encrypted_aes_key,
-            recipient_user._id.to_string(),
-            user_id,
-            hash_password,
-            mongo_expiration_date,
-        )
-        .await
-    {
+    check_expiry(organization.as_ref(), mongo_expiration_date)?;
+
+    db.reserve_storage(
+        user_id,
+        file_size,
+        storage_quota(&config, organization.as_ref()),
+    )
+    .await?;
+
+    let mut invitation = None;
+    let mut group_deliveries: Vec<(ObjectId, ObjectId)> = Vec::new();
+    let saved = match (&group, &recipient_user) {
+        (Some(group), _) => {
+            let file = File {
+                _id: ObjectId::new(),
+                user_id,
+                file_name: file_name.clone(),
+                file_size,
+                content_type: Some(content_type),
+                encrypted_aes_key,
+                encrypted_file: encrypted_data,
+                iv,
+                sha256: Some(sha256),
+                created_at: DateTime::now(),
+                updated_at: DateTime::now(),
+                collection_id: None,
+                version: 1,
+                scan_status,
+                compression,
+                chunk_ids,
+                organization_id: user.organization_id,
+                history_size: 0,
+            };
+            let group_share = form_data.auto_grant.then(|| GroupShare {
+                _id: ObjectId::new(),
+                group_id: group._id,
+                file_id: file._id,
+                sender_id: user_id,
+                password: hash_password.clone(),
+                expires_at: mongo_expiration_date,
+                sign: form_data.sign,
+                created_at: DateTime::now(),
+            });
+            match member_share_links(
+                &file,
+                &group_members,
+                &hash_password,
+                mongo_expiration_date,
+                form_data.sign,
+            )
+            .await
+            {
+                Ok(share_links) => {
+                    group_deliveries = share_links
+                        .iter()
+                        .map(|share_link| (share_link._id, share_link.recipient_user_id))
+                        .collect();
+                    db.save_group_file(file, chunks, share_links, group_share)
+                        .await
+                }
+                Err(e) => Err(e),
+            }
+        }
+        (None, Some(recipient_user)) => {
+            db.save_file(
+                file_name.clone(),
+                file_size,
+                encrypted_data,
+                iv,
+                encrypted_aes_key,
+                recipient_user._id.to_string(),
+                user_id,
+                hash_password,
+                mongo_expiration_date,
+                content_type,
+                sha256,
+                signature,
+                scan_status,
+                compression,
+                chunk_ids,
+                chunks,
+                user.organization_id,
+            )
+            .await
+        }
+        (None, None) => {
+            let file = File {
+                _id: ObjectId::new(),
+                user_id,
+                file_name: file_name.clone(),
+                file_size,
+                content_type: Some(content_type),
+                encrypted_aes_key,
+                encrypted_file: encrypted_data,
+                iv,
+                sha256: Some(sha256),
+                created_at: DateTime::now(),
+                updated_at: DateTime::now(),
+                collection_id: None,
+                version: 1,
+                scan_status,
+                compression,
+                chunk_ids,
+                organization_id: user.organization_id,
+                history_size: 0,
+            };
+            let pending = Invitation {
+                _id: ObjectId::new(),
+                email: normalize_email(&form_data.recipient_email),
+                file_id: file._id,
+                sender_id: user_id,
+                file_name: file_name.clone(),
+                password: hash_password,
+                expires_at: mongo_expiration_date,
+                sign: form_data.sign,
+                created_at: DateTime::now(),
+            };
+            invitation = Some(pending.clone());
+            db.save_invited_file(file, chunks, pending).await
+        }
+    };
+    let result = match saved {
         Ok(res) => res,
         Err(e) => {
+            db.release_storage(user_id, file_size).await?;
             return Err(actix_web::error::ErrorBadRequest(format!(
                 "Failed to save encrypted file: {}",
                 e.to_string()
@@ -205,12 +449,126 @@ pub async fn upload_file(
         }
     };
 
+    let event = AuditEvent {
+        actor_id: Some(user_id),
+        subject_user_id: match &group {
+            Some(_) => None,
+            None => recipient_user
+                .as_ref()
+                .map(|recipient_user| recipient_user._id),
+        },
+        file_id: Some(result),
+        detail: match (&detected, &group, &recipient_user) {
+            (Some(signature), _, _) => Some(format!("Quarantined: {} found", signature)),
+            (None, Some(group), _) => Some(format!("Group {}", group.name)),
+            (None, None, None) => Some(format!("Invitation to {}", form_data.recipient_email)),
+            (None, None, Some(_)) => None,
+        },
+        ..request_event(AuditAction::Upload, &req)
+    };
+    audit::record(db.get_ref(), event).await;
+
+    // The sender is told straight away; the recipient never sees the share
+    if let Some(signature) = detected {
+        let quarantined = Notification {
+            file_id: Some(result),
+            name: Some(file_name),
+            ..notification(user_id, NotificationKind::Quarantined)
+        };
+        notifications::notify(db.get_ref(), &notifier, vec![quarantined]).await;
+
+        return Ok(Json(UploadFileResponse {
+            status: 200,
+            message: format!(
+                "File quarantined: malware detected ({}). It will not be delivered to the recipient. FileId: {}",
+                signature, result
+            ),
+        }));
+    }
+
+    if let Some(group) = group {
+        let uploaded = group_deliveries
+            .iter()
+            .map(|(share_id, recipient_id)| FileEvent {
+                file_name: Some(file_name.clone()),
+                owner_id: Some(user_id),
+                share_id: Some(*share_id),
+                recipient_id: Some(*recipient_id),
+                actor_id: Some(user_id),
+                ..file_event(WebhookEvent::Uploaded, result)
+            })
+            .collect();
+        webhooks::dispatch(db.get_ref(), uploaded).await;
+        let received = group_deliveries
+            .iter()
+            .map(|(share_id, recipient_id)| Notification {
+                actor_id: Some(user_id),
+                share_id: Some(*share_id),
+                file_id: Some(result),
+                name: Some(file_name.clone()),
+                ..notification(*recipient_id, NotificationKind::Received)
+            })
+            .collect();
+        notifications::notify(db.get_ref(), &notifier, received).await;
+
+        return Ok(Json(UploadFileResponse {
+            status: 200,
+            message: format!(
+                "File shared with {} members of {}. FileId: {}",
+                group_deliveries.len(),
+                group.name,
+                result
+            ),
+        }));
+    }
+
+    let Some(recipient_user) = recipient_user else {
+        let uploaded = FileEvent {
+            file_name: Some(file_name.clone()),
+            owner_id: Some(user_id),
+            actor_id: Some(user_id),
+            ..file_event(WebhookEvent::Uploaded, result)
+        };
+        webhooks::dispatch(db.get_ref(), vec![uploaded]).await;
+        if let Some(invitation) = &invitation {
+            send_invitation(&notifier, invitation, &user.email);
+        }
+
+        return Ok(Json(UploadFileResponse {
+            status: 200,
+            message: format!(
+                "File uploaded. {} has no verified account yet and was invited to register for it. FileId: {}",
+                form_data.recipient_email, result
+            ),
+        }));
+    };
+
+    let share_id = db
+        .get_file_share_links(result)
+        .await
+        .ok()
+        .and_then(|share_links| share_links.first().map(|share_link| share_link._id));
+    let uploaded = FileEvent {
+        file_name: Some(file_name.clone()),
+        owner_id: Some(user_id),
+        share_id,
+        recipient_id: Some(recipient_user._id),
+        actor_id: Some(user_id),
+        ..file_event(WebhookEvent::Uploaded, result)
+    };
+    webhooks::dispatch(db.get_ref(), vec![uploaded]).await;
+    let received = Notification {
+        actor_id: Some(user_id),
+        share_id,
+        file_id: Some(result),
+        name: Some(file_name),
+        ..notification(recipient_user._id, NotificationKind::Received)
+    };
+    notifications::notify(db.get_ref(), &notifier, vec![received]).await;
+
     Ok(Json(UploadFileResponse {
         status: 200,
-        message: format!(
-            "File uUploaded successully. FileId: {}",
-            result.inserted_id.to_string()
-        ),
+        message: format!("File uUploaded successully. FileId: {}", result.to_string()),
     }))
 }
 
@@ -218,8 +576,9 @@ pub async fn upload_file(
 pub async fn retrieve_file(
     req: HttpRequest,
     body: Json<RetrieveFileDto>,
-    db: Data<Database>,
-) -> Result<Json<RetrieveFileResponse>, Error> {
+    db: Data<dyn Database>,
+    notifier: Data<Notifier>,
+) -> Result<HttpResponse, Error> {
     let _ = body.validate().map_err(|e: validator::ValidationErrors| {
         actix_web::error::ErrorUnauthorized(format!("User ID not found: {}", e.to_string()))
     });
@@ -245,11 +604,7 @@ pub async fn retrieve_file(
             )));
         }
     };
-    let shared_result = db
-        .get_shared(share_id.clone(), user_id)
-        .await
-        .ok()
-        .expect("Failed to fetch shared file doc");
+    let shared_result = db.get_shared(share_id, user_id).await?;
 
     let matched_password =
         password::compare(&body.password, &shared_result.password).map_err(|e| {
@@ -260,6 +615,16 @@ pub async fn retrieve_file(
         })?;
 
     if !matched_password {
+        let event = AuditEvent {
+            actor_id: Some(user_id),
+            file_id: Some(shared_result.file_id),
+            share_id: Some(share_id),
+            ..request_event(AuditAction::SharePasswordFailed, &req)
+        };
+        audit::record(db.get_ref(), event).await;
+        if let Err(e) = db.record_share_password_failure(share_id).await {
+            eprintln!("Failed to record share access: {}", e);
+        }
         return Err(actix_web::error::ErrorBadRequest(format!(
             "Password don't match"
         )));
@@ -270,32 +635,107 @@ pub async fn retrieve_file(
         .await
         .ok()
         .expect("Error while fetching file");
+    ensure_deliverable(&file_result)?;
 
-    let mut path = PathBuf::from("assets/private_keys");
-    path.push(format!("{}.pem", user_id.clone()));
+    let private_key_pem = load_private_key(user_id)?;
 
-    let private_key = fs::read_to_string(&path).map_err(|e| {
-        actix_web::error::ErrorBadRequest(format!(
-            "Failed to collect private key: {}",
-            e.to_string()
-        ))
-    })?;
+    // Recipients added after upload carry their own copy of the AES key
+    let encrypted_aes_key = shared_result
+        .encrypted_aes_key
+        .unwrap_or(file_result.encrypted_aes_key);
 
-    let private_key_pem = RsaPrivateKey::from_pkcs1_pem(&private_key).map_err(|e| {
-        actix_web::error::ErrorBadRequest(format!(
-            "Failed to decode rsa private key: {}",
-            e.to_string()
-        ))
-    })?;
+    // Every version is encrypted under the same AES key
+    let is_current = body
+        .version
+        .is_none_or(|version| version == file_result.version);
+    let (encrypted_file, iv, compression, chunk_ids, expected_sha256, content_type) = if is_current
+    {
+        (
+            file_result.encrypted_file,
+            file_result.iv,
+            file_result.compression,
+            file_result.chunk_ids,
+            file_result.sha256,
+            file_result.content_type,
+        )
+    } else {
+        let file_version = db
+            .get_file_version(file_result._id, body.version.unwrap_or_default())
+            .await?;
+        (
+            file_version.encrypted_file,
+            file_version.iv,
+            file_version.compression,
+            file_version.chunk_ids,
+            file_version.sha256,
+            file_version.content_type,
+        )
+    };
 
+    let chunks = db.get_chunks(&chunk_ids).await?;
     let decrypt_file = decrypt_file(
-        file_result.encrypted_aes_key,
-        file_result.encrypted_file,
-        file_result.iv,
+        encrypted_aes_key,
+        encrypted_file,
+        iv,
+        compression,
+        chunks,
         &private_key_pem,
     )
     .await?;
 
+    let sha256 = verify_checksum(&decrypt_file, expected_sha256.as_deref())?;
+
+    let mut response = HttpResponse::Ok();
+    response.insert_header(("X-Content-SHA256", sha256.clone()));
+
+    // Signatures cover the current version only. A tampered file fails here,
+    // before it counts as opened or downloaded
+    if let (Some(signature), true) = (&shared_result.signature, is_current) {
+        let verifying_key = load_verifying_key(file_result.user_id)?;
+        verify_signature(
+            &verifying_key,
+            signature,
+            &sha256,
+            &file_result.file_name,
+            user_id,
+        )?;
+        response.insert_header(("X-Content-Signature", STANDARD.encode(signature)));
+        response.insert_header((
+            "X-Signature-Public-Key",
+            STANDARD.encode(verifying_key.as_bytes()),
+        ));
+    }
+
+    let event = AuditEvent {
+        actor_id: Some(user_id),
+        subject_user_id: Some(file_result.user_id),
+        file_id: Some(file_result._id),
+        share_id: Some(share_id),
+        detail: body.version.map(|version| format!("Version {}", version)),
+        ..request_event(AuditAction::Download, &req)
+    };
+    audit::record(db.get_ref(), event).await;
+    if let Err(e) = db.record_share_open(share_id).await {
+        eprintln!("Failed to record share access: {}", e);
+    }
+    let downloaded = Notification {
+        actor_id: Some(user_id),
+        share_id: Some(share_id),
+        file_id: Some(file_result._id),
+        name: Some(file_result.file_name.clone()),
+        ..notification(file_result.user_id, NotificationKind::Downloaded)
+    };
+    notifications::notify(db.get_ref(), &notifier, vec![downloaded]).await;
+    let retrieved = FileEvent {
+        file_name: Some(file_result.file_name.clone()),
+        owner_id: Some(file_result.user_id),
+        share_id: Some(share_id),
+        recipient_id: Some(user_id),
+        actor_id: Some(user_id),
+        ..file_event(WebhookEvent::Retrieved, file_result._id)
+    };
+    webhooks::dispatch(db.get_ref(), vec![retrieved]).await;
+
     // let response = HttpResponse::Ok()
     //     .insert_header((
     //         header::CONTENT_DISPOSITION,
@@ -304,16 +744,18 @@ pub async fn retrieve_file(
     //     .insert_header((header::CONTENT_TYPE, "application/octet-stream")) // Set Content-Type header for binary data
     //     .body(decrypt_file); // Add the file data as the response body
 
-    let response = RetrieveFileResponse { file: decrypt_file };
-    Ok(Json(response))
+    Ok(response.json(RetrieveFileResponse {
+        file: decrypt_file,
+        content_type,
+    }))
 }
 
 #[get("/get-my-files")]
 pub async fn get_user_files(
     req: HttpRequest,
-    db: Data<Database>,
+    db: Data<dyn Database>,
     query: Query<QueryParams>,
-) -> Result<Json<Vec<FilteredFile>>, Error> {
+) -> Result<Json<FileListResponse>, Error> {
     // Extract user_id from request extensions
     let user_id = req.extensions().get::<ObjectId>().cloned();
     let query = query.into_inner();
@@ -325,42 +767,24 @@ pub async fn get_user_files(
         }
     };
 
-    let files = db
-        .get_sent_files(
-            user_id.to_string(),
-            query.skip.unwrap_or(1).try_into().unwrap(),
-            query.limit.unwrap_or(10),
-        )
-        .await
-        .ok()
-        .expect("Failed to fetch files");
+    let options = query.into_options()?;
 
-    let mut res_files: Vec<FilteredFile> = Vec::new();
-    for (file, share_id) in files {
-        let user = match db
-            .get_recipients_email_by_file_id(file._id.clone().to_string())
-            .await
-        {
-            Ok(user) => user,
-            Err(e) => {
-                return Err(actix_web::error::ErrorUnauthorized(format!(
-                    "User not found: {}",
-                    e.to_string()
-                )));
-            }
-        };
-        res_files.push(FilteredFile::filter_file(&file, user.email, Some(share_id)));
-    }
+    let page = db
+        .get_sent_files(user_id.to_string(), &options)
+        .await
+        .map_err(|e| {
+            actix_web::error::ErrorServiceUnavailable(format!("Failed to fetch files: {}", e))
+        })?;
 
-    Ok(Json(res_files))
+    Ok(Json(FileListResponse::new(page, &options)))
 }
 
 #[get("/get-recieved-files")]
 pub async fn get_recieve_files(
     req: HttpRequest,
-    db: Data<Database>,
+    db: Data<dyn Database>,
     query: Query<QueryParams>,
-) -> Result<Json<Vec<FilteredFile>>, Error> {
+) -> Result<Json<FileListResponse>, Error> {
     // Extract user_id from request extensions
     let user_id = req.extensions().get::<ObjectId>().cloned();
     let query = query.into_inner();
@@ -373,40 +797,22 @@ pub async fn get_recieve_files(
         }
     };
 
-    let files = db
-        .get_recieve_files(
-            user_id.to_string(),
-            query.skip.unwrap_or(1).try_into().unwrap(),
-            query.limit.unwrap_or(10),
-        )
-        .await
-        .ok()
-        .expect("Failed to fetch files");
+    let options = query.into_options()?;
 
-    let mut res_files: Vec<FilteredFile> = Vec::new();
-    for (file, share_id) in files {
-        let user = match db
-            .get_user_by_id(mongodb::bson::Bson::ObjectId(file.user_id.clone()))
-            .await
-        {
-            Ok(user) => user,
-            Err(e) => {
-                return Err(actix_web::error::ErrorUnauthorized(format!(
-                    "User not found: {}",
-                    e.to_string()
-                )));
-            }
-        };
-        res_files.push(FilteredFile::filter_file(&file, user.email, Some(share_id)));
-    }
+    let page = db
+        .get_recieve_files(user_id.to_string(), &options)
+        .await
+        .map_err(|e| {
+            actix_web::error::ErrorServiceUnavailable(format!("Failed to fetch files: {}", e))
+        })?;
 
-    Ok(Json(res_files))
+    Ok(Json(FileListResponse::new(page, &options)))
 }
 
 #[delete("/delete-file")]
 pub async fn delete_file(
     req: HttpRequest,
-    db: Data<Database>,
+    db: Data<dyn Database>,
     query: Query<DeleteFileQuery>,
 ) -> Result<Json<()>, Error> {
     // Extract user_id from request extensions
@@ -437,7 +843,815 @@ pub async fn delete_file(
         )));
     }
 
+    // Read before the share goes, so the recipient can still be told
+    let share_id = ObjectId::parse_str(&query.share_id).ok();
+    let recipient_id = match share_id {
+        Some(share_id) => db
+            .get_share_link(share_id)
+            .await
+            .ok()
+            .map(|share_link| share_link.recipient_user_id),
+        None => None,
+    };
+
     db.delete_file_by_share_id(query.share_id.clone()).await?;
 
+    let event = AuditEvent {
+        actor_id: Some(user_id),
+        file_id: Some(res._id),
+        share_id,
+        ..request_event(AuditAction::Delete, &req)
+    };
+    audit::record(db.get_ref(), event).await;
+    let deleted = FileEvent {
+        file_name: Some(res.file_name.clone()),
+        owner_id: Some(user_id),
+        share_id,
+        recipient_id,
+        actor_id: Some(user_id),
+        ..file_event(WebhookEvent::Deleted, res._id)
+    };
+    webhooks::dispatch(db.get_ref(), vec![deleted]).await;
+
     Ok(Json(()))
 }
+
+// Resolves a live share the requesting user sent, along with its file
+async fn get_owned_share(
+    req: &HttpRequest,
+    db: &Data<dyn Database>,
+    share_id: &str,
+) -> Result<(ShareLink, File), Error> {
+    let (share_link, file) = find_owned_share(req, db, share_id).await?;
+
+    if share_link.revoked_at.is_some() {
+        return Err(actix_web::error::ErrorConflict("Share is revoked"));
+    }
+
+    Ok((share_link, file))
+}
+
+// Resolves a share the requesting user sent, revoked or not
+async fn find_owned_share(
+    req: &HttpRequest,
+    db: &Data<dyn Database>,
+    share_id: &str,
+) -> Result<(ShareLink, File), Error> {
+    // Extract user_id from request extensions
+    let user_id = match req.extensions().get::<ObjectId>().cloned() {
+        Some(id) => id,
+        None => {
+            return Err(actix_web::error::ErrorUnauthorized("User ID not found"));
+        }
+    };
+
+    let file = db.get_share_link_doc(share_id.to_string()).await?;
+
+    if file.user_id != user_id {
+        return Err(actix_web::error::ErrorForbidden(
+            "You're not authorized to manage this share",
+        ));
+    }
+
+    let share_id = ObjectId::parse_str(share_id).map_err(|e| {
+        actix_web::error::ErrorBadRequest(format!("Failed to convert to objectid: {}", e))
+    })?;
+    let share_link = db.get_share_link(share_id).await?;
+
+    Ok((share_link, file))
+}
+
+// Quarantined files stay with their sender and are never handed to anyone else
+fn ensure_deliverable(file: &File) -> Result<(), Error> {
+    if file.scan_status == ScanStatus::Quarantined {
+        return Err(actix_web::error::ErrorForbidden(
+            "File is quarantined after failing a malware scan",
+        ));
+    }
+    Ok(())
+}
+
+#[post("/shares/{id}/revoke")]
+pub async fn revoke_share(
+    req: HttpRequest,
+    db: Data<dyn Database>,
+    notifier: Data<Notifier>,
+    path: Path<String>,
+) -> Result<Json<ManageShareResponse>, Error> {
+    let (share_link, file) = find_owned_share(&req, &db, &path).await?;
+
+    if share_link.revoked_at.is_some() {
+        return Err(actix_web::error::ErrorConflict("Share is already revoked"));
+    }
+
+    let revoked_shares = db.revoke_share(share_link._id).await?;
+    announce_revoked(&db, &notifier, &file, file.user_id, &revoked_shares).await;
+
+    Ok(Json(ManageShareResponse {
+        status: 200,
+        message: "Share revoked successfully".to_string(),
+        share_id: None,
+    }))
+}
+
+// Recipients of re-shares lose access too, so each of them is told
+async fn announce_revoked(
+    db: &Data<dyn Database>,
+    notifier: &Notifier,
+    file: &File,
+    actor_id: ObjectId,
+    revoked_shares: &[RevokedShare],
+) {
+    let revoked = revoked_shares
+        .iter()
+        .map(|revoked_share| Notification {
+            actor_id: Some(actor_id),
+            share_id: Some(revoked_share.share_id),
+            file_id: Some(file._id),
+            name: Some(file.file_name.clone()),
+            ..notification(revoked_share.recipient_user_id, NotificationKind::Revoked)
+        })
+        .collect();
+    notifications::notify(db.get_ref(), notifier, revoked).await;
+
+    let events = revoked_shares
+        .iter()
+        .map(|revoked_share| FileEvent {
+            file_name: Some(file.file_name.clone()),
+            owner_id: Some(file.user_id),
+            share_id: Some(revoked_share.share_id),
+            recipient_id: Some(revoked_share.recipient_user_id),
+            actor_id: Some(actor_id),
+            ..file_event(WebhookEvent::Revoked, file._id)
+        })
+        .collect();
+    webhooks::dispatch(db.get_ref(), events).await;
+}
+
+#[patch("/shares/{id}/expiry")]
+pub async fn update_share_expiry(
+    req: HttpRequest,
+    db: Data<dyn Database>,
+    path: Path<String>,
+    body: Json<UpdateShareExpiryDto>,
+) -> Result<Json<ManageShareResponse>, Error> {
+    body.validate().map_err(|e| {
+        actix_web::error::ErrorBadRequest(format!("Failed to validate the form: {}", e))
+    })?;
+
+    let (share_link, file) = get_owned_share(&req, &db, &path).await?;
+
+    let expires_at = DateTime::parse_rfc3339_str(&body.expiration_date).map_err(|e| {
+        actix_web::error::ErrorBadRequest(format!("Failed to parse date time: {}", e))
+    })?;
+    let organization = load_organization(db.get_ref(), file.organization_id).await?;
+    check_expiry(organization.as_ref(), expires_at)?;
+
+    db.update_share_expiry(share_link._id, expires_at).await?;
+
+    Ok(Json(ManageShareResponse {
+        status: 200,
+        message: "Share expiry updated successfully".to_string(),
+        share_id: None,
+    }))
+}
+
+#[post("/shares/{id}/password")]
+pub async fn rotate_share_password(
+    req: HttpRequest,
+    db: Data<dyn Database>,
+    path: Path<String>,
+    body: Json<RotateSharePasswordDto>,
+) -> Result<Json<ManageShareResponse>, Error> {
+    body.validate().map_err(|e| {
+        actix_web::error::ErrorBadRequest(format!("Failed to validate the form: {}", e))
+    })?;
+
+    let (share_link, _) = get_owned_share(&req, &db, &path).await?;
+
+    let hash_password = password::hash(&body.password).map_err(|e| {
+        actix_web::error::ErrorBadRequest(format!("Failed to hash password: {}", e))
+    })?;
+
+    db.update_share_password(share_link._id, hash_password)
+        .await?;
+
+    Ok(Json(ManageShareResponse {
+        status: 200,
+        message: "Share password updated successfully".to_string(),
+        share_id: None,
+    }))
+}
+
+#[patch("/shares/{id}/permissions")]
+pub async fn update_share_permissions(
+    req: HttpRequest,
+    db: Data<dyn Database>,
+    path: Path<String>,
+    body: Json<SharePermissionsDto>,
+) -> Result<Json<ManageShareResponse>, Error> {
+    let (share_link, _) = get_owned_share(&req, &db, &path).await?;
+
+    db.update_share_permissions(share_link._id, body.can_reshare)
+        .await?;
+
+    Ok(Json(ManageShareResponse {
+        status: 200,
+        message: "Share permissions updated successfully".to_string(),
+        share_id: None,
+    }))
+}
+
+#[post("/shares/{id}/recipients")]
+pub async fn add_share_recipient(
+    req: HttpRequest,
+    db: Data<dyn Database>,
+    notifier: Data<Notifier>,
+    path: Path<String>,
+    body: Json<ShareRecipientDto>,
+) -> Result<Json<ManageShareResponse>, Error> {
+    body.validate().map_err(|e| {
+        actix_web::error::ErrorBadRequest(format!("Failed to validate the form: {}", e))
+    })?;
+
+    let (share_link, file) = get_owned_share(&req, &db, &path).await?;
+    ensure_deliverable(&file)?;
+
+    let recipient_user = db
+        .get_user(body.recipient_email.clone())
+        .await
+        .map_err(|e| {
+            actix_web::error::ErrorBadRequest(format!("Failed to get reciepient: {}", e))
+        })?;
+    if recipient_user._id == file.user_id {
+        return Err(actix_web::error::ErrorBadRequest(
+            "You cannot add yourself as a recipient",
+        ));
+    }
+    ensure_verified(&recipient_user)?;
+    ensure_same_organization(file.organization_id, &recipient_user)?;
+    let recipient_public_key = load_public_key(&recipient_user)?;
+
+    // Only recipients hold the AES key, so unwrap it with this share's recipient key pair
+    let holder_private_key = load_private_key(share_link.recipient_user_id)?;
+    let holder_aes_key = share_link
+        .encrypted_aes_key
+        .unwrap_or(file.encrypted_aes_key);
+    let encrypted_aes_key =
+        rewrap_aes_key(&holder_aes_key, &holder_private_key, &recipient_public_key).await?;
+
+    // Recipients added to a signed share get a signature of their own
+    let signature = match (&share_link.signature, &file.sha256) {
+        (Some(_), Some(sha256)) => {
+            let signing_key = load_signing_key(file.user_id)?;
+            Some(sign_upload(
+                &signing_key,
+                sha256,
+                &file.file_name,
+                recipient_user._id,
+            ))
+        }
+        _ => None,
+    };
+
+    // The new recipient inherits the share's password and expiry
+    let share_id = db
+        .create_share_link(ShareLink {
+            _id: ObjectId::new(),
+            recipient_user_id: recipient_user._id,
+            file_id: file._id,
+            password: share_link.password,
+            expires_at: share_link.expires_at,
+            created_at: DateTime::now(),
+            encrypted_aes_key: Some(encrypted_aes_key),
+            revoked_at: None,
+            declined_at: None,
+            archived_at: None,
+            can_reshare: false,
+            parent_share_id: None,
+            signature,
+            first_opened_at: None,
+            last_opened_at: None,
+            open_count: 0,
+            failed_password_attempts: 0,
+            organization_id: file.organization_id,
+        })
+        .await?;
+
+    let event = AuditEvent {
+        actor_id: Some(file.user_id),
+        subject_user_id: Some(recipient_user._id),
+        file_id: Some(file._id),
+        share_id: Some(share_id),
+        ..request_event(AuditAction::ShareGranted, &req)
+    };
+    audit::record(db.get_ref(), event).await;
+    let uploaded = FileEvent {
+        file_name: Some(file.file_name.clone()),
+        owner_id: Some(file.user_id),
+        share_id: Some(share_id),
+        recipient_id: Some(recipient_user._id),
+        actor_id: Some(file.user_id),
+        ..file_event(WebhookEvent::Uploaded, file._id)
+    };
+    webhooks::dispatch(db.get_ref(), vec![uploaded]).await;
+    let received = Notification {
+        actor_id: Some(file.user_id),
+        share_id: Some(share_id),
+        file_id: Some(file._id),
+        name: Some(file.file_name),
+        ..notification(recipient_user._id, NotificationKind::Received)
+    };
+    notifications::notify(db.get_ref(), &notifier, vec![received]).await;
+
+    Ok(Json(ManageShareResponse {
+        status: 200,
+        message: "Recipient added successfully".to_string(),
+        share_id: Some(share_id.to_hex()),
+    }))
+}
+
+#[delete("/shares/{id}/recipients")]
+pub async fn remove_share_recipient(
+    req: HttpRequest,
+    db: Data<dyn Database>,
+    notifier: Data<Notifier>,
+    path: Path<String>,
+    body: Json<ShareRecipientDto>,
+) -> Result<Json<ManageShareResponse>, Error> {
+    body.validate().map_err(|e| {
+        actix_web::error::ErrorBadRequest(format!("Failed to validate the form: {}", e))
+    })?;
+
+    let (_, file) = get_owned_share(&req, &db, &path).await?;
+
+    let recipient_user = db
+        .get_user(body.recipient_email.clone())
+        .await
+        .map_err(|e| {
+            actix_web::error::ErrorBadRequest(format!("Failed to get reciepient: {}", e))
+        })?;
+
+    db.remove_share_recipient(file._id, recipient_user._id)
+        .await?;
+
+    let revoked = Notification {
+        actor_id: Some(file.user_id),
+        file_id: Some(file._id),
+        name: Some(file.file_name),
+        ..notification(recipient_user._id, NotificationKind::Revoked)
+    };
+    notifications::notify(db.get_ref(), &notifier, vec![revoked]).await;
+
+    Ok(Json(ManageShareResponse {
+        status: 200,
+        message: "Recipient removed successfully".to_string(),
+        share_id: None,
+    }))
+}
+
+// Resolves a live share addressed to the requesting user
+async fn get_received_share(
+    req: &HttpRequest,
+    db: &Data<dyn Database>,
+    share_id: &str,
+) -> Result<(ShareLink, ObjectId), Error> {
+    // Extract user_id from request extensions
+    let user_id = match req.extensions().get::<ObjectId>().cloned() {
+        Some(id) => id,
+        None => {
+            return Err(actix_web::error::ErrorUnauthorized("User ID not found"));
+        }
+    };
+
+    let share_id = ObjectId::parse_str(share_id).map_err(|e| {
+        actix_web::error::ErrorBadRequest(format!("Failed to convert to objectid: {}", e))
+    })?;
+    let share_link = db.get_shared(share_id, user_id).await?;
+
+    Ok((share_link, user_id))
+}
+
+#[post("/received/{id}/decline")]
+pub async fn decline_received_share(
+    req: HttpRequest,
+    db: Data<dyn Database>,
+    notifier: Data<Notifier>,
+    path: Path<String>,
+) -> Result<Json<ManageShareResponse>, Error> {
+    let (share_link, user_id) = get_received_share(&req, &db, &path).await?;
+
+    let revoked_shares = db.decline_share(share_link._id, user_id).await?;
+
+    // The sender also sees declined_at on their listing
+    let file = db.get_file(Bson::ObjectId(share_link.file_id)).await?;
+    announce_revoked(&db, &notifier, &file, user_id, &revoked_shares).await;
+    let declined = Notification {
+        actor_id: Some(user_id),
+        share_id: Some(share_link._id),
+        file_id: Some(file._id),
+        name: Some(file.file_name.clone()),
+        ..notification(file.user_id, NotificationKind::Declined)
+    };
+    notifications::notify(db.get_ref(), &notifier, vec![declined]).await;
+
+    let event = FileEvent {
+        file_name: Some(file.file_name),
+        owner_id: Some(file.user_id),
+        share_id: Some(share_link._id),
+        recipient_id: Some(user_id),
+        actor_id: Some(user_id),
+        ..file_event(WebhookEvent::Declined, file._id)
+    };
+    webhooks::dispatch(db.get_ref(), vec![event]).await;
+
+    Ok(Json(ManageShareResponse {
+        status: 200,
+        message: "Share declined successfully".to_string(),
+        share_id: None,
+    }))
+}
+
+#[post("/received/{id}/archive")]
+pub async fn archive_received_share(
+    req: HttpRequest,
+    db: Data<dyn Database>,
+    path: Path<String>,
+) -> Result<Json<ManageShareResponse>, Error> {
+    let (share_link, user_id) = get_received_share(&req, &db, &path).await?;
+
+    db.set_share_archived(share_link._id, user_id, true).await?;
+
+    Ok(Json(ManageShareResponse {
+        status: 200,
+        message: "Share archived successfully".to_string(),
+        share_id: None,
+    }))
+}
+
+#[delete("/received/{id}/archive")]
+pub async fn unarchive_received_share(
+    req: HttpRequest,
+    db: Data<dyn Database>,
+    path: Path<String>,
+) -> Result<Json<ManageShareResponse>, Error> {
+    let (share_link, user_id) = get_received_share(&req, &db, &path).await?;
+
+    db.set_share_archived(share_link._id, user_id, false)
+        .await?;
+
+    Ok(Json(ManageShareResponse {
+        status: 200,
+        message: "Share restored to the inbox".to_string(),
+        share_id: None,
+    }))
+}
+
+#[delete("/received/{id}")]
+pub async fn remove_received_share(
+    req: HttpRequest,
+    db: Data<dyn Database>,
+    notifier: Data<Notifier>,
+    path: Path<String>,
+) -> Result<Json<ManageShareResponse>, Error> {
+    let (share_link, user_id) = get_received_share(&req, &db, &path).await?;
+
+    let revoked_shares = db.remove_received_share(share_link._id, user_id).await?;
+    if !revoked_shares.is_empty() {
+        let file = db.get_file(Bson::ObjectId(share_link.file_id)).await?;
+        announce_revoked(&db, &notifier, &file, user_id, &revoked_shares).await;
+    }
+
+    let event = AuditEvent {
+        actor_id: Some(user_id),
+        file_id: Some(share_link.file_id),
+        share_id: Some(share_link._id),
+        detail: Some("Removed by recipient".to_string()),
+        ..request_event(AuditAction::Delete, &req)
+    };
+    audit::record(db.get_ref(), event).await;
+
+    Ok(Json(ManageShareResponse {
+        status: 200,
+        message: "Share removed successfully".to_string(),
+        share_id: None,
+    }))
+}
+
+#[post("/received/{id}/reshare")]
+pub async fn reshare_received_file(
+    req: HttpRequest,
+    db: Data<dyn Database>,
+    notifier: Data<Notifier>,
+    path: Path<String>,
+    body: Json<ReshareDto>,
+) -> Result<Json<ManageShareResponse>, Error> {
+    body.validate().map_err(|e| {
+        actix_web::error::ErrorBadRequest(format!("Failed to validate the form: {}", e))
+    })?;
+
+    let (share_link, user_id) = get_received_share(&req, &db, &path).await?;
+
+    if !share_link.can_reshare {
+        return Err(actix_web::error::ErrorForbidden(
+            "You're not allowed to re-share this file",
+        ));
+    }
+
+    let expires_at = DateTime::parse_rfc3339_str(&body.expiration_date).map_err(|e| {
+        actix_web::error::ErrorBadRequest(format!("Failed to parse date time: {}", e))
+    })?;
+    if expires_at > share_link.expires_at {
+        return Err(actix_web::error::ErrorBadRequest(
+            "Expiration date cannot be later than your own share's",
+        ));
+    }
+
+    let recipient_user = db
+        .get_user(body.recipient_email.clone())
+        .await
+        .map_err(|e| {
+            actix_web::error::ErrorBadRequest(format!("Failed to get reciepient: {}", e))
+        })?;
+
+    let file = db.get_file(Bson::ObjectId(share_link.file_id)).await?;
+    ensure_deliverable(&file)?;
+    ensure_verified(&recipient_user)?;
+    ensure_same_organization(file.organization_id, &recipient_user)?;
+    let organization = load_organization(db.get_ref(), file.organization_id).await?;
+    check_expiry(organization.as_ref(), expires_at)?;
+    if recipient_user._id == user_id || recipient_user._id == file.user_id {
+        return Err(actix_web::error::ErrorBadRequest(
+            "Recipient already has access to this file",
+        ));
+    }
+
+    // The AES key is re-wrapped under the new recipient's key; the file stays encrypted
+    let recipient_public_key = load_public_key(&recipient_user)?;
+    let private_key = load_private_key(user_id)?;
+    let holder_aes_key = share_link
+        .encrypted_aes_key
+        .unwrap_or(file.encrypted_aes_key);
+    let encrypted_aes_key =
+        rewrap_aes_key(&holder_aes_key, &private_key, &recipient_public_key).await?;
+
+    let hash_password = password::hash(&body.password).map_err(|e| {
+        actix_web::error::ErrorBadRequest(format!("Failed to hash password: {}", e))
+    })?;
+
+    let share_id = db
+        .create_share_link(ShareLink {
+            _id: ObjectId::new(),
+            recipient_user_id: recipient_user._id,
+            file_id: file._id,
+            password: hash_password,
+            expires_at,
+            created_at: DateTime::now(),
+            encrypted_aes_key: Some(encrypted_aes_key),
+            revoked_at: None,
+            declined_at: None,
+            archived_at: None,
+            can_reshare: false,
+            parent_share_id: Some(share_link._id),
+            // Only the owner signs; a re-sharer did not send the content
+            signature: None,
+            first_opened_at: None,
+            last_opened_at: None,
+            open_count: 0,
+            failed_password_attempts: 0,
+            organization_id: file.organization_id,
+        })
+        .await?;
+
+    let received = Notification {
+        actor_id: Some(user_id),
+        share_id: Some(share_id),
+        file_id: Some(file._id),
+        name: Some(file.file_name),
+        ..notification(recipient_user._id, NotificationKind::Received)
+    };
+    notifications::notify(db.get_ref(), &notifier, vec![received]).await;
+
+    Ok(Json(ManageShareResponse {
+        status: 200,
+        message: "File re-shared successfully".to_string(),
+        share_id: Some(share_id.to_hex()),
+    }))
+}
+
+#[post("/shares/{id}/versions")]
+pub async fn upload_file_version(
+    mut payload: Multipart,
+    req: HttpRequest,
+    db: Data<dyn Database>,
+    config: Data<Config>,
+    scanner: Data<dyn Scanner>,
+    path: Path<String>,
+) -> Result<Json<UploadFileVersionResponse>, Error> {
+    let (share_link, file) = get_owned_share(&req, &db, &path).await?;
+    let owner = db.get_user_by_id(Bson::ObjectId(file.user_id)).await?;
+    let organization = load_organization(db.get_ref(), owner.organization_id).await?;
+
+    let mut file_data = Vec::new();
+    let mut file_name = String::new();
+    let mut skip_compression = false;
+    // The size limit covers every file part of the request, not just the one kept
+    let mut upload_size: i64 = 0;
+
+    while let Some(Ok(mut field)) = payload.next().await {
+        if field.name() == Some("skip_compression") {
+            if let Some(bytes) = field.next().await {
+                skip_compression = bytes?.as_ref() == b"true";
+            }
+            continue;
+        }
+        if field.name() != Some("fileUpload") {
+            continue;
+        }
+
+        file_name = field
+            .content_disposition()
+            .and_then(|disposition| disposition.get_filename())
+            .unwrap_or("unknown_file")
+            .to_string();
+
+        file_data = read_field_limited(&mut field, config.max_upload_size - upload_size).await?;
+        upload_size += file_data.len() as i64;
+    }
+
+    if file_data.is_empty() {
+        return Err(actix_web::error::ErrorBadRequest("File is required"));
+    }
+    let file_size = file_data.len() as i64;
+    let content_type = sniff_content_type(&file_data);
+    config.upload_policy.check(&file_name, &content_type)?;
+
+    // An infected revision is refused outright so the current one stays deliverable
+    let (scan_status, detected) = scan_upload(scanner.as_ref(), &file_name, &file_data).await?;
+    if let Some(signature) = detected {
+        return Err(actix_web::error::ErrorUnprocessableEntity(format!(
+            "File failed malware scan: {} found",
+            signature
+        )));
+    }
+    let sha256 = sha256_hex(&file_data);
+
+    // Signed shares are signed again over the new content
+    let signed_shares: Vec<ShareLink> = db
+        .get_file_share_links(file._id)
+        .await?
+        .into_iter()
+        .filter(|share| share.signature.is_some())
+        .collect();
+    let mut signatures = Vec::with_capacity(signed_shares.len());
+    if !signed_shares.is_empty() {
+        let signing_key = load_signing_key(file.user_id)?;
+        for share in &signed_shares {
+            let signature = sign_upload(&signing_key, &sha256, &file_name, share.recipient_user_id);
+            signatures.push((share._id, signature));
+        }
+    }
+
+    // Only recipients hold the AES key, so unwrap it with this share's recipient key pair
+    let holder_private_key = load_private_key(share_link.recipient_user_id)?;
+    let holder_aes_key = share_link
+        .encrypted_aes_key
+        .unwrap_or(file.encrypted_aes_key);
+    let (file_data, compression) = compress_upload(file_data, skip_compression)?;
+    let ChunkedContent {
+        data: file_data,
+        chunk_ids,
+        chunks,
+    } = dedup_upload(config.dedup_key.as_deref(), file_data)?;
+    let (encrypted_file, iv) =
+        encrypt_revision(file_data, &holder_aes_key, &holder_private_key).await?;
+
+    let version = db
+        .add_file_version(
+            file._id,
+            file_name,
+            file_size,
+            encrypted_file,
+            iv,
+            content_type,
+            sha256,
+            signatures,
+            scan_status,
+            compression,
+            chunk_ids,
+            chunks,
+            config.file_version_history,
+            storage_quota(&config, organization.as_ref()),
+        )
+        .await?;
+
+    let event = AuditEvent {
+        actor_id: Some(file.user_id),
+        file_id: Some(file._id),
+        share_id: Some(share_link._id),
+        detail: Some(format!("Version {}", version)),
+        ..request_event(AuditAction::Upload, &req)
+    };
+    audit::record(db.get_ref(), event).await;
+
+    Ok(Json(UploadFileVersionResponse {
+        status: 200,
+        message: "File version uploaded successfully".to_string(),
+        version,
+    }))
+}
+
+#[get("/shares/{id}/versions")]
+pub async fn get_file_versions(
+    req: HttpRequest,
+    db: Data<dyn Database>,
+    path: Path<String>,
+) -> Result<Json<FileVersionListResponse>, Error> {
+    // Extract user_id from request extensions
+    let user_id = match req.extensions().get::<ObjectId>().cloned() {
+        Some(id) => id,
+        None => {
+            return Err(actix_web::error::ErrorUnauthorized("User ID not found"));
+        }
+    };
+
+    // The sender and the share's recipient may both see its history
+    let file = db.get_share_link_doc(path.to_string()).await?;
+    if file.user_id != user_id {
+        get_received_share(&req, &db, &path).await?;
+        ensure_deliverable(&file)?;
+    }
+
+    let versions = db.get_file_versions(file._id).await?;
+
+    Ok(Json(FileVersionListResponse::new(&file, versions)))
+}
+
+// Access receipts for a share the requesting user sent
+#[get("/shares/{id}/access")]
+pub async fn get_share_access(
+    req: HttpRequest,
+    db: Data<dyn Database>,
+    path: Path<String>,
+    query: Query<ShareAccessParams>,
+) -> Result<Json<ShareAccessResponse>, Error> {
+    let (share_link, _) = find_owned_share(&req, &db, &path).await?;
+    let query = query.into_inner().into_query(share_link._id)?;
+
+    let events = db.get_audit_events(query.clone()).await?;
+
+    Ok(Json(ShareAccessResponse::new(&share_link, events, &query)))
+}
+
+// Shares the requesting user sent to addresses that have not registered yet
+#[get("/invitations")]
+pub async fn get_sent_invitations(
+    req: HttpRequest,
+    db: Data<dyn Database>,
+) -> Result<Json<InvitationListResponse>, Error> {
+    // Extract user_id from request extensions
+    let user_id = match req.extensions().get::<ObjectId>().cloned() {
+        Some(id) => id,
+        None => {
+            return Err(actix_web::error::ErrorUnauthorized("User ID not found"));
+        }
+    };
+
+    let invitations = db.get_sent_invitations(user_id).await?;
+
+    Ok(Json(InvitationListResponse::new(invitations)))
+}
+
+// The invited file is left without a recipient and removed by the reconcile job
+#[delete("/invitations/{id}")]
+pub async fn cancel_invitation(
+    req: HttpRequest,
+    db: Data<dyn Database>,
+    path: Path<String>,
+) -> Result<Json<ManageShareResponse>, Error> {
+    // Extract user_id from request extensions
+    let user_id = match req.extensions().get::<ObjectId>().cloned() {
+        Some(id) => id,
+        None => {
+            return Err(actix_web::error::ErrorUnauthorized("User ID not found"));
+        }
+    };
+
+    let invitation_id = ObjectId::parse_str(path.as_str()).map_err(|e| {
+        actix_web::error::ErrorBadRequest(format!("Failed to convert to objectid: {}", e))
+    })?;
+    let invitation = db.get_invitation(invitation_id).await?;
+    if invitation.sender_id != user_id {
+        return Err(actix_web::error::ErrorForbidden(
+            "You're not authorized to cancel this invitation",
+        ));
+    }
+
+    db.delete_invitation(invitation._id).await?;
+
+    Ok(Json(ManageShareResponse {
+        status: 200,
+        message: "Invitation cancelled successfully".to_string(),
+        share_id: None,
+    }))
+}
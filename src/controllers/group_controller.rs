@@ -0,0 +1,312 @@
+use actix_web::{
+    delete, get, patch, post,
+    web::{self, Data, Json, Path},
+    Error, HttpMessage, HttpRequest,
+};
+use mongodb::bson::{oid::ObjectId, Bson, DateTime};
+use validator::Validate;
+
+use crate::{
+    dtos::group::groups::{
+        CreateGroupDto, GroupListResponse, GroupMemberDto, GroupResponse, ManageGroupResponse,
+        RenameGroupDto,
+    },
+    models::group_model::Group,
+    services::{
+        db::Database,
+        groups::{self, get_member_group},
+        notifications::Notifier,
+        organizations::ensure_same_organization,
+        verification::ensure_verified,
+    },
+};
+
+// Initialize routes
+pub fn init(cfg: &mut web::ServiceConfig) {
+    cfg.service(create_group)
+        .service(get_groups)
+        .service(get_group)
+        .service(rename_group)
+        .service(delete_group)
+        .service(add_group_member)
+        .service(update_group_member)
+        .service(remove_group_member);
+}
+
+// Resolves a group the requesting user belongs to, along with their id
+async fn get_joined_group(
+    req: &HttpRequest,
+    db: &Data<dyn Database>,
+    group_id: &str,
+) -> Result<(Group, ObjectId), Error> {
+    // Extract user_id from request extensions
+    let user_id = match req.extensions().get::<ObjectId>().cloned() {
+        Some(id) => id,
+        None => {
+            return Err(actix_web::error::ErrorUnauthorized("User ID not found"));
+        }
+    };
+
+    let group = get_member_group(db.get_ref(), group_id, user_id).await?;
+
+    Ok((group, user_id))
+}
+
+#[post("")]
+pub async fn create_group(
+    req: HttpRequest,
+    db: Data<dyn Database>,
+    body: Json<CreateGroupDto>,
+) -> Result<Json<ManageGroupResponse>, Error> {
+    body.validate().map_err(|e| {
+        actix_web::error::ErrorBadRequest(format!("Failed to validate the form: {}", e))
+    })?;
+
+    // Extract user_id from request extensions
+    let user_id = match req.extensions().get::<ObjectId>().cloned() {
+        Some(id) => id,
+        None => {
+            return Err(actix_web::error::ErrorUnauthorized("User ID not found"));
+        }
+    };
+
+    let user = db.get_user_by_id(Bson::ObjectId(user_id)).await?;
+
+    let mut members = vec![user_id];
+    for email in &body.member_emails {
+        let member = db.get_user(email.clone()).await.map_err(|e| {
+            actix_web::error::ErrorBadRequest(format!("Failed to get member {}: {}", email, e))
+        })?;
+        ensure_verified(&member)?;
+        ensure_same_organization(user.organization_id, &member)?;
+        if !members.contains(&member._id) {
+            members.push(member._id);
+        }
+    }
+
+    let group_id = db
+        .create_group(Group {
+            _id: ObjectId::new(),
+            name: body.name.clone(),
+            owner_id: user_id,
+            members,
+            admins: vec![user_id],
+            created_at: DateTime::now(),
+            updated_at: DateTime::now(),
+        })
+        .await?;
+
+    Ok(Json(ManageGroupResponse {
+        status: 200,
+        message: "Group created successfully".to_string(),
+        group_id: Some(group_id.to_hex()),
+    }))
+}
+
+#[get("")]
+pub async fn get_groups(
+    req: HttpRequest,
+    db: Data<dyn Database>,
+) -> Result<Json<GroupListResponse>, Error> {
+    // Extract user_id from request extensions
+    let user_id = match req.extensions().get::<ObjectId>().cloned() {
+        Some(id) => id,
+        None => {
+            return Err(actix_web::error::ErrorUnauthorized("User ID not found"));
+        }
+    };
+
+    let groups = db.get_user_groups(user_id).await?;
+
+    Ok(Json(GroupListResponse::new(groups, user_id)))
+}
+
+#[get("/{id}")]
+pub async fn get_group(
+    req: HttpRequest,
+    db: Data<dyn Database>,
+    path: Path<String>,
+) -> Result<Json<GroupResponse>, Error> {
+    let (group, user_id) = get_joined_group(&req, &db, &path).await?;
+
+    let members = db.get_group_members(group._id).await?;
+
+    Ok(Json(GroupResponse::new(&group, members, user_id)))
+}
+
+#[patch("/{id}")]
+pub async fn rename_group(
+    req: HttpRequest,
+    db: Data<dyn Database>,
+    path: Path<String>,
+    body: Json<RenameGroupDto>,
+) -> Result<Json<ManageGroupResponse>, Error> {
+    body.validate().map_err(|e| {
+        actix_web::error::ErrorBadRequest(format!("Failed to validate the form: {}", e))
+    })?;
+
+    let (group, user_id) = get_joined_group(&req, &db, &path).await?;
+    if !group.is_admin(user_id) {
+        return Err(actix_web::error::ErrorForbidden(
+            "Only group admins can manage this group",
+        ));
+    }
+
+    db.rename_group(group._id, body.name.clone()).await?;
+
+    Ok(Json(ManageGroupResponse {
+        status: 200,
+        message: "Group renamed successfully".to_string(),
+        group_id: None,
+    }))
+}
+
+// Members keep the shares they already received through the group
+#[delete("/{id}")]
+pub async fn delete_group(
+    req: HttpRequest,
+    db: Data<dyn Database>,
+    path: Path<String>,
+) -> Result<Json<ManageGroupResponse>, Error> {
+    let (group, user_id) = get_joined_group(&req, &db, &path).await?;
+    if group.owner_id != user_id {
+        return Err(actix_web::error::ErrorForbidden(
+            "Only the group owner can delete this group",
+        ));
+    }
+
+    db.delete_group(group._id).await?;
+
+    Ok(Json(ManageGroupResponse {
+        status: 200,
+        message: "Group deleted successfully".to_string(),
+        group_id: None,
+    }))
+}
+
+// The new member also gets the live shares sent to the group for later members
+#[post("/{id}/members")]
+pub async fn add_group_member(
+    req: HttpRequest,
+    db: Data<dyn Database>,
+    notifier: Data<Notifier>,
+    path: Path<String>,
+    body: Json<GroupMemberDto>,
+) -> Result<Json<ManageGroupResponse>, Error> {
+    body.validate().map_err(|e| {
+        actix_web::error::ErrorBadRequest(format!("Failed to validate the form: {}", e))
+    })?;
+
+    let (group, user_id) = get_joined_group(&req, &db, &path).await?;
+    if !group.is_admin(user_id) {
+        return Err(actix_web::error::ErrorForbidden(
+            "Only group admins can add members",
+        ));
+    }
+    if body.admin && group.owner_id != user_id {
+        return Err(actix_web::error::ErrorForbidden(
+            "Only the group owner can add admins",
+        ));
+    }
+
+    let member = db
+        .get_user(body.email.clone())
+        .await
+        .map_err(|e| actix_web::error::ErrorBadRequest(format!("Failed to get member: {}", e)))?;
+    ensure_verified(&member)?;
+    let user = db.get_user_by_id(Bson::ObjectId(user_id)).await?;
+    ensure_same_organization(user.organization_id, &member)?;
+
+    db.add_group_member(group._id, member._id, body.admin)
+        .await?;
+
+    groups::grant_group_shares(db.get_ref(), &notifier, &req, user_id, &group, &member).await;
+
+    Ok(Json(ManageGroupResponse {
+        status: 200,
+        message: "Member added successfully".to_string(),
+        group_id: None,
+    }))
+}
+
+// Makes a member an admin or takes it back
+#[patch("/{id}/members")]
+pub async fn update_group_member(
+    req: HttpRequest,
+    db: Data<dyn Database>,
+    path: Path<String>,
+    body: Json<GroupMemberDto>,
+) -> Result<Json<ManageGroupResponse>, Error> {
+    body.validate().map_err(|e| {
+        actix_web::error::ErrorBadRequest(format!("Failed to validate the form: {}", e))
+    })?;
+
+    let (group, user_id) = get_joined_group(&req, &db, &path).await?;
+    if group.owner_id != user_id {
+        return Err(actix_web::error::ErrorForbidden(
+            "Only the group owner can manage admins",
+        ));
+    }
+
+    let member = db
+        .get_user(body.email.clone())
+        .await
+        .map_err(|e| actix_web::error::ErrorBadRequest(format!("Failed to get member: {}", e)))?;
+    if member._id == group.owner_id {
+        return Err(actix_web::error::ErrorBadRequest(
+            "The group owner is always an admin",
+        ));
+    }
+
+    db.set_group_admin(group._id, member._id, body.admin)
+        .await?;
+
+    Ok(Json(ManageGroupResponse {
+        status: 200,
+        message: "Member updated successfully".to_string(),
+        group_id: None,
+    }))
+}
+
+// Admins remove members and the owner removes admins; anyone but the owner
+// can remove themselves. Removed members keep the shares they already received
+#[delete("/{id}/members")]
+pub async fn remove_group_member(
+    req: HttpRequest,
+    db: Data<dyn Database>,
+    path: Path<String>,
+    body: Json<GroupMemberDto>,
+) -> Result<Json<ManageGroupResponse>, Error> {
+    body.validate().map_err(|e| {
+        actix_web::error::ErrorBadRequest(format!("Failed to validate the form: {}", e))
+    })?;
+
+    let (group, user_id) = get_joined_group(&req, &db, &path).await?;
+
+    let member = db
+        .get_user(body.email.clone())
+        .await
+        .map_err(|e| actix_web::error::ErrorBadRequest(format!("Failed to get member: {}", e)))?;
+    if member._id == group.owner_id {
+        return Err(actix_web::error::ErrorBadRequest(
+            "The group owner can't be removed; delete the group instead",
+        ));
+    }
+
+    let allowed = member._id == user_id
+        || group.owner_id == user_id
+        || (group.is_admin(user_id) && !group.is_admin(member._id));
+    if !allowed {
+        return Err(actix_web::error::ErrorForbidden(
+            "You're not authorized to remove this member",
+        ));
+    }
+
+    db.remove_group_member(group._id, member._id).await?;
+
+    Ok(Json(ManageGroupResponse {
+        status: 200,
+        message: "Member removed successfully".to_string(),
+        group_id: None,
+    }))
+}
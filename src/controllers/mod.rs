@@ -1,3 +1,9 @@
+pub mod admin_controller;
 pub mod auth_controller;
+pub mod collection_controller;
 pub mod file_controller;
+pub mod group_controller;
+pub mod notification_controller;
+pub mod organization_controller;
 pub mod user_controller;
+pub mod webhook_controller;
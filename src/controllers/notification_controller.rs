@@ -0,0 +1,149 @@
+use std::time::Duration;
+
+use actix_web::{
+    get,
+    http::header,
+    post,
+    web::{self, Bytes, Data, Json, Path, Query},
+    Error, HttpMessage, HttpRequest, HttpResponse,
+};
+use futures_util::stream;
+use mongodb::bson::oid::ObjectId;
+use tokio::time;
+
+use crate::{
+    dtos::notification::notifications::{
+        FilteredNotification, MarkReadResponse, NotificationQueryParams, NotificationsResponse,
+    },
+    models::notification_model::Notification,
+    services::{db::Database, notifications::Notifier},
+};
+
+// Comment lines keep idle streams from being closed by proxies
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(30);
+
+// Initialize routes
+pub fn init(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_notifications)
+        .service(stream_notifications)
+        .service(mark_all_notifications_read)
+        .service(mark_notification_read);
+}
+
+#[get("")]
+pub async fn get_notifications(
+    req: HttpRequest,
+    db: Data<dyn Database>,
+    query: Query<NotificationQueryParams>,
+) -> Result<Json<NotificationsResponse>, Error> {
+    // Extract user_id from request extensions
+    let user_id = match req.extensions().get::<ObjectId>().cloned() {
+        Some(id) => id,
+        None => {
+            return Err(actix_web::error::ErrorUnauthorized("User ID not found"));
+        }
+    };
+
+    let query = query.into_inner().into_query(user_id)?;
+    let notifications = db.get_notifications(query.clone()).await?;
+    let unread_count = db.count_unread_notifications(user_id).await?;
+
+    Ok(Json(NotificationsResponse::new(
+        notifications,
+        unread_count,
+        &query,
+    )))
+}
+
+// Server-Sent Events stream of the user's notifications as they happen. Only
+// new notifications are pushed; the inbox holds anything missed while offline
+#[get("/stream")]
+pub async fn stream_notifications(
+    req: HttpRequest,
+    notifier: Data<Notifier>,
+) -> Result<HttpResponse, Error> {
+    // Extract user_id from request extensions
+    let user_id = match req.extensions().get::<ObjectId>().cloned() {
+        Some(id) => id,
+        None => {
+            return Err(actix_web::error::ErrorUnauthorized("User ID not found"));
+        }
+    };
+
+    let receiver = notifier.subscribe(user_id);
+    let keep_alive = time::interval(KEEP_ALIVE_INTERVAL);
+
+    // Runs until the client disconnects, which drops the receiver for the notifier to prune
+    let events = stream::unfold(
+        (receiver, keep_alive),
+        |(mut receiver, mut keep_alive)| async move {
+            let frame = tokio::select! {
+                notification = receiver.recv() => event_frame(&notification?),
+                _ = keep_alive.tick() => Bytes::from_static(b": keep-alive\n\n"),
+            };
+            Some((Ok::<_, Error>(frame), (receiver, keep_alive)))
+        },
+    );
+
+    Ok(HttpResponse::Ok()
+        .insert_header((header::CONTENT_TYPE, "text/event-stream"))
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        .streaming(events))
+}
+
+fn event_frame(notification: &Notification) -> Bytes {
+    let data = serde_json::to_string(&FilteredNotification::filter_notification(notification))
+        .unwrap_or_default();
+    Bytes::from(format!(
+        "id: {}\nevent: {}\ndata: {}\n\n",
+        notification._id,
+        notification.kind.as_str(),
+        data
+    ))
+}
+
+#[post("/read")]
+pub async fn mark_all_notifications_read(
+    req: HttpRequest,
+    db: Data<dyn Database>,
+) -> Result<Json<MarkReadResponse>, Error> {
+    // Extract user_id from request extensions
+    let user_id = match req.extensions().get::<ObjectId>().cloned() {
+        Some(id) => id,
+        None => {
+            return Err(actix_web::error::ErrorUnauthorized("User ID not found"));
+        }
+    };
+
+    let count = db.mark_all_notifications_read(user_id).await?;
+
+    Ok(Json(MarkReadResponse {
+        status: 200,
+        message: format!("{} notifications marked as read", count),
+    }))
+}
+
+#[post("/{id}/read")]
+pub async fn mark_notification_read(
+    req: HttpRequest,
+    db: Data<dyn Database>,
+    path: Path<String>,
+) -> Result<Json<MarkReadResponse>, Error> {
+    // Extract user_id from request extensions
+    let user_id = match req.extensions().get::<ObjectId>().cloned() {
+        Some(id) => id,
+        None => {
+            return Err(actix_web::error::ErrorUnauthorized("User ID not found"));
+        }
+    };
+
+    let notification_id = ObjectId::parse_str(path.as_str()).map_err(|e| {
+        actix_web::error::ErrorBadRequest(format!("Failed to convert to objectid: {}", e))
+    })?;
+    db.mark_notification_read(notification_id, user_id).await?;
+
+    Ok(Json(MarkReadResponse {
+        status: 200,
+        message: "Notification marked as read".to_string(),
+    }))
+}
@@ -0,0 +1,336 @@
+use actix_web::{
+    delete, get, patch, post,
+    web::{self, Data, Json, Path},
+    Error, HttpMessage, HttpRequest,
+};
+use mongodb::bson::{oid::ObjectId, Bson, DateTime};
+use validator::Validate;
+
+use crate::{
+    config::Config,
+    dtos::organization::organizations::{
+        normalize_domains, CreateOrganizationDto, ManageOrganizationResponse,
+        OrganizationListResponse, OrganizationMemberDto, OrganizationResponse,
+        UpdateOrganizationDto,
+    },
+    models::{
+        audit_event_model::{AuditAction, AuditEvent},
+        organization_model::Organization,
+        user_model::User,
+    },
+    services::{
+        audit::{self, request_event},
+        db::Database,
+    },
+};
+
+// Initialize routes
+pub fn init(cfg: &mut web::ServiceConfig) {
+    cfg.service(create_organization)
+        .service(get_organizations)
+        .service(get_organization)
+        .service(update_organization)
+        .service(add_organization_member)
+        .service(update_organization_member)
+        .service(remove_organization_member);
+}
+
+// Resolves the requesting user, refusing anyone not configured as a server admin
+async fn require_server_admin(
+    req: &HttpRequest,
+    db: &Data<dyn Database>,
+    config: &Config,
+) -> Result<User, Error> {
+    // Extract user_id from request extensions
+    let user_id = match req.extensions().get::<ObjectId>().cloned() {
+        Some(id) => id,
+        None => {
+            return Err(actix_web::error::ErrorUnauthorized("User ID not found"));
+        }
+    };
+
+    let user = db.get_user_by_id(Bson::ObjectId(user_id)).await?;
+    if !config.is_admin(&user) {
+        return Err(actix_web::error::ErrorForbidden("Admin access required"));
+    }
+
+    Ok(user)
+}
+
+// Resolves an organization the requesting user belongs to or, as a server
+// admin, hosts; to anyone else it doesn't exist. Also says whether they
+// manage its members
+async fn get_visible_organization(
+    req: &HttpRequest,
+    db: &Data<dyn Database>,
+    config: &Config,
+    organization_id: &str,
+) -> Result<(Organization, User, bool), Error> {
+    // Extract user_id from request extensions
+    let user_id = match req.extensions().get::<ObjectId>().cloned() {
+        Some(id) => id,
+        None => {
+            return Err(actix_web::error::ErrorUnauthorized("User ID not found"));
+        }
+    };
+    let organization_id = ObjectId::parse_str(organization_id).map_err(|e| {
+        actix_web::error::ErrorBadRequest(format!("Failed to convert to objectid: {}", e))
+    })?;
+
+    let user = db.get_user_by_id(Bson::ObjectId(user_id)).await?;
+    let server_admin = config.is_admin(&user);
+    if !server_admin && user.organization_id != Some(organization_id) {
+        return Err(actix_web::error::ErrorNotFound("Organization not found"));
+    }
+
+    let organization = db.get_organization(organization_id).await?;
+    let manages = server_admin || organization.is_admin(user_id);
+
+    Ok((organization, user, manages))
+}
+
+// Like get_visible_organization, refusing anyone who doesn't manage its members
+async fn get_managed_organization(
+    req: &HttpRequest,
+    db: &Data<dyn Database>,
+    config: &Config,
+    organization_id: &str,
+) -> Result<(Organization, User), Error> {
+    let (organization, user, manages) =
+        get_visible_organization(req, db, config, organization_id).await?;
+    if !manages {
+        return Err(actix_web::error::ErrorForbidden(
+            "Only organization admins can manage its members",
+        ));
+    }
+
+    Ok((organization, user))
+}
+
+// Hosting a new client company is up to the server admins
+#[post("")]
+pub async fn create_organization(
+    req: HttpRequest,
+    db: Data<dyn Database>,
+    config: Data<Config>,
+    body: Json<CreateOrganizationDto>,
+) -> Result<Json<ManageOrganizationResponse>, Error> {
+    body.validate().map_err(|e| {
+        actix_web::error::ErrorBadRequest(format!("Failed to validate the form: {}", e))
+    })?;
+    require_server_admin(&req, &db, &config).await?;
+
+    let mut admins = Vec::with_capacity(body.admin_emails.len());
+    for email in &body.admin_emails {
+        let admin = db.get_user(email.clone()).await.map_err(|e| {
+            actix_web::error::ErrorBadRequest(format!("Failed to get admin {}: {}", email, e))
+        })?;
+        if !admins.contains(&admin._id) {
+            admins.push(admin._id);
+        }
+    }
+
+    let organization_id = db
+        .create_organization(Organization {
+            _id: ObjectId::new(),
+            name: body.name.clone(),
+            domains: normalize_domains(&body.domains),
+            max_expiry_days: body.max_expiry_days,
+            storage_quota: body.storage_quota,
+            admins,
+            created_at: DateTime::now(),
+            updated_at: DateTime::now(),
+        })
+        .await?;
+
+    Ok(Json(ManageOrganizationResponse {
+        status: 200,
+        message: "Organization created successfully".to_string(),
+        organization_id: Some(organization_id.to_hex()),
+    }))
+}
+
+#[get("")]
+pub async fn get_organizations(
+    req: HttpRequest,
+    db: Data<dyn Database>,
+    config: Data<Config>,
+) -> Result<Json<OrganizationListResponse>, Error> {
+    require_server_admin(&req, &db, &config).await?;
+
+    let organizations = db.get_organizations().await?;
+
+    Ok(Json(OrganizationListResponse::new(organizations)))
+}
+
+// Members see the policies they are under; admins also see the members
+#[get("/{id}")]
+pub async fn get_organization(
+    req: HttpRequest,
+    db: Data<dyn Database>,
+    config: Data<Config>,
+    path: Path<String>,
+) -> Result<Json<OrganizationResponse>, Error> {
+    let (organization, _, manages) = get_visible_organization(&req, &db, &config, &path).await?;
+
+    let members = if manages {
+        Some(db.get_organization_members(organization._id).await?)
+    } else {
+        None
+    };
+
+    Ok(Json(OrganizationResponse::new(&organization, members)))
+}
+
+// Policies apply to shares and uploads made from now on
+#[patch("/{id}")]
+pub async fn update_organization(
+    req: HttpRequest,
+    db: Data<dyn Database>,
+    config: Data<Config>,
+    path: Path<String>,
+    body: Json<UpdateOrganizationDto>,
+) -> Result<Json<ManageOrganizationResponse>, Error> {
+    body.validate().map_err(|e| {
+        actix_web::error::ErrorBadRequest(format!("Failed to validate the form: {}", e))
+    })?;
+    require_server_admin(&req, &db, &config).await?;
+
+    let organization_id = ObjectId::parse_str(path.as_str()).map_err(|e| {
+        actix_web::error::ErrorBadRequest(format!("Failed to convert to objectid: {}", e))
+    })?;
+    let organization = db.get_organization(organization_id).await?;
+
+    db.update_organization(Organization {
+        name: body.name.clone(),
+        domains: normalize_domains(&body.domains),
+        max_expiry_days: body.max_expiry_days,
+        storage_quota: body.storage_quota,
+        ..organization
+    })
+    .await?;
+
+    Ok(Json(ManageOrganizationResponse {
+        status: 200,
+        message: "Organization updated successfully".to_string(),
+        organization_id: None,
+    }))
+}
+
+// Only accounts outside every organization can be added, and only with an
+// email in one of its domains
+#[post("/{id}/members")]
+pub async fn add_organization_member(
+    req: HttpRequest,
+    db: Data<dyn Database>,
+    config: Data<Config>,
+    path: Path<String>,
+    body: Json<OrganizationMemberDto>,
+) -> Result<Json<ManageOrganizationResponse>, Error> {
+    body.validate().map_err(|e| {
+        actix_web::error::ErrorBadRequest(format!("Failed to validate the form: {}", e))
+    })?;
+
+    let (organization, user) = get_managed_organization(&req, &db, &config, &path).await?;
+
+    let member = db
+        .get_user(body.email.clone())
+        .await
+        .map_err(|e| actix_web::error::ErrorBadRequest(format!("Failed to get member: {}", e)))?;
+    if !organization.allows_email(&member.email) {
+        return Err(actix_web::error::ErrorBadRequest(format!(
+            "{} is not in a domain of {}",
+            member.email, organization.name
+        )));
+    }
+
+    db.join_organization(organization._id, member._id, body.admin)
+        .await?;
+
+    let event = AuditEvent {
+        actor_id: Some(user._id),
+        subject_user_id: Some(member._id),
+        detail: Some(format!("Organization {}", organization.name)),
+        ..request_event(AuditAction::OrganizationJoined, &req)
+    };
+    audit::record(db.get_ref(), event).await;
+
+    Ok(Json(ManageOrganizationResponse {
+        status: 200,
+        message: "Member added successfully".to_string(),
+        organization_id: None,
+    }))
+}
+
+// Makes a member an admin or takes it back
+#[patch("/{id}/members")]
+pub async fn update_organization_member(
+    req: HttpRequest,
+    db: Data<dyn Database>,
+    config: Data<Config>,
+    path: Path<String>,
+    body: Json<OrganizationMemberDto>,
+) -> Result<Json<ManageOrganizationResponse>, Error> {
+    body.validate().map_err(|e| {
+        actix_web::error::ErrorBadRequest(format!("Failed to validate the form: {}", e))
+    })?;
+
+    let (organization, _) = get_managed_organization(&req, &db, &config, &path).await?;
+
+    let member = db
+        .get_user(body.email.clone())
+        .await
+        .map_err(|e| actix_web::error::ErrorBadRequest(format!("Failed to get member: {}", e)))?;
+
+    db.set_organization_admin(organization._id, member._id, body.admin)
+        .await?;
+
+    Ok(Json(ManageOrganizationResponse {
+        status: 200,
+        message: "Member updated successfully".to_string(),
+        organization_id: None,
+    }))
+}
+
+// Admins remove members and anyone can leave. Leaving revokes the shares the
+// member received within the organization; their own files stay with them
+#[delete("/{id}/members")]
+pub async fn remove_organization_member(
+    req: HttpRequest,
+    db: Data<dyn Database>,
+    config: Data<Config>,
+    path: Path<String>,
+    body: Json<OrganizationMemberDto>,
+) -> Result<Json<ManageOrganizationResponse>, Error> {
+    body.validate().map_err(|e| {
+        actix_web::error::ErrorBadRequest(format!("Failed to validate the form: {}", e))
+    })?;
+
+    let (organization, user, manages) = get_visible_organization(&req, &db, &config, &path).await?;
+
+    let member = db
+        .get_user(body.email.clone())
+        .await
+        .map_err(|e| actix_web::error::ErrorBadRequest(format!("Failed to get member: {}", e)))?;
+    if !manages && member._id != user._id {
+        return Err(actix_web::error::ErrorForbidden(
+            "Only organization admins can manage its members",
+        ));
+    }
+
+    db.leave_organization(organization._id, member._id).await?;
+
+    let event = AuditEvent {
+        actor_id: Some(user._id),
+        subject_user_id: Some(member._id),
+        detail: Some(format!("Organization {}", organization.name)),
+        ..request_event(AuditAction::OrganizationLeft, &req)
+    };
+    audit::record(db.get_ref(), event).await;
+
+    Ok(Json(ManageOrganizationResponse {
+        status: 200,
+        message: "Member removed successfully".to_string(),
+        organization_id: None,
+    }))
+}
@@ -1,27 +1,46 @@
 use actix_web::{
-    get,
+    get, patch, post,
     web::{self, Data, Json, Query},
     Error, HttpMessage, HttpRequest,
 };
 use mongodb::bson::oid::ObjectId;
 
 use crate::{
-    dtos::auth::get_user_dto::{
-        self, FilterSearchUserDto, FilterUserDto, SearchUserQuery, SearchUserResponseDto,
-        UserResponseDto,
+    config::Config,
+    dtos::{
+        audit::audit_events::{AuditEventsResponse, AuditQueryParams},
+        auth::{
+            get_user_dto::{
+                self, FilterSearchUserDto, FilterUserDto, SearchUserQuery, SearchUserResponseDto,
+                UsageResponseDto, UserResponseDto,
+            },
+            verify_email_dto::VerifyEmailResponse,
+        },
+        notification::email_preferences::{EmailPreferencesResponse, UpdateEmailPreferencesDto},
+    },
+    services::{
+        db::Database,
+        notifications::Notifier,
+        organizations::{load_organization, storage_quota},
+        verification,
     },
-    services::db::Database,
 };
 
 // Initialize routes
 pub fn init(cfg: &mut web::ServiceConfig) {
-    cfg.service(get_user).service(search_users);
+    cfg.service(get_user)
+        .service(search_users)
+        .service(get_usage)
+        .service(get_audit_events)
+        .service(get_email_preferences)
+        .service(update_email_preferences)
+        .service(resend_verification);
 }
 
 #[get("/get-me")]
 pub async fn get_user(
     req: HttpRequest,
-    db: Data<Database>,
+    db: Data<dyn Database>,
 ) -> Result<Json<UserResponseDto>, Error> {
     // Extract user_id from request extensions
     let user_id = req.extensions().get::<ObjectId>().cloned();
@@ -59,13 +78,29 @@ pub async fn get_user(
     }))
 }
 
+// Only finds users of the requesting user's own organization
 #[get("/filter-user")]
 pub async fn search_users(
-    db: Data<Database>,
+    req: HttpRequest,
+    db: Data<dyn Database>,
     query: Query<SearchUserQuery>,
 ) -> Result<Json<SearchUserResponseDto>, Error> {
+    // Extract user_id from request extensions
+    let user_id = match req.extensions().get::<ObjectId>().cloned() {
+        Some(id) => id,
+        None => {
+            return Err(actix_web::error::ErrorUnauthorized("User ID not found"));
+        }
+    };
+    let user = db
+        .get_user_by_id(mongodb::bson::Bson::ObjectId(user_id))
+        .await?;
+
     let query = query.into_inner();
-    let users = match db.search_user(query.email_text.clone().to_string()).await {
+    let users = match db
+        .search_user(query.email_text.clone().to_string(), user.organization_id)
+        .await
+    {
         Ok(users) => users,
         Err(e) => {
             return Err(actix_web::error::ErrorBadRequest(format!(
@@ -85,3 +120,122 @@ pub async fn search_users(
         users: filtered_users,
     }))
 }
+
+#[get("/usage")]
+pub async fn get_usage(
+    req: HttpRequest,
+    db: Data<dyn Database>,
+    config: Data<Config>,
+) -> Result<Json<UsageResponseDto>, Error> {
+    // Extract user_id from request extensions
+    let user_id = match req.extensions().get::<ObjectId>().cloned() {
+        Some(id) => id,
+        None => {
+            return Err(actix_web::error::ErrorUnauthorized("User ID not found"));
+        }
+    };
+
+    let user = db
+        .get_user_by_id(mongodb::bson::Bson::ObjectId(user_id))
+        .await?;
+    let organization = load_organization(db.get_ref(), user.organization_id).await?;
+    let quota = storage_quota(&config, organization.as_ref());
+
+    Ok(Json(UsageResponseDto {
+        status: 200.to_string(),
+        used: user.storage_used,
+        quota,
+        remaining: (quota - user.storage_used).max(0),
+        max_upload_size: config.max_upload_size,
+    }))
+}
+
+// Events the user took part in, whether they acted or were acted upon
+#[get("/audit")]
+pub async fn get_audit_events(
+    req: HttpRequest,
+    db: Data<dyn Database>,
+    query: Query<AuditQueryParams>,
+) -> Result<Json<AuditEventsResponse>, Error> {
+    // Extract user_id from request extensions
+    let user_id = match req.extensions().get::<ObjectId>().cloned() {
+        Some(id) => id,
+        None => {
+            return Err(actix_web::error::ErrorUnauthorized("User ID not found"));
+        }
+    };
+
+    let query = query.into_inner().into_query(Some(user_id))?;
+    let events = db.get_audit_events(query.clone()).await?;
+
+    Ok(Json(AuditEventsResponse::new(events, &query)))
+}
+
+#[get("/email-preferences")]
+pub async fn get_email_preferences(
+    req: HttpRequest,
+    db: Data<dyn Database>,
+) -> Result<Json<EmailPreferencesResponse>, Error> {
+    // Extract user_id from request extensions
+    let user_id = match req.extensions().get::<ObjectId>().cloned() {
+        Some(id) => id,
+        None => {
+            return Err(actix_web::error::ErrorUnauthorized("User ID not found"));
+        }
+    };
+
+    let preferences = db.get_email_preferences(user_id).await?;
+
+    Ok(Json(EmailPreferencesResponse::new(&preferences)))
+}
+
+#[patch("/email-preferences")]
+pub async fn update_email_preferences(
+    req: HttpRequest,
+    db: Data<dyn Database>,
+    body: Json<UpdateEmailPreferencesDto>,
+) -> Result<Json<EmailPreferencesResponse>, Error> {
+    // Extract user_id from request extensions
+    let user_id = match req.extensions().get::<ObjectId>().cloned() {
+        Some(id) => id,
+        None => {
+            return Err(actix_web::error::ErrorUnauthorized("User ID not found"));
+        }
+    };
+
+    let mut preferences = db.get_email_preferences(user_id).await?;
+    body.apply(&mut preferences);
+    db.save_email_preferences(preferences.clone()).await?;
+
+    Ok(Json(EmailPreferencesResponse::new(&preferences)))
+}
+
+// Replaces the outstanding token, so only the latest email verifies
+#[post("/resend-verification")]
+pub async fn resend_verification(
+    req: HttpRequest,
+    db: Data<dyn Database>,
+    notifier: Data<Notifier>,
+) -> Result<Json<VerifyEmailResponse>, Error> {
+    // Extract user_id from request extensions
+    let user_id = match req.extensions().get::<ObjectId>().cloned() {
+        Some(id) => id,
+        None => {
+            return Err(actix_web::error::ErrorUnauthorized("User ID not found"));
+        }
+    };
+
+    let user = db
+        .get_user_by_id(mongodb::bson::Bson::ObjectId(user_id))
+        .await?;
+    if user.email_verified_at.is_some() {
+        return Err(actix_web::error::ErrorConflict("Email is already verified"));
+    }
+
+    verification::send_verification(db.get_ref(), &notifier, &user).await?;
+
+    Ok(Json(VerifyEmailResponse {
+        status: 200,
+        message: "Verification email sent".to_string(),
+    }))
+}
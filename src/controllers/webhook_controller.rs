@@ -0,0 +1,161 @@
+use actix_web::{
+    delete, get, post,
+    web::{self, Data, Json, Path, Query},
+    Error, HttpMessage, HttpRequest,
+};
+use mongodb::bson::{oid::ObjectId, Bson};
+
+use crate::{
+    config::Config,
+    dtos::{
+        audit::audit_events::parse_id,
+        webhook::webhooks::{
+            CreateWebhookDto, CreateWebhookResponse, FilteredWebhook, ManageWebhookResponse,
+            WebhookDeliveriesResponse, WebhookDeliveryParams, WebhooksResponse,
+        },
+    },
+    models::webhook_model::{DeliveryStatus, Webhook},
+    services::{db::Database, webhooks},
+};
+
+// Initialize routes
+pub fn init(cfg: &mut web::ServiceConfig) {
+    cfg.service(create_webhook)
+        .service(get_webhooks)
+        .service(delete_webhook)
+        .service(get_webhook_deliveries)
+        .service(retry_webhook_delivery);
+}
+
+// Resolves a webhook the requesting user registered; admin webhooks belong to every admin
+async fn get_managed_webhook(
+    req: &HttpRequest,
+    db: &Data<dyn Database>,
+    config: &Config,
+    webhook_id: ObjectId,
+) -> Result<Webhook, Error> {
+    // Extract user_id from request extensions
+    let user_id = match req.extensions().get::<ObjectId>().cloned() {
+        Some(id) => id,
+        None => {
+            return Err(actix_web::error::ErrorUnauthorized("User ID not found"));
+        }
+    };
+
+    let webhook = db.get_webhook(webhook_id).await?;
+    let allowed = match webhook.user_id {
+        Some(owner_id) => owner_id == user_id,
+        None => {
+            let user = db.get_user_by_id(Bson::ObjectId(user_id)).await?;
+            config.is_admin(&user)
+        }
+    };
+    if !allowed {
+        return Err(actix_web::error::ErrorForbidden(
+            "You're not authorized to manage this webhook",
+        ));
+    }
+
+    Ok(webhook)
+}
+
+#[post("")]
+pub async fn create_webhook(
+    req: HttpRequest,
+    db: Data<dyn Database>,
+    body: Json<CreateWebhookDto>,
+) -> Result<Json<CreateWebhookResponse>, Error> {
+    // Extract user_id from request extensions
+    let user_id = match req.extensions().get::<ObjectId>().cloned() {
+        Some(id) => id,
+        None => {
+            return Err(actix_web::error::ErrorUnauthorized("User ID not found"));
+        }
+    };
+
+    let webhook =
+        webhooks::register(db.get_ref(), Some(user_id), body.url.clone(), body.events()).await?;
+
+    Ok(Json(CreateWebhookResponse {
+        status: 200.to_string(),
+        webhook: FilteredWebhook::filter_webhook(&webhook),
+        secret: webhook.secret,
+    }))
+}
+
+#[get("")]
+pub async fn get_webhooks(
+    req: HttpRequest,
+    db: Data<dyn Database>,
+) -> Result<Json<WebhooksResponse>, Error> {
+    // Extract user_id from request extensions
+    let user_id = match req.extensions().get::<ObjectId>().cloned() {
+        Some(id) => id,
+        None => {
+            return Err(actix_web::error::ErrorUnauthorized("User ID not found"));
+        }
+    };
+
+    let webhooks = db.get_webhooks(Some(user_id)).await?;
+
+    Ok(Json(WebhooksResponse::new(webhooks)))
+}
+
+#[delete("/{id}")]
+pub async fn delete_webhook(
+    req: HttpRequest,
+    db: Data<dyn Database>,
+    config: Data<Config>,
+    path: Path<String>,
+) -> Result<Json<ManageWebhookResponse>, Error> {
+    let webhook = get_managed_webhook(&req, &db, &config, parse_id(&path)?).await?;
+
+    db.delete_webhook(webhook._id).await?;
+
+    Ok(Json(ManageWebhookResponse {
+        status: 200,
+        message: "Webhook deleted successfully".to_string(),
+    }))
+}
+
+// The webhook's delivery log, newest first; `status=dead` lists its dead letters
+#[get("/{id}/deliveries")]
+pub async fn get_webhook_deliveries(
+    req: HttpRequest,
+    db: Data<dyn Database>,
+    config: Data<Config>,
+    path: Path<String>,
+    query: Query<WebhookDeliveryParams>,
+) -> Result<Json<WebhookDeliveriesResponse>, Error> {
+    let webhook = get_managed_webhook(&req, &db, &config, parse_id(&path)?).await?;
+
+    let query = query.into_inner().into_query(webhook._id)?;
+    let deliveries = db.get_webhook_deliveries(query.clone()).await?;
+
+    Ok(Json(WebhookDeliveriesResponse::new(deliveries, &query)))
+}
+
+// Sends a dead letter again, with a fresh set of attempts
+#[post("/deliveries/{id}/retry")]
+pub async fn retry_webhook_delivery(
+    req: HttpRequest,
+    db: Data<dyn Database>,
+    config: Data<Config>,
+    path: Path<String>,
+) -> Result<Json<ManageWebhookResponse>, Error> {
+    let delivery = db.get_webhook_delivery(parse_id(&path)?).await?;
+    get_managed_webhook(&req, &db, &config, delivery.webhook_id).await?;
+
+    if delivery.status != DeliveryStatus::Dead {
+        return Err(actix_web::error::ErrorConflict(
+            "Only dead deliveries can be retried",
+        ));
+    }
+    db.update_webhook_delivery(webhooks::requeue(delivery))
+        .await?;
+
+    Ok(Json(ManageWebhookResponse {
+        status: 200,
+        message: "Webhook delivery queued for retry".to_string(),
+    }))
+}
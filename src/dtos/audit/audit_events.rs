@@ -0,0 +1,110 @@
+use actix_web::Error;
+use mongodb::bson::{oid::ObjectId, DateTime};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    models::audit_event_model::{AuditAction, AuditEvent},
+    services::audit::{AuditQuery, DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE},
+};
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct AuditQueryParams {
+    // Only honoured for admins; everyone else sees their own events
+    pub user_id: Option<String>,
+    pub action: Option<AuditAction>,
+    pub cursor: Option<String>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FilteredAuditEvent {
+    pub id: String,
+    pub action: AuditAction,
+    pub actor_id: Option<String>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub subject_user_id: Option<String>,
+    pub file_id: Option<String>,
+    pub share_id: Option<String>,
+    pub collection_id: Option<String>,
+    pub detail: Option<String>,
+    pub created_at: DateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuditEventsResponse {
+    pub status: String,
+    pub events: Vec<FilteredAuditEvent>,
+    pub next_cursor: Option<String>,
+}
+
+impl AuditQueryParams {
+    pub fn into_query(self, user_id: Option<ObjectId>) -> Result<AuditQuery, Error> {
+        let before = match self.cursor.as_deref() {
+            Some("") | None => None,
+            Some(cursor) => Some(parse_id(cursor)?),
+        };
+
+        Ok(AuditQuery {
+            user_id,
+            share_id: None,
+            actions: self.action.into_iter().collect(),
+            before,
+            limit: self
+                .limit
+                .unwrap_or(DEFAULT_PAGE_SIZE)
+                .clamp(1, MAX_PAGE_SIZE),
+        })
+    }
+
+    // The user filter an admin asked for, if any
+    pub fn requested_user(&self) -> Result<Option<ObjectId>, Error> {
+        match self.user_id.as_deref() {
+            Some("") | None => Ok(None),
+            Some(user_id) => parse_id(user_id).map(Some),
+        }
+    }
+}
+
+impl FilteredAuditEvent {
+    pub fn filter_event(event: &AuditEvent) -> Self {
+        FilteredAuditEvent {
+            id: event._id.to_string(),
+            action: event.action,
+            actor_id: event.actor_id.map(|id| id.to_string()),
+            ip: event.ip.to_owned(),
+            user_agent: event.user_agent.to_owned(),
+            subject_user_id: event.subject_user_id.map(|id| id.to_string()),
+            file_id: event.file_id.map(|id| id.to_string()),
+            share_id: event.share_id.map(|id| id.to_string()),
+            collection_id: event.collection_id.map(|id| id.to_string()),
+            detail: event.detail.to_owned(),
+            created_at: event.created_at,
+        }
+    }
+}
+
+impl AuditEventsResponse {
+    pub fn new(events: Vec<AuditEvent>, query: &AuditQuery) -> Self {
+        // A full page may be followed by more
+        let next_cursor = match events.last() {
+            Some(last) if events.len() == query.limit => Some(last._id.to_string()),
+            _ => None,
+        };
+
+        AuditEventsResponse {
+            status: 200.to_string(),
+            events: events
+                .iter()
+                .map(FilteredAuditEvent::filter_event)
+                .collect(),
+            next_cursor,
+        }
+    }
+}
+
+pub fn parse_id(id: &str) -> Result<ObjectId, Error> {
+    ObjectId::parse_str(id).map_err(|e| {
+        actix_web::error::ErrorBadRequest(format!("Failed to convert to objectid: {}", e))
+    })
+}
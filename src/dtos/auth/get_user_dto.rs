@@ -8,6 +8,8 @@ pub struct FilterUserDto {
     pub name: String,
     pub email: String,
     pub public_key: String,
+    pub organization_id: Option<String>,
+    pub email_verified: bool,
 }
 
 impl FilterUserDto {
@@ -17,6 +19,8 @@ impl FilterUserDto {
             name: user.username.to_owned(),
             email: user.email.to_owned(),
             public_key: user.public_key.to_owned(),
+            organization_id: user.organization_id.map(|id| id.to_string()),
+            email_verified: user.email_verified_at.is_some(),
         }
     }
 }
@@ -59,3 +63,12 @@ pub struct SearchUserResponseDto {
     pub status: String,
     pub users: Vec<FilterSearchUserDto>,
 }
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UsageResponseDto {
+    pub status: String,
+    pub used: i64,
+    pub quota: i64,
+    pub remaining: i64,
+    pub max_upload_size: i64,
+}
@@ -1,4 +1,5 @@
 pub mod register_user_dto;
 pub mod login_user_dto;
 pub mod get_user_dto;
-pub mod refresh_token_dto;
\ No newline at end of file
+pub mod refresh_token_dto;
+pub mod verify_email_dto;
\ No newline at end of file
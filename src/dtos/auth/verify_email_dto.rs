@@ -0,0 +1,14 @@
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+#[derive(Debug, Validate, Clone, Deserialize)]
+pub struct VerifyEmailDto {
+    #[validate(length(min = 1, message = "Token is required"))]
+    pub token: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VerifyEmailResponse {
+    pub status: i32,
+    pub message: String,
+}
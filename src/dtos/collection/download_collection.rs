@@ -0,0 +1,11 @@
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+#[derive(Validate, Debug, Default, Clone, Serialize, Deserialize)]
+pub struct DownloadCollectionDto {
+    #[validate(
+        length(min = 1, message = "Password is required."),
+        length(min = 6, message = "Password must be at least 6 characters")
+    )]
+    pub password: String,
+}
@@ -0,0 +1,45 @@
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+
+use crate::models::shared_collection_model::SharedCollection;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FilteredCollection {
+    pub id: String,
+    pub name: String,
+    pub file_count: i64,
+    pub total_size: i64,
+    pub shared_at: DateTime,
+    pub expires_at: DateTime,
+    pub counterpart_email: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CollectionListResponse {
+    pub collections: Vec<FilteredCollection>,
+}
+
+impl FilteredCollection {
+    pub fn filter_collection(collection: &SharedCollection) -> Self {
+        FilteredCollection {
+            id: collection._id.to_string(),
+            name: collection.name.to_owned(),
+            file_count: collection.file_count,
+            total_size: collection.total_size,
+            shared_at: collection.created_at.to_owned(),
+            expires_at: collection.expires_at.to_owned(),
+            counterpart_email: collection.counterpart_email.to_owned(),
+        }
+    }
+}
+
+impl CollectionListResponse {
+    pub fn new(collections: Vec<SharedCollection>) -> Self {
+        CollectionListResponse {
+            collections: collections
+                .iter()
+                .map(FilteredCollection::filter_collection)
+                .collect(),
+        }
+    }
+}
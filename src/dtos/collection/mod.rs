@@ -0,0 +1,3 @@
+pub mod download_collection;
+pub mod get_collections;
+pub mod upload_collection;
@@ -0,0 +1,37 @@
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+use crate::dtos::file::upload_file::validate_expiration_date;
+
+#[derive(Validate, Debug, Default, Clone, Serialize, Deserialize)]
+pub struct CollectionUploadDtos {
+    #[validate(length(min = 1, message = "Collection name is required."))]
+    pub collection_name: String,
+
+    #[validate(email(message = "Invalid email format"))]
+    pub recipient_email: String,
+
+    #[validate(
+        length(min = 1, message = "New password is required."),
+        length(min = 6, message = "New password must be at least 6 characters")
+    )]
+    pub password: String,
+
+    #[validate(custom = "validate_expiration_date")]
+    pub expiration_date: String,
+
+    // Sign the upload with the sender's Ed25519 key
+    #[serde(default)]
+    pub sign: bool,
+
+    // Store the content as-is, e.g. for media that is already compressed
+    #[serde(default)]
+    pub skip_compression: bool,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct UploadCollectionResponse {
+    pub status: i32,
+    pub message: String,
+    pub collection_id: String,
+}
@@ -0,0 +1,64 @@
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+
+use crate::models::{file_model::File, file_version_model::FileVersionSummary};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FilteredFileVersion {
+    pub version: i64,
+    pub name: String,
+    pub size: i64,
+    pub content_type: Option<String>,
+    pub sha256: Option<String>,
+    pub created_at: DateTime,
+    pub current: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FileVersionListResponse {
+    pub current_version: i64,
+    pub versions: Vec<FilteredFileVersion>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct UploadFileVersionResponse {
+    pub status: i32,
+    pub message: String,
+    pub version: i64,
+}
+
+impl FilteredFileVersion {
+    pub fn filter_version(version: &FileVersionSummary) -> Self {
+        FilteredFileVersion {
+            version: version.version,
+            name: version.file_name.to_owned(),
+            size: version.file_size,
+            content_type: version.content_type.to_owned(),
+            sha256: version.sha256.to_owned(),
+            created_at: version.created_at.to_owned(),
+            current: false,
+        }
+    }
+}
+
+impl FileVersionListResponse {
+    // The current version leads, followed by the retained history
+    pub fn new(file: &File, versions: Vec<FileVersionSummary>) -> Self {
+        let current = FilteredFileVersion {
+            version: file.version,
+            name: file.file_name.to_owned(),
+            size: file.file_size,
+            content_type: file.content_type.to_owned(),
+            sha256: file.sha256.to_owned(),
+            created_at: file.updated_at.to_owned(),
+            current: true,
+        };
+
+        FileVersionListResponse {
+            current_version: file.version,
+            versions: std::iter::once(current)
+                .chain(versions.iter().map(FilteredFileVersion::filter_version))
+                .collect(),
+        }
+    }
+}
@@ -1,33 +1,136 @@
+use actix_web::Error;
+use base64::{engine::general_purpose::STANDARD, Engine};
 use mongodb::bson::DateTime;
 use serde::{Deserialize, Serialize};
 
-use crate::models::file_model::File;
+use crate::{
+    models::{file_model::ScanStatus, shared_file_model::SharedFile},
+    services::listing::{
+        FileListOptions, ListingCursor, ShareStatus, SharedFilePage, SortField, SortOrder,
+        DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE,
+    },
+};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct FilteredFile {
     pub id: String,
     pub name: String,
     pub size: i64,
+    pub content_type: Option<String>,
+    pub version: i64,
+    pub sha256: Option<String>,
+    pub scan_status: ScanStatus,
     pub shared_at: DateTime,
+    pub expires_at: DateTime,
+    pub revoked_at: Option<DateTime>,
+    pub declined_at: Option<DateTime>,
+    pub archived_at: Option<DateTime>,
+    pub can_reshare: bool,
+    pub parent_share_id: Option<String>,
+    pub signature: Option<String>,
+    pub first_opened_at: Option<DateTime>,
+    pub last_opened_at: Option<DateTime>,
+    pub open_count: i64,
+    pub failed_password_attempts: i64,
     pub recipients_email: String,
     pub share_id: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct QueryParams {
-    pub skip: Option<i32>,
+    pub cursor: Option<String>,
     pub limit: Option<usize>,
+    pub sort_by: Option<SortField>,
+    pub order: Option<SortOrder>,
+    pub email: Option<String>,
+    pub name: Option<String>,
+    pub min_size: Option<i64>,
+    pub max_size: Option<i64>,
+    pub status: Option<ShareStatus>,
+    pub archived: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FileListResponse {
+    pub files: Vec<FilteredFile>,
+    pub total: u64,
+    pub has_more: bool,
+    pub next_cursor: Option<String>,
 }
 
 impl FilteredFile {
-    pub fn filter_file(file: &File, recipients_email: String, share_id: Option<String>) -> Self {
+    pub fn filter_file(file: &SharedFile) -> Self {
         FilteredFile {
             id: file._id.to_string(),
             name: file.file_name.to_owned(),
             size: file.file_size.to_owned(),
+            content_type: file.content_type.to_owned(),
+            version: file.version,
+            sha256: file.sha256.to_owned(),
+            scan_status: file.scan_status,
             shared_at: file.created_at.to_owned(),
-            recipients_email,
-            share_id,
+            expires_at: file.expires_at.to_owned(),
+            revoked_at: file.revoked_at.to_owned(),
+            declined_at: file.declined_at.to_owned(),
+            archived_at: file.archived_at.to_owned(),
+            can_reshare: file.can_reshare,
+            parent_share_id: file.parent_share_id.map(|id| id.to_string()),
+            signature: file
+                .signature
+                .as_ref()
+                .map(|signature| STANDARD.encode(signature)),
+            first_opened_at: file.first_opened_at,
+            last_opened_at: file.last_opened_at,
+            open_count: file.open_count,
+            failed_password_attempts: file.failed_password_attempts,
+            recipients_email: file.counterpart_email.to_owned(),
+            share_id: Some(file.share_id.to_string()),
+        }
+    }
+}
+
+impl QueryParams {
+    pub fn into_options(self) -> Result<FileListOptions, Error> {
+        let after = match self.cursor.as_deref() {
+            Some("") | None => None,
+            Some(cursor) => Some(ListingCursor::decode(cursor)?),
+        };
+
+        let options = FileListOptions {
+            sort: self.sort_by.unwrap_or_default(),
+            order: self.order.unwrap_or_default(),
+            after,
+            limit: self
+                .limit
+                .unwrap_or(DEFAULT_PAGE_SIZE)
+                .clamp(1, MAX_PAGE_SIZE),
+            counterpart_email: self.email.filter(|email| !email.is_empty()),
+            name: self.name.filter(|name| !name.is_empty()),
+            min_size: self.min_size,
+            max_size: self.max_size,
+            status: self.status.unwrap_or_default(),
+            archived: self.archived.unwrap_or_default(),
+        };
+        options.validate_cursor()?;
+
+        Ok(options)
+    }
+}
+
+impl FileListResponse {
+    pub fn new(page: SharedFilePage, options: &FileListOptions) -> Self {
+        let next_cursor = match page.files.last() {
+            Some(last) if page.has_more => {
+                Some(ListingCursor::after(last, options.sort, options.order).encode())
+            }
+            _ => None,
+        };
+
+        FileListResponse {
+            files: page.files.iter().map(FilteredFile::filter_file).collect(),
+            total: page.total,
+            has_more: page.has_more,
+            next_cursor,
         }
     }
 }
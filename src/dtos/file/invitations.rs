@@ -0,0 +1,45 @@
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+
+use crate::models::invitation_model::Invitation;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FilteredInvitation {
+    pub id: String,
+    pub email: String,
+    pub file_id: String,
+    pub file_name: String,
+    pub expires_at: DateTime,
+    pub created_at: DateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InvitationListResponse {
+    pub status: String,
+    pub invitations: Vec<FilteredInvitation>,
+}
+
+impl FilteredInvitation {
+    pub fn filter_invitation(invitation: &Invitation) -> Self {
+        FilteredInvitation {
+            id: invitation._id.to_string(),
+            email: invitation.email.to_owned(),
+            file_id: invitation.file_id.to_string(),
+            file_name: invitation.file_name.to_owned(),
+            expires_at: invitation.expires_at,
+            created_at: invitation.created_at,
+        }
+    }
+}
+
+impl InvitationListResponse {
+    pub fn new(invitations: Vec<Invitation>) -> Self {
+        InvitationListResponse {
+            status: 200.to_string(),
+            invitations: invitations
+                .iter()
+                .map(FilteredInvitation::filter_invitation)
+                .collect(),
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+use super::upload_file::validate_expiration_date;
+
+#[derive(Validate, Debug, Default, Clone, Serialize, Deserialize)]
+pub struct UpdateShareExpiryDto {
+    #[validate(custom = "validate_expiration_date")]
+    pub expiration_date: String,
+}
+
+#[derive(Validate, Debug, Default, Clone, Serialize, Deserialize)]
+pub struct RotateSharePasswordDto {
+    #[validate(
+        length(min = 1, message = "New password is required."),
+        length(min = 6, message = "New password must be at least 6 characters")
+    )]
+    pub password: String,
+}
+
+#[derive(Validate, Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ShareRecipientDto {
+    #[validate(email(message = "Invalid email format"))]
+    pub recipient_email: String,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct SharePermissionsDto {
+    pub can_reshare: bool,
+}
+
+#[derive(Validate, Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ReshareDto {
+    #[validate(email(message = "Invalid email format"))]
+    pub recipient_email: String,
+
+    #[validate(
+        length(min = 1, message = "New password is required."),
+        length(min = 6, message = "New password must be at least 6 characters")
+    )]
+    pub password: String,
+
+    #[validate(custom = "validate_expiration_date")]
+    pub expiration_date: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ManageShareResponse {
+    pub status: i32,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub share_id: Option<String>,
+}
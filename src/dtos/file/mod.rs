@@ -1,4 +1,7 @@
 pub mod upload_file;
 pub mod retrieve_file;
 pub mod get_files;
-pub mod delete_file;
\ No newline at end of file
+pub mod delete_file;
+pub mod manage_share;
+pub mod file_versions;
+pub mod share_access;pub mod invitations;
@@ -11,9 +11,13 @@ pub struct RetrieveFileDto {
         length(min = 6, message = "Password must be at least 6 characters")
     )]
     pub password: String,
+
+    // Defaults to the current version
+    pub version: Option<i64>,
 }
 
 #[derive(Debug, Default, Deserialize, Serialize)]
 pub struct RetrieveFileResponse {
     pub file: Vec<u8>,
+    pub content_type: Option<String>,
 }
@@ -0,0 +1,75 @@
+use actix_web::Error;
+use mongodb::bson::{oid::ObjectId, DateTime};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    dtos::audit::audit_events::{parse_id, FilteredAuditEvent},
+    models::{
+        audit_event_model::{AuditAction, AuditEvent},
+        share_link_model::ShareLink,
+    },
+    services::audit::{AuditQuery, DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE},
+};
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ShareAccessParams {
+    pub cursor: Option<String>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ShareAccessResponse {
+    pub status: String,
+    pub share_id: String,
+    pub first_opened_at: Option<DateTime>,
+    pub last_opened_at: Option<DateTime>,
+    pub open_count: i64,
+    pub failed_password_attempts: i64,
+    // Opens and failed password attempts, newest first
+    pub events: Vec<FilteredAuditEvent>,
+    pub next_cursor: Option<String>,
+}
+
+impl ShareAccessParams {
+    pub fn into_query(self, share_id: ObjectId) -> Result<AuditQuery, Error> {
+        let before = match self.cursor.as_deref() {
+            Some("") | None => None,
+            Some(cursor) => Some(parse_id(cursor)?),
+        };
+
+        Ok(AuditQuery {
+            user_id: None,
+            share_id: Some(share_id),
+            actions: vec![AuditAction::Download, AuditAction::SharePasswordFailed],
+            before,
+            limit: self
+                .limit
+                .unwrap_or(DEFAULT_PAGE_SIZE)
+                .clamp(1, MAX_PAGE_SIZE),
+        })
+    }
+}
+
+impl ShareAccessResponse {
+    pub fn new(share_link: &ShareLink, events: Vec<AuditEvent>, query: &AuditQuery) -> Self {
+        // A full page may be followed by more
+        let next_cursor = match events.last() {
+            Some(last) if events.len() == query.limit => Some(last._id.to_string()),
+            _ => None,
+        };
+
+        ShareAccessResponse {
+            status: 200.to_string(),
+            share_id: share_link._id.to_string(),
+            first_opened_at: share_link.first_opened_at,
+            last_opened_at: share_link.last_opened_at,
+            open_count: share_link.open_count,
+            failed_password_attempts: share_link.failed_password_attempts,
+            events: events
+                .iter()
+                .map(FilteredAuditEvent::filter_event)
+                .collect(),
+            next_cursor,
+        }
+    }
+}
@@ -1,12 +1,21 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use validator::{Validate, ValidationError};
+use validator::{validate_email, Validate, ValidationError};
 
 #[derive(Validate, Debug, Default, Clone, Serialize, Deserialize)]
+#[validate(schema(function = "validate_recipient", skip_on_field_errors = false))]
 pub struct FileUploadDtos {
-    #[validate(email(message = "Invalid email format"))]
+    // Left empty when sharing with a group
     pub recipient_email: String,
 
+    // Id of a group the sender belongs to, instead of a recipient email
+    #[serde(default)]
+    pub recipient_group: Option<String>,
+
+    // Members who join the group before the share expires get it too
+    #[serde(default)]
+    pub auto_grant: bool,
+
     #[validate(
         length(min = 1, message = "New password is required."),
         length(min = 6, message = "New password must be at least 6 characters")
@@ -15,9 +24,34 @@ pub struct FileUploadDtos {
 
     #[validate(custom = "validate_expiration_date")]
     pub expiration_date: String,
+
+    // Sign the upload with the sender's Ed25519 key
+    #[serde(default)]
+    pub sign: bool,
+
+    // Store the content as-is, e.g. for media that is already compressed
+    #[serde(default)]
+    pub skip_compression: bool,
+}
+
+pub fn validate_recipient(form: &FileUploadDtos) -> Result<(), ValidationError> {
+    match &form.recipient_group {
+        Some(_) if !form.recipient_email.is_empty() => {
+            let mut error = ValidationError::new("recipient_conflict");
+            error.message = Some("Share with either an email or a group, not both.".into());
+            Err(error)
+        }
+        Some(_) => Ok(()),
+        None if validate_email(&form.recipient_email) => Ok(()),
+        None => {
+            let mut error = ValidationError::new("email");
+            error.message = Some("Invalid email format".into());
+            Err(error)
+        }
+    }
 }
 
-fn validate_expiration_date(expiration_date: &str) -> Result<(), ValidationError> {
+pub fn validate_expiration_date(expiration_date: &str) -> Result<(), ValidationError> {
     if expiration_date.is_empty() {
         let mut error = ValidationError::new("expiration_date_required");
         error.message = Some("Expiration date is required.".into());
@@ -0,0 +1,123 @@
+use mongodb::bson::{oid::ObjectId, DateTime};
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+use crate::models::{group_model::Group, user_model::User};
+
+#[derive(Validate, Debug, Default, Clone, Serialize, Deserialize)]
+pub struct CreateGroupDto {
+    #[validate(length(min = 1, max = 100, message = "Group name must be 1 to 100 characters"))]
+    pub name: String,
+
+    // Besides the creator, who is always a member
+    #[serde(default)]
+    pub member_emails: Vec<String>,
+}
+
+#[derive(Validate, Debug, Default, Clone, Serialize, Deserialize)]
+pub struct RenameGroupDto {
+    #[validate(length(min = 1, max = 100, message = "Group name must be 1 to 100 characters"))]
+    pub name: String,
+}
+
+#[derive(Validate, Debug, Default, Clone, Serialize, Deserialize)]
+pub struct GroupMemberDto {
+    #[validate(email(message = "Invalid email format"))]
+    pub email: String,
+
+    #[serde(default)]
+    pub admin: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FilteredGroup {
+    pub id: String,
+    pub name: String,
+    pub owner_id: String,
+    pub member_count: usize,
+    // Whether the requesting user manages the members
+    pub admin: bool,
+    pub created_at: DateTime,
+    pub updated_at: DateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FilteredGroupMember {
+    pub id: String,
+    pub name: String,
+    pub email: String,
+    pub admin: bool,
+    pub owner: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GroupListResponse {
+    pub status: String,
+    pub groups: Vec<FilteredGroup>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GroupResponse {
+    pub status: String,
+    pub group: FilteredGroup,
+    pub members: Vec<FilteredGroupMember>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ManageGroupResponse {
+    pub status: i32,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group_id: Option<String>,
+}
+
+impl FilteredGroup {
+    pub fn filter_group(group: &Group, user_id: ObjectId) -> Self {
+        FilteredGroup {
+            id: group._id.to_string(),
+            name: group.name.to_owned(),
+            owner_id: group.owner_id.to_string(),
+            member_count: group.members.len(),
+            admin: group.is_admin(user_id),
+            created_at: group.created_at,
+            updated_at: group.updated_at,
+        }
+    }
+}
+
+impl FilteredGroupMember {
+    pub fn filter_member(group: &Group, member: &User) -> Self {
+        FilteredGroupMember {
+            id: member._id.to_string(),
+            name: member.username.to_owned(),
+            email: member.email.to_owned(),
+            admin: group.is_admin(member._id),
+            owner: group.owner_id == member._id,
+        }
+    }
+}
+
+impl GroupListResponse {
+    pub fn new(groups: Vec<Group>, user_id: ObjectId) -> Self {
+        GroupListResponse {
+            status: 200.to_string(),
+            groups: groups
+                .iter()
+                .map(|group| FilteredGroup::filter_group(group, user_id))
+                .collect(),
+        }
+    }
+}
+
+impl GroupResponse {
+    pub fn new(group: &Group, members: Vec<User>, user_id: ObjectId) -> Self {
+        GroupResponse {
+            status: 200.to_string(),
+            group: FilteredGroup::filter_group(group, user_id),
+            members: members
+                .iter()
+                .map(|member| FilteredGroupMember::filter_member(group, member))
+                .collect(),
+        }
+    }
+}
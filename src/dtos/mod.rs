@@ -1,2 +1,8 @@
+pub mod audit;
 pub mod auth;
+pub mod collection;
 pub mod file;
+pub mod group;
+pub mod notification;
+pub mod organization;
+pub mod webhook;
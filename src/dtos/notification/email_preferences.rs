@@ -0,0 +1,56 @@
+use serde::{Deserialize, Serialize};
+
+use crate::models::email_preferences_model::EmailPreferences;
+
+// Fields left out keep their current setting
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct UpdateEmailPreferencesDto {
+    pub share_received: Option<bool>,
+    pub share_expiring: Option<bool>,
+    pub share_downloaded: Option<bool>,
+    pub share_revoked: Option<bool>,
+    pub share_declined: Option<bool>,
+}
+
+impl UpdateEmailPreferencesDto {
+    pub fn apply(&self, preferences: &mut EmailPreferences) {
+        if let Some(share_received) = self.share_received {
+            preferences.share_received = share_received;
+        }
+        if let Some(share_expiring) = self.share_expiring {
+            preferences.share_expiring = share_expiring;
+        }
+        if let Some(share_downloaded) = self.share_downloaded {
+            preferences.share_downloaded = share_downloaded;
+        }
+        if let Some(share_revoked) = self.share_revoked {
+            preferences.share_revoked = share_revoked;
+        }
+        if let Some(share_declined) = self.share_declined {
+            preferences.share_declined = share_declined;
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EmailPreferencesResponse {
+    pub status: String,
+    pub share_received: bool,
+    pub share_expiring: bool,
+    pub share_downloaded: bool,
+    pub share_revoked: bool,
+    pub share_declined: bool,
+}
+
+impl EmailPreferencesResponse {
+    pub fn new(preferences: &EmailPreferences) -> Self {
+        EmailPreferencesResponse {
+            status: 200.to_string(),
+            share_received: preferences.share_received,
+            share_expiring: preferences.share_expiring,
+            share_downloaded: preferences.share_downloaded,
+            share_revoked: preferences.share_revoked,
+            share_declined: preferences.share_declined,
+        }
+    }
+}
@@ -0,0 +1,2 @@
+pub mod email_preferences;
+pub mod notifications;
@@ -0,0 +1,102 @@
+use actix_web::Error;
+use mongodb::bson::{oid::ObjectId, DateTime};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    dtos::audit::audit_events::parse_id,
+    models::notification_model::{Notification, NotificationKind},
+    services::notifications::{NotificationQuery, DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE},
+};
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct NotificationQueryParams {
+    pub unread: Option<bool>,
+    pub cursor: Option<String>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FilteredNotification {
+    pub id: String,
+    pub kind: NotificationKind,
+    pub actor_id: Option<String>,
+    pub share_id: Option<String>,
+    pub file_id: Option<String>,
+    pub collection_id: Option<String>,
+    pub name: Option<String>,
+    pub read_at: Option<DateTime>,
+    pub created_at: DateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NotificationsResponse {
+    pub status: String,
+    pub notifications: Vec<FilteredNotification>,
+    pub unread_count: u64,
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MarkReadResponse {
+    pub status: i32,
+    pub message: String,
+}
+
+impl NotificationQueryParams {
+    pub fn into_query(self, user_id: ObjectId) -> Result<NotificationQuery, Error> {
+        let before = match self.cursor.as_deref() {
+            Some("") | None => None,
+            Some(cursor) => Some(parse_id(cursor)?),
+        };
+
+        Ok(NotificationQuery {
+            user_id,
+            unread_only: self.unread.unwrap_or_default(),
+            before,
+            limit: self
+                .limit
+                .unwrap_or(DEFAULT_PAGE_SIZE)
+                .clamp(1, MAX_PAGE_SIZE),
+        })
+    }
+}
+
+impl FilteredNotification {
+    pub fn filter_notification(notification: &Notification) -> Self {
+        FilteredNotification {
+            id: notification._id.to_string(),
+            kind: notification.kind,
+            actor_id: notification.actor_id.map(|id| id.to_string()),
+            share_id: notification.share_id.map(|id| id.to_string()),
+            file_id: notification.file_id.map(|id| id.to_string()),
+            collection_id: notification.collection_id.map(|id| id.to_string()),
+            name: notification.name.to_owned(),
+            read_at: notification.read_at,
+            created_at: notification.created_at,
+        }
+    }
+}
+
+impl NotificationsResponse {
+    pub fn new(
+        notifications: Vec<Notification>,
+        unread_count: u64,
+        query: &NotificationQuery,
+    ) -> Self {
+        // A full page may be followed by more
+        let next_cursor = match notifications.last() {
+            Some(last) if notifications.len() == query.limit => Some(last._id.to_string()),
+            _ => None,
+        };
+
+        NotificationsResponse {
+            status: 200.to_string(),
+            notifications: notifications
+                .iter()
+                .map(FilteredNotification::filter_notification)
+                .collect(),
+            unread_count,
+            next_cursor,
+        }
+    }
+}
@@ -0,0 +1,188 @@
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+use validator::{Validate, ValidationError};
+
+use crate::models::{organization_model::Organization, user_model::User};
+
+#[derive(Validate, Debug, Default, Clone, Serialize, Deserialize)]
+pub struct CreateOrganizationDto {
+    #[validate(length(
+        min = 1,
+        max = 100,
+        message = "Organization name must be 1 to 100 characters"
+    ))]
+    pub name: String,
+
+    #[serde(default)]
+    #[validate(custom = "validate_domains")]
+    pub domains: Vec<String>,
+
+    #[validate(range(
+        min = 1,
+        max = 3650,
+        message = "Maximum expiry must be between a day and ten years"
+    ))]
+    pub max_expiry_days: Option<i64>,
+
+    #[validate(range(min = 0, message = "Storage quota can't be negative"))]
+    pub storage_quota: Option<i64>,
+
+    // Existing accounts outside every organization, who join it as its admins
+    #[serde(default)]
+    pub admin_emails: Vec<String>,
+}
+
+// Replaces the name and every policy; policies left out are lifted
+#[derive(Validate, Debug, Default, Clone, Serialize, Deserialize)]
+pub struct UpdateOrganizationDto {
+    #[validate(length(
+        min = 1,
+        max = 100,
+        message = "Organization name must be 1 to 100 characters"
+    ))]
+    pub name: String,
+
+    #[serde(default)]
+    #[validate(custom = "validate_domains")]
+    pub domains: Vec<String>,
+
+    #[validate(range(
+        min = 1,
+        max = 3650,
+        message = "Maximum expiry must be between a day and ten years"
+    ))]
+    pub max_expiry_days: Option<i64>,
+
+    #[validate(range(min = 0, message = "Storage quota can't be negative"))]
+    pub storage_quota: Option<i64>,
+}
+
+#[derive(Validate, Debug, Default, Clone, Serialize, Deserialize)]
+pub struct OrganizationMemberDto {
+    #[validate(email(message = "Invalid email format"))]
+    pub email: String,
+
+    #[serde(default)]
+    pub admin: bool,
+}
+
+// Lowercased, without a leading @ and without duplicates
+pub fn normalize_domains(domains: &[String]) -> Vec<String> {
+    let mut normalized: Vec<String> = Vec::with_capacity(domains.len());
+    for domain in domains {
+        let domain = domain.trim().trim_start_matches('@').to_lowercase();
+        if !normalized.contains(&domain) {
+            normalized.push(domain);
+        }
+    }
+    normalized
+}
+
+pub fn validate_domains(domains: &[String]) -> Result<(), ValidationError> {
+    for domain in normalize_domains(domains) {
+        if domain.is_empty() || domain.contains('@') || !domain.contains('.') {
+            let mut error = ValidationError::new("domain");
+            error.message = Some(format!("Invalid domain: {}", domain).into());
+            return Err(error);
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FilteredOrganization {
+    pub id: String,
+    pub name: String,
+    pub domains: Vec<String>,
+    pub max_expiry_days: Option<i64>,
+    pub storage_quota: Option<i64>,
+    pub admin_count: usize,
+    pub created_at: DateTime,
+    pub updated_at: DateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FilteredOrganizationMember {
+    pub id: String,
+    pub name: String,
+    pub email: String,
+    pub admin: bool,
+    pub storage_used: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OrganizationListResponse {
+    pub status: String,
+    pub organizations: Vec<FilteredOrganization>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OrganizationResponse {
+    pub status: String,
+    pub organization: FilteredOrganization,
+    // Only listed for the organization's admins
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub members: Option<Vec<FilteredOrganizationMember>>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ManageOrganizationResponse {
+    pub status: i32,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub organization_id: Option<String>,
+}
+
+impl FilteredOrganization {
+    pub fn filter_organization(organization: &Organization) -> Self {
+        FilteredOrganization {
+            id: organization._id.to_string(),
+            name: organization.name.to_owned(),
+            domains: organization.domains.clone(),
+            max_expiry_days: organization.max_expiry_days,
+            storage_quota: organization.storage_quota,
+            admin_count: organization.admins.len(),
+            created_at: organization.created_at,
+            updated_at: organization.updated_at,
+        }
+    }
+}
+
+impl FilteredOrganizationMember {
+    pub fn filter_member(organization: &Organization, member: &User) -> Self {
+        FilteredOrganizationMember {
+            id: member._id.to_string(),
+            name: member.username.to_owned(),
+            email: member.email.to_owned(),
+            admin: organization.is_admin(member._id),
+            storage_used: member.storage_used,
+        }
+    }
+}
+
+impl OrganizationListResponse {
+    pub fn new(organizations: Vec<Organization>) -> Self {
+        OrganizationListResponse {
+            status: 200.to_string(),
+            organizations: organizations
+                .iter()
+                .map(FilteredOrganization::filter_organization)
+                .collect(),
+        }
+    }
+}
+
+impl OrganizationResponse {
+    pub fn new(organization: &Organization, members: Option<Vec<User>>) -> Self {
+        OrganizationResponse {
+            status: 200.to_string(),
+            organization: FilteredOrganization::filter_organization(organization),
+            members: members.map(|members| {
+                members
+                    .iter()
+                    .map(|member| FilteredOrganizationMember::filter_member(organization, member))
+                    .collect()
+            }),
+        }
+    }
+}
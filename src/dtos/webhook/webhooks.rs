@@ -0,0 +1,179 @@
+use actix_web::Error;
+use mongodb::bson::{oid::ObjectId, DateTime};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    dtos::audit::audit_events::parse_id,
+    models::webhook_model::{DeliveryStatus, Webhook, WebhookDelivery, WebhookEvent},
+    services::webhooks::{WebhookDeliveryQuery, DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE},
+};
+
+const ALL_EVENTS: [WebhookEvent; 6] = [
+    WebhookEvent::Uploaded,
+    WebhookEvent::Retrieved,
+    WebhookEvent::Deleted,
+    WebhookEvent::Expired,
+    WebhookEvent::Declined,
+    WebhookEvent::Revoked,
+];
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct CreateWebhookDto {
+    pub url: String,
+    // Every event when left out or empty
+    #[serde(default)]
+    pub events: Vec<WebhookEvent>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct WebhookDeliveryParams {
+    pub status: Option<DeliveryStatus>,
+    pub cursor: Option<String>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FilteredWebhook {
+    pub id: String,
+    pub url: String,
+    pub events: Vec<WebhookEvent>,
+    pub created_at: DateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FilteredWebhookDelivery {
+    pub id: String,
+    pub event: WebhookEvent,
+    pub payload: String,
+    pub status: DeliveryStatus,
+    pub attempts: i64,
+    // When a pending delivery is next tried
+    pub next_attempt_at: Option<DateTime>,
+    pub last_attempt_at: Option<DateTime>,
+    pub response_status: Option<i64>,
+    pub last_error: Option<String>,
+    pub delivered_at: Option<DateTime>,
+    pub created_at: DateTime,
+}
+
+// The secret is only ever shown here, when the webhook is created
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateWebhookResponse {
+    pub status: String,
+    pub webhook: FilteredWebhook,
+    pub secret: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WebhooksResponse {
+    pub status: String,
+    pub webhooks: Vec<FilteredWebhook>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WebhookDeliveriesResponse {
+    pub status: String,
+    pub deliveries: Vec<FilteredWebhookDelivery>,
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ManageWebhookResponse {
+    pub status: i32,
+    pub message: String,
+}
+
+impl CreateWebhookDto {
+    pub fn events(&self) -> Vec<WebhookEvent> {
+        if self.events.is_empty() {
+            return ALL_EVENTS.to_vec();
+        }
+        let mut events = Vec::with_capacity(self.events.len());
+        for event in &self.events {
+            if !events.contains(event) {
+                events.push(*event);
+            }
+        }
+        events
+    }
+}
+
+impl WebhookDeliveryParams {
+    pub fn into_query(self, webhook_id: ObjectId) -> Result<WebhookDeliveryQuery, Error> {
+        let before = match self.cursor.as_deref() {
+            Some("") | None => None,
+            Some(cursor) => Some(parse_id(cursor)?),
+        };
+
+        Ok(WebhookDeliveryQuery {
+            webhook_id,
+            status: self.status,
+            before,
+            limit: self
+                .limit
+                .unwrap_or(DEFAULT_PAGE_SIZE)
+                .clamp(1, MAX_PAGE_SIZE),
+        })
+    }
+}
+
+impl FilteredWebhook {
+    pub fn filter_webhook(webhook: &Webhook) -> Self {
+        FilteredWebhook {
+            id: webhook._id.to_string(),
+            url: webhook.url.to_owned(),
+            events: webhook.events.to_owned(),
+            created_at: webhook.created_at,
+        }
+    }
+}
+
+impl FilteredWebhookDelivery {
+    pub fn filter_delivery(delivery: &WebhookDelivery) -> Self {
+        FilteredWebhookDelivery {
+            id: delivery._id.to_string(),
+            event: delivery.event,
+            payload: delivery.payload.to_owned(),
+            status: delivery.status,
+            attempts: delivery.attempts,
+            next_attempt_at: (delivery.status == DeliveryStatus::Pending)
+                .then_some(delivery.next_attempt_at),
+            last_attempt_at: delivery.last_attempt_at,
+            response_status: delivery.response_status,
+            last_error: delivery.last_error.to_owned(),
+            delivered_at: delivery.delivered_at,
+            created_at: delivery.created_at,
+        }
+    }
+}
+
+impl WebhooksResponse {
+    pub fn new(webhooks: Vec<Webhook>) -> Self {
+        WebhooksResponse {
+            status: 200.to_string(),
+            webhooks: webhooks
+                .iter()
+                .map(FilteredWebhook::filter_webhook)
+                .collect(),
+        }
+    }
+}
+
+impl WebhookDeliveriesResponse {
+    pub fn new(deliveries: Vec<WebhookDelivery>, query: &WebhookDeliveryQuery) -> Self {
+        // A full page may be followed by more
+        let next_cursor = match deliveries.last() {
+            Some(last) if deliveries.len() == query.limit => Some(last._id.to_string()),
+            _ => None,
+        };
+
+        WebhookDeliveriesResponse {
+            status: 200.to_string(),
+            deliveries: deliveries
+                .iter()
+                .map(FilteredWebhookDelivery::filter_delivery)
+                .collect(),
+            next_cursor,
+        }
+    }
+}
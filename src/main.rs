@@ -1,4 +1,4 @@
-use std::str::FromStr;
+use std::{str::FromStr, sync::Arc};
 
 use actix_cors::Cors;
 use actix_web::{
@@ -8,12 +8,24 @@ use actix_web::{
 };
 use actix_web_httpauth::middleware::HttpAuthentication;
 use chrono::Local;
-use config::Config;
-use controllers::{auth_controller, file_controller, user_controller};
+use config::{Config, DatabaseBackend, MailerBackend, ScannerBackend};
+use controllers::{
+    admin_controller, auth_controller, collection_controller, file_controller, group_controller,
+    notification_controller, organization_controller, user_controller, webhook_controller,
+};
 use cron::Schedule;
 use dotenv::dotenv;
 use middleware::validator;
-use services::db::Database;
+use services::{
+    audit,
+    db::Database,
+    mailer::{FileMailer, Mailer, NoopMailer, SmtpMailer},
+    mongo::MongoDatabase,
+    notifications::{self, Notifier},
+    scanner::{ClamdScanner, NoopScanner, Scanner},
+    sql::SqlDatabase,
+    webhooks,
+};
 use tokio::time;
 
 mod config;
@@ -29,12 +41,49 @@ async fn main() -> std::io::Result<()> {
     dotenv().ok(); // Load the .env file
     let config = Config::init();
     let config_data = Data::new(config);
-    let db = Database::init(config_data.database_url.clone().to_string()).await;
-    let db_data = Data::new(db);
+    let db: Arc<dyn Database> = match config_data.database_backend {
+        DatabaseBackend::Mongo => {
+            Arc::new(MongoDatabase::init(config_data.database_url.clone()).await)
+        }
+        DatabaseBackend::Sqlite | DatabaseBackend::Postgres => Arc::new(
+            SqlDatabase::init(
+                config_data.database_url.clone(),
+                config_data.database_backend,
+            )
+            .await,
+        ),
+    };
+    let db_data: Data<dyn Database> = Data::from(db);
+
+    // `server verify-audit` checks the audit chain and exits instead of serving
+    if std::env::args().nth(1).as_deref() == Some("verify-audit") {
+        return verify_audit(db_data.get_ref()).await;
+    }
+
+    let scanner: Arc<dyn Scanner> = match &config_data.scanner {
+        ScannerBackend::Disabled => Arc::new(NoopScanner),
+        ScannerBackend::Clamd(address) => Arc::new(ClamdScanner::new(address.clone())),
+    };
+    let scanner_data: Data<dyn Scanner> = Data::from(scanner);
+    let mailer: Arc<dyn Mailer> = match &config_data.mailer {
+        MailerBackend::Disabled => Arc::new(NoopMailer),
+        MailerBackend::Smtp(url) => Arc::new(
+            SmtpMailer::new(url, &config_data.mail_from).expect("Failed to configure SMTP mailer"),
+        ),
+        MailerBackend::File(dir) => Arc::new(
+            FileMailer::new(dir, &config_data.mail_from).expect("Failed to configure file mailer"),
+        ),
+    };
+    let notifier_data = Data::new(Notifier::new(mailer));
     let port = config_data.port.clone().to_string();
     let db_data_for_cron = db_data.clone();
+    let db_data_for_webhooks = db_data.clone();
+    let notifier_data_for_cron = notifier_data.clone();
+    tokio::spawn(async move {
+        start_cron_jobs(db_data_for_cron, notifier_data_for_cron).await;
+    });
     tokio::spawn(async move {
-        start_cron_jobs(db_data_for_cron).await;
+        webhooks::run_delivery_worker(db_data_for_webhooks).await;
     });
     let addr = format!("0.0.0.0:{}",port);
     HttpServer::new(move || {
@@ -51,10 +100,13 @@ async fn main() -> std::io::Result<()> {
             .wrap(cors)
             .app_data(db_data.clone())
             .app_data(config_data.clone())
+            .app_data(scanner_data.clone())
+            .app_data(notifier_data.clone())
             .configure(auth_controller::init)
             .service(
                 web::scope("/user")
                     .wrap(auth.clone())
+                    .service(web::scope("/groups").configure(group_controller::init))
                     .configure(user_controller::init),
             )
             .service(
@@ -62,13 +114,38 @@ async fn main() -> std::io::Result<()> {
                     .wrap(auth.clone())
                     .configure(file_controller::init),
             )
+            .service(
+                web::scope("/collection")
+                    .wrap(auth.clone())
+                    .configure(collection_controller::init),
+            )
+            .service(
+                web::scope("/admin")
+                    .wrap(auth.clone())
+                    .configure(admin_controller::init),
+            )
+            .service(
+                web::scope("/notifications")
+                    .wrap(auth.clone())
+                    .configure(notification_controller::init),
+            )
+            .service(
+                web::scope("/webhooks")
+                    .wrap(auth.clone())
+                    .configure(webhook_controller::init),
+            )
+            .service(
+                web::scope("/organizations")
+                    .wrap(auth.clone())
+                    .configure(organization_controller::init),
+            )
     })
     .bind(addr)?
     .run()
     .await
 }
 
-async fn start_cron_jobs(db_client: Data<Database>) {
+async fn start_cron_jobs(db_client: Data<dyn Database>, notifier: Data<Notifier>) {
     // Schedule a cron job to run every day at midnight
     let schedule = Schedule::from_str("0 0 * * * *").unwrap();
     let mut next = schedule.upcoming(Local);
@@ -83,12 +160,66 @@ async fn start_cron_jobs(db_client: Data<Database>) {
 
             // Run the job
             println!("Running scheduled task to delete expired files...");
-            if let Err(err) = db_client.delete_expired_files().await {
-                eprintln!("Error deleting expired files: {:?}", err);
-            } else {
+            // actix errors are not Send, so none may be held across an await
+            let expired_shares = db_client
+                .delete_expired_files()
+                .await
+                .map_err(|err| eprintln!("Error deleting expired files: {:?}", err))
+                .ok();
+            if let Some(expired_shares) = expired_shares {
                 println!("Successfully deleted expired files.");
+                webhooks::dispatch(db_client.get_ref(), webhooks::expired_events(expired_shares))
+                    .await;
+            }
+
+            println!("Running scheduled task to reconcile orphaned files and shares...");
+            if let Err(err) = db_client.reconcile().await {
+                eprintln!("Error reconciling files and shares: {:?}", err);
+            }
+
+            println!("Running scheduled task to notify recipients of expiring shares...");
+            if let Err(err) =
+                notifications::notify_expiring_shares(db_client.get_ref(), &notifier).await
+            {
+                eprintln!("Error notifying recipients of expiring shares: {:?}", err);
+            }
+
+            println!("Running scheduled task to checkpoint the audit chain...");
+            // actix errors are not Send, so none may be held across an await
+            let signing_key = utils::keys::load_audit_signing_key()
+                .map_err(|err| eprintln!("Error loading the audit signing key: {:?}", err))
+                .ok();
+            if let Some(signing_key) = signing_key {
+                if let Err(err) = audit::checkpoint(db_client.get_ref(), &signing_key).await {
+                    eprintln!("Error checkpointing the audit chain: {:?}", err);
+                }
             }
             next = schedule.upcoming(Local); // Update the next schedule
         }
     }
 }
+
+async fn verify_audit(db: &dyn Database) -> std::io::Result<()> {
+    let verifying_key = utils::keys::load_audit_signing_key()
+        .map_err(|e| std::io::Error::other(e.to_string()))?
+        .verifying_key();
+    let verification = audit::verify_chain(db, &verifying_key)
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+
+    println!(
+        "Checked {} audit events and {} checkpoints up to event {}",
+        verification.events_checked,
+        verification.checkpoints_checked,
+        verification.head_sequence
+    );
+    for problem in &verification.problems {
+        println!("  {}", problem);
+    }
+    if !verification.valid {
+        return Err(std::io::Error::other("Audit chain verification failed"));
+    }
+    println!("Audit chain is intact");
+
+    Ok(())
+}
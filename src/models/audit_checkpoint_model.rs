@@ -0,0 +1,14 @@
+use mongodb::bson::{oid::ObjectId, DateTime};
+use serde::{Deserialize, Serialize};
+
+// Server-signed statement of the audit chain's head at a point in time. An
+// attacker who rewrites the chain cannot produce matching checkpoints
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditCheckpoint {
+    pub _id: ObjectId,
+    pub sequence: i64,
+    pub hash: String,
+    // Ed25519 signature over the sequence, hash and creation time
+    pub signature: Vec<u8>,
+    pub created_at: DateTime,
+}
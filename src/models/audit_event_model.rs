@@ -0,0 +1,89 @@
+use mongodb::bson::{oid::ObjectId, DateTime};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    Login,
+    LoginFailed,
+    Upload,
+    Download,
+    SharePasswordFailed,
+    Delete,
+    Expire,
+    InvitationAccepted,
+    GroupShareGranted,
+    ShareGranted,
+    OrganizationJoined,
+    OrganizationLeft,
+    AdminAuditQuery,
+    AdminAuditVerify,
+}
+
+impl AuditAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::Login => "login",
+            AuditAction::LoginFailed => "login_failed",
+            AuditAction::Upload => "upload",
+            AuditAction::Download => "download",
+            AuditAction::SharePasswordFailed => "share_password_failed",
+            AuditAction::Delete => "delete",
+            AuditAction::Expire => "expire",
+            AuditAction::InvitationAccepted => "invitation_accepted",
+            AuditAction::GroupShareGranted => "group_share_granted",
+            AuditAction::ShareGranted => "share_granted",
+            AuditAction::OrganizationJoined => "organization_joined",
+            AuditAction::OrganizationLeft => "organization_left",
+            AuditAction::AdminAuditQuery => "admin_audit_query",
+            AuditAction::AdminAuditVerify => "admin_audit_verify",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "login" => Some(AuditAction::Login),
+            "login_failed" => Some(AuditAction::LoginFailed),
+            "upload" => Some(AuditAction::Upload),
+            "download" => Some(AuditAction::Download),
+            "share_password_failed" => Some(AuditAction::SharePasswordFailed),
+            "delete" => Some(AuditAction::Delete),
+            "expire" => Some(AuditAction::Expire),
+            "invitation_accepted" => Some(AuditAction::InvitationAccepted),
+            "group_share_granted" => Some(AuditAction::GroupShareGranted),
+            "share_granted" => Some(AuditAction::ShareGranted),
+            "organization_joined" => Some(AuditAction::OrganizationJoined),
+            "organization_left" => Some(AuditAction::OrganizationLeft),
+            "admin_audit_query" => Some(AuditAction::AdminAuditQuery),
+            "admin_audit_verify" => Some(AuditAction::AdminAuditVerify),
+            _ => None,
+        }
+    }
+}
+
+// Append-only record of a security-relevant event; never updated or deleted.
+// Events form a hash chain so edits and deletions can be detected
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEvent {
+    pub _id: ObjectId,
+    // Position in the chain, from 1; 0 for events recorded before chaining
+    #[serde(default)]
+    pub sequence: i64,
+    #[serde(default)]
+    pub prev_hash: String,
+    #[serde(default)]
+    pub hash: String,
+    pub action: AuditAction,
+    // None for events the server raises itself, such as expirations
+    pub actor_id: Option<ObjectId>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    // Account the event concerns besides the actor, e.g. a share's recipient
+    // or the owner of an account someone failed to log in to
+    pub subject_user_id: Option<ObjectId>,
+    pub file_id: Option<ObjectId>,
+    pub share_id: Option<ObjectId>,
+    pub collection_id: Option<ObjectId>,
+    pub detail: Option<String>,
+    pub created_at: DateTime,
+}
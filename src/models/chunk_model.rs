@@ -0,0 +1,13 @@
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+
+// A deduplicated piece of file content, shared by every file and version that
+// lists its id and deleted once the last of them is gone
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Chunk {
+    // Hex digest derived from the chunk's keyed hash
+    pub _id: String,
+    pub data: Vec<u8>,
+    pub refcount: i64,
+    pub created_at: DateTime,
+}
@@ -0,0 +1,11 @@
+use mongodb::bson::{oid::ObjectId, DateTime};
+use serde::{Deserialize, Serialize};
+
+// Groups files uploaded together; each file still has its own share links
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Collection {
+    pub _id: ObjectId,
+    pub user_id: ObjectId,
+    pub name: String,
+    pub created_at: DateTime,
+}
@@ -0,0 +1,48 @@
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+use crate::models::notification_model::NotificationKind;
+
+// Which notifications a user also receives by email; all of them unless
+// turned off
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmailPreferences {
+    // The user's id
+    pub _id: ObjectId,
+    pub share_received: bool,
+    pub share_expiring: bool,
+    pub share_downloaded: bool,
+    pub share_revoked: bool,
+    // Added after the others, so stored preferences may lack it
+    #[serde(default = "enabled")]
+    pub share_declined: bool,
+}
+
+fn enabled() -> bool {
+    true
+}
+
+impl EmailPreferences {
+    pub fn new(user_id: ObjectId) -> Self {
+        EmailPreferences {
+            _id: user_id,
+            share_received: true,
+            share_expiring: true,
+            share_downloaded: true,
+            share_revoked: true,
+            share_declined: true,
+        }
+    }
+
+    pub fn allows(&self, kind: NotificationKind) -> bool {
+        match kind {
+            NotificationKind::Received => self.share_received,
+            NotificationKind::ExpiringSoon => self.share_expiring,
+            NotificationKind::Downloaded => self.share_downloaded,
+            NotificationKind::Revoked => self.share_revoked,
+            NotificationKind::Declined => self.share_declined,
+            // Security notices cannot be turned off
+            NotificationKind::Quarantined => true,
+        }
+    }
+}
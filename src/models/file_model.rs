@@ -1,15 +1,89 @@
 use mongodb::bson::{oid::ObjectId, DateTime};
 use serde::{Deserialize, Serialize};
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScanStatus {
+    // No scanner was configured, or the file predates scanning
+    #[default]
+    NotScanned,
+    Clean,
+    // Kept for the sender but never delivered to recipients
+    Quarantined,
+}
+
+impl ScanStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ScanStatus::NotScanned => "not_scanned",
+            ScanStatus::Clean => "clean",
+            ScanStatus::Quarantined => "quarantined",
+        }
+    }
+
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "clean" => ScanStatus::Clean,
+            "quarantined" => ScanStatus::Quarantined,
+            _ => ScanStatus::NotScanned,
+        }
+    }
+}
+
+// Codec applied to the plaintext before encryption
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Compression {
+    #[default]
+    None,
+    Zstd,
+}
+
+impl Compression {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Compression::None => "none",
+            Compression::Zstd => "zstd",
+        }
+    }
+
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "zstd" => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct File {
     pub _id: ObjectId,
     pub user_id: ObjectId,
     pub file_name: String,
     pub file_size: i64,
+    // Sniffed at upload; absent on files uploaded before detection
+    pub content_type: Option<String>,
     pub encrypted_aes_key: Vec<u8>,
     pub encrypted_file: Vec<u8>,
     pub iv: Vec<u8>,
+    // Hex SHA-256 of the plaintext; absent on files uploaded before checksums
+    pub sha256: Option<String>,
     pub created_at: DateTime,
     pub updated_at: DateTime,
+    pub collection_id: Option<ObjectId>,
+    pub version: i64,
+    #[serde(default)]
+    pub scan_status: ScanStatus,
+    #[serde(default)]
+    pub compression: Compression,
+    // Deduplicated content: encrypted_file then holds the chunk keys, not the content
+    #[serde(default)]
+    pub chunk_ids: Vec<String>,
+    // The sender's organization at upload
+    #[serde(default)]
+    pub organization_id: Option<ObjectId>,
+    // Bytes of the superseded revisions kept in the version history, which
+    // count towards the owner's storage like the current one
+    #[serde(default)]
+    pub history_size: i64,
 }
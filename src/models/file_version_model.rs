@@ -0,0 +1,34 @@
+use mongodb::bson::{oid::ObjectId, DateTime};
+use serde::{Deserialize, Serialize};
+
+use crate::models::file_model::Compression;
+
+// A superseded revision of a file, encrypted under the file's AES key
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FileVersion {
+    pub _id: ObjectId,
+    pub file_id: ObjectId,
+    pub version: i64,
+    pub file_name: String,
+    pub file_size: i64,
+    pub content_type: Option<String>,
+    pub encrypted_file: Vec<u8>,
+    pub iv: Vec<u8>,
+    pub sha256: Option<String>,
+    pub created_at: DateTime,
+    #[serde(default)]
+    pub compression: Compression,
+    #[serde(default)]
+    pub chunk_ids: Vec<String>,
+}
+
+// A revision without its encrypted payload
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FileVersionSummary {
+    pub version: i64,
+    pub file_name: String,
+    pub file_size: i64,
+    pub content_type: Option<String>,
+    pub sha256: Option<String>,
+    pub created_at: DateTime,
+}
@@ -0,0 +1,42 @@
+use mongodb::bson::{oid::ObjectId, DateTime};
+use serde::{Deserialize, Serialize};
+
+// Users shared with together under one name. The owner is always a member and
+// an admin; admins manage the members and the owner manages the admins
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Group {
+    pub _id: ObjectId,
+    pub name: String,
+    pub owner_id: ObjectId,
+    pub members: Vec<ObjectId>,
+    // A subset of members
+    pub admins: Vec<ObjectId>,
+    pub created_at: DateTime,
+    pub updated_at: DateTime,
+}
+
+impl Group {
+    pub fn is_member(&self, user_id: ObjectId) -> bool {
+        self.members.contains(&user_id)
+    }
+
+    pub fn is_admin(&self, user_id: ObjectId) -> bool {
+        self.admins.contains(&user_id)
+    }
+}
+
+// A file shared with a group that also goes to members who join while it is
+// live. Members at send time get their share links straight away
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupShare {
+    pub _id: ObjectId,
+    pub group_id: ObjectId,
+    pub file_id: ObjectId,
+    pub sender_id: ObjectId,
+    // Hash of the share password, carried over to every share link
+    pub password: String,
+    pub expires_at: DateTime,
+    // Later members' share links are signed like the ones made at send time
+    pub sign: bool,
+    pub created_at: DateTime,
+}
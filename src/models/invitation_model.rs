@@ -0,0 +1,23 @@
+use mongodb::bson::{oid::ObjectId, DateTime};
+use serde::{Deserialize, Serialize};
+
+// A share with an email address that has no account yet. Until someone
+// registers with it, the file's AES key is wrapped to the server's invitation
+// key; registering turns the invitation into an ordinary share link
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Invitation {
+    pub _id: ObjectId,
+    // Lowercased, and matched against the email the invitee registers with
+    pub email: String,
+    pub file_id: ObjectId,
+    pub sender_id: ObjectId,
+    // Kept so invitations list without loading the file
+    pub file_name: String,
+    // Hash of the share password, carried over to the share link
+    pub password: String,
+    pub expires_at: DateTime,
+    // The sender asked to sign the upload; the signature covers the
+    // recipient's id, so it is made once they have one
+    pub sign: bool,
+    pub created_at: DateTime,
+}
@@ -0,0 +1,10 @@
+use mongodb::bson::{oid::ObjectId, DateTime};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Migration {
+    pub _id: ObjectId,
+    pub version: i64,
+    pub description: String,
+    pub applied_at: DateTime,
+}
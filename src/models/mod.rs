@@ -1,3 +1,17 @@
+pub mod audit_checkpoint_model;
+pub mod audit_event_model;
+pub mod chunk_model;
+pub mod collection_model;
+pub mod email_preferences_model;
 pub mod file_model;
+pub mod file_version_model;
+pub mod group_model;
+pub mod invitation_model;
+pub mod migration_model;
+pub mod notification_model;
+pub mod organization_model;
 pub mod share_link_model;
+pub mod shared_collection_model;
+pub mod shared_file_model;
 pub mod user_model;
+pub mod webhook_model;
@@ -0,0 +1,86 @@
+use mongodb::bson::{oid::ObjectId, DateTime};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NotificationKind {
+    #[serde(rename = "share.received")]
+    Received,
+    #[serde(rename = "share.downloaded")]
+    Downloaded,
+    #[serde(rename = "share.expiring_soon")]
+    ExpiringSoon,
+    #[serde(rename = "share.revoked")]
+    Revoked,
+    #[serde(rename = "share.declined")]
+    Declined,
+    // An upload failed its malware scan; only its sender is told
+    #[serde(rename = "file.quarantined")]
+    Quarantined,
+}
+
+impl NotificationKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NotificationKind::Received => "share.received",
+            NotificationKind::Downloaded => "share.downloaded",
+            NotificationKind::ExpiringSoon => "share.expiring_soon",
+            NotificationKind::Revoked => "share.revoked",
+            NotificationKind::Declined => "share.declined",
+            NotificationKind::Quarantined => "file.quarantined",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "share.received" => Some(NotificationKind::Received),
+            "share.downloaded" => Some(NotificationKind::Downloaded),
+            "share.expiring_soon" => Some(NotificationKind::ExpiringSoon),
+            "share.revoked" => Some(NotificationKind::Revoked),
+            "share.declined" => Some(NotificationKind::Declined),
+            "file.quarantined" => Some(NotificationKind::Quarantined),
+            _ => None,
+        }
+    }
+}
+
+// An entry in a user's inbox, also pushed to their open event streams
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Notification {
+    pub _id: ObjectId,
+    // The user the notification is for
+    pub user_id: ObjectId,
+    pub kind: NotificationKind,
+    // The user whose action raised it, if any
+    pub actor_id: Option<ObjectId>,
+    pub share_id: Option<ObjectId>,
+    pub file_id: Option<ObjectId>,
+    pub collection_id: Option<ObjectId>,
+    // Name of the file or collection, so the inbox reads without further lookups
+    pub name: Option<String>,
+    pub read_at: Option<DateTime>,
+    pub created_at: DateTime,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kinds_parse_back_from_their_names() {
+        for kind in [
+            NotificationKind::Received,
+            NotificationKind::Downloaded,
+            NotificationKind::ExpiringSoon,
+            NotificationKind::Revoked,
+            NotificationKind::Declined,
+            NotificationKind::Quarantined,
+        ] {
+            assert_eq!(NotificationKind::parse(kind.as_str()), Some(kind));
+            assert_eq!(
+                serde_json::to_value(kind).unwrap(),
+                serde_json::json!(kind.as_str())
+            );
+        }
+        assert_eq!(NotificationKind::parse("share.unknown"), None);
+    }
+}
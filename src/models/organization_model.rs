@@ -0,0 +1,42 @@
+use mongodb::bson::{oid::ObjectId, DateTime};
+use serde::{Deserialize, Serialize};
+
+// A client company hosted on the server. Its members only find and share with
+// each other, under the organization's policies; users outside every
+// organization form a tenant of their own
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Organization {
+    pub _id: ObjectId,
+    pub name: String,
+    // Lowercased email domains its members may have. Registering with one of
+    // them joins the organization; empty allows any domain and captures none
+    pub domains: Vec<String>,
+    // Longest a share may last, in days
+    pub max_expiry_days: Option<i64>,
+    // Bytes of active files each member may hold, instead of the server default
+    pub storage_quota: Option<i64>,
+    // Members who manage the membership
+    pub admins: Vec<ObjectId>,
+    pub created_at: DateTime,
+    pub updated_at: DateTime,
+}
+
+impl Organization {
+    pub fn is_admin(&self, user_id: ObjectId) -> bool {
+        self.admins.contains(&user_id)
+    }
+
+    pub fn allows_email(&self, email: &str) -> bool {
+        self.domains.is_empty() || self.domains.contains(&email_domain(email))
+    }
+}
+
+// The part after the last @, lowercased
+pub fn email_domain(email: &str) -> String {
+    email
+        .rsplit_once('@')
+        .map(|(_, domain)| domain)
+        .unwrap_or_default()
+        .trim()
+        .to_lowercase()
+}
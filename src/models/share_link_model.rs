@@ -4,9 +4,49 @@ use serde::{Deserialize, Serialize};
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ShareLink {
     pub _id: ObjectId,
-    pub reciepents_user_id: ObjectId,
+    pub recipient_user_id: ObjectId,
     pub file_id: ObjectId,
     pub password: String,
     pub expires_at: DateTime,
     pub created_at: DateTime,
+    // AES key wrapped for this recipient; shares created at upload use the file's key
+    pub encrypted_aes_key: Option<Vec<u8>>,
+    pub revoked_at: Option<DateTime>,
+    pub declined_at: Option<DateTime>,
+    // Set by the recipient to hide the share from their default listing
+    pub archived_at: Option<DateTime>,
+    #[serde(default)]
+    pub can_reshare: bool,
+    // The share this one was re-shared from; revoking it revokes this one too
+    pub parent_share_id: Option<ObjectId>,
+    // Owner's Ed25519 signature over the file hash, file name and recipient
+    pub signature: Option<Vec<u8>>,
+    // Access receipts: the recipient's successful opens and wrong passwords
+    pub first_opened_at: Option<DateTime>,
+    pub last_opened_at: Option<DateTime>,
+    #[serde(default)]
+    pub open_count: i64,
+    #[serde(default)]
+    pub failed_password_attempts: i64,
+    // The file's organization; leaving it revokes the share
+    #[serde(default)]
+    pub organization_id: Option<ObjectId>,
+}
+
+// A share revoked by its sender, or along with the share it was re-shared from
+#[derive(Debug, Clone)]
+pub struct RevokedShare {
+    pub share_id: ObjectId,
+    pub recipient_user_id: ObjectId,
+}
+
+// A share removed once it expired, with its file's details from before the
+// file itself may have gone with it
+#[derive(Debug, Clone)]
+pub struct ExpiredShare {
+    pub share_id: ObjectId,
+    pub file_id: ObjectId,
+    pub recipient_user_id: ObjectId,
+    pub owner_id: Option<ObjectId>,
+    pub file_name: Option<String>,
 }
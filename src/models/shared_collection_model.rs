@@ -0,0 +1,14 @@
+use mongodb::bson::{oid::ObjectId, DateTime};
+use serde::{Deserialize, Serialize};
+
+// A collection with its files' share links rolled up per counterpart
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SharedCollection {
+    pub _id: ObjectId,
+    pub name: String,
+    pub file_count: i64,
+    pub total_size: i64,
+    pub created_at: DateTime,
+    pub expires_at: DateTime,
+    pub counterpart_email: String,
+}
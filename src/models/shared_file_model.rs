@@ -0,0 +1,35 @@
+use mongodb::bson::{oid::ObjectId, DateTime};
+use serde::{Deserialize, Serialize};
+
+use crate::models::file_model::ScanStatus;
+
+// A file joined with its share link and the user on the other side of it,
+// without the encrypted payload
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SharedFile {
+    pub _id: ObjectId,
+    pub file_name: String,
+    pub file_size: i64,
+    pub content_type: Option<String>,
+    pub version: i64,
+    pub sha256: Option<String>,
+    #[serde(default)]
+    pub scan_status: ScanStatus,
+    pub created_at: DateTime,
+    pub expires_at: DateTime,
+    pub revoked_at: Option<DateTime>,
+    pub declined_at: Option<DateTime>,
+    pub archived_at: Option<DateTime>,
+    #[serde(default)]
+    pub can_reshare: bool,
+    pub parent_share_id: Option<ObjectId>,
+    pub signature: Option<Vec<u8>>,
+    pub first_opened_at: Option<DateTime>,
+    pub last_opened_at: Option<DateTime>,
+    #[serde(default)]
+    pub open_count: i64,
+    #[serde(default)]
+    pub failed_password_attempts: i64,
+    pub share_id: ObjectId,
+    pub counterpart_email: String,
+}
@@ -8,6 +8,20 @@ pub struct User {
     pub email: String,
     pub password: String,
     pub public_key: String,
+    // Total file_size of the user's active files
+    #[serde(default)]
+    pub storage_used: i64,
+    // None outside every organization
+    #[serde(default)]
+    pub organization_id: Option<ObjectId>,
+    // None until the user proves they own the email
+    #[serde(default)]
+    pub email_verified_at: Option<DateTime>,
+    // SHA-256 of the outstanding verification token, never the token itself
+    #[serde(default)]
+    pub email_verification_token: Option<String>,
+    #[serde(default)]
+    pub email_verification_expires_at: Option<DateTime>,
     pub created_at: DateTime,
     pub updated_at: DateTime,
 }
@@ -0,0 +1,104 @@
+use mongodb::bson::{oid::ObjectId, DateTime};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WebhookEvent {
+    #[serde(rename = "file.uploaded")]
+    Uploaded,
+    #[serde(rename = "file.retrieved")]
+    Retrieved,
+    #[serde(rename = "file.deleted")]
+    Deleted,
+    #[serde(rename = "file.expired")]
+    Expired,
+    #[serde(rename = "file.declined")]
+    Declined,
+    #[serde(rename = "file.revoked")]
+    Revoked,
+}
+
+impl WebhookEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookEvent::Uploaded => "file.uploaded",
+            WebhookEvent::Retrieved => "file.retrieved",
+            WebhookEvent::Deleted => "file.deleted",
+            WebhookEvent::Expired => "file.expired",
+            WebhookEvent::Declined => "file.declined",
+            WebhookEvent::Revoked => "file.revoked",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "file.uploaded" => Some(WebhookEvent::Uploaded),
+            "file.retrieved" => Some(WebhookEvent::Retrieved),
+            "file.deleted" => Some(WebhookEvent::Deleted),
+            "file.expired" => Some(WebhookEvent::Expired),
+            "file.declined" => Some(WebhookEvent::Declined),
+            "file.revoked" => Some(WebhookEvent::Revoked),
+            _ => None,
+        }
+    }
+}
+
+// An endpoint that is sent the events it subscribes to. A user's webhook gets
+// the events of files they sent or received; an admin's gets everyone's
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Webhook {
+    pub _id: ObjectId,
+    // None for webhooks registered by an admin
+    pub user_id: Option<ObjectId>,
+    pub url: String,
+    // Key of the HMAC-SHA256 signature sent with every delivery
+    pub secret: String,
+    pub events: Vec<WebhookEvent>,
+    pub created_at: DateTime,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryStatus {
+    Pending,
+    Delivered,
+    // Out of attempts; kept as a dead letter until retried by hand
+    Dead,
+}
+
+impl DeliveryStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryStatus::Pending => "pending",
+            DeliveryStatus::Delivered => "delivered",
+            DeliveryStatus::Dead => "dead",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "pending" => Some(DeliveryStatus::Pending),
+            "delivered" => Some(DeliveryStatus::Delivered),
+            "dead" => Some(DeliveryStatus::Dead),
+            _ => None,
+        }
+    }
+}
+
+// One event on its way to one webhook, and the outcome of its last attempt
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookDelivery {
+    pub _id: ObjectId,
+    pub webhook_id: ObjectId,
+    pub event: WebhookEvent,
+    // The JSON body, fixed when the event happens so retries send the same one
+    pub payload: String,
+    pub status: DeliveryStatus,
+    pub attempts: i64,
+    pub next_attempt_at: DateTime,
+    pub last_attempt_at: Option<DateTime>,
+    // HTTP status of the last response, if one came back
+    pub response_status: Option<i64>,
+    pub last_error: Option<String>,
+    pub delivered_at: Option<DateTime>,
+    pub created_at: DateTime,
+}
@@ -0,0 +1,292 @@
+use std::collections::HashMap;
+
+use actix_web::{http::header, Error, HttpRequest};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use mongodb::bson::{oid::ObjectId, DateTime};
+use serde::Serialize;
+
+use crate::{
+    models::{
+        audit_checkpoint_model::AuditCheckpoint,
+        audit_event_model::{AuditAction, AuditEvent},
+    },
+    services::db::Database,
+    utils::file::integrity::sha256_hex,
+};
+
+pub const DEFAULT_PAGE_SIZE: usize = 50;
+pub const MAX_PAGE_SIZE: usize = 500;
+
+// What the first event in the chain points back to
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+const VERIFY_BATCH_SIZE: usize = 1000;
+const MAX_REPORTED_PROBLEMS: usize = 100;
+
+#[derive(Debug, Serialize)]
+pub struct AuditVerification {
+    pub valid: bool,
+    pub events_checked: u64,
+    pub checkpoints_checked: u64,
+    pub head_sequence: i64,
+    // The first problems found, in chain order
+    pub problems: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AuditQuery {
+    // Events the user took part in, as actor or subject; None means everyone's
+    pub user_id: Option<ObjectId>,
+    pub share_id: Option<ObjectId>,
+    // Any of these actions; empty means every action
+    pub actions: Vec<AuditAction>,
+    // Id of the last event of the previous page, newest first
+    pub before: Option<ObjectId>,
+    pub limit: usize,
+}
+
+// An event raised by the server itself, with no request behind it
+pub fn system_event(action: AuditAction) -> AuditEvent {
+    AuditEvent {
+        _id: ObjectId::new(),
+        sequence: 0,
+        prev_hash: String::new(),
+        hash: String::new(),
+        action,
+        actor_id: None,
+        ip: None,
+        user_agent: None,
+        subject_user_id: None,
+        file_id: None,
+        share_id: None,
+        collection_id: None,
+        detail: None,
+        created_at: DateTime::now(),
+    }
+}
+
+// An event raised by a request, carrying its peer address and user agent.
+// The peer address is used rather than forwarding headers, which clients control
+pub fn request_event(action: AuditAction, req: &HttpRequest) -> AuditEvent {
+    AuditEvent {
+        ip: req.peer_addr().map(|addr| addr.ip().to_string()),
+        user_agent: req
+            .headers()
+            .get(header::USER_AGENT)
+            .and_then(|agent| agent.to_str().ok())
+            .map(str::to_string),
+        ..system_event(action)
+    }
+}
+
+// Auditing never fails the request it describes; a lost event is logged instead
+pub async fn record(db: &dyn Database, event: AuditEvent) {
+    let action = event.action;
+    if let Err(e) = db.record_audit_event(event).await {
+        eprintln!("Failed to record {} audit event: {}", action.as_str(), e);
+    }
+}
+
+// Appends the event after the chain's current head, given as its sequence and
+// hash, or 0 and GENESIS_HASH for an empty chain
+pub fn chain_event(event: &mut AuditEvent, head_sequence: i64, head_hash: &str) {
+    event.sequence = head_sequence + 1;
+    event.prev_hash = head_hash.to_string();
+    event.hash = event_hash(event);
+}
+
+// Covers every field but the hash itself, serialised as a JSON array so no
+// field can run into the next
+pub fn event_hash(event: &AuditEvent) -> String {
+    let hex = |id: Option<ObjectId>| id.map(|id| id.to_hex());
+    let fields = (
+        event.sequence,
+        &event.prev_hash,
+        event._id.to_hex(),
+        event.action.as_str(),
+        hex(event.actor_id),
+        &event.ip,
+        &event.user_agent,
+        hex(event.subject_user_id),
+        hex(event.file_id),
+        hex(event.share_id),
+        hex(event.collection_id),
+        &event.detail,
+        event.created_at.timestamp_millis(),
+    );
+    sha256_hex(&serde_json::to_vec(&fields).unwrap_or_default())
+}
+
+fn missing_events(first: i64, last: i64) -> String {
+    if first == last {
+        format!("Event {} is missing", first)
+    } else {
+        format!("Events {} to {} are missing", first, last)
+    }
+}
+
+fn checkpoint_message(sequence: i64, hash: &str, created_at: DateTime) -> Vec<u8> {
+    serde_json::to_vec(&(sequence, hash, created_at.timestamp_millis())).unwrap_or_default()
+}
+
+// Signs the chain head unless the latest checkpoint already covers it
+pub async fn checkpoint(
+    db: &dyn Database,
+    signing_key: &SigningKey,
+) -> Result<Option<AuditCheckpoint>, Error> {
+    let head = match db.get_audit_head().await? {
+        Some(head) => head,
+        None => return Ok(None),
+    };
+    let covered = db
+        .get_audit_checkpoints()
+        .await?
+        .last()
+        .is_some_and(|latest| latest.sequence >= head.sequence);
+    if covered {
+        return Ok(None);
+    }
+
+    let created_at = DateTime::now();
+    let checkpoint = AuditCheckpoint {
+        _id: ObjectId::new(),
+        sequence: head.sequence,
+        signature: signing_key
+            .sign(&checkpoint_message(head.sequence, &head.hash, created_at))
+            .to_bytes()
+            .to_vec(),
+        hash: head.hash,
+        created_at,
+    };
+    db.save_audit_checkpoint(checkpoint.clone()).await?;
+
+    Ok(Some(checkpoint))
+}
+
+// Walks the whole chain, checking each event's hash and link to the one
+// before it, and compares it against every signed checkpoint. Events dropped
+// from the end of the chain after its last checkpoint cannot be detected
+pub async fn verify_chain(
+    db: &dyn Database,
+    verifying_key: &VerifyingKey,
+) -> Result<AuditVerification, Error> {
+    let mut problems = Vec::new();
+    let mut report = |problem: String| {
+        if problems.len() < MAX_REPORTED_PROBLEMS {
+            problems.push(problem);
+        }
+    };
+
+    let checkpoints = db.get_audit_checkpoints().await?;
+    let mut signed: HashMap<i64, &AuditCheckpoint> = HashMap::new();
+    for checkpoint in &checkpoints {
+        let verified = Signature::from_slice(&checkpoint.signature).is_ok_and(|signature| {
+            verifying_key
+                .verify(
+                    &checkpoint_message(
+                        checkpoint.sequence,
+                        &checkpoint.hash,
+                        checkpoint.created_at,
+                    ),
+                    &signature,
+                )
+                .is_ok()
+        });
+        if verified {
+            signed.insert(checkpoint.sequence, checkpoint);
+        } else {
+            report(format!(
+                "Checkpoint at event {} has an invalid signature",
+                checkpoint.sequence
+            ));
+        }
+    }
+
+    let mut events_checked: u64 = 0;
+    let mut head_sequence: i64 = 0;
+    let mut head_hash = GENESIS_HASH.to_string();
+    loop {
+        let events = db.get_audit_chain(head_sequence, VERIFY_BATCH_SIZE).await?;
+        if events.is_empty() {
+            break;
+        }
+
+        for event in events {
+            let expected = head_sequence + 1;
+            if event.sequence != expected {
+                report(missing_events(expected, event.sequence - 1));
+            } else if event.prev_hash != head_hash {
+                report(format!(
+                    "Event {} does not follow the event before it",
+                    event.sequence
+                ));
+            }
+            if event_hash(&event) != event.hash {
+                report(format!("Event {} was modified", event.sequence));
+            }
+            if let Some(checkpoint) = signed.get(&event.sequence) {
+                if checkpoint.hash != event.hash {
+                    report(format!(
+                        "Event {} does not match its signed checkpoint",
+                        event.sequence
+                    ));
+                }
+            }
+
+            events_checked += 1;
+            head_sequence = event.sequence;
+            head_hash = event.hash;
+        }
+    }
+
+    if let Some(latest) = signed
+        .keys()
+        .max()
+        .filter(|&&latest| latest > head_sequence)
+    {
+        report(missing_events(head_sequence + 1, *latest));
+    }
+
+    Ok(AuditVerification {
+        valid: problems.is_empty(),
+        events_checked,
+        checkpoints_checked: checkpoints.len() as u64,
+        head_sequence,
+        problems,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chained_events_link_to_their_predecessor() {
+        let mut first = system_event(AuditAction::Login);
+        chain_event(&mut first, 0, GENESIS_HASH);
+        let mut second = system_event(AuditAction::Upload);
+        chain_event(&mut second, first.sequence, &first.hash);
+
+        assert_eq!(first.sequence, 1);
+        assert_eq!(first.prev_hash, GENESIS_HASH);
+        assert_eq!(second.sequence, 2);
+        assert_eq!(second.prev_hash, first.hash);
+        assert_eq!(event_hash(&second), second.hash);
+    }
+
+    #[test]
+    fn edited_events_no_longer_match_their_hash() {
+        let mut event = system_event(AuditAction::Download);
+        event.detail = Some("report.pdf".to_string());
+        chain_event(&mut event, 0, GENESIS_HASH);
+
+        event.detail = Some("invoice.pdf".to_string());
+        assert_ne!(event_hash(&event), event.hash);
+    }
+
+    #[test]
+    fn describes_missing_ranges() {
+        assert_eq!(missing_events(4, 4), "Event 4 is missing");
+        assert_eq!(missing_events(4, 7), "Events 4 to 7 are missing");
+    }
+}
@@ -1,116 +1,71 @@
 use actix_web::Error;
-use futures_util::{StreamExt, TryStreamExt};
-use mongodb::{
-    bson::{doc, oid::ObjectId, Bson, DateTime, Document, Regex},
-    results::{InsertOneResult, UpdateResult},
-    Client, Collection,
+use async_trait::async_trait;
+use mongodb::bson::{oid::ObjectId, Bson, DateTime};
+
+use crate::{
+    models::{
+        audit_checkpoint_model::AuditCheckpoint,
+        audit_event_model::AuditEvent,
+        chunk_model::Chunk,
+        collection_model::Collection,
+        email_preferences_model::EmailPreferences,
+        file_model::{Compression, File, ScanStatus},
+        file_version_model::{FileVersion, FileVersionSummary},
+        group_model::{Group, GroupShare},
+        invitation_model::Invitation,
+        notification_model::Notification,
+        organization_model::Organization,
+        share_link_model::{ExpiredShare, RevokedShare, ShareLink},
+        shared_collection_model::SharedCollection,
+        user_model::User,
+        webhook_model::{Webhook, WebhookDelivery},
+    },
+    services::{
+        audit::AuditQuery,
+        listing::{FileListOptions, SharedFilePage},
+        notifications::NotificationQuery,
+        webhooks::WebhookDeliveryQuery,
+    },
 };
 
-use crate::models::{file_model::File, share_link_model::ShareLink, user_model::User};
-
-pub struct Database {
-    user: Collection<User>,
-    file: Collection<File>,
-    share_link: Collection<ShareLink>,
-}
-
-impl Database {
-    pub async fn init(db_url: String) -> Self {
-        let client: Client = Client::with_uri_str(db_url).await.unwrap();
-        let db: mongodb::Database = client.database("file");
-
-        let user: Collection<User> = db.collection("user");
-        let file: Collection<File> = db.collection("file");
-        let share_link: Collection<ShareLink> = db.collection("share_link");
-
-        Database {
-            user,
-            file,
-            share_link,
-        }
-    }
-
-    pub async fn create_user(
+// Persistence interface shared by the MongoDB and relational backends
+#[async_trait]
+pub trait Database: Send + Sync {
+    async fn create_user(
         &self,
         name: String,
         email: String,
         password: String,
-    ) -> Result<InsertOneResult, Error> {
-        let user = User {
-            _id: ObjectId::new(), // Generate a new ObjectId
-            username: name,
-            email,
-            password,
-            public_key: "".to_string(),
-            created_at: DateTime::now(), // Set current date and time
-            updated_at: DateTime::now(), // Set current date and time
-        };
-
-        let result: InsertOneResult = self
-            .user
-            .insert_one(user)
-            .await
-            .ok()
-            .expect("Error creating user");
-
-        Ok(result)
-    }
-
-    pub async fn get_user(&self, email: String) -> Result<User, Error> {
-        let filter = doc! {"email":email};
-
-        let exists_user: Option<User> = self
-            .user
-            .find_one(filter)
-            .await
-            .ok()
-            .expect("Error fetching data");
-
-        exists_user.ok_or_else(|| {
-            Error::from(std::io::Error::new(
-                std::io::ErrorKind::NotFound,
-                "User not found",
-            ))
-        })
-    }
-
-    pub async fn update_public_key(
+    ) -> Result<ObjectId, Error>;
+
+    // Emails are stored and matched lowercased
+    async fn get_user(&self, email: String) -> Result<User, Error>;
+
+    // Replaces any outstanding verification token of the user
+    async fn set_email_verification(
         &self,
-        id: Bson,
-        public_key: String,
-    ) -> Result<UpdateResult, Error> {
-        let filter: Document = doc! { "_id": id };
-        let update: Document = doc! { "$set": { "public_key": public_key } };
-
-        let update_result: UpdateResult = self
-            .user
-            .update_one(filter.clone(), update)
-            .await
-            .ok()
-            .expect("Error updating user");
-
-        Ok(update_result)
-    }
-
-    pub async fn get_user_by_id(&self, id: Bson) -> Result<User, Error> {
-        let filter: Document = doc! { "_id": id };
-        // Use await? to handle the Result from find_one
-        let fetch_user = self
-            .user
-            .find_one(filter)
-            .await
-            .ok()
-            .expect("Error while fetching user");
-
-        fetch_user.ok_or_else(|| {
-            Error::from(std::io::Error::new(
-                std::io::ErrorKind::NotFound,
-                "User not found",
-            ))
-        })
-    }
-
-    pub async fn save_file(
+        user_id: ObjectId,
+        token_hash: String,
+        expires_at: DateTime,
+    ) -> Result<(), Error>;
+
+    // Marks the email of the user holding the unexpired token as verified and
+    // consumes the token
+    async fn verify_email(&self, token_hash: String) -> Result<User, Error>;
+
+    async fn update_public_key(&self, id: Bson, public_key: String) -> Result<(), Error>;
+
+    async fn get_user_by_id(&self, id: Bson) -> Result<User, Error>;
+
+    // Adds to the user's storage_used unless that would take it past `quota`
+    async fn reserve_storage(&self, user_id: ObjectId, bytes: i64, quota: i64)
+        -> Result<(), Error>;
+
+    // Hands back a reservation whose upload was never stored
+    async fn release_storage(&self, user_id: ObjectId, bytes: i64) -> Result<(), Error>;
+
+    #[allow(clippy::too_many_arguments)]
+    async fn save_file(
         &self,
         file_name: String,
         file_size: i64,
@@ -121,478 +76,376 @@ impl Database {
         user_id: ObjectId,
         password: String,
         expiration_date: DateTime,
-    ) -> Result<InsertOneResult, Error> {
-        let file = File {
-            _id: ObjectId::new(), // Generate a new ObjectId
-            user_id,
-            file_name,
-            file_size,
-            encrypted_aes_key: aes_key,
-            encrypted_file: file_data,
-            iv,
-            created_at: DateTime::now(), // Set current date and time
-            updated_at: DateTime::now(), // Set current date and time
-        };
-
-        let result = self
-            .file
-            .insert_one(file)
-            .await
-            .ok()
-            .expect("FAILED TO INSERT FILE IN DATABASE");
-
-        // Safely extract the ObjectId from the result.inserted_id
-        let file_id = match result.inserted_id {
-            Bson::ObjectId(oid) => oid, // Successfully extracted ObjectId
-            _ => {
-                return Err(actix_web::error::ErrorBadRequest(format!(
-                    "Failed to convert bson to objectId"
-                )))
-            }
-        };
-
-        // Safely extract the ObjectId from the reciepient_user_id
-        let reciepient_user_id = match ObjectId::parse_str(&reciepient_user_id) {
-            Ok(id) => id,
-            Err(e) => {
-                return Err(actix_web::error::ErrorBadRequest(format!(
-                    "Failed to convert to objectid: {}",
-                    e.to_string()
-                )));
-            }
-        };
-
-        let share_link = ShareLink {
-            _id: ObjectId::new(),
-            file_id,
-            password,
-            reciepents_user_id: reciepient_user_id,
-            created_at: DateTime::now(), // Set current date and time
-            expires_at: expiration_date,
-        };
-        let _share_result = self
-            .share_link
-            .insert_one(share_link)
-            .await
-            .ok()
-            .expect("Failed to save the share document");
-
-        Ok(result)
-    }
-
-    pub async fn get_shared(
+        content_type: String,
+        sha256: String,
+        signature: Option<Vec<u8>>,
+        scan_status: ScanStatus,
+        compression: Compression,
+        chunk_ids: Vec<String>,
+        chunks: Vec<Chunk>,
+        organization_id: Option<ObjectId>,
+    ) -> Result<ObjectId, Error>;
+
+    async fn get_shared(&self, share_id: ObjectId, user_id: ObjectId) -> Result<ShareLink, Error>;
+
+    async fn get_file(&self, file_id: Bson) -> Result<File, Error>;
+
+    async fn get_sent_files(
+        &self,
+        user_id: String,
+        options: &FileListOptions,
+    ) -> Result<SharedFilePage, Error>;
+
+    async fn get_recieve_files(
+        &self,
+        user_id: String,
+        options: &FileListOptions,
+    ) -> Result<SharedFilePage, Error>;
+
+    async fn delete_file_by_share_id(&self, share_id: String) -> Result<bool, Error>;
+
+    async fn get_share_link_doc(&self, share_id: String) -> Result<File, Error>;
+
+    // Only among the organization's members, or users outside every
+    // organization for None
+    async fn search_user(
+        &self,
+        email_text: String,
+        organization_id: Option<ObjectId>,
+    ) -> Result<Vec<User>, Error>;
+
+    // Also removes expired invitations and group shares. Returns the shares removed
+    async fn delete_expired_files(&self) -> Result<Vec<ExpiredShare>, Error>;
+
+    async fn get_share_link(&self, share_id: ObjectId) -> Result<ShareLink, Error>;
+
+    // Reuses the file's revoked or declined link to the same recipient, if
+    // any, returning its id; fails when a live one exists
+    async fn create_share_link(&self, share_link: ShareLink) -> Result<ObjectId, Error>;
+
+    // Stores a file shared with an email that has no account yet, along with
+    // its invitation, in one transaction
+    async fn save_invited_file(
+        &self,
+        file: File,
+        chunks: Vec<Chunk>,
+        invitation: Invitation,
+    ) -> Result<ObjectId, Error>;
+
+    // Pending invitations for an email address, oldest first
+    async fn get_invitations(&self, email: String) -> Result<Vec<Invitation>, Error>;
+
+    // Newest first
+    async fn get_sent_invitations(&self, sender_id: ObjectId) -> Result<Vec<Invitation>, Error>;
+
+    async fn get_invitation(&self, invitation_id: ObjectId) -> Result<Invitation, Error>;
+
+    // Replaces the invitation with the share link and the file's server-held
+    // AES key with the invitee's copy, in one transaction
+    async fn accept_invitation(
+        &self,
+        invitation_id: ObjectId,
+        encrypted_aes_key: Vec<u8>,
+        share_link: ShareLink,
+    ) -> Result<(), Error>;
+
+    // The file it leaves behind is removed by the reconcile job
+    async fn delete_invitation(&self, invitation_id: ObjectId) -> Result<(), Error>;
+
+    async fn create_group(&self, group: Group) -> Result<ObjectId, Error>;
+
+    async fn get_group(&self, group_id: ObjectId) -> Result<Group, Error>;
+
+    // Groups the user is a member of, by name
+    async fn get_user_groups(&self, user_id: ObjectId) -> Result<Vec<Group>, Error>;
+
+    async fn get_group_members(&self, group_id: ObjectId) -> Result<Vec<User>, Error>;
+
+    async fn rename_group(&self, group_id: ObjectId, name: String) -> Result<(), Error>;
+
+    // Along with its group shares; the share links already made stay
+    async fn delete_group(&self, group_id: ObjectId) -> Result<(), Error>;
+
+    // Fails when the user is already a member
+    async fn add_group_member(
+        &self,
+        group_id: ObjectId,
+        user_id: ObjectId,
+        admin: bool,
+    ) -> Result<(), Error>;
+
+    // The share links the member already has stay
+    async fn remove_group_member(&self, group_id: ObjectId, user_id: ObjectId)
+        -> Result<(), Error>;
+
+    async fn set_group_admin(
+        &self,
+        group_id: ObjectId,
+        user_id: ObjectId,
+        admin: bool,
+    ) -> Result<(), Error>;
+
+    // Stores a file shared with a group, its members' share links and the
+    // group share for members who join later, in one transaction
+    async fn save_group_file(
+        &self,
+        file: File,
+        chunks: Vec<Chunk>,
+        share_links: Vec<ShareLink>,
+        group_share: Option<GroupShare>,
+    ) -> Result<ObjectId, Error>;
+
+    // The group's unexpired group shares, oldest first
+    async fn get_group_shares(&self, group_id: ObjectId) -> Result<Vec<GroupShare>, Error>;
+
+    async fn create_organization(&self, organization: Organization) -> Result<ObjectId, Error>;
+
+    async fn get_organization(&self, organization_id: ObjectId) -> Result<Organization, Error>;
+
+    // By name
+    async fn get_organizations(&self) -> Result<Vec<Organization>, Error>;
+
+    // The organization that claims the email domain, if any
+    async fn get_organization_by_domain(
+        &self,
+        domain: String,
+    ) -> Result<Option<Organization>, Error>;
+
+    // Replaces its name and policies; the admins stay
+    async fn update_organization(&self, organization: Organization) -> Result<(), Error>;
+
+    // By email
+    async fn get_organization_members(&self, organization_id: ObjectId)
+        -> Result<Vec<User>, Error>;
+
+    // Fails when the user already belongs to an organization; an admin joins
+    // with their admin rights in the same transaction
+    async fn join_organization(
+        &self,
+        organization_id: ObjectId,
+        user_id: ObjectId,
+        admin: bool,
+    ) -> Result<(), Error>;
+
+    // Also drops them as an admin and revokes the shares they received
+    // within the organization, in one transaction
+    async fn leave_organization(
+        &self,
+        organization_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<(), Error>;
+
+    async fn set_organization_admin(
+        &self,
+        organization_id: ObjectId,
+        user_id: ObjectId,
+        admin: bool,
+    ) -> Result<(), Error>;
+
+    // Also revokes every share re-shared from this one, however deep, and
+    // returns each share it revoked
+    async fn revoke_share(&self, share_id: ObjectId) -> Result<Vec<RevokedShare>, Error>;
+
+    async fn update_share_expiry(
+        &self,
+        share_id: ObjectId,
+        expires_at: DateTime,
+    ) -> Result<(), Error>;
+
+    async fn update_share_password(
+        &self,
+        share_id: ObjectId,
+        password: String,
+    ) -> Result<(), Error>;
+
+    // Also revokes every share re-shared from the removed one. Fails rather
+    // than leave the file without a live share link
+    async fn remove_share_recipient(
+        &self,
+        file_id: ObjectId,
+        recipient_user_id: ObjectId,
+    ) -> Result<(), Error>;
+
+    async fn update_share_permissions(
+        &self,
+        share_id: ObjectId,
+        can_reshare: bool,
+    ) -> Result<(), Error>;
+
+    // Also revokes every share re-shared from this one and returns each of them
+    async fn decline_share(
+        &self,
+        share_id: ObjectId,
+        recipient_user_id: ObjectId,
+    ) -> Result<Vec<RevokedShare>, Error>;
+
+    async fn set_share_archived(
         &self,
         share_id: ObjectId,
+        recipient_user_id: ObjectId,
+        archived: bool,
+    ) -> Result<(), Error>;
+
+    // Declines instead when this is the file's last live share link, so the
+    // sender keeps the file. Either way every share re-shared from this one
+    // is revoked and returned
+    async fn remove_received_share(
+        &self,
+        share_id: ObjectId,
+        recipient_user_id: ObjectId,
+    ) -> Result<Vec<RevokedShare>, Error>;
+
+    // Access receipts: a successful open by the share's recipient
+    async fn record_share_open(&self, share_id: ObjectId) -> Result<(), Error>;
+
+    async fn record_share_password_failure(&self, share_id: ObjectId) -> Result<(), Error>;
+
+    // Stores a collection, its files and their share links in one transaction
+    async fn save_collection(
+        &self,
+        collection: Collection,
+        files: Vec<File>,
+        share_links: Vec<ShareLink>,
+        chunks: Vec<Chunk>,
+    ) -> Result<ObjectId, Error>;
+
+    async fn get_collection(&self, collection_id: ObjectId) -> Result<Collection, Error>;
+
+    async fn get_sent_collections(&self, user_id: ObjectId)
+        -> Result<Vec<SharedCollection>, Error>;
+
+    async fn get_received_collections(
+        &self,
         user_id: ObjectId,
-    ) -> Result<ShareLink, Error> {
-        let filter: Document = doc! { "reciepents_user_id": user_id,"_id": share_id };
-
-        let result = self
-            .share_link
-            .find_one(filter)
-            .await
-            .ok()
-            .expect("Couldn't find the shared_file");
-
-        result.ok_or_else(|| {
-            Error::from(std::io::Error::new(
-                std::io::ErrorKind::NotFound,
-                "Shared file not found",
-            ))
-        })
-    }
-
-    pub async fn get_file(&self, file_id: Bson) -> Result<File, Error> {
-        let filter: Document = doc! { "_id": file_id };
-        // Use await? to handle the Result from find_one
-        let fetch_file = self
-            .file
-            .find_one(filter)
-            .await
-            .ok()
-            .expect("Error while fetching user");
-
-        fetch_file.ok_or_else(|| {
-            Error::from(std::io::Error::new(
-                std::io::ErrorKind::NotFound,
-                "File not found",
-            ))
-        })
-    }
-
-    pub async fn get_sent_files(
+    ) -> Result<Vec<SharedCollection>, Error>;
+
+    // Live share links the recipient holds for files in the collection,
+    // leaving out quarantined files
+    async fn get_collection_shares(
         &self,
-        user_id: String,
-        page: u32,
+        collection_id: ObjectId,
+        recipient_user_id: ObjectId,
+    ) -> Result<Vec<ShareLink>, Error>;
+
+    // Every share link of a file, whatever its state
+    async fn get_file_share_links(&self, file_id: ObjectId) -> Result<Vec<ShareLink>, Error>;
+
+    // Moves the current content into the version history, makes the new
+    // revision current with its share signatures replaced and prunes history
+    // beyond `history` entries. Retained revisions count towards the owner's
+    // storage, so the new one must fit their `quota` less what pruning frees.
+    // Returns the new version number
+    #[allow(clippy::too_many_arguments)]
+    async fn add_file_version(
+        &self,
+        file_id: ObjectId,
+        file_name: String,
+        file_size: i64,
+        encrypted_file: Vec<u8>,
+        iv: Vec<u8>,
+        content_type: String,
+        sha256: String,
+        signatures: Vec<(ObjectId, Vec<u8>)>,
+        scan_status: ScanStatus,
+        compression: Compression,
+        chunk_ids: Vec<String>,
+        chunks: Vec<Chunk>,
+        history: i64,
+        quota: i64,
+    ) -> Result<i64, Error>;
+
+    // Superseded revisions, newest first
+    async fn get_file_versions(&self, file_id: ObjectId) -> Result<Vec<FileVersionSummary>, Error>;
+
+    async fn get_file_version(&self, file_id: ObjectId, version: i64)
+        -> Result<FileVersion, Error>;
+
+    // Contents of deduplicated chunks, in the order asked for
+    async fn get_chunks(&self, chunk_ids: &[String]) -> Result<Vec<Vec<u8>>, Error>;
+
+    // Repairs files without a share link, share links without a file,
+    // collections without files and versions and group shares without a file
+    async fn reconcile(&self) -> Result<(), Error>;
+
+    // Links the event to the head of the audit chain and appends it
+    async fn record_audit_event(&self, event: AuditEvent) -> Result<(), Error>;
+
+    // Newest first
+    async fn get_audit_events(&self, query: AuditQuery) -> Result<Vec<AuditEvent>, Error>;
+
+    // Latest chained event
+    async fn get_audit_head(&self) -> Result<Option<AuditEvent>, Error>;
+
+    // Chained events after `after_sequence`, in chain order
+    async fn get_audit_chain(
+        &self,
+        after_sequence: i64,
         limit: usize,
-    ) -> Result<Vec<(File, String)>, Error> {
-        // Safely extract the ObjectId from the reciepient_user_id
-        let user_id = match ObjectId::parse_str(&user_id) {
-            Ok(id) => id,
-            Err(e) => {
-                return Err(actix_web::error::ErrorBadRequest(format!(
-                    "Failed to convert to objectid: {}",
-                    e.to_string()
-                )));
-            }
-        };
-
-        let filter = doc! {"user_id": user_id};
-        let offset = (page - 1) * limit as u32;
-        // Execute the query and get the cursor
-        let cursor = self
-            .file
-            .find(filter)
-            .skip(offset.into())
-            .limit(limit.try_into().unwrap())
-            .await
-            .ok()
-            .expect("Failed to get files");
-
-        // Collect files into a vector
-        let mut files: Vec<(File, String)> = Vec::new();
-        // Use the StreamExt trait to process the cursor asynchronously
-        let mut stream = cursor.into_stream();
-        while let Some(result) = stream.next().await {
-            match result {
-                Ok(file) => {
-                    let filter = doc! {"file_id": file._id};
-
-                    match self.share_link.find_one(filter).await {
-                        Ok(Some(share_link)) => {
-                            files.push((file, share_link._id.to_string()));
-                        }
-                        Ok(None) => {
-                            return Err(actix_web::error::ErrorServiceUnavailable(format!(
-                                "Unable to fetch file"
-                            )));
-                        }
-                        Err(e) => {
-                            return Err(actix_web::error::ErrorServiceUnavailable(format!(
-                                "Unable to fetch file: {}",
-                                e
-                            )));
-                        }
-                    }
-                } // Push the file if successful
-                Err(e) => {
-                    // Log the error if necessary
-                    actix_web::error::ErrorServiceUnavailable(format!(
-                        "Unable to fetch file: {}",
-                        e.to_string()
-                    ));
-                    // Optionally, you could handle the error further here
-                }
-            }
-        }
-
-        Ok(files)
-    }
-
-    pub async fn get_recieve_files(
+    ) -> Result<Vec<AuditEvent>, Error>;
+
+    async fn save_audit_checkpoint(&self, checkpoint: AuditCheckpoint) -> Result<(), Error>;
+
+    // Oldest first
+    async fn get_audit_checkpoints(&self) -> Result<Vec<AuditCheckpoint>, Error>;
+
+    async fn save_notifications(&self, notifications: Vec<Notification>) -> Result<(), Error>;
+
+    // Newest first
+    async fn get_notifications(&self, query: NotificationQuery)
+        -> Result<Vec<Notification>, Error>;
+
+    async fn count_unread_notifications(&self, user_id: ObjectId) -> Result<u64, Error>;
+
+    async fn mark_notification_read(
+        &self,
+        notification_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<(), Error>;
+
+    // Returns how many were unread
+    async fn mark_all_notifications_read(&self, user_id: ObjectId) -> Result<u64, Error>;
+
+    // Live shares expiring within `window_ms` whose recipient has not been
+    // told about this expiry date yet, marked as told
+    async fn claim_expiring_shares(&self, window_ms: i64) -> Result<Vec<ShareLink>, Error>;
+
+    // The defaults when the user never changed them
+    async fn get_email_preferences(&self, user_id: ObjectId) -> Result<EmailPreferences, Error>;
+
+    async fn save_email_preferences(&self, preferences: EmailPreferences) -> Result<(), Error>;
+
+    async fn create_webhook(&self, webhook: Webhook) -> Result<(), Error>;
+
+    // A user's webhooks, or the admin webhooks for None
+    async fn get_webhooks(&self, user_id: Option<ObjectId>) -> Result<Vec<Webhook>, Error>;
+
+    async fn get_webhook(&self, webhook_id: ObjectId) -> Result<Webhook, Error>;
+
+    // Along with its delivery log
+    async fn delete_webhook(&self, webhook_id: ObjectId) -> Result<(), Error>;
+
+    // Every admin webhook plus those of the given users
+    async fn get_subscribed_webhooks(&self, user_ids: Vec<ObjectId>)
+        -> Result<Vec<Webhook>, Error>;
+
+    async fn save_webhook_deliveries(&self, deliveries: Vec<WebhookDelivery>) -> Result<(), Error>;
+
+    // Up to `limit` pending deliveries due now, oldest first, each pushed
+    // `lease_ms` into the future so no other worker claims it meanwhile
+    async fn claim_due_deliveries(
         &self,
-        user_id: String,
-        page: u32,
         limit: usize,
-    ) -> Result<Vec<(File, String)>, Error> {
-        // Safely extract the ObjectId from the reciepient_user_id
-        let user_id = match ObjectId::parse_str(&user_id) {
-            Ok(id) => id,
-            Err(e) => {
-                return Err(actix_web::error::ErrorBadRequest(format!(
-                    "Failed to convert to objectid: {}",
-                    e.to_string()
-                )));
-            }
-        };
-
-        let filter = doc! {"reciepents_user_id": user_id};
-        let offset = (page - 1) * limit as u32;
-        let share_links = self
-            .share_link
-            .find(filter.clone())
-            .skip(offset.into())
-            .limit(limit.try_into().unwrap())
-            .await
-            .ok()
-            .expect("Failed to fetch shared links");
-
-        // Collect files into a vector
-        let mut files: Vec<(File, String)> = Vec::new();
-        // Use the StreamExt trait to process the cursor asynchronously
-        let mut stream = share_links.into_stream();
-        while let Some(result) = stream.next().await {
-            match result {
-                Ok(share_link) => {
-                    let filter = doc! {"_id": share_link.file_id};
-                    // Attempt to find the file and handle errors appropriately
-                    match self.file.find_one(filter).await {
-                        Ok(Some(file_data)) => {
-                            files.push((file_data, share_link._id.to_string()));
-                            // Push the file if successful
-                        }
-                        Ok(None) => {
-                            return Err(actix_web::error::ErrorServiceUnavailable(format!(
-                                "Unable to fetch file"
-                            )));
-                            // Optionally handle the case where the file does not exist
-                        }
-                        Err(e) => {
-                            return Err(actix_web::error::ErrorServiceUnavailable(format!(
-                                "Unable to fetch file: {}",
-                                e
-                            )));
-                        }
-                    }
-                } // Push the file if successful
-                Err(e) => {
-                    // Log the error if necessary
-                    actix_web::error::ErrorServiceUnavailable(format!(
-                        "Unable to fetch shared_link: {}",
-                        e.to_string()
-                    ));
-                    // Optionally, you could handle the error further here
-                }
-            }
-        }
-
-        Ok(files)
-    }
-
-    pub async fn get_recipients_email_by_file_id(&self, file_id: String) -> Result<User, Error> {
-        // Safely extract the ObjectId from the file_id
-        let file_id = match ObjectId::parse_str(&file_id) {
-            Ok(id) => id,
-            Err(e) => {
-                return Err(actix_web::error::ErrorBadRequest(format!(
-                    "Failed to convert to objectid: {}",
-                    e.to_string()
-                )));
-            }
-        };
-
-        let filter = doc! {"file_id": file_id};
-        let shared_link = match self
-            .share_link
-            .find_one(filter)
-            .await
-            .ok()
-            .expect("Failed to fetch shared link")
-        {
-            Some(link) => link,
-            None => {
-                return Err(actix_web::error::ErrorBadRequest(format!(
-                    "Failed to get shared link"
-                )));
-            }
-        };
-
-        let filter = doc! {"_id": shared_link.reciepents_user_id };
-        let user = match self
-            .user
-            .find_one(filter)
-            .await
-            .ok()
-            .expect("Failed to fetch user")
-        {
-            Some(user) => user,
-            None => {
-                return Err(actix_web::error::ErrorBadRequest(format!(
-                    "Failed to get user"
-                )));
-            }
-        };
-
-        Ok(user)
-    }
-
-    pub async fn delete_file_by_share_id(&self, share_id: String) -> Result<bool, Error> {
-        // Safely extract the ObjectId from the share_id
-        let share_id = match ObjectId::parse_str(&share_id) {
-            Ok(id) => id,
-            Err(e) => {
-                return Err(actix_web::error::ErrorBadRequest(format!(
-                    "Failed to convert to objectid: {}",
-                    e.to_string()
-                )));
-            }
-        };
-        let filter = doc! {"_id": share_id,};
-        let delete_share_link = match self
-            .share_link
-            .find_one_and_delete(filter)
-            .await
-            .ok()
-            .expect("Failed to delete shared link")
-        {
-            Some(shared_link) => shared_link,
-            None => {
-                return Err(actix_web::error::ErrorBadRequest(format!(
-                    "Failed to delete shared link"
-                )));
-            }
-        };
-
-        let filter = doc! {"_id": delete_share_link.file_id};
-
-        let _deleted_file = match self
-            .file
-            .find_one_and_delete(filter)
-            .await
-            .ok()
-            .expect("Failed to delete file")
-        {
-            Some(file) => file,
-            None => {
-                return Err(actix_web::error::ErrorBadRequest(format!(
-                    "Failed to delete file"
-                )));
-            }
-        };
-        Ok(true)
-    }
-
-    pub async fn get_share_link_doc(&self, share_id: String) -> Result<File, Error> {
-        // Safely extract the ObjectId from the share_id
-        let share_id = match ObjectId::parse_str(&share_id) {
-            Ok(id) => id,
-            Err(e) => {
-                return Err(actix_web::error::ErrorBadRequest(format!(
-                    "Failed to convert to objectid: {}",
-                    e.to_string()
-                )));
-            }
-        };
-        let filter = doc! {"_id": share_id};
-        let share_link = match self
-            .share_link
-            .find_one(filter)
-            .await
-            .ok()
-            .expect("Failed to fetch shared link")
-        {
-            Some(shared_link) => shared_link,
-            None => {
-                return Err(actix_web::error::ErrorBadRequest(format!(
-                    "Failed to delete shared link"
-                )));
-            }
-        };
-
-        let filter = doc! {"_id": share_link.file_id};
-
-        let file = match self
-            .file
-            .find_one(filter)
-            .await
-            .ok()
-            .expect("Failed to delete file")
-        {
-            Some(file) => file,
-            None => {
-                return Err(actix_web::error::ErrorBadRequest(format!(
-                    "Failed to delete file"
-                )));
-            }
-        };
-
-        Ok(file)
-    }
-
-    pub async fn search_user(&self, email_text: String) -> Result<Vec<User>, Error> {
-        // Create a regex pattern that matches email addresses containing the substring
-        let filter = doc! {
-            "email": Regex {
-                pattern: email_text,
-                options: "i".to_string(), // 'i' for case-insensitive matching
-            }
-        };
-
-        // Perform the search
-        let cursor = match self.user.find(filter).await {
-            Ok(cursor) => cursor,
-            Err(e) => {
-                return Err(actix_web::error::ErrorBadRequest(format!(
-                    "Failed to fetch users: {}",
-                    e.to_string()
-                )));
-            }
-        };
-
-        // Collect the results into a vector
-        let users: Vec<User> = match cursor.try_collect().await {
-            Ok(users) => users,
-            Err(e) => {
-                return Err(actix_web::error::ErrorBadRequest(format!(
-                    "Failed to fetch users: {}",
-                    e.to_string()
-                )));
-            }
-        };
-
-        Ok(users) // Return the list of users found
-    }
-
-    pub async fn delete_expired_files(&self) -> Result<(), Error> {
-        // Current time in UTC
-        let now: DateTime = DateTime::now();
-
-        let filter = doc! {"expires_at":{"$lt": now}};
-        let cursor = self
-            .share_link
-            .find(filter)
-            .await
-            .ok()
-            .expect("Failed to fetch expired docs");
-        let mut file_ids: Vec<ObjectId> = Vec::new();
-        let mut share_ids: Vec<ObjectId> = Vec::new();
-        let mut stream = cursor.into_stream();
-        while let Some(result) = stream.next().await {
-            match result {
-                Ok(shared_link) => {
-                    share_ids.push(shared_link._id);
-                    let filter = doc! {"_id": shared_link.file_id};
-                    let file = self
-                        .file
-                        .find_one(filter)
-                        .await
-                        .ok()
-                        .expect("Failed to fetch file")
-                        .expect("Unable to fetch");
-                    file_ids.push(file._id);
-                }
-                Err(e) => {
-                    actix_web::error::ErrorServiceUnavailable(format!(
-                        "Unable to fetch share_link: {}",
-                        e.to_string()
-                    ));
-                }
-            }
-        }
-
-        let delete_shared_links_result = self
-            .share_link
-            .delete_many(doc! {"expires_at":{"$lt":now}})
-            .await
-            .ok()
-            .expect("Failed to delete the shared links");
-
-        let delete_files_result = self
-            .file
-            .delete_many(doc! {"_id":{"$in":file_ids}})
-            .await
-            .ok()
-            .expect("Failed to delete files");
-
-        println!(
-            "Successfully deleted {} expired shared links.",
-            delete_shared_links_result.deleted_count
-        );
-        println!(
-            "Successfully deleted {} expired files.",
-            delete_files_result.deleted_count
-        );
-
-        Ok(())
-    }
+        lease_ms: i64,
+    ) -> Result<Vec<WebhookDelivery>, Error>;
+
+    // Stores the outcome of an attempt or a manual retry
+    async fn update_webhook_delivery(&self, delivery: WebhookDelivery) -> Result<(), Error>;
+
+    // Newest first
+    async fn get_webhook_deliveries(
+        &self,
+        query: WebhookDeliveryQuery,
+    ) -> Result<Vec<WebhookDelivery>, Error>;
+
+    async fn get_webhook_delivery(&self, delivery_id: ObjectId) -> Result<WebhookDelivery, Error>;
 }
@@ -0,0 +1,265 @@
+use actix_web::{Error, HttpRequest};
+use mongodb::bson::{oid::ObjectId, Bson, DateTime};
+
+use crate::{
+    models::{
+        audit_event_model::{AuditAction, AuditEvent},
+        file_model::{File, ScanStatus},
+        group_model::{Group, GroupShare},
+        notification_model::{Notification, NotificationKind},
+        share_link_model::ShareLink,
+        user_model::User,
+    },
+    services::{
+        audit::{self, request_event},
+        db::Database,
+        notifications::{self, notification, Notifier},
+    },
+    utils::{
+        file::{integrity::sign_upload, rewrap::rewrap_aes_key},
+        keys::{load_private_key, load_public_key, load_signing_key},
+    },
+};
+
+// Resolves a group the user belongs to; to anyone else it doesn't exist
+pub async fn get_member_group(
+    db: &dyn Database,
+    group_id: &str,
+    user_id: ObjectId,
+) -> Result<Group, Error> {
+    let group_id = ObjectId::parse_str(group_id).map_err(|e| {
+        actix_web::error::ErrorBadRequest(format!("Failed to convert to objectid: {}", e))
+    })?;
+
+    let group = db.get_group(group_id).await?;
+    if !group.is_member(user_id) {
+        return Err(actix_web::error::ErrorNotFound("Group not found"));
+    }
+
+    Ok(group)
+}
+
+// Everyone a share with the group goes to, which is every member but the
+// sender, leaving out members who have since moved to another organization
+pub async fn share_recipients(
+    db: &dyn Database,
+    group: &Group,
+    sender: &User,
+) -> Result<Vec<User>, Error> {
+    let mut members = db.get_group_members(group._id).await?;
+    members.retain(|member| {
+        member._id != sender._id && member.organization_id == sender.organization_id
+    });
+
+    if members.is_empty() {
+        return Err(actix_web::error::ErrorBadRequest(
+            "The group has no other members to share with",
+        ));
+    }
+
+    Ok(members)
+}
+
+// One share link per member. The file's AES key is wrapped to the first
+// member's key pair, so their share uses it and the others get their own copy
+pub async fn member_share_links(
+    file: &File,
+    members: &[User],
+    password: &str,
+    expires_at: DateTime,
+    sign: bool,
+) -> Result<Vec<ShareLink>, Error> {
+    let Some((holder, others)) = members.split_first() else {
+        return Ok(Vec::new());
+    };
+
+    let signing_key = match (sign, &file.sha256) {
+        (true, Some(_)) => Some(load_signing_key(file.user_id)?),
+        _ => None,
+    };
+    let signature = |member: &User| match (&signing_key, &file.sha256) {
+        (Some(signing_key), Some(sha256)) => Some(sign_upload(
+            signing_key,
+            sha256,
+            &file.file_name,
+            member._id,
+        )),
+        _ => None,
+    };
+
+    let mut share_links = Vec::with_capacity(members.len());
+    share_links.push(member_share_link(
+        file,
+        holder._id,
+        password,
+        expires_at,
+        None,
+        signature(holder),
+    ));
+
+    let holder_private_key = load_private_key(holder._id)?;
+    for member in others {
+        let encrypted_aes_key = rewrap_aes_key(
+            &file.encrypted_aes_key,
+            &holder_private_key,
+            &load_public_key(member)?,
+        )
+        .await?;
+        share_links.push(member_share_link(
+            file,
+            member._id,
+            password,
+            expires_at,
+            Some(encrypted_aes_key),
+            signature(member),
+        ));
+    }
+
+    Ok(share_links)
+}
+
+// Gives a member who just joined the group's live group shares. A share
+// that fails to be granted is logged and skipped
+pub async fn grant_group_shares(
+    db: &dyn Database,
+    notifier: &Notifier,
+    req: &HttpRequest,
+    actor_id: ObjectId,
+    group: &Group,
+    member: &User,
+) {
+    let group_shares = match db.get_group_shares(group._id).await {
+        Ok(group_shares) => group_shares,
+        Err(e) => {
+            eprintln!("Failed to fetch group shares of {}: {}", group._id, e);
+            return;
+        }
+    };
+
+    let mut received = Vec::with_capacity(group_shares.len());
+    for group_share in group_shares {
+        if group_share.sender_id == member._id {
+            continue;
+        }
+        match grant(db, &group_share, member).await {
+            Ok(Some((share_id, file))) => {
+                let event = AuditEvent {
+                    actor_id: Some(actor_id),
+                    subject_user_id: Some(member._id),
+                    file_id: Some(file._id),
+                    share_id: Some(share_id),
+                    detail: Some(format!("Group {}", group.name)),
+                    ..request_event(AuditAction::GroupShareGranted, req)
+                };
+                audit::record(db, event).await;
+                received.push(Notification {
+                    actor_id: Some(group_share.sender_id),
+                    share_id: Some(share_id),
+                    file_id: Some(file._id),
+                    name: Some(file.file_name),
+                    ..notification(member._id, NotificationKind::Received)
+                });
+            }
+            Ok(None) => {}
+            Err(e) => eprintln!("Failed to grant group share {}: {}", group_share._id, e),
+        }
+    }
+    notifications::notify(db, notifier, received).await;
+}
+
+// Wraps the file's AES key to the member from the key pair of one of its
+// current recipients. Quarantined files, members of another organization and
+// members who kept a share of the file from an earlier membership get nothing new
+async fn grant(
+    db: &dyn Database,
+    group_share: &GroupShare,
+    member: &User,
+) -> Result<Option<(ObjectId, File)>, Error> {
+    let file = db.get_file(Bson::ObjectId(group_share.file_id)).await?;
+    if file.scan_status == ScanStatus::Quarantined {
+        return Ok(None);
+    }
+
+    if file.organization_id != member.organization_id {
+        return Ok(None);
+    }
+
+    let share_links = db.get_file_share_links(file._id).await?;
+    if share_links
+        .iter()
+        .any(|share_link| share_link.recipient_user_id == member._id)
+    {
+        return Ok(None);
+    }
+    let Some(holder) = share_links.first() else {
+        return Ok(None);
+    };
+
+    let holder_private_key = load_private_key(holder.recipient_user_id)?;
+    let holder_aes_key = holder
+        .encrypted_aes_key
+        .as_deref()
+        .unwrap_or(&file.encrypted_aes_key);
+    let encrypted_aes_key = rewrap_aes_key(
+        holder_aes_key,
+        &holder_private_key,
+        &load_public_key(member)?,
+    )
+    .await?;
+
+    let signature = match (group_share.sign, &file.sha256) {
+        (true, Some(sha256)) => {
+            let signing_key = load_signing_key(group_share.sender_id)?;
+            Some(sign_upload(
+                &signing_key,
+                sha256,
+                &file.file_name,
+                member._id,
+            ))
+        }
+        _ => None,
+    };
+
+    let share_id = db
+        .create_share_link(member_share_link(
+            &file,
+            member._id,
+            &group_share.password,
+            group_share.expires_at,
+            Some(encrypted_aes_key),
+            signature,
+        ))
+        .await?;
+
+    Ok(Some((share_id, file)))
+}
+
+fn member_share_link(
+    file: &File,
+    recipient_user_id: ObjectId,
+    password: &str,
+    expires_at: DateTime,
+    encrypted_aes_key: Option<Vec<u8>>,
+    signature: Option<Vec<u8>>,
+) -> ShareLink {
+    ShareLink {
+        _id: ObjectId::new(),
+        recipient_user_id,
+        file_id: file._id,
+        password: password.to_string(),
+        expires_at,
+        created_at: DateTime::now(),
+        encrypted_aes_key,
+        revoked_at: None,
+        declined_at: None,
+        archived_at: None,
+        can_reshare: false,
+        parent_share_id: None,
+        signature,
+        first_opened_at: None,
+        last_opened_at: None,
+        open_count: 0,
+        failed_password_attempts: 0,
+        organization_id: file.organization_id,
+    }
+}
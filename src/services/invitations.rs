@@ -0,0 +1,134 @@
+use actix_web::{Error, HttpRequest};
+use mongodb::bson::{oid::ObjectId, Bson, DateTime};
+
+use crate::{
+    models::{
+        audit_event_model::{AuditAction, AuditEvent},
+        invitation_model::Invitation,
+        notification_model::{Notification, NotificationKind},
+        share_link_model::ShareLink,
+        user_model::User,
+    },
+    services::{
+        audit::{self, request_event},
+        db::Database,
+        mail_templates::invitation_email,
+        notifications::{self, notification, Notifier},
+    },
+    utils::{
+        email::normalize_email,
+        file::{integrity::sign_upload, rewrap::rewrap_aes_key},
+        keys::{load_invitation_key, load_public_key, load_signing_key},
+    },
+};
+
+// Tells the invitee about the share; they have no inbox to notify yet
+pub fn send_invitation(notifier: &Notifier, invitation: &Invitation, sender_email: &str) {
+    notifier.send_emails(vec![invitation_email(invitation, sender_email)]);
+}
+
+// Turns the invitations waiting for a user's email into share links once they
+// have verified it. An invitation that cannot be accepted stays pending
+// until it expires
+pub async fn accept_pending(
+    db: &dyn Database,
+    notifier: &Notifier,
+    req: &HttpRequest,
+    user: &User,
+) {
+    let invitations = match db.get_invitations(normalize_email(&user.email)).await {
+        Ok(invitations) => invitations,
+        Err(e) => {
+            eprintln!("Failed to fetch invitations for {}: {}", user.email, e);
+            return;
+        }
+    };
+
+    let mut received = Vec::with_capacity(invitations.len());
+    for invitation in invitations {
+        let invitation_id = invitation._id;
+        match accept(db, user, invitation).await {
+            Ok((share_id, invitation)) => {
+                let event = AuditEvent {
+                    actor_id: Some(user._id),
+                    subject_user_id: Some(invitation.sender_id),
+                    file_id: Some(invitation.file_id),
+                    share_id: Some(share_id),
+                    ..request_event(AuditAction::InvitationAccepted, req)
+                };
+                audit::record(db, event).await;
+                received.push(Notification {
+                    actor_id: Some(invitation.sender_id),
+                    share_id: Some(share_id),
+                    file_id: Some(invitation.file_id),
+                    name: Some(invitation.file_name),
+                    ..notification(user._id, NotificationKind::Received)
+                });
+            }
+            Err(e) => eprintln!("Failed to accept invitation {}: {}", invitation_id, e),
+        }
+    }
+    notifications::notify(db, notifier, received).await;
+}
+
+// Re-wraps the file's AES key from the server's invitation key to the user's
+async fn accept(
+    db: &dyn Database,
+    user: &User,
+    invitation: Invitation,
+) -> Result<(ObjectId, Invitation), Error> {
+    let file = db.get_file(Bson::ObjectId(invitation.file_id)).await?;
+    // The sender's organization stopped claiming the invitee's domain
+    if file.organization_id != user.organization_id {
+        return Err(actix_web::error::ErrorForbidden(
+            "The invitation was sent from another organization",
+        ));
+    }
+
+    let invitation_key = load_invitation_key()?;
+    let recipient_public_key = load_public_key(user)?;
+    let encrypted_aes_key = rewrap_aes_key(
+        &file.encrypted_aes_key,
+        &invitation_key,
+        &recipient_public_key,
+    )
+    .await?;
+
+    let signature = match (invitation.sign, &file.sha256) {
+        (true, Some(sha256)) => {
+            let signing_key = load_signing_key(invitation.sender_id)?;
+            Some(sign_upload(&signing_key, sha256, &file.file_name, user._id))
+        }
+        _ => None,
+    };
+
+    // The share reads the file's key, which from now on is wrapped to the user
+    let share_id = ObjectId::new();
+    db.accept_invitation(
+        invitation._id,
+        encrypted_aes_key,
+        ShareLink {
+            _id: share_id,
+            recipient_user_id: user._id,
+            file_id: file._id,
+            password: invitation.password.clone(),
+            expires_at: invitation.expires_at,
+            created_at: DateTime::now(),
+            encrypted_aes_key: None,
+            revoked_at: None,
+            declined_at: None,
+            archived_at: None,
+            can_reshare: false,
+            parent_share_id: None,
+            signature,
+            first_opened_at: None,
+            last_opened_at: None,
+            open_count: 0,
+            failed_password_attempts: 0,
+            organization_id: file.organization_id,
+        },
+    )
+    .await?;
+
+    Ok((share_id, invitation))
+}
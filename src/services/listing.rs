@@ -0,0 +1,181 @@
+use actix_web::Error;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+use crate::models::shared_file_model::SharedFile;
+
+pub const DEFAULT_PAGE_SIZE: usize = 10;
+pub const MAX_PAGE_SIZE: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortField {
+    Name,
+    Size,
+    #[default]
+    SharedAt,
+    ExpiresAt,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShareStatus {
+    #[default]
+    All,
+    Active,
+    Expired,
+    Revoked,
+    Declined,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum CursorValue {
+    Int(i64),
+    Text(String),
+}
+
+// Position of the last row of a page: its sort key plus the share id as a tiebreak
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListingCursor {
+    pub sort: SortField,
+    pub order: SortOrder,
+    pub value: CursorValue,
+    pub share_id: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FileListOptions {
+    pub sort: SortField,
+    pub order: SortOrder,
+    pub after: Option<ListingCursor>,
+    pub limit: usize,
+    pub counterpart_email: Option<String>,
+    pub name: Option<String>,
+    pub min_size: Option<i64>,
+    pub max_size: Option<i64>,
+    pub status: ShareStatus,
+    // Received listings show either archived or unarchived shares, never both
+    pub archived: bool,
+}
+
+#[derive(Debug)]
+pub struct SharedFilePage {
+    pub files: Vec<SharedFile>,
+    pub total: u64,
+    pub has_more: bool,
+}
+
+impl ListingCursor {
+    pub fn after(file: &SharedFile, sort: SortField, order: SortOrder) -> Self {
+        let value = match sort {
+            SortField::Name => CursorValue::Text(file.file_name.clone()),
+            SortField::Size => CursorValue::Int(file.file_size),
+            SortField::SharedAt => CursorValue::Int(file.created_at.timestamp_millis()),
+            SortField::ExpiresAt => CursorValue::Int(file.expires_at.timestamp_millis()),
+        };
+
+        ListingCursor {
+            sort,
+            order,
+            value,
+            share_id: file.share_id.to_hex(),
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        // Serializing plain strings and integers cannot fail
+        let json = serde_json::to_vec(self).unwrap_or_default();
+        URL_SAFE_NO_PAD.encode(json)
+    }
+
+    pub fn decode(cursor: &str) -> Result<Self, Error> {
+        let cursor: Option<ListingCursor> = URL_SAFE_NO_PAD
+            .decode(cursor)
+            .ok()
+            .and_then(|json| serde_json::from_slice(&json).ok());
+
+        match cursor {
+            Some(cursor) if cursor.is_well_formed() => Ok(cursor),
+            _ => Err(actix_web::error::ErrorBadRequest("Invalid cursor")),
+        }
+    }
+
+    // Text keys only come from name sorting, numeric keys from everything else
+    fn is_well_formed(&self) -> bool {
+        let value_matches = match self.sort {
+            SortField::Name => matches!(self.value, CursorValue::Text(_)),
+            _ => matches!(self.value, CursorValue::Int(_)),
+        };
+        value_matches && ObjectId::parse_str(&self.share_id).is_ok()
+    }
+}
+
+impl FileListOptions {
+    // A cursor is only valid for the sort it was issued for
+    pub fn validate_cursor(&self) -> Result<(), Error> {
+        match &self.after {
+            Some(cursor) if cursor.sort != self.sort || cursor.order != self.order => Err(
+                actix_web::error::ErrorBadRequest("Cursor does not match the requested sort"),
+            ),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cursor(sort: SortField, value: CursorValue) -> ListingCursor {
+        ListingCursor {
+            sort,
+            order: SortOrder::Asc,
+            value,
+            share_id: ObjectId::new().to_hex(),
+        }
+    }
+
+    #[test]
+    fn cursors_round_trip() {
+        let cursor = cursor(SortField::Name, CursorValue::Text("report.pdf".to_string()));
+        let decoded = ListingCursor::decode(&cursor.encode()).unwrap();
+
+        assert_eq!(decoded.value, cursor.value);
+        assert_eq!(decoded.share_id, cursor.share_id);
+    }
+
+    #[test]
+    fn rejects_malformed_cursors() {
+        let mismatched = cursor(SortField::Size, CursorValue::Text("large".to_string()));
+        let bad_id = ListingCursor {
+            share_id: "not-an-id".to_string(),
+            ..cursor(SortField::Size, CursorValue::Int(10))
+        };
+
+        assert!(ListingCursor::decode("not base64!").is_err());
+        assert!(ListingCursor::decode(&mismatched.encode()).is_err());
+        assert!(ListingCursor::decode(&bad_id.encode()).is_err());
+    }
+
+    #[test]
+    fn cursors_only_apply_to_their_own_sort() {
+        let options = FileListOptions {
+            sort: SortField::Size,
+            order: SortOrder::Asc,
+            after: Some(cursor(SortField::Name, CursorValue::Text("a".to_string()))),
+            ..FileListOptions::default()
+        };
+
+        assert!(options.validate_cursor().is_err());
+    }
+}
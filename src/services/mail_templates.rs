@@ -0,0 +1,207 @@
+use mongodb::bson::DateTime;
+
+use crate::{
+    models::{
+        invitation_model::Invitation,
+        notification_model::{Notification, NotificationKind},
+    },
+    services::mailer::Email,
+};
+
+// Placeholders are {name}, {actor} and {expires_at}. Share passwords are
+// never part of an email; recipients get them from the sender directly
+const SHARE_RECEIVED_SUBJECT: &str = "{actor} shared \"{name}\" with you";
+const SHARE_RECEIVED_BODY: &str = "Hello,
+
+{actor} shared \"{name}\" with you. It is available until {expires_at}.
+
+Sign in to download it. You will need the password the sender gives you; it is never sent by email.
+";
+
+const SHARE_EXPIRING_SUBJECT: &str = "\"{name}\" expires soon";
+const SHARE_EXPIRING_BODY: &str = "Hello,
+
+\"{name}\", which was shared with you, expires at {expires_at}. Sign in to download it before then.
+";
+
+const SHARE_DOWNLOADED_SUBJECT: &str = "{actor} downloaded \"{name}\"";
+const SHARE_DOWNLOADED_BODY: &str = "Hello,
+
+{actor} downloaded \"{name}\", which you shared with them.
+";
+
+const SHARE_REVOKED_SUBJECT: &str = "\"{name}\" is no longer shared with you";
+const SHARE_REVOKED_BODY: &str = "Hello,
+
+{actor} stopped sharing \"{name}\" with you. It can no longer be downloaded.
+";
+
+const SHARE_DECLINED_SUBJECT: &str = "{actor} declined \"{name}\"";
+const SHARE_DECLINED_BODY: &str = "Hello,
+
+{actor} declined \"{name}\", which you shared with them. They can no longer download it.
+";
+
+const FILE_QUARANTINED_SUBJECT: &str = "\"{name}\" was quarantined";
+const FILE_QUARANTINED_BODY: &str = "Hello,
+
+\"{name}\", which you uploaded, failed a malware scan. It is kept in your files but will not be delivered to anyone.
+";
+
+// Sent to addresses without an account, which receive the share once they
+// register with it
+const INVITATION_SUBJECT: &str = "{actor} shared \"{name}\" with you";
+const INVITATION_BODY: &str = "Hello,
+
+{actor} shared \"{name}\" with you. It is available until {expires_at}.
+
+Register with this email address, or verify it if you already have, to receive it. You will need the password the sender gives you; it is never sent by email.
+";
+
+const VERIFICATION_SUBJECT: &str = "Verify your email address";
+const VERIFICATION_BODY: &str = "Hello,
+
+Submit this code to verify your email address:
+
+{token}
+
+It expires at {expires_at}. If you did not create an account, ignore this email.
+";
+
+// What an email says beyond the notification itself
+pub struct EmailContext {
+    pub actor_email: Option<String>,
+    pub expires_at: Option<DateTime>,
+}
+
+pub fn notification_email(
+    to: String,
+    notification: &Notification,
+    context: &EmailContext,
+) -> Email {
+    let (subject, body) = match notification.kind {
+        NotificationKind::Received => (SHARE_RECEIVED_SUBJECT, SHARE_RECEIVED_BODY),
+        NotificationKind::ExpiringSoon => (SHARE_EXPIRING_SUBJECT, SHARE_EXPIRING_BODY),
+        NotificationKind::Downloaded => (SHARE_DOWNLOADED_SUBJECT, SHARE_DOWNLOADED_BODY),
+        NotificationKind::Revoked => (SHARE_REVOKED_SUBJECT, SHARE_REVOKED_BODY),
+        NotificationKind::Declined => (SHARE_DECLINED_SUBJECT, SHARE_DECLINED_BODY),
+        NotificationKind::Quarantined => (FILE_QUARANTINED_SUBJECT, FILE_QUARANTINED_BODY),
+    };
+
+    render(
+        to,
+        (subject, body),
+        notification.name.as_deref(),
+        context.actor_email.as_deref(),
+        context.expires_at,
+    )
+}
+
+pub fn invitation_email(invitation: &Invitation, sender_email: &str) -> Email {
+    render(
+        invitation.email.clone(),
+        (INVITATION_SUBJECT, INVITATION_BODY),
+        Some(&invitation.file_name),
+        Some(sender_email),
+        Some(invitation.expires_at),
+    )
+}
+
+pub fn verification_email(to: String, token: &str, expires_at: DateTime) -> Email {
+    let Email { to, subject, body } = render(
+        to,
+        (VERIFICATION_SUBJECT, VERIFICATION_BODY),
+        None,
+        None,
+        Some(expires_at),
+    );
+    Email {
+        to,
+        subject,
+        body: body.replace("{token}", token),
+    }
+}
+
+fn render(
+    to: String,
+    (subject, body): (&str, &str),
+    name: Option<&str>,
+    actor: Option<&str>,
+    expires_at: Option<DateTime>,
+) -> Email {
+    let name = name.unwrap_or("A file");
+    let actor = actor.unwrap_or("Someone");
+    let expires_at = expires_at
+        .and_then(|expires_at| expires_at.try_to_rfc3339_string().ok())
+        .unwrap_or_else(|| "it expires".to_string());
+    let fill = |template: &str| {
+        template
+            .replace("{name}", name)
+            .replace("{actor}", actor)
+            .replace("{expires_at}", &expires_at)
+    };
+
+    Email {
+        to,
+        subject: fill(subject),
+        body: fill(body),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::oid::ObjectId;
+
+    use super::*;
+    use crate::{
+        models::email_preferences_model::EmailPreferences, services::notifications::notification,
+    };
+
+    #[test]
+    fn fills_in_the_share_details() {
+        let notification = Notification {
+            name: Some("report.pdf".to_string()),
+            ..notification(ObjectId::new(), NotificationKind::Received)
+        };
+        let context = EmailContext {
+            actor_email: Some("alice@example.com".to_string()),
+            expires_at: Some(DateTime::from_millis(0)),
+        };
+        let email = notification_email("bob@example.com".to_string(), &notification, &context);
+
+        assert_eq!(email.to, "bob@example.com");
+        assert_eq!(
+            email.subject,
+            "alice@example.com shared \"report.pdf\" with you"
+        );
+        assert!(email.body.contains("available until 1970-01-01T00:00:00Z"));
+        assert!(!email.body.contains('{'));
+    }
+
+    #[test]
+    fn falls_back_when_details_are_missing() {
+        let notification = notification(ObjectId::new(), NotificationKind::Received);
+        let context = EmailContext {
+            actor_email: None,
+            expires_at: None,
+        };
+        let email = notification_email("bob@example.com".to_string(), &notification, &context);
+
+        assert_eq!(email.subject, "Someone shared \"A file\" with you");
+    }
+
+    #[test]
+    fn security_notices_ignore_preferences() {
+        let preferences = EmailPreferences {
+            share_received: false,
+            share_expiring: false,
+            share_downloaded: false,
+            share_revoked: false,
+            share_declined: false,
+            ..EmailPreferences::new(ObjectId::new())
+        };
+
+        assert!(!preferences.allows(NotificationKind::Received));
+        assert!(preferences.allows(NotificationKind::Quarantined));
+    }
+}
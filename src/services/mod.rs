@@ -1 +1,15 @@
+pub mod audit;
 pub mod db;
+pub mod groups;
+pub mod invitations;
+pub mod listing;
+pub mod mail_templates;
+pub mod mailer;
+pub mod mongo;
+pub mod mongo_migrations;
+pub mod notifications;
+pub mod organizations;
+pub mod scanner;
+pub mod sql;
+pub mod verification;
+pub mod webhooks;
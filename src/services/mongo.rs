@@ -0,0 +1,3349 @@
+use actix_web::Error;
+use async_trait::async_trait;
+use std::collections::HashMap;
+
+use futures_util::TryStreamExt;
+use mongodb::{
+    bson::{self, doc, oid::ObjectId, Bson, DateTime, Document, Regex},
+    error::{ErrorKind, WriteFailure, TRANSIENT_TRANSACTION_ERROR},
+    options::ReturnDocument,
+    results::UpdateResult,
+    Client, ClientSession, Collection,
+};
+use serde::Deserialize;
+use tokio::sync::Mutex;
+
+use crate::{
+    models::{
+        audit_checkpoint_model::AuditCheckpoint,
+        audit_event_model::{AuditAction, AuditEvent},
+        chunk_model::Chunk,
+        collection_model,
+        email_preferences_model::EmailPreferences,
+        file_model::{Compression, File, ScanStatus},
+        file_version_model::{FileVersion, FileVersionSummary},
+        group_model::{Group, GroupShare},
+        invitation_model::Invitation,
+        notification_model::Notification,
+        organization_model::Organization,
+        share_link_model::{ExpiredShare, RevokedShare, ShareLink},
+        shared_collection_model::SharedCollection,
+        shared_file_model::SharedFile,
+        user_model::User,
+        webhook_model::{DeliveryStatus, Webhook, WebhookDelivery},
+    },
+    services::{
+        audit::{chain_event, system_event, AuditQuery, GENESIS_HASH},
+        db::Database,
+        listing::{
+            CursorValue, FileListOptions, ShareStatus, SharedFilePage, SortField, SortOrder,
+        },
+        mongo_migrations,
+        notifications::NotificationQuery,
+        webhooks::WebhookDeliveryQuery,
+    },
+    utils::email::normalize_email,
+};
+
+const DUPLICATE_KEY_ERROR: i32 = 11000;
+
+// Appends racing another server instance for the same sequence abort with a
+// transient error and are tried again from a fresh read of the head
+const AUDIT_APPEND_ATTEMPTS: usize = 5;
+
+// Documents younger than this may belong to a write that is still in flight
+const RECONCILE_GRACE_PERIOD_MS: i64 = 10 * 60 * 1000;
+
+pub struct MongoDatabase {
+    client: Client,
+    user: Collection<User>,
+    file: Collection<File>,
+    share_link: Collection<ShareLink>,
+    collection: Collection<collection_model::Collection>,
+    file_version: Collection<FileVersion>,
+    chunk: Collection<Chunk>,
+    audit_event: Collection<AuditEvent>,
+    audit_checkpoint: Collection<AuditCheckpoint>,
+    notification: Collection<Notification>,
+    email_preference: Collection<EmailPreferences>,
+    webhook: Collection<Webhook>,
+    webhook_delivery: Collection<WebhookDelivery>,
+    invitation: Collection<Invitation>,
+    group: Collection<Group>,
+    group_share: Collection<GroupShare>,
+    organization: Collection<Organization>,
+    // Serialises this process's appends to the audit chain, each of which reads
+    // the head first; the unique sequence index catches other instances
+    audit_lock: Mutex<()>,
+}
+
+impl MongoDatabase {
+    pub async fn init(db_url: String) -> Self {
+        let client: Client = Client::with_uri_str(db_url).await.unwrap();
+        let db: mongodb::Database = client.database("file");
+
+        mongo_migrations::run(&db)
+            .await
+            .expect("Failed to run database migrations");
+
+        let user: Collection<User> = db.collection("user");
+        let file: Collection<File> = db.collection("file");
+        let share_link: Collection<ShareLink> = db.collection("share_link");
+        let collection: Collection<collection_model::Collection> = db.collection("collection");
+        let file_version: Collection<FileVersion> = db.collection("file_version");
+        let chunk: Collection<Chunk> = db.collection("chunk");
+        let audit_event: Collection<AuditEvent> = db.collection("audit_event");
+        let audit_checkpoint: Collection<AuditCheckpoint> = db.collection("audit_checkpoint");
+        let notification: Collection<Notification> = db.collection("notification");
+        let email_preference: Collection<EmailPreferences> = db.collection("email_preference");
+        let webhook: Collection<Webhook> = db.collection("webhook");
+        let webhook_delivery: Collection<WebhookDelivery> = db.collection("webhook_delivery");
+        let invitation: Collection<Invitation> = db.collection("invitation");
+        let group: Collection<Group> = db.collection("group");
+        let group_share: Collection<GroupShare> = db.collection("group_share");
+        let organization: Collection<Organization> = db.collection("organization");
+
+        MongoDatabase {
+            client,
+            user,
+            file,
+            share_link,
+            collection,
+            file_version,
+            chunk,
+            audit_event,
+            audit_checkpoint,
+            notification,
+            email_preference,
+            webhook,
+            webhook_delivery,
+            invitation,
+            group,
+            group_share,
+            organization,
+            audit_lock: Mutex::new(()),
+        }
+    }
+
+    // Runs a pipeline ending in the `listing_stages` facet and unpacks the single result
+    async fn aggregate_page(
+        &self,
+        collection: Collection<Document>,
+        pipeline: Vec<Document>,
+        limit: usize,
+    ) -> Result<SharedFilePage, Error> {
+        let mut cursor = collection
+            .aggregate(pipeline)
+            .await
+            .map_err(query_error("Unable to fetch files"))?;
+        let result = cursor
+            .try_next()
+            .await
+            .map_err(query_error("Unable to fetch files"))?;
+
+        let page: FacetPage = match result {
+            Some(document) => bson::from_document(document).map_err(|e| {
+                actix_web::error::ErrorServiceUnavailable(format!("Unable to fetch files: {}", e))
+            })?,
+            None => FacetPage {
+                total: Vec::new(),
+                files: Vec::new(),
+            },
+        };
+        let total = page.total.first().map_or(0, |count| count.count);
+
+        let mut files = page.files;
+        let has_more = files.len() > limit;
+        files.truncate(limit);
+
+        Ok(SharedFilePage {
+            files,
+            total,
+            has_more,
+        })
+    }
+
+    async fn update_share_link(&self, filter: Document, fields: Document) -> Result<(), Error> {
+        let result = self
+            .share_link
+            .update_one(filter, doc! {"$set": fields})
+            .await
+            .map_err(query_error("Failed to update shared link"))?;
+
+        if result.matched_count == 0 {
+            return Err(actix_web::error::ErrorNotFound("Shared link not found"));
+        }
+
+        Ok(())
+    }
+
+    // Ids of the share and of every share re-shared from it, however deep;
+    // None when the share does not exist
+    async fn share_chain_ids(
+        &self,
+        share_id: ObjectId,
+        session: &mut ClientSession,
+    ) -> Result<Option<Vec<Bson>>, Error> {
+        let mut cursor = self
+            .share_link
+            .clone_with_type::<Document>()
+            .aggregate(vec![
+                doc! {"$match": {"_id": share_id}},
+                doc! {"$graphLookup": {
+                    "from": "share_link",
+                    "startWith": "$_id",
+                    "connectFromField": "_id",
+                    "connectToField": "parent_share_id",
+                    "as": "descendants",
+                }},
+                doc! {"$project": {"descendant_ids": "$descendants._id"}},
+            ])
+            .session(&mut *session)
+            .await
+            .map_err(query_error("Failed to fetch re-shared links"))?;
+        let chain = cursor
+            .next(&mut *session)
+            .await
+            .transpose()
+            .map_err(query_error("Failed to fetch re-shared links"))?;
+        let chain = match chain {
+            Some(chain) => chain,
+            None => return Ok(None),
+        };
+
+        let mut share_ids = vec![Bson::ObjectId(share_id)];
+        if let Ok(descendant_ids) = chain.get_array("descendant_ids") {
+            share_ids.extend(descendant_ids.iter().cloned());
+        }
+
+        Ok(Some(share_ids))
+    }
+
+    // Revokes the live shares among these and returns each of them
+    async fn revoke_share_ids(
+        &self,
+        share_ids: Vec<Bson>,
+        session: &mut ClientSession,
+    ) -> Result<Vec<RevokedShare>, Error> {
+        let filter = doc! {"_id": {"$in": share_ids}, "revoked_at": null};
+
+        let mut cursor = self
+            .share_link
+            .find(filter.clone())
+            .session(&mut *session)
+            .await
+            .map_err(query_error("Failed to fetch shared links"))?;
+        let mut revoked = Vec::new();
+        while let Some(share_link) = cursor
+            .next(&mut *session)
+            .await
+            .transpose()
+            .map_err(query_error("Failed to fetch shared links"))?
+        {
+            revoked.push(RevokedShare {
+                share_id: share_link._id,
+                recipient_user_id: share_link.recipient_user_id,
+            });
+        }
+
+        self.share_link
+            .update_many(filter, doc! {"$set": {"revoked_at": DateTime::now()}})
+            .session(&mut *session)
+            .await
+            .map_err(query_error("Failed to revoke shared links"))?;
+
+        Ok(revoked)
+    }
+
+    // Every share re-shared from this one, however deep, but not the share itself
+    async fn reshare_ids(
+        &self,
+        share_id: ObjectId,
+        session: &mut ClientSession,
+    ) -> Result<Vec<Bson>, Error> {
+        let mut share_ids = self
+            .share_chain_ids(share_id, session)
+            .await?
+            .unwrap_or_default();
+        share_ids.retain(|id| *id != Bson::ObjectId(share_id));
+        Ok(share_ids)
+    }
+
+    // Chains the event onto the current head; callers hold the audit lock.
+    // Errors are left unmapped so callers can tell a lost race from a failure
+    async fn append_audit_event(
+        &self,
+        mut event: AuditEvent,
+        session: &mut ClientSession,
+    ) -> mongodb::error::Result<()> {
+        let head = self
+            .audit_event
+            .find_one(doc! {"sequence": {"$gt": 0}})
+            .sort(doc! {"sequence": -1})
+            .session(&mut *session)
+            .await?;
+        match head {
+            Some(head) => chain_event(&mut event, head.sequence, &head.hash),
+            None => chain_event(&mut event, 0, GENESIS_HASH),
+        }
+
+        self.audit_event
+            .insert_one(event)
+            .session(&mut *session)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn start_transaction(&self) -> Result<ClientSession, Error> {
+        let mut session = self
+            .client
+            .start_session()
+            .await
+            .map_err(query_error("Failed to start session"))?;
+        session
+            .start_transaction()
+            .await
+            .map_err(query_error("Failed to start transaction"))?;
+        Ok(session)
+    }
+
+    async fn aggregate_collections(
+        &self,
+        collection: Collection<Document>,
+        pipeline: Vec<Document>,
+    ) -> Result<Vec<SharedCollection>, Error> {
+        let cursor = collection
+            .aggregate(pipeline)
+            .await
+            .map_err(query_error("Unable to fetch collections"))?;
+        let documents: Vec<Document> = cursor
+            .try_collect()
+            .await
+            .map_err(query_error("Unable to fetch collections"))?;
+
+        documents
+            .into_iter()
+            .map(|document| {
+                bson::from_document(document).map_err(|e| {
+                    actix_web::error::ErrorServiceUnavailable(format!(
+                        "Failed to decode collection: {}",
+                        e
+                    ))
+                })
+            })
+            .collect()
+    }
+
+    // Stores chunks not seen before and adds references to those already stored
+    async fn retain_chunks(
+        &self,
+        chunks: Vec<Chunk>,
+        session: &mut ClientSession,
+    ) -> Result<(), Error> {
+        for chunk in chunks {
+            let data = bson::to_bson(&chunk.data).map_err(|e| {
+                actix_web::error::ErrorInternalServerError(format!("Failed to encode chunk: {}", e))
+            })?;
+            self.chunk
+                .update_one(
+                    doc! {"_id": chunk._id},
+                    doc! {
+                        "$inc": {"refcount": chunk.refcount},
+                        "$setOnInsert": {"data": data, "created_at": chunk.created_at},
+                    },
+                )
+                .upsert(true)
+                .session(&mut *session)
+                .await
+                .map_err(query_error("Failed to store chunk"))?;
+        }
+
+        Ok(())
+    }
+
+    // Drops the chunk references held by documents about to be deleted and
+    // deletes chunks nothing references any more
+    async fn release_chunks(
+        &self,
+        collection: Collection<Document>,
+        filter: Document,
+        session: &mut ClientSession,
+    ) -> Result<(), Error> {
+        let mut cursor = collection
+            .find(filter)
+            .projection(doc! {"chunk_ids": 1})
+            .session(&mut *session)
+            .await
+            .map_err(query_error("Failed to fetch chunk references"))?;
+        let mut references: HashMap<String, i64> = HashMap::new();
+        while let Some(document) = cursor
+            .next(&mut *session)
+            .await
+            .transpose()
+            .map_err(query_error("Failed to fetch chunk references"))?
+        {
+            let Ok(chunk_ids) = document.get_array("chunk_ids") else {
+                continue;
+            };
+            for chunk_id in chunk_ids.iter().filter_map(Bson::as_str) {
+                *references.entry(chunk_id.to_string()).or_default() += 1;
+            }
+        }
+
+        if references.is_empty() {
+            return Ok(());
+        }
+
+        let chunk_ids: Vec<&String> = references.keys().collect();
+        for (chunk_id, count) in &references {
+            self.chunk
+                .update_one(doc! {"_id": chunk_id}, doc! {"$inc": {"refcount": -count}})
+                .session(&mut *session)
+                .await
+                .map_err(query_error("Failed to release chunk"))?;
+        }
+        self.chunk
+            .delete_many(doc! {"_id": {"$in": chunk_ids}, "refcount": {"$lte": 0}})
+            .session(&mut *session)
+            .await
+            .map_err(query_error("Failed to delete chunks"))?;
+
+        Ok(())
+    }
+
+    // Gives the space of files about to be deleted, and of their version
+    // history, back to their owners and drops their chunk references
+    async fn release_file_storage(
+        &self,
+        file_ids: &[ObjectId],
+        session: &mut ClientSession,
+    ) -> Result<(), Error> {
+        self.release_chunks(
+            self.file.clone_with_type(),
+            doc! {"_id": {"$in": file_ids}},
+            &mut *session,
+        )
+        .await?;
+
+        let mut cursor = self
+            .file
+            .clone_with_type::<Document>()
+            .find(doc! {"_id": {"$in": file_ids}})
+            .projection(doc! {"user_id": 1, "file_size": 1, "history_size": 1})
+            .session(&mut *session)
+            .await
+            .map_err(query_error("Failed to fetch files"))?;
+        let mut files: Vec<Document> = Vec::new();
+        while let Some(file) = cursor
+            .next(&mut *session)
+            .await
+            .transpose()
+            .map_err(query_error("Failed to fetch files"))?
+        {
+            files.push(file);
+        }
+
+        for file in files {
+            let (Ok(user_id), Ok(file_size)) =
+                (file.get_object_id("user_id"), file.get_i64("file_size"))
+            else {
+                continue;
+            };
+            let history_size = file.get_i64("history_size").unwrap_or(0);
+            self.user
+                .update_one(
+                    doc! {"_id": user_id},
+                    doc! {"$inc": {"storage_used": -(file_size + history_size)}},
+                )
+                .session(&mut *session)
+                .await
+                .map_err(query_error("Failed to update storage usage"))?;
+        }
+
+        Ok(())
+    }
+
+    // Ids of documents older than `cutoff` whose `local_field` matches nothing in `foreign`
+    async fn find_unmatched_ids(
+        &self,
+        collection: Collection<Document>,
+        local_field: &str,
+        foreign: &str,
+        foreign_field: &str,
+        cutoff: DateTime,
+    ) -> Result<Vec<ObjectId>, Error> {
+        let pipeline = vec![
+            doc! {"$match": {"created_at": {"$lt": cutoff}}},
+            doc! {"$lookup": {
+                "from": foreign,
+                "localField": local_field,
+                "foreignField": foreign_field,
+                "as": "matches",
+            }},
+            doc! {"$match": {"matches": {"$size": 0}}},
+            doc! {"$project": {"_id": 1}},
+        ];
+
+        let cursor = collection
+            .aggregate(pipeline)
+            .await
+            .map_err(query_error("Failed to run reconcile query"))?;
+        let documents: Vec<Document> = cursor
+            .try_collect()
+            .await
+            .map_err(query_error("Failed to run reconcile query"))?;
+
+        Ok(documents
+            .iter()
+            .filter_map(|document| document.get_object_id("_id").ok())
+            .collect())
+    }
+}
+
+#[derive(Deserialize)]
+struct FacetCount {
+    count: u64,
+}
+
+#[derive(Deserialize)]
+struct FacetPage {
+    total: Vec<FacetCount>,
+    files: Vec<SharedFile>,
+}
+
+fn sort_key(sort: SortField) -> &'static str {
+    match sort {
+        SortField::Name => "file_name",
+        SortField::Size => "file_size",
+        SortField::SharedAt => "created_at",
+        SortField::ExpiresAt => "expires_at",
+    }
+}
+
+// Filter, keyset and facet stages applied to rows shaped like `SharedFile`
+fn listing_stages(options: &FileListOptions) -> Result<Vec<Document>, Error> {
+    let mut filter = Document::new();
+    if let Some(email) = &options.counterpart_email {
+        filter.insert("counterpart_email", contains_pattern(email));
+    }
+    if let Some(name) = &options.name {
+        filter.insert("file_name", contains_pattern(name));
+    }
+
+    let mut size = Document::new();
+    if let Some(min_size) = options.min_size {
+        size.insert("$gte", min_size);
+    }
+    if let Some(max_size) = options.max_size {
+        size.insert("$lte", max_size);
+    }
+    if !size.is_empty() {
+        filter.insert("file_size", size);
+    }
+
+    match options.status {
+        ShareStatus::All => {}
+        ShareStatus::Active => {
+            filter.insert("expires_at", doc! {"$gte": DateTime::now()});
+            filter.insert("revoked_at", Bson::Null);
+            filter.insert("declined_at", Bson::Null);
+        }
+        ShareStatus::Expired => {
+            filter.insert("expires_at", doc! {"$lt": DateTime::now()});
+        }
+        ShareStatus::Revoked => {
+            filter.insert("revoked_at", doc! {"$ne": null});
+        }
+        ShareStatus::Declined => {
+            filter.insert("declined_at", doc! {"$ne": null});
+        }
+    }
+
+    let key = sort_key(options.sort);
+    let (direction, comparison) = match options.order {
+        SortOrder::Asc => (1, "$gt"),
+        SortOrder::Desc => (-1, "$lt"),
+    };
+
+    let mut page_stages = Vec::new();
+    if let Some(cursor) = &options.after {
+        let value = match (&cursor.value, options.sort) {
+            (CursorValue::Text(text), _) => Bson::String(text.clone()),
+            (CursorValue::Int(millis), SortField::SharedAt | SortField::ExpiresAt) => {
+                Bson::DateTime(DateTime::from_millis(*millis))
+            }
+            (CursorValue::Int(number), _) => Bson::Int64(*number),
+        };
+        let share_id = ObjectId::parse_str(&cursor.share_id)
+            .map_err(|_| actix_web::error::ErrorBadRequest("Invalid cursor"))?;
+
+        page_stages.push(doc! {"$match": {"$or": [
+            {key: {comparison: value.clone()}},
+            {key: value, "share_id": {comparison: share_id}},
+        ]}});
+    }
+    page_stages.push(doc! {"$sort": {key: direction, "share_id": direction}});
+    page_stages.push(doc! {"$limit": (options.limit + 1) as i64});
+
+    Ok(vec![
+        doc! {"$match": filter},
+        doc! {"$facet": {
+            "total": [{"$count": "count"}],
+            "files": page_stages,
+        }},
+    ])
+}
+
+// Case-insensitive substring match with the search text taken literally
+fn contains_pattern(text: &str) -> Regex {
+    let mut pattern = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "\\^$.|?*+()[]{}".contains(c) {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+
+    Regex {
+        pattern,
+        options: "i".to_string(),
+    }
+}
+
+fn query_error(context: &'static str) -> impl Fn(mongodb::error::Error) -> Error {
+    move |e| actix_web::error::ErrorServiceUnavailable(format!("{}: {}", context, e))
+}
+
+// Writes to organization.domains trip its unique index when another
+// organization claims one of the domains
+fn domain_conflict(context: &'static str) -> impl Fn(mongodb::error::Error) -> Error {
+    move |e| match *e.kind {
+        ErrorKind::Write(WriteFailure::WriteError(ref write_error))
+            if write_error.code == DUPLICATE_KEY_ERROR =>
+        {
+            actix_web::error::ErrorConflict("A domain belongs to another organization")
+        }
+        _ => actix_web::error::ErrorServiceUnavailable(format!("{}: {}", context, e)),
+    }
+}
+
+#[async_trait]
+impl Database for MongoDatabase {
+    async fn create_user(
+        &self,
+        name: String,
+        email: String,
+        password: String,
+    ) -> Result<ObjectId, Error> {
+        let user = User {
+            _id: ObjectId::new(), // Generate a new ObjectId
+            username: name,
+            email: normalize_email(&email),
+            password,
+            public_key: "".to_string(),
+            storage_used: 0,
+            organization_id: None,
+            email_verified_at: None,
+            email_verification_token: None,
+            email_verification_expires_at: None,
+            created_at: DateTime::now(), // Set current date and time
+            updated_at: DateTime::now(), // Set current date and time
+        };
+
+        let user_id = user._id;
+        self.user
+            .insert_one(user)
+            .await
+            .map_err(|e| match *e.kind {
+                ErrorKind::Write(WriteFailure::WriteError(ref write_error))
+                    if write_error.code == DUPLICATE_KEY_ERROR =>
+                {
+                    actix_web::error::ErrorConflict("User already exists")
+                }
+                _ => actix_web::error::ErrorBadRequest(format!("Error creating user: {}", e)),
+            })?;
+
+        Ok(user_id)
+    }
+
+    async fn get_user(&self, email: String) -> Result<User, Error> {
+        let filter = doc! {"email": normalize_email(&email)};
+
+        let exists_user: Option<User> = self
+            .user
+            .find_one(filter)
+            .await
+            .ok()
+            .expect("Error fetching data");
+
+        exists_user.ok_or_else(|| {
+            Error::from(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "User not found",
+            ))
+        })
+    }
+
+    async fn set_email_verification(
+        &self,
+        user_id: ObjectId,
+        token_hash: String,
+        expires_at: DateTime,
+    ) -> Result<(), Error> {
+        let result = self
+            .user
+            .update_one(
+                doc! {"_id": user_id},
+                doc! {"$set": {
+                    "email_verification_token": token_hash,
+                    "email_verification_expires_at": expires_at,
+                    "updated_at": DateTime::now(),
+                }},
+            )
+            .await
+            .map_err(query_error("Failed to store verification token"))?;
+
+        if result.matched_count == 0 {
+            return Err(actix_web::error::ErrorNotFound("User not found"));
+        }
+
+        Ok(())
+    }
+
+    async fn verify_email(&self, token_hash: String) -> Result<User, Error> {
+        let now = DateTime::now();
+        let user = self
+            .user
+            .find_one_and_update(
+                doc! {
+                    "email_verification_token": token_hash,
+                    "email_verification_expires_at": {"$gt": now},
+                },
+                vec![doc! {"$set": {
+                    "email_verified_at": {"$ifNull": ["$email_verified_at", now]},
+                    "email_verification_token": Bson::Null,
+                    "email_verification_expires_at": Bson::Null,
+                    "updated_at": now,
+                }}],
+            )
+            .return_document(ReturnDocument::After)
+            .await
+            .map_err(query_error("Failed to verify email"))?;
+
+        user.ok_or_else(|| {
+            actix_web::error::ErrorBadRequest("Invalid or expired verification token")
+        })
+    }
+
+    async fn update_public_key(&self, id: Bson, public_key: String) -> Result<(), Error> {
+        let filter: Document = doc! { "_id": id };
+        let update: Document = doc! { "$set": { "public_key": public_key } };
+
+        let _update_result: UpdateResult = self
+            .user
+            .update_one(filter.clone(), update)
+            .await
+            .ok()
+            .expect("Error updating user");
+
+        Ok(())
+    }
+
+    async fn get_user_by_id(&self, id: Bson) -> Result<User, Error> {
+        let filter: Document = doc! { "_id": id };
+        // Use await? to handle the Result from find_one
+        let fetch_user = self
+            .user
+            .find_one(filter)
+            .await
+            .ok()
+            .expect("Error while fetching user");
+
+        fetch_user.ok_or_else(|| {
+            Error::from(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "User not found",
+            ))
+        })
+    }
+
+    async fn reserve_storage(
+        &self,
+        user_id: ObjectId,
+        bytes: i64,
+        quota: i64,
+    ) -> Result<(), Error> {
+        // The filter and $inc apply atomically, so concurrent uploads cannot overshoot
+        let result = self
+            .user
+            .update_one(
+                doc! {"_id": user_id, "storage_used": {"$lte": quota - bytes}},
+                doc! {"$inc": {"storage_used": bytes}},
+            )
+            .await
+            .map_err(query_error("Failed to update storage usage"))?;
+
+        if result.matched_count == 0 {
+            return Err(actix_web::error::ErrorPayloadTooLarge(
+                "Storage quota exceeded",
+            ));
+        }
+
+        Ok(())
+    }
+
+    async fn release_storage(&self, user_id: ObjectId, bytes: i64) -> Result<(), Error> {
+        self.user
+            .update_one(
+                doc! {"_id": user_id},
+                doc! {"$inc": {"storage_used": -bytes}},
+            )
+            .await
+            .map_err(query_error("Failed to update storage usage"))?;
+
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn save_file(
+        &self,
+        file_name: String,
+        file_size: i64,
+        file_data: Vec<u8>,
+        iv: Vec<u8>,
+        aes_key: Vec<u8>,
+        reciepient_user_id: String,
+        user_id: ObjectId,
+        password: String,
+        expiration_date: DateTime,
+        content_type: String,
+        sha256: String,
+        signature: Option<Vec<u8>>,
+        scan_status: ScanStatus,
+        compression: Compression,
+        chunk_ids: Vec<String>,
+        chunks: Vec<Chunk>,
+        organization_id: Option<ObjectId>,
+    ) -> Result<ObjectId, Error> {
+        let file = File {
+            _id: ObjectId::new(), // Generate a new ObjectId
+            user_id,
+            file_name,
+            file_size,
+            content_type: Some(content_type),
+            encrypted_aes_key: aes_key,
+            encrypted_file: file_data,
+            iv,
+            sha256: Some(sha256),
+            created_at: DateTime::now(), // Set current date and time
+            updated_at: DateTime::now(), // Set current date and time
+            collection_id: None,
+            version: 1,
+            scan_status,
+            compression,
+            chunk_ids,
+            organization_id,
+            history_size: 0,
+        };
+
+        let file_id = file._id;
+
+        // Safely extract the ObjectId from the reciepient_user_id
+        let reciepient_user_id = match ObjectId::parse_str(&reciepient_user_id) {
+            Ok(id) => id,
+            Err(e) => {
+                return Err(actix_web::error::ErrorBadRequest(format!(
+                    "Failed to convert to objectid: {}",
+                    e
+                )));
+            }
+        };
+
+        let share_link = ShareLink {
+            _id: ObjectId::new(),
+            file_id,
+            password,
+            recipient_user_id: reciepient_user_id,
+            created_at: DateTime::now(), // Set current date and time
+            expires_at: expiration_date,
+            encrypted_aes_key: None,
+            revoked_at: None,
+            declined_at: None,
+            archived_at: None,
+            can_reshare: false,
+            parent_share_id: None,
+            signature,
+            first_opened_at: None,
+            last_opened_at: None,
+            open_count: 0,
+            failed_password_attempts: 0,
+            organization_id,
+        };
+
+        // The file and its share link are committed together or not at all
+        let mut session = self.start_transaction().await?;
+
+        self.file
+            .insert_one(file)
+            .session(&mut session)
+            .await
+            .map_err(query_error("Failed to insert file in database"))?;
+        self.retain_chunks(chunks, &mut session).await?;
+
+        self.share_link
+            .insert_one(share_link)
+            .session(&mut session)
+            .await
+            .map_err(query_error("Failed to save the share document"))?;
+
+        session
+            .commit_transaction()
+            .await
+            .map_err(query_error("Failed to commit transaction"))?;
+
+        Ok(file_id)
+    }
+
+    async fn get_shared(&self, share_id: ObjectId, user_id: ObjectId) -> Result<ShareLink, Error> {
+        let filter: Document = doc! { "recipient_user_id": user_id,"_id": share_id, "revoked_at": null, "declined_at": null };
+
+        let result = self
+            .share_link
+            .find_one(filter)
+            .await
+            .ok()
+            .expect("Couldn't find the shared_file");
+
+        result.ok_or_else(|| {
+            Error::from(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "Shared file not found",
+            ))
+        })
+    }
+
+    async fn get_file(&self, file_id: Bson) -> Result<File, Error> {
+        let filter: Document = doc! { "_id": file_id };
+        // Use await? to handle the Result from find_one
+        let fetch_file = self
+            .file
+            .find_one(filter)
+            .await
+            .ok()
+            .expect("Error while fetching user");
+
+        fetch_file.ok_or_else(|| {
+            Error::from(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "File not found",
+            ))
+        })
+    }
+
+    async fn get_sent_files(
+        &self,
+        user_id: String,
+        options: &FileListOptions,
+    ) -> Result<SharedFilePage, Error> {
+        // Safely extract the ObjectId from the reciepient_user_id
+        let user_id = match ObjectId::parse_str(&user_id) {
+            Ok(id) => id,
+            Err(e) => {
+                return Err(actix_web::error::ErrorBadRequest(format!(
+                    "Failed to convert to objectid: {}",
+                    e
+                )));
+            }
+        };
+
+        let mut pipeline = vec![
+            doc! {"$match": {"user_id": user_id}},
+            doc! {"$project": {"encrypted_file": 0, "encrypted_aes_key": 0, "iv": 0}},
+            doc! {"$lookup": {
+                "from": "share_link",
+                "localField": "_id",
+                "foreignField": "file_id",
+                "as": "share",
+            }},
+            doc! {"$unwind": "$share"},
+            doc! {"$lookup": {
+                "from": "user",
+                "localField": "share.recipient_user_id",
+                "foreignField": "_id",
+                "as": "counterpart",
+            }},
+            doc! {"$unwind": "$counterpart"},
+            doc! {"$project": {
+                "file_name": 1,
+                "file_size": 1,
+                "content_type": 1,
+                "version": 1,
+                "sha256": 1,
+                "scan_status": 1,
+                "created_at": "$share.created_at",
+                "expires_at": "$share.expires_at",
+                "revoked_at": "$share.revoked_at",
+                "declined_at": "$share.declined_at",
+                "archived_at": "$share.archived_at",
+                "can_reshare": "$share.can_reshare",
+                "parent_share_id": "$share.parent_share_id",
+                "signature": "$share.signature",
+                "first_opened_at": "$share.first_opened_at",
+                "last_opened_at": "$share.last_opened_at",
+                "open_count": "$share.open_count",
+                "failed_password_attempts": "$share.failed_password_attempts",
+                "share_id": "$share._id",
+                "counterpart_email": "$counterpart.email",
+            }},
+        ];
+        pipeline.extend(listing_stages(options)?);
+
+        self.aggregate_page(self.file.clone_with_type(), pipeline, options.limit)
+            .await
+    }
+
+    async fn get_recieve_files(
+        &self,
+        user_id: String,
+        options: &FileListOptions,
+    ) -> Result<SharedFilePage, Error> {
+        // Safely extract the ObjectId from the reciepient_user_id
+        let user_id = match ObjectId::parse_str(&user_id) {
+            Ok(id) => id,
+            Err(e) => {
+                return Err(actix_web::error::ErrorBadRequest(format!(
+                    "Failed to convert to objectid: {}",
+                    e
+                )));
+            }
+        };
+
+        let archived = if options.archived {
+            doc! {"$ne": null}
+        } else {
+            doc! {"$eq": null}
+        };
+
+        let mut pipeline = vec![
+            doc! {"$match": {
+                "recipient_user_id": user_id,
+                "revoked_at": null,
+                "declined_at": null,
+                "archived_at": archived,
+            }},
+            doc! {"$lookup": {
+                "from": "file",
+                "localField": "file_id",
+                "foreignField": "_id",
+                "pipeline": [{"$project": {"encrypted_file": 0, "encrypted_aes_key": 0, "iv": 0}}],
+                "as": "file",
+            }},
+            doc! {"$unwind": "$file"},
+            doc! {"$match": {"file.scan_status": {"$ne": ScanStatus::Quarantined.as_str()}}},
+            doc! {"$lookup": {
+                "from": "user",
+                "localField": "file.user_id",
+                "foreignField": "_id",
+                "as": "counterpart",
+            }},
+            doc! {"$unwind": "$counterpart"},
+            doc! {"$project": {
+                "_id": "$file._id",
+                "file_name": "$file.file_name",
+                "file_size": "$file.file_size",
+                "content_type": "$file.content_type",
+                "version": "$file.version",
+                "sha256": "$file.sha256",
+                "scan_status": "$file.scan_status",
+                "created_at": 1,
+                "expires_at": 1,
+                "revoked_at": 1,
+                "declined_at": 1,
+                "archived_at": 1,
+                "can_reshare": 1,
+                "parent_share_id": 1,
+                "signature": 1,
+                "first_opened_at": 1,
+                "last_opened_at": 1,
+                "open_count": 1,
+                "failed_password_attempts": 1,
+                "share_id": "$_id",
+                "counterpart_email": "$counterpart.email",
+            }},
+        ];
+        pipeline.extend(listing_stages(options)?);
+
+        self.aggregate_page(self.share_link.clone_with_type(), pipeline, options.limit)
+            .await
+    }
+
+    async fn delete_file_by_share_id(&self, share_id: String) -> Result<bool, Error> {
+        // Safely extract the ObjectId from the share_id
+        let share_id = match ObjectId::parse_str(&share_id) {
+            Ok(id) => id,
+            Err(e) => {
+                return Err(actix_web::error::ErrorBadRequest(format!(
+                    "Failed to convert to objectid: {}",
+                    e.to_string()
+                )));
+            }
+        };
+        let mut session = self.start_transaction().await?;
+
+        let filter = doc! {"_id": share_id};
+        let delete_share_link = match self
+            .share_link
+            .find_one_and_delete(filter)
+            .session(&mut session)
+            .await
+            .map_err(query_error("Failed to delete shared link"))?
+        {
+            Some(shared_link) => shared_link,
+            None => {
+                return Err(actix_web::error::ErrorBadRequest(
+                    "Failed to delete shared link",
+                ));
+            }
+        };
+
+        self.release_file_storage(&[delete_share_link.file_id], &mut session)
+            .await?;
+
+        let filter = doc! {"_id": delete_share_link.file_id};
+
+        let _deleted_file = match self
+            .file
+            .find_one_and_delete(filter)
+            .session(&mut session)
+            .await
+            .map_err(query_error("Failed to delete file"))?
+        {
+            Some(file) => file,
+            None => {
+                return Err(actix_web::error::ErrorBadRequest("Failed to delete file"));
+            }
+        };
+
+        // Deleting the file ends every other recipient's share of it too
+        self.share_link
+            .delete_many(doc! {"file_id": delete_share_link.file_id})
+            .session(&mut session)
+            .await
+            .map_err(query_error("Failed to delete shared links"))?;
+        self.group_share
+            .delete_many(doc! {"file_id": delete_share_link.file_id})
+            .session(&mut session)
+            .await
+            .map_err(query_error("Failed to delete group shares"))?;
+
+        self.release_chunks(
+            self.file_version.clone_with_type(),
+            doc! {"file_id": delete_share_link.file_id},
+            &mut session,
+        )
+        .await?;
+        self.file_version
+            .delete_many(doc! {"file_id": delete_share_link.file_id})
+            .session(&mut session)
+            .await
+            .map_err(query_error("Failed to delete file versions"))?;
+
+        session
+            .commit_transaction()
+            .await
+            .map_err(query_error("Failed to commit transaction"))?;
+
+        Ok(true)
+    }
+
+    async fn get_share_link_doc(&self, share_id: String) -> Result<File, Error> {
+        // Safely extract the ObjectId from the share_id
+        let share_id = match ObjectId::parse_str(&share_id) {
+            Ok(id) => id,
+            Err(e) => {
+                return Err(actix_web::error::ErrorBadRequest(format!(
+                    "Failed to convert to objectid: {}",
+                    e.to_string()
+                )));
+            }
+        };
+        let filter = doc! {"_id": share_id};
+        let share_link = match self
+            .share_link
+            .find_one(filter)
+            .await
+            .ok()
+            .expect("Failed to fetch shared link")
+        {
+            Some(shared_link) => shared_link,
+            None => {
+                return Err(actix_web::error::ErrorBadRequest(format!(
+                    "Failed to delete shared link"
+                )));
+            }
+        };
+
+        let filter = doc! {"_id": share_link.file_id};
+
+        let file = match self
+            .file
+            .find_one(filter)
+            .await
+            .ok()
+            .expect("Failed to delete file")
+        {
+            Some(file) => file,
+            None => {
+                return Err(actix_web::error::ErrorBadRequest(format!(
+                    "Failed to delete file"
+                )));
+            }
+        };
+
+        Ok(file)
+    }
+
+    async fn search_user(
+        &self,
+        email_text: String,
+        organization_id: Option<ObjectId>,
+    ) -> Result<Vec<User>, Error> {
+        // Create a regex pattern that matches email addresses containing the substring
+        // (null also matches users from before organizations)
+        let filter = doc! {
+            "email": Regex {
+                pattern: email_text,
+                options: "i".to_string(), // 'i' for case-insensitive matching
+            },
+            "organization_id": organization_id,
+        };
+
+        // Perform the search
+        let cursor = match self.user.find(filter).await {
+            Ok(cursor) => cursor,
+            Err(e) => {
+                return Err(actix_web::error::ErrorBadRequest(format!(
+                    "Failed to fetch users: {}",
+                    e.to_string()
+                )));
+            }
+        };
+
+        // Collect the results into a vector
+        let users: Vec<User> = match cursor.try_collect().await {
+            Ok(users) => users,
+            Err(e) => {
+                return Err(actix_web::error::ErrorBadRequest(format!(
+                    "Failed to fetch users: {}",
+                    e.to_string()
+                )));
+            }
+        };
+
+        Ok(users) // Return the list of users found
+    }
+
+    async fn delete_expired_files(&self) -> Result<Vec<ExpiredShare>, Error> {
+        // Current time in UTC
+        let now: DateTime = DateTime::now();
+
+        let _audit_guard = self.audit_lock.lock().await;
+        let mut session = self.start_transaction().await?;
+
+        let filter = doc! {"expires_at":{"$lt": now}};
+        let mut cursor = self
+            .share_link
+            .find(filter)
+            .session(&mut session)
+            .await
+            .map_err(query_error("Failed to fetch expired docs"))?;
+        let mut file_ids: Vec<ObjectId> = Vec::new();
+        let mut share_ids: Vec<ObjectId> = Vec::new();
+        let mut events: Vec<AuditEvent> = Vec::new();
+        let mut expired_shares: Vec<ExpiredShare> = Vec::new();
+        while let Some(shared_link) = cursor
+            .next(&mut session)
+            .await
+            .transpose()
+            .map_err(query_error("Unable to fetch share_link"))?
+        {
+            share_ids.push(shared_link._id);
+            file_ids.push(shared_link.file_id);
+            events.push(AuditEvent {
+                share_id: Some(shared_link._id),
+                file_id: Some(shared_link.file_id),
+                subject_user_id: Some(shared_link.recipient_user_id),
+                ..system_event(AuditAction::Expire)
+            });
+            expired_shares.push(ExpiredShare {
+                share_id: shared_link._id,
+                file_id: shared_link.file_id,
+                recipient_user_id: shared_link.recipient_user_id,
+                owner_id: None,
+                file_name: None,
+            });
+        }
+
+        // Invitations nobody registered for in time
+        let mut cursor = self
+            .invitation
+            .find(doc! {"expires_at": {"$lt": now}})
+            .session(&mut session)
+            .await
+            .map_err(query_error("Failed to fetch expired invitations"))?;
+        let mut invitation_ids: Vec<ObjectId> = Vec::new();
+        while let Some(invitation) = cursor
+            .next(&mut session)
+            .await
+            .transpose()
+            .map_err(query_error("Unable to fetch invitation"))?
+        {
+            invitation_ids.push(invitation._id);
+            file_ids.push(invitation.file_id);
+            events.push(AuditEvent {
+                file_id: Some(invitation.file_id),
+                detail: Some(format!("Invitation to {}", invitation.email)),
+                ..system_event(AuditAction::Expire)
+            });
+        }
+
+        // Read before the files go, so the expirations can still be reported in full
+        let mut owners = self
+            .file
+            .clone_with_type::<Document>()
+            .find(doc! {"_id": {"$in": &file_ids}})
+            .projection(doc! {"user_id": 1, "file_name": 1})
+            .session(&mut session)
+            .await
+            .map_err(query_error("Failed to fetch expired files"))?;
+        let mut files: HashMap<ObjectId, (ObjectId, String)> = HashMap::new();
+        while let Some(file) = owners
+            .next(&mut session)
+            .await
+            .transpose()
+            .map_err(query_error("Failed to fetch expired files"))?
+        {
+            if let (Ok(file_id), Ok(user_id), Ok(file_name)) = (
+                file.get_object_id("_id"),
+                file.get_object_id("user_id"),
+                file.get_str("file_name"),
+            ) {
+                files.insert(file_id, (user_id, file_name.to_string()));
+            }
+        }
+        for expired_share in &mut expired_shares {
+            if let Some((owner_id, file_name)) = files.get(&expired_share.file_id) {
+                expired_share.owner_id = Some(*owner_id);
+                expired_share.file_name = Some(file_name.clone());
+            }
+        }
+
+        // Losing a race with another instance fails the whole run, which is
+        // tried again on the next schedule
+        for event in events {
+            self.append_audit_event(event, &mut session)
+                .await
+                .map_err(query_error("Failed to record audit event"))?;
+        }
+
+        let delete_shared_links_result = self
+            .share_link
+            .delete_many(doc! {"_id":{"$in":share_ids}})
+            .session(&mut session)
+            .await
+            .map_err(query_error("Failed to delete the shared links"))?;
+        self.invitation
+            .delete_many(doc! {"_id": {"$in": invitation_ids}})
+            .session(&mut session)
+            .await
+            .map_err(query_error("Failed to delete the invitations"))?;
+
+        // A file stays while any other recipient's share of it is still live
+        let mut shared_file_ids = self
+            .share_link
+            .distinct("file_id", doc! {"file_id": {"$in": &file_ids}})
+            .session(&mut session)
+            .await
+            .map_err(query_error("Failed to fetch remaining shared links"))?;
+        shared_file_ids.extend(
+            self.invitation
+                .distinct("file_id", doc! {"file_id": {"$in": &file_ids}})
+                .session(&mut session)
+                .await
+                .map_err(query_error("Failed to fetch remaining invitations"))?,
+        );
+        file_ids.retain(|file_id| !shared_file_ids.contains(&Bson::ObjectId(*file_id)));
+
+        // Expired group shares and those of the files about to go
+        self.group_share
+            .delete_many(doc! {"$or": [
+                {"expires_at": {"$lt": now}},
+                {"file_id": {"$in": &file_ids}},
+            ]})
+            .session(&mut session)
+            .await
+            .map_err(query_error("Failed to delete the group shares"))?;
+
+        self.release_file_storage(&file_ids, &mut session).await?;
+        self.release_chunks(
+            self.file_version.clone_with_type(),
+            doc! {"file_id": {"$in": &file_ids}},
+            &mut session,
+        )
+        .await?;
+
+        self.file_version
+            .delete_many(doc! {"file_id":{"$in":&file_ids}})
+            .session(&mut session)
+            .await
+            .map_err(query_error("Failed to delete file versions"))?;
+
+        let delete_files_result = self
+            .file
+            .delete_many(doc! {"_id":{"$in":file_ids}})
+            .session(&mut session)
+            .await
+            .map_err(query_error("Failed to delete files"))?;
+
+        session
+            .commit_transaction()
+            .await
+            .map_err(query_error("Failed to commit transaction"))?;
+
+        println!(
+            "Successfully deleted {} expired shared links.",
+            delete_shared_links_result.deleted_count
+        );
+        println!(
+            "Successfully deleted {} expired files.",
+            delete_files_result.deleted_count
+        );
+
+        Ok(expired_shares)
+    }
+
+    async fn get_share_link(&self, share_id: ObjectId) -> Result<ShareLink, Error> {
+        let share_link = self
+            .share_link
+            .find_one(doc! {"_id": share_id})
+            .await
+            .map_err(query_error("Failed to fetch shared link"))?;
+
+        share_link.ok_or_else(|| {
+            Error::from(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "Shared link not found",
+            ))
+        })
+    }
+
+    async fn create_share_link(&self, share_link: ShareLink) -> Result<ObjectId, Error> {
+        // A revoked or declined link to the same recipient is brought back
+        // under its own id rather than colliding with the unique index
+        let dead = self
+            .share_link
+            .find_one(doc! {
+                "file_id": share_link.file_id,
+                "recipient_user_id": share_link.recipient_user_id,
+                "$or": [
+                    {"revoked_at": {"$ne": null}},
+                    {"declined_at": {"$ne": null}},
+                ],
+            })
+            .await
+            .map_err(query_error("Failed to look up the share document"))?;
+        if let Some(dead) = dead {
+            let result = self
+                .share_link
+                .replace_one(
+                    doc! {
+                        "_id": dead._id,
+                        "$or": [
+                            {"revoked_at": {"$ne": null}},
+                            {"declined_at": {"$ne": null}},
+                        ],
+                    },
+                    ShareLink {
+                        _id: dead._id,
+                        ..share_link
+                    },
+                )
+                .await
+                .map_err(query_error("Failed to save the share document"))?;
+            if result.matched_count == 0 {
+                return Err(actix_web::error::ErrorConflict(
+                    "File is already shared with this user",
+                ));
+            }
+            return Ok(dead._id);
+        }
+
+        let share_id = share_link._id;
+
+        self.share_link
+            .insert_one(share_link)
+            .await
+            .map_err(|e| match *e.kind {
+                ErrorKind::Write(WriteFailure::WriteError(ref write_error))
+                    if write_error.code == DUPLICATE_KEY_ERROR =>
+                {
+                    actix_web::error::ErrorConflict("File is already shared with this user")
+                }
+                _ => actix_web::error::ErrorBadRequest(format!(
+                    "Failed to save the share document: {}",
+                    e
+                )),
+            })?;
+
+        Ok(share_id)
+    }
+
+    async fn save_invited_file(
+        &self,
+        file: File,
+        chunks: Vec<Chunk>,
+        invitation: Invitation,
+    ) -> Result<ObjectId, Error> {
+        let file_id = file._id;
+
+        let mut session = self.start_transaction().await?;
+
+        self.file
+            .insert_one(file)
+            .session(&mut session)
+            .await
+            .map_err(query_error("Failed to insert file in database"))?;
+        self.retain_chunks(chunks, &mut session).await?;
+
+        let invitation = Invitation {
+            email: normalize_email(&invitation.email),
+            ..invitation
+        };
+        self.invitation
+            .insert_one(invitation)
+            .session(&mut session)
+            .await
+            .map_err(query_error("Failed to save the invitation"))?;
+
+        session
+            .commit_transaction()
+            .await
+            .map_err(query_error("Failed to commit transaction"))?;
+
+        Ok(file_id)
+    }
+
+    async fn get_invitations(&self, email: String) -> Result<Vec<Invitation>, Error> {
+        let cursor = self
+            .invitation
+            .find(doc! {"email": normalize_email(&email)})
+            .sort(doc! {"created_at": 1})
+            .await
+            .map_err(query_error("Failed to fetch invitations"))?;
+        cursor
+            .try_collect()
+            .await
+            .map_err(query_error("Failed to fetch invitations"))
+    }
+
+    async fn get_sent_invitations(&self, sender_id: ObjectId) -> Result<Vec<Invitation>, Error> {
+        let cursor = self
+            .invitation
+            .find(doc! {"sender_id": sender_id})
+            .sort(doc! {"created_at": -1, "_id": -1})
+            .await
+            .map_err(query_error("Failed to fetch invitations"))?;
+        cursor
+            .try_collect()
+            .await
+            .map_err(query_error("Failed to fetch invitations"))
+    }
+
+    async fn get_invitation(&self, invitation_id: ObjectId) -> Result<Invitation, Error> {
+        self.invitation
+            .find_one(doc! {"_id": invitation_id})
+            .await
+            .map_err(query_error("Failed to fetch invitation"))?
+            .ok_or_else(|| actix_web::error::ErrorNotFound("Invitation not found"))
+    }
+
+    async fn accept_invitation(
+        &self,
+        invitation_id: ObjectId,
+        encrypted_aes_key: Vec<u8>,
+        share_link: ShareLink,
+    ) -> Result<(), Error> {
+        let encrypted_aes_key = bson::to_bson(&encrypted_aes_key).map_err(|e| {
+            actix_web::error::ErrorInternalServerError(format!("Failed to encode aes key: {}", e))
+        })?;
+
+        let mut session = self.start_transaction().await?;
+
+        // Deleted first, so an invitation accepted twice fails the second time
+        let result = self
+            .invitation
+            .delete_one(doc! {"_id": invitation_id})
+            .session(&mut session)
+            .await
+            .map_err(query_error("Failed to delete invitation"))?;
+        if result.deleted_count == 0 {
+            return Err(actix_web::error::ErrorNotFound("Invitation not found"));
+        }
+
+        self.file
+            .update_one(
+                doc! {"_id": share_link.file_id},
+                doc! {"$set": {
+                    "encrypted_aes_key": encrypted_aes_key,
+                    "updated_at": DateTime::now(),
+                }},
+            )
+            .session(&mut session)
+            .await
+            .map_err(query_error("Failed to update file"))?;
+
+        self.share_link
+            .insert_one(share_link)
+            .session(&mut session)
+            .await
+            .map_err(query_error("Failed to save the share document"))?;
+
+        session
+            .commit_transaction()
+            .await
+            .map_err(query_error("Failed to commit transaction"))?;
+
+        Ok(())
+    }
+
+    async fn delete_invitation(&self, invitation_id: ObjectId) -> Result<(), Error> {
+        let result = self
+            .invitation
+            .delete_one(doc! {"_id": invitation_id})
+            .await
+            .map_err(query_error("Failed to delete invitation"))?;
+
+        if result.deleted_count == 0 {
+            return Err(actix_web::error::ErrorNotFound("Invitation not found"));
+        }
+
+        Ok(())
+    }
+
+    async fn create_group(&self, group: Group) -> Result<ObjectId, Error> {
+        let group_id = group._id;
+
+        self.group
+            .insert_one(group)
+            .await
+            .map_err(query_error("Failed to save the group"))?;
+
+        Ok(group_id)
+    }
+
+    async fn get_group(&self, group_id: ObjectId) -> Result<Group, Error> {
+        self.group
+            .find_one(doc! {"_id": group_id})
+            .await
+            .map_err(query_error("Failed to fetch group"))?
+            .ok_or_else(|| actix_web::error::ErrorNotFound("Group not found"))
+    }
+
+    async fn get_user_groups(&self, user_id: ObjectId) -> Result<Vec<Group>, Error> {
+        let cursor = self
+            .group
+            .find(doc! {"members": user_id})
+            .sort(doc! {"name": 1, "_id": 1})
+            .await
+            .map_err(query_error("Failed to fetch groups"))?;
+        cursor
+            .try_collect()
+            .await
+            .map_err(query_error("Failed to fetch groups"))
+    }
+
+    async fn get_group_members(&self, group_id: ObjectId) -> Result<Vec<User>, Error> {
+        let group = self.get_group(group_id).await?;
+
+        let cursor = self
+            .user
+            .find(doc! {"_id": {"$in": group.members}})
+            .sort(doc! {"email": 1})
+            .await
+            .map_err(query_error("Failed to fetch group members"))?;
+        cursor
+            .try_collect()
+            .await
+            .map_err(query_error("Failed to fetch group members"))
+    }
+
+    async fn rename_group(&self, group_id: ObjectId, name: String) -> Result<(), Error> {
+        let result = self
+            .group
+            .update_one(
+                doc! {"_id": group_id},
+                doc! {"$set": {"name": name, "updated_at": DateTime::now()}},
+            )
+            .await
+            .map_err(query_error("Failed to update group"))?;
+
+        if result.matched_count == 0 {
+            return Err(actix_web::error::ErrorNotFound("Group not found"));
+        }
+
+        Ok(())
+    }
+
+    async fn delete_group(&self, group_id: ObjectId) -> Result<(), Error> {
+        let mut session = self.start_transaction().await?;
+
+        self.group_share
+            .delete_many(doc! {"group_id": group_id})
+            .session(&mut session)
+            .await
+            .map_err(query_error("Failed to delete group shares"))?;
+
+        let result = self
+            .group
+            .delete_one(doc! {"_id": group_id})
+            .session(&mut session)
+            .await
+            .map_err(query_error("Failed to delete group"))?;
+        if result.deleted_count == 0 {
+            return Err(actix_web::error::ErrorNotFound("Group not found"));
+        }
+
+        session
+            .commit_transaction()
+            .await
+            .map_err(query_error("Failed to commit transaction"))?;
+
+        Ok(())
+    }
+
+    async fn add_group_member(
+        &self,
+        group_id: ObjectId,
+        user_id: ObjectId,
+        admin: bool,
+    ) -> Result<(), Error> {
+        let mut added = doc! {"members": user_id};
+        if admin {
+            added.insert("admins", user_id);
+        }
+
+        let result = self
+            .group
+            .update_one(
+                doc! {"_id": group_id, "members": {"$ne": user_id}},
+                doc! {"$push": added, "$set": {"updated_at": DateTime::now()}},
+            )
+            .await
+            .map_err(query_error("Failed to update group"))?;
+
+        if result.matched_count == 0 {
+            return Err(actix_web::error::ErrorConflict(
+                "User is already a member of this group",
+            ));
+        }
+
+        Ok(())
+    }
+
+    async fn remove_group_member(
+        &self,
+        group_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<(), Error> {
+        let result = self
+            .group
+            .update_one(
+                doc! {"_id": group_id, "members": user_id},
+                doc! {
+                    "$pull": {"members": user_id, "admins": user_id},
+                    "$set": {"updated_at": DateTime::now()},
+                },
+            )
+            .await
+            .map_err(query_error("Failed to update group"))?;
+
+        if result.matched_count == 0 {
+            return Err(actix_web::error::ErrorNotFound(
+                "User is not a member of this group",
+            ));
+        }
+
+        Ok(())
+    }
+
+    async fn set_group_admin(
+        &self,
+        group_id: ObjectId,
+        user_id: ObjectId,
+        admin: bool,
+    ) -> Result<(), Error> {
+        let operator = if admin { "$addToSet" } else { "$pull" };
+
+        let result = self
+            .group
+            .update_one(
+                doc! {"_id": group_id, "members": user_id},
+                doc! {
+                    operator: {"admins": user_id},
+                    "$set": {"updated_at": DateTime::now()},
+                },
+            )
+            .await
+            .map_err(query_error("Failed to update group"))?;
+
+        if result.matched_count == 0 {
+            return Err(actix_web::error::ErrorNotFound(
+                "User is not a member of this group",
+            ));
+        }
+
+        Ok(())
+    }
+
+    async fn save_group_file(
+        &self,
+        file: File,
+        chunks: Vec<Chunk>,
+        share_links: Vec<ShareLink>,
+        group_share: Option<GroupShare>,
+    ) -> Result<ObjectId, Error> {
+        let file_id = file._id;
+
+        let mut session = self.start_transaction().await?;
+
+        self.file
+            .insert_one(file)
+            .session(&mut session)
+            .await
+            .map_err(query_error("Failed to insert file in database"))?;
+        self.retain_chunks(chunks, &mut session).await?;
+
+        self.share_link
+            .insert_many(share_links)
+            .session(&mut session)
+            .await
+            .map_err(query_error("Failed to save the share document"))?;
+
+        if let Some(group_share) = group_share {
+            self.group_share
+                .insert_one(group_share)
+                .session(&mut session)
+                .await
+                .map_err(query_error("Failed to save the group share"))?;
+        }
+
+        session
+            .commit_transaction()
+            .await
+            .map_err(query_error("Failed to commit transaction"))?;
+
+        Ok(file_id)
+    }
+
+    async fn get_group_shares(&self, group_id: ObjectId) -> Result<Vec<GroupShare>, Error> {
+        let cursor = self
+            .group_share
+            .find(doc! {"group_id": group_id, "expires_at": {"$gt": DateTime::now()}})
+            .sort(doc! {"created_at": 1, "_id": 1})
+            .await
+            .map_err(query_error("Failed to fetch group shares"))?;
+        cursor
+            .try_collect()
+            .await
+            .map_err(query_error("Failed to fetch group shares"))
+    }
+
+    async fn create_organization(&self, organization: Organization) -> Result<ObjectId, Error> {
+        let organization_id = organization._id;
+        let admins = organization.admins.clone();
+        let now = organization.created_at;
+
+        let mut session = self.start_transaction().await?;
+
+        self.organization
+            .insert_one(organization)
+            .session(&mut session)
+            .await
+            .map_err(domain_conflict("Failed to save the organization"))?;
+
+        for admin_id in admins {
+            let joined = self
+                .user
+                .update_one(
+                    doc! {"_id": admin_id, "organization_id": null},
+                    doc! {"$set": {"organization_id": organization_id, "updated_at": now}},
+                )
+                .session(&mut session)
+                .await
+                .map_err(query_error("Failed to update user"))?;
+            if joined.matched_count == 0 {
+                return Err(actix_web::error::ErrorConflict(
+                    "User already belongs to an organization",
+                ));
+            }
+        }
+
+        session
+            .commit_transaction()
+            .await
+            .map_err(query_error("Failed to commit transaction"))?;
+
+        Ok(organization_id)
+    }
+
+    async fn get_organization(&self, organization_id: ObjectId) -> Result<Organization, Error> {
+        self.organization
+            .find_one(doc! {"_id": organization_id})
+            .await
+            .map_err(query_error("Failed to fetch organization"))?
+            .ok_or_else(|| actix_web::error::ErrorNotFound("Organization not found"))
+    }
+
+    async fn get_organizations(&self) -> Result<Vec<Organization>, Error> {
+        let cursor = self
+            .organization
+            .find(doc! {})
+            .sort(doc! {"name": 1, "_id": 1})
+            .await
+            .map_err(query_error("Failed to fetch organizations"))?;
+        cursor
+            .try_collect()
+            .await
+            .map_err(query_error("Failed to fetch organizations"))
+    }
+
+    async fn get_organization_by_domain(
+        &self,
+        domain: String,
+    ) -> Result<Option<Organization>, Error> {
+        self.organization
+            .find_one(doc! {"domains": domain})
+            .await
+            .map_err(query_error("Failed to fetch organization"))
+    }
+
+    async fn update_organization(&self, organization: Organization) -> Result<(), Error> {
+        let result = self
+            .organization
+            .update_one(
+                doc! {"_id": organization._id},
+                doc! {"$set": {
+                    "name": organization.name,
+                    "domains": organization.domains,
+                    "max_expiry_days": organization.max_expiry_days,
+                    "storage_quota": organization.storage_quota,
+                    "updated_at": DateTime::now(),
+                }},
+            )
+            .await
+            .map_err(domain_conflict("Failed to update organization"))?;
+
+        if result.matched_count == 0 {
+            return Err(actix_web::error::ErrorNotFound("Organization not found"));
+        }
+
+        Ok(())
+    }
+
+    async fn get_organization_members(
+        &self,
+        organization_id: ObjectId,
+    ) -> Result<Vec<User>, Error> {
+        let cursor = self
+            .user
+            .find(doc! {"organization_id": organization_id})
+            .sort(doc! {"email": 1})
+            .await
+            .map_err(query_error("Failed to fetch organization members"))?;
+        cursor
+            .try_collect()
+            .await
+            .map_err(query_error("Failed to fetch organization members"))
+    }
+
+    async fn join_organization(
+        &self,
+        organization_id: ObjectId,
+        user_id: ObjectId,
+        admin: bool,
+    ) -> Result<(), Error> {
+        let now = DateTime::now();
+
+        let mut session = self.start_transaction().await?;
+
+        let result = self
+            .user
+            .update_one(
+                doc! {"_id": user_id, "organization_id": null},
+                doc! {"$set": {"organization_id": organization_id, "updated_at": now}},
+            )
+            .session(&mut session)
+            .await
+            .map_err(query_error("Failed to update user"))?;
+        if result.matched_count == 0 {
+            return Err(actix_web::error::ErrorConflict(
+                "User already belongs to an organization",
+            ));
+        }
+
+        let mut update = doc! {"$set": {"updated_at": now}};
+        if admin {
+            update.insert("$addToSet", doc! {"admins": user_id});
+        }
+        self.organization
+            .update_one(doc! {"_id": organization_id}, update)
+            .session(&mut session)
+            .await
+            .map_err(query_error("Failed to update organization"))?;
+
+        session
+            .commit_transaction()
+            .await
+            .map_err(query_error("Failed to commit transaction"))?;
+
+        Ok(())
+    }
+
+    async fn leave_organization(
+        &self,
+        organization_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<(), Error> {
+        let now = DateTime::now();
+
+        let mut session = self.start_transaction().await?;
+
+        let result = self
+            .user
+            .update_one(
+                doc! {"_id": user_id, "organization_id": organization_id},
+                doc! {"$set": {"organization_id": null, "updated_at": now}},
+            )
+            .session(&mut session)
+            .await
+            .map_err(query_error("Failed to update user"))?;
+        if result.matched_count == 0 {
+            return Err(actix_web::error::ErrorNotFound(
+                "User is not a member of this organization",
+            ));
+        }
+
+        self.organization
+            .update_one(
+                doc! {"_id": organization_id},
+                doc! {"$pull": {"admins": user_id}, "$set": {"updated_at": now}},
+            )
+            .session(&mut session)
+            .await
+            .map_err(query_error("Failed to update organization"))?;
+        self.share_link
+            .update_many(
+                doc! {
+                    "recipient_user_id": user_id,
+                    "organization_id": organization_id,
+                    "revoked_at": null,
+                },
+                doc! {"$set": {"revoked_at": now}},
+            )
+            .session(&mut session)
+            .await
+            .map_err(query_error("Failed to revoke shares"))?;
+
+        session
+            .commit_transaction()
+            .await
+            .map_err(query_error("Failed to commit transaction"))?;
+
+        Ok(())
+    }
+
+    async fn set_organization_admin(
+        &self,
+        organization_id: ObjectId,
+        user_id: ObjectId,
+        admin: bool,
+    ) -> Result<(), Error> {
+        let member = self
+            .user
+            .find_one(doc! {"_id": user_id, "organization_id": organization_id})
+            .await
+            .map_err(query_error("Failed to fetch user"))?;
+        if member.is_none() {
+            return Err(actix_web::error::ErrorNotFound(
+                "User is not a member of this organization",
+            ));
+        }
+
+        let operator = if admin { "$addToSet" } else { "$pull" };
+        let result = self
+            .organization
+            .update_one(
+                doc! {"_id": organization_id},
+                doc! {
+                    operator: {"admins": user_id},
+                    "$set": {"updated_at": DateTime::now()},
+                },
+            )
+            .await
+            .map_err(query_error("Failed to update organization"))?;
+
+        if result.matched_count == 0 {
+            return Err(actix_web::error::ErrorNotFound("Organization not found"));
+        }
+
+        Ok(())
+    }
+
+    async fn revoke_share(&self, share_id: ObjectId) -> Result<Vec<RevokedShare>, Error> {
+        let mut session = self.start_transaction().await?;
+
+        let share_ids = match self.share_chain_ids(share_id, &mut session).await? {
+            Some(share_ids) => share_ids,
+            None => return Err(actix_web::error::ErrorNotFound("Shared link not found")),
+        };
+        let revoked = self.revoke_share_ids(share_ids, &mut session).await?;
+
+        session
+            .commit_transaction()
+            .await
+            .map_err(query_error("Failed to commit transaction"))?;
+
+        Ok(revoked)
+    }
+
+    async fn update_share_permissions(
+        &self,
+        share_id: ObjectId,
+        can_reshare: bool,
+    ) -> Result<(), Error> {
+        self.update_share_link(doc! {"_id": share_id}, doc! {"can_reshare": can_reshare})
+            .await
+    }
+
+    async fn update_share_expiry(
+        &self,
+        share_id: ObjectId,
+        expires_at: DateTime,
+    ) -> Result<(), Error> {
+        self.update_share_link(doc! {"_id": share_id}, doc! {"expires_at": expires_at})
+            .await
+    }
+
+    async fn update_share_password(
+        &self,
+        share_id: ObjectId,
+        password: String,
+    ) -> Result<(), Error> {
+        self.update_share_link(doc! {"_id": share_id}, doc! {"password": password})
+            .await
+    }
+
+    async fn remove_share_recipient(
+        &self,
+        file_id: ObjectId,
+        recipient_user_id: ObjectId,
+    ) -> Result<(), Error> {
+        let mut session = self.start_transaction().await?;
+
+        let share_link = self
+            .share_link
+            .find_one(doc! {"file_id": file_id, "recipient_user_id": recipient_user_id})
+            .session(&mut session)
+            .await
+            .map_err(query_error("Failed to fetch shared link"))?
+            .ok_or_else(|| actix_web::error::ErrorNotFound("File is not shared with this user"))?;
+
+        // Whoever the recipient re-shared the file with loses it too
+        let share_ids = self
+            .share_chain_ids(share_link._id, &mut session)
+            .await?
+            .unwrap_or_default();
+
+        // Revoked and declined links no longer reach anyone, and neither do
+        // the ones re-shared from this one
+        let other_recipients = self
+            .share_link
+            .count_documents(doc! {
+                "file_id": file_id,
+                "_id": {"$nin": &share_ids},
+                "revoked_at": null,
+                "declined_at": null,
+            })
+            .session(&mut session)
+            .await
+            .map_err(query_error("Failed to count shared links"))?;
+        if other_recipients == 0 {
+            return Err(actix_web::error::ErrorBadRequest(
+                "Cannot remove the last recipient; delete the file instead",
+            ));
+        }
+
+        self.share_link
+            .update_many(
+                doc! {"_id": {"$in": share_ids}, "revoked_at": null},
+                doc! {"$set": {"revoked_at": DateTime::now()}},
+            )
+            .session(&mut session)
+            .await
+            .map_err(query_error("Failed to revoke re-shared links"))?;
+
+        self.share_link
+            .delete_one(doc! {"_id": share_link._id})
+            .session(&mut session)
+            .await
+            .map_err(query_error("Failed to delete shared link"))?;
+
+        session
+            .commit_transaction()
+            .await
+            .map_err(query_error("Failed to commit transaction"))?;
+
+        Ok(())
+    }
+
+    async fn decline_share(
+        &self,
+        share_id: ObjectId,
+        recipient_user_id: ObjectId,
+    ) -> Result<Vec<RevokedShare>, Error> {
+        let mut session = self.start_transaction().await?;
+
+        let result = self
+            .share_link
+            .update_one(
+                doc! {"_id": share_id, "recipient_user_id": recipient_user_id},
+                doc! {"$set": {"declined_at": DateTime::now()}},
+            )
+            .session(&mut session)
+            .await
+            .map_err(query_error("Failed to update shared link"))?;
+        if result.matched_count == 0 {
+            return Err(actix_web::error::ErrorNotFound("Shared link not found"));
+        }
+        let share_ids = self.reshare_ids(share_id, &mut session).await?;
+        let revoked = self.revoke_share_ids(share_ids, &mut session).await?;
+
+        session
+            .commit_transaction()
+            .await
+            .map_err(query_error("Failed to commit transaction"))?;
+
+        Ok(revoked)
+    }
+
+    async fn set_share_archived(
+        &self,
+        share_id: ObjectId,
+        recipient_user_id: ObjectId,
+        archived: bool,
+    ) -> Result<(), Error> {
+        let archived_at = if archived {
+            Bson::DateTime(DateTime::now())
+        } else {
+            Bson::Null
+        };
+
+        self.update_share_link(
+            doc! {"_id": share_id, "recipient_user_id": recipient_user_id},
+            doc! {"archived_at": archived_at},
+        )
+        .await
+    }
+
+    async fn remove_received_share(
+        &self,
+        share_id: ObjectId,
+        recipient_user_id: ObjectId,
+    ) -> Result<Vec<RevokedShare>, Error> {
+        let mut session = self.start_transaction().await?;
+
+        let filter = doc! {"_id": share_id, "recipient_user_id": recipient_user_id};
+        let share_link = self
+            .share_link
+            .find_one(filter.clone())
+            .session(&mut session)
+            .await
+            .map_err(query_error("Failed to fetch shared link"))?
+            .ok_or_else(|| actix_web::error::ErrorNotFound("Shared link not found"))?;
+
+        // Only live links outside this one's re-shares keep the file reachable
+        let mut share_ids = self
+            .share_chain_ids(share_id, &mut session)
+            .await?
+            .unwrap_or_default();
+        let other_recipients = self
+            .share_link
+            .count_documents(doc! {
+                "file_id": share_link.file_id,
+                "_id": {"$nin": &share_ids},
+                "revoked_at": null,
+                "declined_at": null,
+            })
+            .session(&mut session)
+            .await
+            .map_err(query_error("Failed to count shared links"))?;
+
+        share_ids.retain(|id| *id != Bson::ObjectId(share_id));
+        let revoked = self.revoke_share_ids(share_ids, &mut session).await?;
+        if other_recipients > 0 {
+            self.share_link
+                .delete_one(filter)
+                .session(&mut session)
+                .await
+                .map_err(query_error("Failed to delete shared link"))?;
+        } else if share_link.declined_at.is_none() {
+            self.share_link
+                .update_one(filter, doc! {"$set": {"declined_at": DateTime::now()}})
+                .session(&mut session)
+                .await
+                .map_err(query_error("Failed to update shared link"))?;
+        }
+
+        session
+            .commit_transaction()
+            .await
+            .map_err(query_error("Failed to commit transaction"))?;
+
+        Ok(revoked)
+    }
+
+    async fn record_share_open(&self, share_id: ObjectId) -> Result<(), Error> {
+        let now = DateTime::now();
+        self.share_link
+            .update_one(
+                doc! {"_id": share_id},
+                doc! {"$inc": {"open_count": 1_i64}, "$set": {"last_opened_at": now}},
+            )
+            .await
+            .map_err(query_error("Failed to update shared link"))?;
+        self.share_link
+            .update_one(
+                doc! {"_id": share_id, "first_opened_at": null},
+                doc! {"$set": {"first_opened_at": now}},
+            )
+            .await
+            .map_err(query_error("Failed to update shared link"))?;
+
+        Ok(())
+    }
+
+    async fn record_share_password_failure(&self, share_id: ObjectId) -> Result<(), Error> {
+        self.share_link
+            .update_one(
+                doc! {"_id": share_id},
+                doc! {"$inc": {"failed_password_attempts": 1_i64}},
+            )
+            .await
+            .map_err(query_error("Failed to update shared link"))?;
+
+        Ok(())
+    }
+
+    async fn save_collection(
+        &self,
+        collection: collection_model::Collection,
+        files: Vec<File>,
+        share_links: Vec<ShareLink>,
+        chunks: Vec<Chunk>,
+    ) -> Result<ObjectId, Error> {
+        let collection_id = collection._id;
+
+        let mut session = self.start_transaction().await?;
+
+        self.collection
+            .insert_one(collection)
+            .session(&mut session)
+            .await
+            .map_err(query_error("Failed to insert collection in database"))?;
+
+        self.file
+            .insert_many(files)
+            .session(&mut session)
+            .await
+            .map_err(query_error("Failed to insert file in database"))?;
+        self.retain_chunks(chunks, &mut session).await?;
+
+        self.share_link
+            .insert_many(share_links)
+            .session(&mut session)
+            .await
+            .map_err(query_error("Failed to save the share document"))?;
+
+        session
+            .commit_transaction()
+            .await
+            .map_err(query_error("Failed to commit transaction"))?;
+
+        Ok(collection_id)
+    }
+
+    async fn get_collection(
+        &self,
+        collection_id: ObjectId,
+    ) -> Result<collection_model::Collection, Error> {
+        let collection = self
+            .collection
+            .find_one(doc! {"_id": collection_id})
+            .await
+            .map_err(query_error("Failed to fetch collection"))?;
+
+        collection.ok_or_else(|| actix_web::error::ErrorNotFound("Collection not found"))
+    }
+
+    async fn get_sent_collections(
+        &self,
+        user_id: ObjectId,
+    ) -> Result<Vec<SharedCollection>, Error> {
+        let pipeline = vec![
+            doc! {"$match": {"user_id": user_id}},
+            doc! {"$lookup": {
+                "from": "file",
+                "localField": "_id",
+                "foreignField": "collection_id",
+                "pipeline": [{"$project": {"file_size": 1}}],
+                "as": "file",
+            }},
+            doc! {"$unwind": "$file"},
+            doc! {"$lookup": {
+                "from": "share_link",
+                "localField": "file._id",
+                "foreignField": "file_id",
+                "as": "share",
+            }},
+            doc! {"$unwind": "$share"},
+            doc! {"$group": {
+                "_id": {"collection": "$_id", "recipient": "$share.recipient_user_id"},
+                "name": {"$first": "$name"},
+                "file_count": {"$sum": 1_i64},
+                "total_size": {"$sum": "$file.file_size"},
+                "created_at": {"$min": "$share.created_at"},
+                "expires_at": {"$max": "$share.expires_at"},
+            }},
+            doc! {"$lookup": {
+                "from": "user",
+                "localField": "_id.recipient",
+                "foreignField": "_id",
+                "as": "counterpart",
+            }},
+            doc! {"$unwind": "$counterpart"},
+            doc! {"$project": {
+                "_id": "$_id.collection",
+                "name": 1,
+                "file_count": 1,
+                "total_size": 1,
+                "created_at": 1,
+                "expires_at": 1,
+                "counterpart_email": "$counterpart.email",
+            }},
+            doc! {"$sort": {"created_at": -1, "_id": -1}},
+        ];
+
+        self.aggregate_collections(self.collection.clone_with_type(), pipeline)
+            .await
+    }
+
+    async fn get_received_collections(
+        &self,
+        user_id: ObjectId,
+    ) -> Result<Vec<SharedCollection>, Error> {
+        let pipeline = vec![
+            doc! {"$match": {
+                "recipient_user_id": user_id,
+                "revoked_at": null,
+                "declined_at": null,
+            }},
+            doc! {"$lookup": {
+                "from": "file",
+                "localField": "file_id",
+                "foreignField": "_id",
+                "pipeline": [{"$project": {"file_size": 1, "collection_id": 1, "user_id": 1, "scan_status": 1}}],
+                "as": "file",
+            }},
+            doc! {"$unwind": "$file"},
+            doc! {"$match": {
+                "file.collection_id": {"$ne": null},
+                "file.scan_status": {"$ne": ScanStatus::Quarantined.as_str()},
+            }},
+            doc! {"$group": {
+                "_id": "$file.collection_id",
+                "owner_id": {"$first": "$file.user_id"},
+                "file_count": {"$sum": 1_i64},
+                "total_size": {"$sum": "$file.file_size"},
+                "created_at": {"$min": "$created_at"},
+                "expires_at": {"$max": "$expires_at"},
+            }},
+            doc! {"$lookup": {
+                "from": "collection",
+                "localField": "_id",
+                "foreignField": "_id",
+                "as": "collection",
+            }},
+            doc! {"$unwind": "$collection"},
+            doc! {"$lookup": {
+                "from": "user",
+                "localField": "owner_id",
+                "foreignField": "_id",
+                "as": "counterpart",
+            }},
+            doc! {"$unwind": "$counterpart"},
+            doc! {"$project": {
+                "name": "$collection.name",
+                "file_count": 1,
+                "total_size": 1,
+                "created_at": 1,
+                "expires_at": 1,
+                "counterpart_email": "$counterpart.email",
+            }},
+            doc! {"$sort": {"created_at": -1, "_id": -1}},
+        ];
+
+        self.aggregate_collections(self.share_link.clone_with_type(), pipeline)
+            .await
+    }
+
+    async fn get_collection_shares(
+        &self,
+        collection_id: ObjectId,
+        recipient_user_id: ObjectId,
+    ) -> Result<Vec<ShareLink>, Error> {
+        let file_ids = self
+            .file
+            .distinct(
+                "_id",
+                doc! {
+                    "collection_id": collection_id,
+                    "scan_status": {"$ne": ScanStatus::Quarantined.as_str()},
+                },
+            )
+            .await
+            .map_err(query_error("Failed to fetch collection files"))?;
+
+        let cursor = self
+            .share_link
+            .find(doc! {
+                "file_id": {"$in": file_ids},
+                "recipient_user_id": recipient_user_id,
+                "revoked_at": null,
+                "declined_at": null,
+            })
+            .sort(doc! {"created_at": 1, "_id": 1})
+            .await
+            .map_err(query_error("Failed to fetch shared links"))?;
+
+        cursor
+            .try_collect()
+            .await
+            .map_err(query_error("Failed to fetch shared links"))
+    }
+
+    async fn get_file_share_links(&self, file_id: ObjectId) -> Result<Vec<ShareLink>, Error> {
+        let cursor = self
+            .share_link
+            .find(doc! {"file_id": file_id})
+            .await
+            .map_err(query_error("Failed to fetch shared links"))?;
+        cursor
+            .try_collect()
+            .await
+            .map_err(query_error("Failed to fetch shared links"))
+    }
+
+    async fn add_file_version(
+        &self,
+        file_id: ObjectId,
+        file_name: String,
+        file_size: i64,
+        encrypted_file: Vec<u8>,
+        iv: Vec<u8>,
+        content_type: String,
+        sha256: String,
+        signatures: Vec<(ObjectId, Vec<u8>)>,
+        scan_status: ScanStatus,
+        compression: Compression,
+        chunk_ids: Vec<String>,
+        chunks: Vec<Chunk>,
+        history: i64,
+        quota: i64,
+    ) -> Result<i64, Error> {
+        let mut session = self.start_transaction().await?;
+
+        let file = match self
+            .file
+            .find_one(doc! {"_id": file_id})
+            .session(&mut session)
+            .await
+            .map_err(query_error("Failed to fetch file"))?
+        {
+            Some(file) => file,
+            None => return Err(actix_web::error::ErrorNotFound("File not found")),
+        };
+
+        // A revision was current from the file's last update until now
+        self.file_version
+            .insert_one(FileVersion {
+                _id: ObjectId::new(),
+                file_id,
+                version: file.version,
+                file_name: file.file_name,
+                file_size: file.file_size,
+                content_type: file.content_type,
+                encrypted_file: file.encrypted_file,
+                iv: file.iv,
+                sha256: file.sha256,
+                created_at: file.updated_at,
+                compression: file.compression,
+                chunk_ids: file.chunk_ids,
+            })
+            .session(&mut session)
+            .await
+            .map_err(query_error("Failed to save file version"))?;
+
+        let version = file.version + 1;
+        let pruned = doc! {"file_id": file_id, "version": {"$lt": version - history}};
+        let mut cursor = self
+            .file_version
+            .clone_with_type::<Document>()
+            .find(pruned.clone())
+            .projection(doc! {"file_size": 1})
+            .session(&mut session)
+            .await
+            .map_err(query_error("Failed to fetch file versions"))?;
+        let mut pruned_size = 0;
+        while let Some(pruned_version) = cursor
+            .next(&mut session)
+            .await
+            .transpose()
+            .map_err(query_error("Failed to fetch file versions"))?
+        {
+            pruned_size += pruned_version.get_i64("file_size").unwrap_or(0);
+        }
+
+        // The superseded revision stays in the owner's storage until pruned
+        let growth = file_size - pruned_size;
+        let mut owner_filter = doc! {"_id": file.user_id};
+        if growth > 0 {
+            owner_filter.insert("storage_used", doc! {"$lte": quota - growth});
+        }
+        let usage = self
+            .user
+            .update_one(owner_filter, doc! {"$inc": {"storage_used": growth}})
+            .session(&mut session)
+            .await
+            .map_err(query_error("Failed to update storage usage"))?;
+
+        if usage.matched_count == 0 {
+            return Err(actix_web::error::ErrorPayloadTooLarge(
+                "Storage quota exceeded",
+            ));
+        }
+
+        let revision = File {
+            _id: file._id,
+            user_id: file.user_id,
+            file_name,
+            file_size,
+            content_type: Some(content_type),
+            encrypted_aes_key: file.encrypted_aes_key,
+            encrypted_file,
+            iv,
+            sha256: Some(sha256),
+            created_at: file.created_at,
+            updated_at: DateTime::now(),
+            collection_id: file.collection_id,
+            version,
+            scan_status,
+            compression,
+            chunk_ids,
+            organization_id: file.organization_id,
+            history_size: file.history_size + file.file_size - pruned_size,
+        };
+
+        let result = self
+            .file
+            .replace_one(doc! {"_id": file_id, "version": file.version}, revision)
+            .session(&mut session)
+            .await
+            .map_err(query_error("Failed to update file"))?;
+
+        if result.matched_count == 0 {
+            return Err(actix_web::error::ErrorConflict(
+                "File was updated by another request",
+            ));
+        }
+
+        for (share_id, signature) in signatures {
+            let signature = bson::to_bson(&signature).map_err(|e| {
+                actix_web::error::ErrorInternalServerError(format!(
+                    "Failed to encode share signature: {}",
+                    e
+                ))
+            })?;
+            self.share_link
+                .update_one(
+                    doc! {"_id": share_id, "file_id": file_id},
+                    doc! {"$set": {"signature": signature}},
+                )
+                .session(&mut session)
+                .await
+                .map_err(query_error("Failed to update share signature"))?;
+        }
+
+        // The superseded revision keeps its chunk references in the history
+        self.retain_chunks(chunks, &mut session).await?;
+
+        self.release_chunks(
+            self.file_version.clone_with_type(),
+            pruned.clone(),
+            &mut session,
+        )
+        .await?;
+        self.file_version
+            .delete_many(pruned)
+            .session(&mut session)
+            .await
+            .map_err(query_error("Failed to prune file versions"))?;
+
+        session
+            .commit_transaction()
+            .await
+            .map_err(query_error("Failed to commit transaction"))?;
+
+        Ok(version)
+    }
+
+    async fn get_file_versions(&self, file_id: ObjectId) -> Result<Vec<FileVersionSummary>, Error> {
+        let cursor = self
+            .file_version
+            .clone_with_type::<FileVersionSummary>()
+            .find(doc! {"file_id": file_id})
+            .projection(
+                doc! {"version": 1, "file_name": 1, "file_size": 1, "content_type": 1, "sha256": 1, "created_at": 1},
+            )
+            .sort(doc! {"version": -1})
+            .await
+            .map_err(query_error("Failed to fetch file versions"))?;
+        cursor
+            .try_collect()
+            .await
+            .map_err(query_error("Failed to fetch file versions"))
+    }
+
+    async fn get_file_version(
+        &self,
+        file_id: ObjectId,
+        version: i64,
+    ) -> Result<FileVersion, Error> {
+        let file_version = self
+            .file_version
+            .find_one(doc! {"file_id": file_id, "version": version})
+            .await
+            .map_err(query_error("Failed to fetch file version"))?;
+
+        file_version.ok_or_else(|| actix_web::error::ErrorNotFound("File version not found"))
+    }
+
+    async fn get_chunks(&self, chunk_ids: &[String]) -> Result<Vec<Vec<u8>>, Error> {
+        let cursor = self
+            .chunk
+            .find(doc! {"_id": {"$in": chunk_ids}})
+            .await
+            .map_err(query_error("Failed to fetch chunks"))?;
+        let chunks: Vec<Chunk> = cursor
+            .try_collect()
+            .await
+            .map_err(query_error("Failed to fetch chunks"))?;
+        let chunks: HashMap<String, Vec<u8>> = chunks
+            .into_iter()
+            .map(|chunk| (chunk._id, chunk.data))
+            .collect();
+
+        // A chunk may appear more than once in the list
+        chunk_ids
+            .iter()
+            .map(|chunk_id| {
+                chunks
+                    .get(chunk_id)
+                    .cloned()
+                    .ok_or_else(|| actix_web::error::ErrorNotFound("Chunk not found"))
+            })
+            .collect()
+    }
+
+    async fn record_audit_event(&self, event: AuditEvent) -> Result<(), Error> {
+        let _audit_guard = self.audit_lock.lock().await;
+
+        let mut attempt = 1;
+        loop {
+            let mut session = self.start_transaction().await?;
+            let result = match self.append_audit_event(event.clone(), &mut session).await {
+                Ok(()) => session.commit_transaction().await,
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(()) => return Ok(()),
+                Err(e)
+                    if attempt < AUDIT_APPEND_ATTEMPTS
+                        && e.contains_label(TRANSIENT_TRANSACTION_ERROR) =>
+                {
+                    attempt += 1
+                }
+                Err(e) => return Err(query_error("Failed to record audit event")(e)),
+            }
+        }
+    }
+
+    async fn get_audit_events(&self, query: AuditQuery) -> Result<Vec<AuditEvent>, Error> {
+        let mut conditions: Vec<Document> = Vec::new();
+        if let Some(user_id) = query.user_id {
+            conditions.push(doc! {"$or": [{"actor_id": user_id}, {"subject_user_id": user_id}]});
+        }
+        if let Some(share_id) = query.share_id {
+            conditions.push(doc! {"share_id": share_id});
+        }
+        if !query.actions.is_empty() {
+            let actions: Vec<&str> = query.actions.iter().map(AuditAction::as_str).collect();
+            conditions.push(doc! {"action": {"$in": actions}});
+        }
+        if let Some(before) = query.before {
+            let cursor_event = self
+                .audit_event
+                .find_one(doc! {"_id": before})
+                .await
+                .map_err(query_error("Failed to fetch audit events"))?
+                .ok_or_else(|| actix_web::error::ErrorBadRequest("Unknown audit cursor"))?;
+            conditions.push(doc! {"$or": [
+                {"created_at": {"$lt": cursor_event.created_at}},
+                {"created_at": cursor_event.created_at, "_id": {"$lt": before}},
+            ]});
+        }
+        let filter = if conditions.is_empty() {
+            doc! {}
+        } else {
+            doc! {"$and": conditions}
+        };
+
+        let cursor = self
+            .audit_event
+            .find(filter)
+            .sort(doc! {"created_at": -1, "_id": -1})
+            .limit(query.limit as i64)
+            .await
+            .map_err(query_error("Failed to fetch audit events"))?;
+        cursor
+            .try_collect()
+            .await
+            .map_err(query_error("Failed to fetch audit events"))
+    }
+
+    async fn get_audit_head(&self) -> Result<Option<AuditEvent>, Error> {
+        self.audit_event
+            .find_one(doc! {"sequence": {"$gt": 0}})
+            .sort(doc! {"sequence": -1})
+            .await
+            .map_err(query_error("Failed to fetch audit chain head"))
+    }
+
+    async fn get_audit_chain(
+        &self,
+        after_sequence: i64,
+        limit: usize,
+    ) -> Result<Vec<AuditEvent>, Error> {
+        let cursor = self
+            .audit_event
+            .find(doc! {"sequence": {"$gt": after_sequence}})
+            .sort(doc! {"sequence": 1})
+            .limit(limit as i64)
+            .await
+            .map_err(query_error("Failed to fetch audit chain"))?;
+        cursor
+            .try_collect()
+            .await
+            .map_err(query_error("Failed to fetch audit chain"))
+    }
+
+    async fn save_audit_checkpoint(&self, checkpoint: AuditCheckpoint) -> Result<(), Error> {
+        self.audit_checkpoint
+            .insert_one(checkpoint)
+            .await
+            .map_err(query_error("Failed to save audit checkpoint"))?;
+
+        Ok(())
+    }
+
+    async fn get_audit_checkpoints(&self) -> Result<Vec<AuditCheckpoint>, Error> {
+        let cursor = self
+            .audit_checkpoint
+            .find(doc! {})
+            .sort(doc! {"sequence": 1})
+            .await
+            .map_err(query_error("Failed to fetch audit checkpoints"))?;
+        cursor
+            .try_collect()
+            .await
+            .map_err(query_error("Failed to fetch audit checkpoints"))
+    }
+
+    async fn save_notifications(&self, notifications: Vec<Notification>) -> Result<(), Error> {
+        self.notification
+            .insert_many(notifications)
+            .await
+            .map_err(query_error("Failed to save notifications"))?;
+
+        Ok(())
+    }
+
+    async fn get_notifications(
+        &self,
+        query: NotificationQuery,
+    ) -> Result<Vec<Notification>, Error> {
+        let mut filter = doc! {"user_id": query.user_id};
+        if query.unread_only {
+            filter.insert("read_at", Bson::Null);
+        }
+        if let Some(before) = query.before {
+            let cursor_notification = self
+                .notification
+                .find_one(doc! {"_id": before})
+                .await
+                .map_err(query_error("Failed to fetch notifications"))?
+                .ok_or_else(|| actix_web::error::ErrorBadRequest("Unknown notification cursor"))?;
+            filter.insert(
+                "$or",
+                vec![
+                    doc! {"created_at": {"$lt": cursor_notification.created_at}},
+                    doc! {"created_at": cursor_notification.created_at, "_id": {"$lt": before}},
+                ],
+            );
+        }
+
+        let cursor = self
+            .notification
+            .find(filter)
+            .sort(doc! {"created_at": -1, "_id": -1})
+            .limit(query.limit as i64)
+            .await
+            .map_err(query_error("Failed to fetch notifications"))?;
+        cursor
+            .try_collect()
+            .await
+            .map_err(query_error("Failed to fetch notifications"))
+    }
+
+    async fn count_unread_notifications(&self, user_id: ObjectId) -> Result<u64, Error> {
+        self.notification
+            .count_documents(doc! {"user_id": user_id, "read_at": null})
+            .await
+            .map_err(query_error("Failed to count notifications"))
+    }
+
+    async fn mark_notification_read(
+        &self,
+        notification_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<(), Error> {
+        let result = self
+            .notification
+            .update_one(
+                doc! {"_id": notification_id, "user_id": user_id},
+                vec![doc! {"$set": {"read_at": {"$ifNull": ["$read_at", DateTime::now()]}}}],
+            )
+            .await
+            .map_err(query_error("Failed to update notification"))?;
+
+        if result.matched_count == 0 {
+            return Err(actix_web::error::ErrorNotFound("Notification not found"));
+        }
+
+        Ok(())
+    }
+
+    async fn mark_all_notifications_read(&self, user_id: ObjectId) -> Result<u64, Error> {
+        let result = self
+            .notification
+            .update_many(
+                doc! {"user_id": user_id, "read_at": null},
+                doc! {"$set": {"read_at": DateTime::now()}},
+            )
+            .await
+            .map_err(query_error("Failed to update notifications"))?;
+
+        Ok(result.modified_count)
+    }
+
+    async fn claim_expiring_shares(&self, window_ms: i64) -> Result<Vec<ShareLink>, Error> {
+        let now = DateTime::now();
+        let until = DateTime::from_millis(now.timestamp_millis() + window_ms);
+
+        let mut session = self.start_transaction().await?;
+
+        // A share whose expiry moved later since the last notice is due another
+        let mut cursor = self
+            .share_link
+            .find(doc! {
+                "revoked_at": null,
+                "declined_at": null,
+                "expires_at": {"$gt": now, "$lte": until},
+                "$or": [
+                    {"expiry_notified_at": null},
+                    {"$expr": {"$lt": [
+                        "$expiry_notified_at",
+                        {"$subtract": ["$expires_at", window_ms]},
+                    ]}},
+                ],
+            })
+            .session(&mut session)
+            .await
+            .map_err(query_error("Failed to fetch expiring shared links"))?;
+        let mut share_links: Vec<ShareLink> = Vec::new();
+        while let Some(share_link) = cursor
+            .next(&mut session)
+            .await
+            .transpose()
+            .map_err(query_error("Failed to fetch expiring shared links"))?
+        {
+            share_links.push(share_link);
+        }
+
+        let share_ids: Vec<ObjectId> = share_links.iter().map(|share| share._id).collect();
+        self.share_link
+            .update_many(
+                doc! {"_id": {"$in": share_ids}},
+                doc! {"$set": {"expiry_notified_at": now}},
+            )
+            .session(&mut session)
+            .await
+            .map_err(query_error("Failed to update shared links"))?;
+
+        session
+            .commit_transaction()
+            .await
+            .map_err(query_error("Failed to commit transaction"))?;
+
+        Ok(share_links)
+    }
+
+    async fn get_email_preferences(&self, user_id: ObjectId) -> Result<EmailPreferences, Error> {
+        let preferences = self
+            .email_preference
+            .find_one(doc! {"_id": user_id})
+            .await
+            .map_err(query_error("Failed to fetch email preferences"))?;
+
+        Ok(preferences.unwrap_or_else(|| EmailPreferences::new(user_id)))
+    }
+
+    async fn save_email_preferences(&self, preferences: EmailPreferences) -> Result<(), Error> {
+        self.email_preference
+            .replace_one(doc! {"_id": preferences._id}, &preferences)
+            .upsert(true)
+            .await
+            .map_err(query_error("Failed to save email preferences"))?;
+
+        Ok(())
+    }
+
+    async fn create_webhook(&self, webhook: Webhook) -> Result<(), Error> {
+        self.webhook
+            .insert_one(webhook)
+            .await
+            .map_err(query_error("Failed to save webhook"))?;
+
+        Ok(())
+    }
+
+    async fn get_webhooks(&self, user_id: Option<ObjectId>) -> Result<Vec<Webhook>, Error> {
+        let cursor = self
+            .webhook
+            .find(doc! {"user_id": user_id})
+            .sort(doc! {"created_at": 1})
+            .await
+            .map_err(query_error("Failed to fetch webhooks"))?;
+        cursor
+            .try_collect()
+            .await
+            .map_err(query_error("Failed to fetch webhooks"))
+    }
+
+    async fn get_webhook(&self, webhook_id: ObjectId) -> Result<Webhook, Error> {
+        self.webhook
+            .find_one(doc! {"_id": webhook_id})
+            .await
+            .map_err(query_error("Failed to fetch webhook"))?
+            .ok_or_else(|| actix_web::error::ErrorNotFound("Webhook not found"))
+    }
+
+    async fn delete_webhook(&self, webhook_id: ObjectId) -> Result<(), Error> {
+        let mut session = self.start_transaction().await?;
+
+        self.webhook_delivery
+            .delete_many(doc! {"webhook_id": webhook_id})
+            .session(&mut session)
+            .await
+            .map_err(query_error("Failed to delete webhook deliveries"))?;
+        let result = self
+            .webhook
+            .delete_one(doc! {"_id": webhook_id})
+            .session(&mut session)
+            .await
+            .map_err(query_error("Failed to delete webhook"))?;
+        if result.deleted_count == 0 {
+            return Err(actix_web::error::ErrorNotFound("Webhook not found"));
+        }
+
+        session
+            .commit_transaction()
+            .await
+            .map_err(query_error("Failed to commit transaction"))?;
+
+        Ok(())
+    }
+
+    async fn get_subscribed_webhooks(
+        &self,
+        user_ids: Vec<ObjectId>,
+    ) -> Result<Vec<Webhook>, Error> {
+        let cursor = self
+            .webhook
+            .find(doc! {"$or": [{"user_id": null}, {"user_id": {"$in": user_ids}}]})
+            .await
+            .map_err(query_error("Failed to fetch webhooks"))?;
+        cursor
+            .try_collect()
+            .await
+            .map_err(query_error("Failed to fetch webhooks"))
+    }
+
+    async fn save_webhook_deliveries(&self, deliveries: Vec<WebhookDelivery>) -> Result<(), Error> {
+        self.webhook_delivery
+            .insert_many(deliveries)
+            .await
+            .map_err(query_error("Failed to save webhook deliveries"))?;
+
+        Ok(())
+    }
+
+    async fn claim_due_deliveries(
+        &self,
+        limit: usize,
+        lease_ms: i64,
+    ) -> Result<Vec<WebhookDelivery>, Error> {
+        let now = DateTime::now();
+        let leased_until = DateTime::from_millis(now.timestamp_millis() + lease_ms);
+
+        // One at a time, so another worker can never claim the same delivery
+        let mut claimed = Vec::new();
+        while claimed.len() < limit {
+            let delivery = self
+                .webhook_delivery
+                .find_one_and_update(
+                    doc! {
+                        "status": DeliveryStatus::Pending.as_str(),
+                        "next_attempt_at": {"$lte": now},
+                    },
+                    doc! {"$set": {"next_attempt_at": leased_until}},
+                )
+                .sort(doc! {"next_attempt_at": 1, "_id": 1})
+                .await
+                .map_err(query_error("Failed to claim webhook delivery"))?;
+            match delivery {
+                Some(delivery) => claimed.push(delivery),
+                None => break,
+            }
+        }
+
+        Ok(claimed)
+    }
+
+    async fn update_webhook_delivery(&self, delivery: WebhookDelivery) -> Result<(), Error> {
+        let result = self
+            .webhook_delivery
+            .replace_one(doc! {"_id": delivery._id}, delivery)
+            .await
+            .map_err(query_error("Failed to update webhook delivery"))?;
+
+        if result.matched_count == 0 {
+            return Err(actix_web::error::ErrorNotFound("Webhook delivery not found"));
+        }
+
+        Ok(())
+    }
+
+    async fn get_webhook_deliveries(
+        &self,
+        query: WebhookDeliveryQuery,
+    ) -> Result<Vec<WebhookDelivery>, Error> {
+        let mut filter = doc! {"webhook_id": query.webhook_id};
+        if let Some(status) = query.status {
+            filter.insert("status", status.as_str());
+        }
+        if let Some(before) = query.before {
+            let cursor_delivery = self
+                .webhook_delivery
+                .find_one(doc! {"_id": before})
+                .await
+                .map_err(query_error("Failed to fetch webhook deliveries"))?
+                .ok_or_else(|| actix_web::error::ErrorBadRequest("Unknown delivery cursor"))?;
+            filter.insert(
+                "$or",
+                vec![
+                    doc! {"created_at": {"$lt": cursor_delivery.created_at}},
+                    doc! {"created_at": cursor_delivery.created_at, "_id": {"$lt": before}},
+                ],
+            );
+        }
+
+        let cursor = self
+            .webhook_delivery
+            .find(filter)
+            .sort(doc! {"created_at": -1, "_id": -1})
+            .limit(query.limit as i64)
+            .await
+            .map_err(query_error("Failed to fetch webhook deliveries"))?;
+        cursor
+            .try_collect()
+            .await
+            .map_err(query_error("Failed to fetch webhook deliveries"))
+    }
+
+    async fn get_webhook_delivery(
+        &self,
+        delivery_id: ObjectId,
+    ) -> Result<WebhookDelivery, Error> {
+        self.webhook_delivery
+            .find_one(doc! {"_id": delivery_id})
+            .await
+            .map_err(query_error("Failed to fetch webhook delivery"))?
+            .ok_or_else(|| actix_web::error::ErrorNotFound("Webhook delivery not found"))
+    }
+
+    async fn reconcile(&self) -> Result<(), Error> {
+        let cutoff =
+            DateTime::from_millis(DateTime::now().timestamp_millis() - RECONCILE_GRACE_PERIOD_MS);
+
+        // Files that no share link points at can never be retrieved, unless
+        // they are still waiting for an invitee to register
+        let mut orphaned_files = self
+            .find_unmatched_ids(
+                self.file.clone_with_type(),
+                "_id",
+                "share_link",
+                "file_id",
+                cutoff,
+            )
+            .await?;
+        let invited_file_ids = self
+            .invitation
+            .distinct("file_id", doc! {"file_id": {"$in": &orphaned_files}})
+            .await
+            .map_err(query_error("Failed to run reconcile query"))?;
+        orphaned_files.retain(|file_id| !invited_file_ids.contains(&Bson::ObjectId(*file_id)));
+
+        // Share links whose file is gone can only fail on retrieval
+        let dangling_shares = self
+            .find_unmatched_ids(
+                self.share_link.clone_with_type(),
+                "file_id",
+                "file",
+                "_id",
+                cutoff,
+            )
+            .await?;
+
+        let orphaned_versions = self
+            .find_unmatched_ids(
+                self.file_version.clone_with_type(),
+                "file_id",
+                "file",
+                "_id",
+                cutoff,
+            )
+            .await?;
+
+        // Collections whose files have all expired or been deleted
+        let empty_collections = self
+            .find_unmatched_ids(
+                self.collection.clone_with_type(),
+                "_id",
+                "file",
+                "collection_id",
+                cutoff,
+            )
+            .await?;
+
+        let dangling_group_shares = self
+            .find_unmatched_ids(
+                self.group_share.clone_with_type(),
+                "file_id",
+                "file",
+                "_id",
+                cutoff,
+            )
+            .await?;
+
+        let mut session = self.start_transaction().await?;
+
+        self.release_file_storage(&orphaned_files, &mut session)
+            .await?;
+
+        let delete_files_result = self
+            .file
+            .delete_many(doc! {"_id": {"$in": orphaned_files}})
+            .session(&mut session)
+            .await
+            .map_err(query_error("Failed to delete orphaned files"))?;
+
+        self.release_chunks(
+            self.file_version.clone_with_type(),
+            doc! {"_id": {"$in": &orphaned_versions}},
+            &mut session,
+        )
+        .await?;
+        let delete_versions_result = self
+            .file_version
+            .delete_many(doc! {"_id": {"$in": orphaned_versions}})
+            .session(&mut session)
+            .await
+            .map_err(query_error("Failed to delete orphaned file versions"))?;
+
+        let delete_shared_links_result = self
+            .share_link
+            .delete_many(doc! {"_id": {"$in": dangling_shares}})
+            .session(&mut session)
+            .await
+            .map_err(query_error("Failed to delete dangling shared links"))?;
+
+        let delete_collections_result = self
+            .collection
+            .delete_many(doc! {"_id": {"$in": empty_collections}})
+            .session(&mut session)
+            .await
+            .map_err(query_error("Failed to delete empty collections"))?;
+
+        self.group_share
+            .delete_many(doc! {"_id": {"$in": dangling_group_shares}})
+            .session(&mut session)
+            .await
+            .map_err(query_error("Failed to delete dangling group shares"))?;
+
+        session
+            .commit_transaction()
+            .await
+            .map_err(query_error("Failed to commit transaction"))?;
+
+        println!(
+            "Reconciled {} orphaned files, {} orphaned file versions, {} dangling shared links and {} empty collections.",
+            delete_files_result.deleted_count,
+            delete_versions_result.deleted_count,
+            delete_shared_links_result.deleted_count,
+            delete_collections_result.deleted_count
+        );
+
+        Ok(())
+    }
+}
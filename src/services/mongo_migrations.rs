@@ -0,0 +1,583 @@
+use futures_util::{future::BoxFuture, TryStreamExt};
+use mongodb::{
+    bson::{doc, oid::ObjectId, DateTime, Document},
+    error::Result,
+    options::IndexOptions,
+    Collection, Database, IndexModel,
+};
+
+use crate::models::migration_model::Migration;
+
+struct MigrationStep {
+    version: i64,
+    description: &'static str,
+    up: for<'a> fn(&'a Database) -> BoxFuture<'a, Result<()>>,
+}
+
+// Applied in order at startup; never edit or reorder a step once it has shipped
+const MIGRATIONS: &[MigrationStep] = &[
+    MigrationStep {
+        version: 1,
+        description: "create unique index on user.email",
+        up: create_user_email_index,
+    },
+    MigrationStep {
+        version: 2,
+        description: "rename share_link.reciepents_user_id to recipient_user_id",
+        up: rename_share_link_recipient_field,
+    },
+    MigrationStep {
+        version: 3,
+        description: "create listing indexes on file and share_link",
+        up: create_listing_indexes,
+    },
+    MigrationStep {
+        version: 4,
+        description: "create unique index on share_link (file_id, recipient_user_id)",
+        up: create_share_recipient_index,
+    },
+    MigrationStep {
+        version: 5,
+        description: "create index on share_link.parent_share_id",
+        up: create_share_parent_index,
+    },
+    MigrationStep {
+        version: 6,
+        description: "create collection indexes",
+        up: create_collection_indexes,
+    },
+    MigrationStep {
+        version: 7,
+        description: "set file.version and create unique index on file_version (file_id, version)",
+        up: create_file_versions,
+    },
+    MigrationStep {
+        version: 8,
+        description: "set user.storage_used from the sizes of their files",
+        up: set_user_storage_used,
+    },
+    MigrationStep {
+        version: 9,
+        description: "create audit_event indexes",
+        up: create_audit_event_indexes,
+    },
+    MigrationStep {
+        version: 10,
+        description: "create unique index on audit_event.sequence and audit_checkpoint index",
+        up: create_audit_chain_indexes,
+    },
+    MigrationStep {
+        version: 11,
+        description: "create index on audit_event.share_id",
+        up: create_audit_share_index,
+    },
+    MigrationStep {
+        version: 12,
+        description: "create notification index on (user_id, created_at)",
+        up: create_notification_index,
+    },
+    MigrationStep {
+        version: 13,
+        description: "create webhook and webhook_delivery indexes",
+        up: create_webhook_indexes,
+    },
+    MigrationStep {
+        version: 14,
+        description: "create invitation indexes on email, sender and expiry",
+        up: create_invitation_indexes,
+    },
+    MigrationStep {
+        version: 15,
+        description: "create group and group_share indexes",
+        up: create_group_indexes,
+    },
+    MigrationStep {
+        version: 16,
+        description: "create organization indexes and unique index on organization.domains",
+        up: create_organization_indexes,
+    },
+    MigrationStep {
+        version: 17,
+        description: "set file.history_size and count version history in user.storage_used",
+        up: set_file_history_size,
+    },
+    MigrationStep {
+        version: 18,
+        description: "lowercase user.email and create unique index on user.email_verification_token",
+        up: create_email_verification,
+    },
+];
+
+pub async fn run(db: &Database) -> Result<()> {
+    let migrations: Collection<Migration> = db.collection("_migrations");
+
+    migrations
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! {"version": 1})
+                .options(IndexOptions::builder().unique(true).build())
+                .build(),
+        )
+        .await?;
+
+    let applied: Vec<i64> = migrations
+        .find(doc! {})
+        .await?
+        .try_collect::<Vec<Migration>>()
+        .await?
+        .into_iter()
+        .map(|migration| migration.version)
+        .collect();
+
+    for step in MIGRATIONS {
+        if applied.contains(&step.version) {
+            continue;
+        }
+
+        println!("Applying migration {}: {}", step.version, step.description);
+        (step.up)(db).await?;
+
+        migrations
+            .insert_one(Migration {
+                _id: ObjectId::new(),
+                version: step.version,
+                description: step.description.to_string(),
+                applied_at: DateTime::now(),
+            })
+            .await?;
+    }
+
+    Ok(())
+}
+
+fn create_user_email_index(db: &Database) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move {
+        check_duplicate_emails(db).await?;
+
+        db.collection::<Document>("user")
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! {"email": 1})
+                    .options(IndexOptions::builder().unique(true).build())
+                    .build(),
+            )
+            .await?;
+        Ok(())
+    })
+}
+
+// The unique index cannot be built over accounts that share an email, and
+// which of them to keep is for an operator to decide, so the migration stops
+// and lists them until they are merged or renamed
+async fn check_duplicate_emails(db: &Database) -> Result<()> {
+    let duplicates: Vec<Document> = db
+        .collection::<Document>("user")
+        .aggregate(vec![
+            doc! {"$group": {
+                "_id": {"$toLower": "$email"},
+                "user_ids": {"$push": "$_id"},
+                "count": {"$sum": 1},
+            }},
+            doc! {"$match": {"count": {"$gt": 1}}},
+            doc! {"$sort": {"_id": 1}},
+        ])
+        .await?
+        .try_collect()
+        .await?;
+
+    if duplicates.is_empty() {
+        return Ok(());
+    }
+
+    let accounts: Vec<String> = duplicates
+        .iter()
+        .map(|duplicate| {
+            let user_ids: Vec<String> = duplicate
+                .get_array("user_ids")
+                .map(|user_ids| {
+                    user_ids
+                        .iter()
+                        .filter_map(|user_id| user_id.as_object_id())
+                        .map(|user_id| user_id.to_hex())
+                        .collect()
+                })
+                .unwrap_or_default();
+            format!(
+                "{} (users {})",
+                duplicate.get_str("_id").unwrap_or_default(),
+                user_ids.join(", ")
+            )
+        })
+        .collect();
+
+    Err(std::io::Error::other(format!(
+        "{} emails belong to more than one user, ignoring case; merge or rename these accounts before restarting: {}",
+        duplicates.len(),
+        accounts.join("; ")
+    ))
+    .into())
+}
+
+fn rename_share_link_recipient_field(db: &Database) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move {
+        db.collection::<Document>("share_link")
+            .update_many(
+                doc! {"reciepents_user_id": {"$exists": true}},
+                doc! {"$rename": {"reciepents_user_id": "recipient_user_id"}},
+            )
+            .await?;
+        Ok(())
+    })
+}
+
+fn create_listing_indexes(db: &Database) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move {
+        db.collection::<Document>("file")
+            .create_index(IndexModel::builder().keys(doc! {"user_id": 1}).build())
+            .await?;
+
+        // expires_at is a plain index rather than a TTL index: expired shares
+        // must be removed by delete_expired_files so their file goes with them
+        db.collection::<Document>("share_link")
+            .create_indexes(vec![
+                IndexModel::builder().keys(doc! {"file_id": 1}).build(),
+                IndexModel::builder()
+                    .keys(doc! {"recipient_user_id": 1})
+                    .build(),
+                IndexModel::builder().keys(doc! {"expires_at": 1}).build(),
+            ])
+            .await?;
+        Ok(())
+    })
+}
+
+fn create_share_recipient_index(db: &Database) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move {
+        db.collection::<Document>("share_link")
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! {"file_id": 1, "recipient_user_id": 1})
+                    .options(IndexOptions::builder().unique(true).build())
+                    .build(),
+            )
+            .await?;
+        Ok(())
+    })
+}
+
+fn create_share_parent_index(db: &Database) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move {
+        db.collection::<Document>("share_link")
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! {"parent_share_id": 1})
+                    .build(),
+            )
+            .await?;
+        Ok(())
+    })
+}
+
+fn create_collection_indexes(db: &Database) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move {
+        db.collection::<Document>("collection")
+            .create_index(IndexModel::builder().keys(doc! {"user_id": 1}).build())
+            .await?;
+
+        db.collection::<Document>("file")
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! {"collection_id": 1})
+                    .build(),
+            )
+            .await?;
+        Ok(())
+    })
+}
+
+fn create_file_versions(db: &Database) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move {
+        db.collection::<Document>("file")
+            .update_many(
+                doc! {"version": {"$exists": false}},
+                doc! {"$set": {"version": 1_i64}},
+            )
+            .await?;
+
+        db.collection::<Document>("file_version")
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! {"file_id": 1, "version": 1})
+                    .options(IndexOptions::builder().unique(true).build())
+                    .build(),
+            )
+            .await?;
+        Ok(())
+    })
+}
+
+fn set_user_storage_used(db: &Database) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move {
+        let users = db.collection::<Document>("user");
+        users
+            .update_many(doc! {}, doc! {"$set": {"storage_used": 0_i64}})
+            .await?;
+
+        let totals: Vec<Document> = db
+            .collection::<Document>("file")
+            .aggregate(vec![
+                doc! {"$group": {"_id": "$user_id", "storage_used": {"$sum": "$file_size"}}},
+            ])
+            .await?
+            .try_collect()
+            .await?;
+
+        for total in totals {
+            let (Ok(user_id), Ok(storage_used)) =
+                (total.get_object_id("_id"), total.get_i64("storage_used"))
+            else {
+                continue;
+            };
+            users
+                .update_one(
+                    doc! {"_id": user_id},
+                    doc! {"$set": {"storage_used": storage_used}},
+                )
+                .await?;
+        }
+        Ok(())
+    })
+}
+
+fn create_audit_event_indexes(db: &Database) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move {
+        db.collection::<Document>("audit_event")
+            .create_indexes(vec![
+                IndexModel::builder()
+                    .keys(doc! {"created_at": -1, "_id": -1})
+                    .build(),
+                IndexModel::builder().keys(doc! {"actor_id": 1}).build(),
+                IndexModel::builder()
+                    .keys(doc! {"subject_user_id": 1})
+                    .build(),
+            ])
+            .await?;
+        Ok(())
+    })
+}
+
+fn create_audit_chain_indexes(db: &Database) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move {
+        // Events recorded before chaining have no sequence
+        db.collection::<Document>("audit_event")
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! {"sequence": 1})
+                    .options(
+                        IndexOptions::builder()
+                            .unique(true)
+                            .partial_filter_expression(doc! {"sequence": {"$gt": 0}})
+                            .build(),
+                    )
+                    .build(),
+            )
+            .await?;
+        db.collection::<Document>("audit_checkpoint")
+            .create_index(IndexModel::builder().keys(doc! {"sequence": 1}).build())
+            .await?;
+        Ok(())
+    })
+}
+
+fn create_audit_share_index(db: &Database) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move {
+        db.collection::<Document>("audit_event")
+            .create_index(IndexModel::builder().keys(doc! {"share_id": 1}).build())
+            .await?;
+        Ok(())
+    })
+}
+
+fn create_notification_index(db: &Database) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move {
+        db.collection::<Document>("notification")
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! {"user_id": 1, "created_at": -1, "_id": -1})
+                    .build(),
+            )
+            .await?;
+        Ok(())
+    })
+}
+
+fn create_webhook_indexes(db: &Database) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move {
+        db.collection::<Document>("webhook")
+            .create_index(IndexModel::builder().keys(doc! {"user_id": 1}).build())
+            .await?;
+        db.collection::<Document>("webhook_delivery")
+            .create_indexes(vec![
+                IndexModel::builder()
+                    .keys(doc! {"status": 1, "next_attempt_at": 1})
+                    .build(),
+                IndexModel::builder()
+                    .keys(doc! {"webhook_id": 1, "created_at": -1, "_id": -1})
+                    .build(),
+            ])
+            .await?;
+        Ok(())
+    })
+}
+
+fn create_invitation_indexes(db: &Database) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move {
+        db.collection::<Document>("invitation")
+            .create_indexes(vec![
+                IndexModel::builder().keys(doc! {"email": 1}).build(),
+                IndexModel::builder()
+                    .keys(doc! {"sender_id": 1, "created_at": -1})
+                    .build(),
+                IndexModel::builder().keys(doc! {"file_id": 1}).build(),
+                IndexModel::builder().keys(doc! {"expires_at": 1}).build(),
+            ])
+            .await?;
+        Ok(())
+    })
+}
+
+fn create_group_indexes(db: &Database) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move {
+        db.collection::<Document>("group")
+            .create_index(IndexModel::builder().keys(doc! {"members": 1}).build())
+            .await?;
+        db.collection::<Document>("group_share")
+            .create_indexes(vec![
+                IndexModel::builder()
+                    .keys(doc! {"group_id": 1, "created_at": 1})
+                    .build(),
+                IndexModel::builder().keys(doc! {"file_id": 1}).build(),
+                IndexModel::builder().keys(doc! {"expires_at": 1}).build(),
+            ])
+            .await?;
+        Ok(())
+    })
+}
+
+fn create_organization_indexes(db: &Database) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move {
+        // Organizations without domains would otherwise collide on the empty array
+        db.collection::<Document>("organization")
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! {"domains": 1})
+                    .options(
+                        IndexOptions::builder()
+                            .unique(true)
+                            .partial_filter_expression(doc! {"domains": {"$type": "string"}})
+                            .build(),
+                    )
+                    .build(),
+            )
+            .await?;
+        db.collection::<Document>("user")
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! {"organization_id": 1})
+                    .build(),
+            )
+            .await?;
+        db.collection::<Document>("share_link")
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! {"recipient_user_id": 1, "organization_id": 1})
+                    .build(),
+            )
+            .await?;
+        Ok(())
+    })
+}
+
+fn set_file_history_size(db: &Database) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move {
+        let files = db.collection::<Document>("file");
+        files
+            .update_many(doc! {}, doc! {"$set": {"history_size": 0_i64}})
+            .await?;
+
+        let totals: Vec<Document> = db
+            .collection::<Document>("file_version")
+            .aggregate(vec![
+                doc! {"$group": {"_id": "$file_id", "history_size": {"$sum": "$file_size"}}},
+            ])
+            .await?
+            .try_collect()
+            .await?;
+
+        for total in totals {
+            let (Ok(file_id), Ok(history_size)) =
+                (total.get_object_id("_id"), total.get_i64("history_size"))
+            else {
+                continue;
+            };
+            let Some(file) = files
+                .find_one_and_update(
+                    doc! {"_id": file_id},
+                    doc! {"$set": {"history_size": history_size}},
+                )
+                .await?
+            else {
+                continue;
+            };
+            let Ok(user_id) = file.get_object_id("user_id") else {
+                continue;
+            };
+            db.collection::<Document>("user")
+                .update_one(
+                    doc! {"_id": user_id},
+                    doc! {"$inc": {"storage_used": history_size}},
+                )
+                .await?;
+        }
+        Ok(())
+    })
+}
+
+// Emails are matched lowercased from now on; accounts differing only in case
+// have to be merged or renamed first
+fn create_email_verification(db: &Database) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move {
+        check_duplicate_emails(db).await?;
+
+        let users = db.collection::<Document>("user");
+        users
+            .update_many(
+                doc! {},
+                vec![doc! {"$set": {"email": {"$toLower": {"$trim": {"input": "$email"}}}}}],
+            )
+            .await?;
+        // Accounts from before verification existed count as verified
+        users
+            .update_many(
+                doc! {"email_verified_at": {"$exists": false}},
+                vec![doc! {"$set": {"email_verified_at": "$created_at"}}],
+            )
+            .await?;
+        users
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! {"email_verification_token": 1})
+                    .options(
+                        IndexOptions::builder()
+                            .unique(true)
+                            .partial_filter_expression(
+                                doc! {"email_verification_token": {"$type": "string"}},
+                            )
+                            .build(),
+                    )
+                    .build(),
+            )
+            .await?;
+        Ok(())
+    })
+}
@@ -0,0 +1,205 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use actix_web::Error;
+use mongodb::bson::{oid::ObjectId, Bson, DateTime};
+use tokio::sync::mpsc::{self, error::TrySendError};
+
+use crate::{
+    models::notification_model::{Notification, NotificationKind},
+    services::{
+        db::Database,
+        mail_templates::{notification_email, EmailContext},
+        mailer::{Email, Mailer},
+    },
+};
+
+pub const DEFAULT_PAGE_SIZE: usize = 50;
+pub const MAX_PAGE_SIZE: usize = 200;
+
+// Recipients are told once a share will expire within this window
+pub const EXPIRING_SOON_WINDOW_MS: i64 = 24 * 60 * 60 * 1000;
+
+// Notifications a slow stream may fall behind by before it misses some; the
+// inbox still has them
+const STREAM_BUFFER: usize = 64;
+
+#[derive(Debug, Clone)]
+pub struct NotificationQuery {
+    pub user_id: ObjectId,
+    pub unread_only: bool,
+    // Id of the last notification of the previous page, newest first
+    pub before: Option<ObjectId>,
+    pub limit: usize,
+}
+
+// Fans notifications out to the event streams open on this server instance,
+// and by email to users who want them
+pub struct Notifier {
+    subscribers: Mutex<HashMap<ObjectId, Vec<mpsc::Sender<Notification>>>>,
+    mailer: Arc<dyn Mailer>,
+}
+
+impl Notifier {
+    pub fn new(mailer: Arc<dyn Mailer>) -> Self {
+        Notifier {
+            subscribers: Mutex::new(HashMap::new()),
+            mailer,
+        }
+    }
+
+    pub fn subscribe(&self, user_id: ObjectId) -> mpsc::Receiver<Notification> {
+        let (sender, receiver) = mpsc::channel(STREAM_BUFFER);
+        let mut subscribers = self.subscribers.lock().unwrap_or_else(|e| e.into_inner());
+        let senders = subscribers.entry(user_id).or_default();
+        senders.retain(|sender| !sender.is_closed());
+        senders.push(sender);
+        receiver
+    }
+
+    pub fn publish(&self, notification: &Notification) {
+        let mut subscribers = self.subscribers.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(senders) = subscribers.get_mut(&notification.user_id) else {
+            return;
+        };
+        senders.retain(|sender| {
+            !matches!(
+                sender.try_send(notification.clone()),
+                Err(TrySendError::Closed(_))
+            )
+        });
+        if senders.is_empty() {
+            subscribers.remove(&notification.user_id);
+        }
+    }
+
+    pub fn mail_enabled(&self) -> bool {
+        self.mailer.enabled()
+    }
+
+    // Sent in the background so a slow mail server never holds up the request
+    pub fn send_emails(&self, emails: Vec<Email>) {
+        if emails.is_empty() || !self.mailer.enabled() {
+            return;
+        }
+        let mailer = self.mailer.clone();
+        tokio::spawn(async move {
+            for email in emails {
+                if let Err(e) = mailer.send(&email).await {
+                    eprintln!("Failed to send email to {}: {}", email.to, e);
+                }
+            }
+        });
+    }
+}
+
+pub fn notification(user_id: ObjectId, kind: NotificationKind) -> Notification {
+    Notification {
+        _id: ObjectId::new(),
+        user_id,
+        kind,
+        actor_id: None,
+        share_id: None,
+        file_id: None,
+        collection_id: None,
+        name: None,
+        read_at: None,
+        created_at: DateTime::now(),
+    }
+}
+
+// Stores the notifications in their users' inboxes, then pushes them live.
+// Notifying never fails the request that raised it; a lost notification is
+// logged instead
+pub async fn notify(db: &dyn Database, notifier: &Notifier, notifications: Vec<Notification>) {
+    if notifications.is_empty() {
+        return;
+    }
+    if let Err(e) = db.save_notifications(notifications.clone()).await {
+        eprintln!("Failed to save notifications: {}", e);
+        return;
+    }
+    for notification in &notifications {
+        notifier.publish(notification);
+    }
+
+    if !notifier.mail_enabled() {
+        return;
+    }
+    let mut emails = Vec::new();
+    for notification in &notifications {
+        if let Some(email) = compose_email(db, notification).await {
+            emails.push(email);
+        }
+    }
+    notifier.send_emails(emails);
+}
+
+// None when the user turned this kind of email off or cannot be looked up
+async fn compose_email(db: &dyn Database, notification: &Notification) -> Option<Email> {
+    let preferences = db.get_email_preferences(notification.user_id).await.ok()?;
+    if !preferences.allows(notification.kind) {
+        return None;
+    }
+    let to = db
+        .get_user_by_id(Bson::ObjectId(notification.user_id))
+        .await
+        .ok()?
+        .email;
+
+    let actor_email = match notification.actor_id {
+        Some(actor_id) => db
+            .get_user_by_id(Bson::ObjectId(actor_id))
+            .await
+            .ok()
+            .map(|actor| actor.email),
+        None => None,
+    };
+    let expires_at = match (notification.share_id, notification.collection_id) {
+        (Some(share_id), _) => db
+            .get_share_link(share_id)
+            .await
+            .ok()
+            .map(|share_link| share_link.expires_at),
+        (None, Some(collection_id)) => db
+            .get_collection_shares(collection_id, notification.user_id)
+            .await
+            .ok()
+            .and_then(|share_links| share_links.iter().map(|share| share.expires_at).min()),
+        (None, None) => None,
+    };
+
+    Some(notification_email(
+        to,
+        notification,
+        &EmailContext {
+            actor_email,
+            expires_at,
+        },
+    ))
+}
+
+// Tells recipients about their shares expiring within the window, once per expiry date
+pub async fn notify_expiring_shares(db: &dyn Database, notifier: &Notifier) -> Result<(), Error> {
+    let share_links = db.claim_expiring_shares(EXPIRING_SOON_WINDOW_MS).await?;
+
+    let mut notifications = Vec::with_capacity(share_links.len());
+    for share_link in share_links {
+        let name = db
+            .get_file(Bson::ObjectId(share_link.file_id))
+            .await
+            .ok()
+            .map(|file| file.file_name);
+        notifications.push(Notification {
+            share_id: Some(share_link._id),
+            file_id: Some(share_link.file_id),
+            name,
+            ..notification(share_link.recipient_user_id, NotificationKind::ExpiringSoon)
+        });
+    }
+    notify(db, notifier, notifications).await;
+
+    Ok(())
+}
@@ -0,0 +1,181 @@
+use actix_web::{Error, HttpRequest};
+use mongodb::bson::{oid::ObjectId, DateTime};
+
+use crate::{
+    config::Config,
+    models::{
+        audit_event_model::{AuditAction, AuditEvent},
+        organization_model::{email_domain, Organization},
+        user_model::User,
+    },
+    services::{
+        audit::{self, request_event},
+        db::Database,
+    },
+};
+
+const DAY_MS: i64 = 24 * 60 * 60 * 1000;
+
+// The organization behind a user's, file's or share's organization_id, if any
+pub async fn load_organization(
+    db: &dyn Database,
+    organization_id: Option<ObjectId>,
+) -> Result<Option<Organization>, Error> {
+    match organization_id {
+        Some(organization_id) => Ok(Some(db.get_organization(organization_id).await?)),
+        None => Ok(None),
+    }
+}
+
+// The organization an account with this email joins once it is verified
+pub async fn organization_for_email(
+    db: &dyn Database,
+    email: &str,
+) -> Result<Option<Organization>, Error> {
+    db.get_organization_by_domain(email_domain(email)).await
+}
+
+// Puts a user who just verified their email into the organization claiming
+// its domain. Anyone can register any address, so this never happens before.
+// A failed join is logged; an admin can still add the user by hand
+pub async fn join_by_domain(db: &dyn Database, req: &HttpRequest, user: &mut User) {
+    if user.organization_id.is_some() {
+        return;
+    }
+    let organization = match organization_for_email(db, &user.email).await {
+        Ok(Some(organization)) => organization,
+        Ok(None) => return,
+        Err(e) => {
+            eprintln!("Failed to fetch the organization for {}: {}", user.email, e);
+            return;
+        }
+    };
+    if let Err(e) = db
+        .join_organization(organization._id, user._id, false)
+        .await
+    {
+        eprintln!(
+            "Failed to add {} to {}: {}",
+            user.email, organization.name, e
+        );
+        return;
+    }
+    user.organization_id = Some(organization._id);
+
+    let event = AuditEvent {
+        actor_id: Some(user._id),
+        subject_user_id: Some(user._id),
+        detail: Some(format!("Organization {}", organization.name)),
+        ..request_event(AuditAction::OrganizationJoined, req)
+    };
+    audit::record(db, event).await;
+}
+
+// Tenants are isolated, so shares only go to users of the organization the
+// sender or the file belongs to
+pub fn ensure_same_organization(
+    organization_id: Option<ObjectId>,
+    recipient: &User,
+) -> Result<(), Error> {
+    if organization_id != recipient.organization_id {
+        return Err(actix_web::error::ErrorForbidden(
+            "Recipients must belong to your organization",
+        ));
+    }
+
+    Ok(())
+}
+
+// An invitee lands in whichever organization claims their domain when they
+// verify their email, which has to be the sender's for the invitation to be
+// accepted
+pub async fn check_invitee(db: &dyn Database, sender: &User, email: &str) -> Result<(), Error> {
+    let organization = organization_for_email(db, email).await?;
+    if organization.map(|organization| organization._id) != sender.organization_id {
+        return Err(actix_web::error::ErrorForbidden(
+            "Recipients must belong to your organization",
+        ));
+    }
+
+    Ok(())
+}
+
+// Bytes of active files each member may hold
+pub fn storage_quota(config: &Config, organization: Option<&Organization>) -> i64 {
+    organization
+        .and_then(|organization| organization.storage_quota)
+        .unwrap_or(config.user_storage_quota)
+}
+
+// Rejects expiry dates past the longest share the organization allows
+pub fn check_expiry(
+    organization: Option<&Organization>,
+    expires_at: DateTime,
+) -> Result<(), Error> {
+    let Some((name, max_expiry_days)) = organization.and_then(|organization| {
+        organization
+            .max_expiry_days
+            .map(|days| (&organization.name, days))
+    }) else {
+        return Ok(());
+    };
+
+    // A limit too large to represent cannot be exceeded
+    let Some(latest) = max_expiry_days
+        .checked_mul(DAY_MS)
+        .and_then(|max_expiry_ms| {
+            DateTime::now()
+                .timestamp_millis()
+                .checked_add(max_expiry_ms)
+        })
+    else {
+        return Ok(());
+    };
+    if expires_at.timestamp_millis() > latest {
+        return Err(actix_web::error::ErrorBadRequest(format!(
+            "Shares in {} may last at most {} days",
+            name, max_expiry_days
+        )));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn organization(max_expiry_days: Option<i64>) -> Organization {
+        Organization {
+            _id: ObjectId::new(),
+            name: "Acme".to_string(),
+            domains: vec!["acme.com".to_string()],
+            max_expiry_days,
+            storage_quota: None,
+            admins: Vec::new(),
+            created_at: DateTime::now(),
+            updated_at: DateTime::now(),
+        }
+    }
+
+    fn in_days(days: i64) -> DateTime {
+        DateTime::from_millis(DateTime::now().timestamp_millis() + days * DAY_MS)
+    }
+
+    #[test]
+    fn allows_expiry_within_the_limit() {
+        assert!(check_expiry(Some(&organization(Some(7))), in_days(6)).is_ok());
+        assert!(check_expiry(Some(&organization(None)), in_days(365)).is_ok());
+        assert!(check_expiry(None, in_days(365)).is_ok());
+    }
+
+    #[test]
+    fn rejects_expiry_past_the_limit() {
+        assert!(check_expiry(Some(&organization(Some(7))), in_days(8)).is_err());
+    }
+
+    #[test]
+    fn does_not_overflow_on_huge_limits() {
+        assert!(check_expiry(Some(&organization(Some(i64::MAX))), in_days(365)).is_ok());
+    }
+}
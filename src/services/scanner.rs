@@ -0,0 +1,302 @@
+use std::time::Duration;
+
+use actix_web::{error, Error};
+use async_trait::async_trait;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{TcpStream, UnixStream},
+    time,
+};
+
+use crate::models::file_model::ScanStatus;
+
+// clamd rejects INSTREAM chunks above its StreamMaxLength, so send small ones
+const CLAMD_CHUNK_SIZE: usize = 64 * 1024;
+const CLAMD_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScanVerdict {
+    Clean,
+    // Name of the signature that matched
+    Infected(String),
+}
+
+// Inspects plaintext before it is encrypted and delivered
+#[async_trait]
+pub trait Scanner: Send + Sync {
+    // Whether verdicts mean anything; the no-op scanner passes everything
+    fn enabled(&self) -> bool;
+
+    async fn scan(&self, data: &[u8]) -> Result<ScanVerdict, Error>;
+}
+
+pub struct NoopScanner;
+
+#[async_trait]
+impl Scanner for NoopScanner {
+    fn enabled(&self) -> bool {
+        false
+    }
+
+    async fn scan(&self, _data: &[u8]) -> Result<ScanVerdict, Error> {
+        Ok(ScanVerdict::Clean)
+    }
+}
+
+// Streams content to a clamd daemon over TCP ("host:port") or a Unix socket
+// ("unix:/path" or an absolute path)
+pub struct ClamdScanner {
+    address: String,
+    timeout: Duration,
+}
+
+impl ClamdScanner {
+    pub fn new(address: String) -> Self {
+        ClamdScanner {
+            address,
+            timeout: CLAMD_TIMEOUT,
+        }
+    }
+
+    async fn request(&self, data: &[u8]) -> std::io::Result<String> {
+        match self.address.strip_prefix("unix:") {
+            Some(path) => instream(UnixStream::connect(path).await?, data).await,
+            None if self.address.starts_with('/') => {
+                instream(UnixStream::connect(&self.address).await?, data).await
+            }
+            None => instream(TcpStream::connect(&self.address).await?, data).await,
+        }
+    }
+}
+
+#[async_trait]
+impl Scanner for ClamdScanner {
+    fn enabled(&self) -> bool {
+        true
+    }
+
+    // Fails closed: an unreachable daemon rejects the upload rather than
+    // letting it through unscanned
+    async fn scan(&self, data: &[u8]) -> Result<ScanVerdict, Error> {
+        let reply = time::timeout(self.timeout, self.request(data))
+            .await
+            .map_err(|_| error::ErrorServiceUnavailable("Malware scan timed out"))?
+            .map_err(|e| error::ErrorServiceUnavailable(format!("Malware scan failed: {}", e)))?;
+
+        parse_reply(&reply)
+    }
+}
+
+// Scans an upload and returns the status to store with it, plus the matched
+// signature when it has to be quarantined
+pub async fn scan_upload(
+    scanner: &dyn Scanner,
+    file_name: &str,
+    data: &[u8],
+) -> Result<(ScanStatus, Option<String>), Error> {
+    if !scanner.enabled() {
+        return Ok((ScanStatus::NotScanned, None));
+    }
+
+    match scanner.scan(data).await? {
+        ScanVerdict::Clean => Ok((ScanStatus::Clean, None)),
+        ScanVerdict::Infected(signature) => {
+            println!("Quarantined upload {}: {} found", file_name, signature);
+            Ok((ScanStatus::Quarantined, Some(signature)))
+        }
+    }
+}
+
+async fn instream<S>(mut stream: S, data: &[u8]) -> std::io::Result<String>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    stream.write_all(b"zINSTREAM\0").await?;
+    for chunk in data.chunks(CLAMD_CHUNK_SIZE) {
+        stream
+            .write_all(&(chunk.len() as u32).to_be_bytes())
+            .await?;
+        stream.write_all(chunk).await?;
+    }
+    // A zero-length chunk ends the stream
+    stream.write_all(&0u32.to_be_bytes()).await?;
+    stream.flush().await?;
+
+    let mut reply = Vec::new();
+    stream.read_to_end(&mut reply).await?;
+    Ok(String::from_utf8_lossy(&reply)
+        .trim_end_matches('\0')
+        .trim()
+        .to_string())
+}
+
+// Replies look like "stream: OK" or "stream: <signature> FOUND"
+fn parse_reply(reply: &str) -> Result<ScanVerdict, Error> {
+    let result = reply.strip_prefix("stream:").unwrap_or(reply).trim();
+
+    if result == "OK" {
+        return Ok(ScanVerdict::Clean);
+    }
+    match result.strip_suffix(" FOUND") {
+        Some(signature) => Ok(ScanVerdict::Infected(signature.trim().to_string())),
+        None => Err(error::ErrorServiceUnavailable(format!(
+            "Malware scan failed: {}",
+            reply
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::StatusCode;
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    // Reads one INSTREAM request and returns its content
+    async fn read_instream(stream: &mut TcpStream) -> Vec<u8> {
+        let mut command = [0u8; 10];
+        stream.read_exact(&mut command).await.unwrap();
+        assert_eq!(&command, b"zINSTREAM\0");
+
+        let mut content = Vec::new();
+        loop {
+            let mut length = [0u8; 4];
+            stream.read_exact(&mut length).await.unwrap();
+            let length = u32::from_be_bytes(length) as usize;
+            if length == 0 {
+                return content;
+            }
+            assert!(length <= CLAMD_CHUNK_SIZE);
+            let mut chunk = vec![0u8; length];
+            stream.read_exact(&mut chunk).await.unwrap();
+            content.extend_from_slice(&chunk);
+        }
+    }
+
+    // A clamd stand-in that answers one scan with `reply`, or never answers
+    // when it is None. Returns its address and the content it was sent
+    async fn stub_daemon(
+        reply: Option<&'static str>,
+    ) -> (String, tokio::task::JoinHandle<Vec<u8>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let daemon = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let content = read_instream(&mut stream).await;
+            match reply {
+                Some(reply) => stream.write_all(reply.as_bytes()).await.unwrap(),
+                None => time::sleep(Duration::from_secs(5)).await,
+            }
+            content
+        });
+        (address, daemon)
+    }
+
+    fn status(error: &Error) -> StatusCode {
+        error.as_response_error().status_code()
+    }
+
+    #[test]
+    fn parses_clean_reply() {
+        assert_eq!(parse_reply("stream: OK").unwrap(), ScanVerdict::Clean);
+        assert_eq!(parse_reply("OK").unwrap(), ScanVerdict::Clean);
+    }
+
+    #[test]
+    fn parses_infected_reply() {
+        assert_eq!(
+            parse_reply("stream: Eicar-Signature FOUND").unwrap(),
+            ScanVerdict::Infected("Eicar-Signature".to_string())
+        );
+    }
+
+    #[test]
+    fn rejects_error_reply() {
+        let error = parse_reply("INSTREAM size limit exceeded. ERROR").unwrap_err();
+        assert_eq!(status(&error), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(parse_reply("").is_err());
+    }
+
+    #[tokio::test]
+    async fn streams_content_in_chunks() {
+        let (address, daemon) = stub_daemon(Some("stream: OK\0")).await;
+        let data: Vec<u8> = (0..CLAMD_CHUNK_SIZE * 2 + 10)
+            .map(|i| (i % 251) as u8)
+            .collect();
+
+        let verdict = ClamdScanner::new(address).scan(&data).await.unwrap();
+
+        assert_eq!(verdict, ScanVerdict::Clean);
+        assert_eq!(daemon.await.unwrap(), data);
+    }
+
+    #[tokio::test]
+    async fn reports_infected_content() {
+        let (address, _daemon) = stub_daemon(Some("stream: Eicar-Signature FOUND\0")).await;
+
+        let verdict = ClamdScanner::new(address).scan(b"X5O!P%@AP").await.unwrap();
+
+        assert_eq!(
+            verdict,
+            ScanVerdict::Infected("Eicar-Signature".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn fails_closed_on_daemon_error() {
+        let (address, _daemon) = stub_daemon(Some("stream: Can't allocate memory ERROR\0")).await;
+
+        let error = ClamdScanner::new(address).scan(b"data").await.unwrap_err();
+
+        assert_eq!(status(&error), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn fails_closed_on_timeout() {
+        let (address, _daemon) = stub_daemon(None).await;
+        let scanner = ClamdScanner {
+            address,
+            timeout: Duration::from_millis(200),
+        };
+
+        let error = scanner.scan(b"data").await.unwrap_err();
+
+        assert_eq!(status(&error), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(error.to_string().contains("timed out"));
+    }
+
+    #[tokio::test]
+    async fn fails_closed_when_unreachable() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        drop(listener);
+
+        let error = ClamdScanner::new(address).scan(b"data").await.unwrap_err();
+
+        assert_eq!(status(&error), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn quarantines_infected_uploads() {
+        let (address, _daemon) = stub_daemon(Some("stream: Eicar-Signature FOUND\0")).await;
+
+        let (scan_status, detected) =
+            scan_upload(&ClamdScanner::new(address), "eicar.txt", b"X5O!P%@AP")
+                .await
+                .unwrap();
+
+        assert_eq!(scan_status, ScanStatus::Quarantined);
+        assert_eq!(detected.as_deref(), Some("Eicar-Signature"));
+    }
+
+    #[tokio::test]
+    async fn skips_scanning_when_disabled() {
+        let (scan_status, detected) = scan_upload(&NoopScanner, "file.txt", b"data")
+            .await
+            .unwrap();
+
+        assert_eq!(scan_status, ScanStatus::NotScanned);
+        assert_eq!(detected, None);
+    }
+}
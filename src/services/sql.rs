@@ -0,0 +1,4056 @@
+use std::collections::HashMap;
+
+use actix_web::Error;
+use async_trait::async_trait;
+use mongodb::bson::{oid::ObjectId, Bson, DateTime};
+use sqlx::{
+    any::{install_default_drivers, AnyArguments, AnyPoolOptions, AnyRow},
+    Any, AnyConnection, AnyPool, Row,
+};
+
+use crate::{
+    config::DatabaseBackend,
+    models::{
+        audit_checkpoint_model::AuditCheckpoint,
+        audit_event_model::{AuditAction, AuditEvent},
+        chunk_model::Chunk,
+        collection_model::Collection,
+        email_preferences_model::EmailPreferences,
+        file_model::{Compression, File, ScanStatus},
+        file_version_model::{FileVersion, FileVersionSummary},
+        group_model::{Group, GroupShare},
+        invitation_model::Invitation,
+        notification_model::{Notification, NotificationKind},
+        organization_model::Organization,
+        share_link_model::{ExpiredShare, RevokedShare, ShareLink},
+        shared_collection_model::SharedCollection,
+        shared_file_model::SharedFile,
+        user_model::User,
+        webhook_model::{DeliveryStatus, Webhook, WebhookDelivery, WebhookEvent},
+    },
+    services::{
+        audit::{chain_event, system_event, AuditQuery},
+        db::Database,
+        listing::{
+            CursorValue, FileListOptions, ShareStatus, SharedFilePage, SortField, SortOrder,
+        },
+        notifications::NotificationQuery,
+        webhooks::WebhookDeliveryQuery,
+    },
+    utils::email::normalize_email,
+};
+
+const USER_COLUMNS: &str =
+    "u.id, u.username, u.email, u.password, u.public_key, u.storage_used, u.organization_id, u.email_verified_at, u.email_verification_token, u.email_verification_expires_at, u.created_at, u.updated_at";
+const FILE_COLUMNS: &str = "f.id, f.user_id, f.file_name, f.file_size, f.content_type, f.encrypted_aes_key, f.encrypted_file, f.iv, f.sha256, f.created_at, f.updated_at, f.collection_id, f.version, f.scan_status, f.compression, f.chunk_ids, f.organization_id, f.history_size";
+const FILE_VERSION_COLUMNS: &str =
+    "v.id, v.file_id, v.version, v.file_name, v.file_size, v.content_type, v.encrypted_file, v.iv, v.sha256, v.created_at, v.compression, v.chunk_ids";
+const FILE_VERSION_SUMMARY_COLUMNS: &str =
+    "v.version, v.file_name, v.file_size, v.content_type, v.sha256, v.created_at";
+const SHARED_FILE_COLUMNS: &str =
+    "f.id, f.file_name, f.file_size, f.content_type, f.version, f.sha256, f.scan_status, s.created_at, s.expires_at, s.revoked_at, s.declined_at, s.archived_at, s.can_reshare, s.parent_share_id, s.signature, s.first_opened_at, s.last_opened_at, s.open_count, s.failed_password_attempts, s.id AS share_id";
+const COLLECTION_COLUMNS: &str = "c.id, c.user_id, c.name, c.created_at";
+const SHARED_COLLECTION_COLUMNS: &str = "c.id, c.name, COUNT(*) AS file_count, CAST(SUM(f.file_size) AS BIGINT) AS total_size, MIN(s.created_at) AS created_at, MAX(s.expires_at) AS expires_at, u.email AS counterpart_email";
+const GROUP_COLUMNS: &str = "g.id, g.name, g.owner_id, g.created_at, g.updated_at";
+const ORGANIZATION_COLUMNS: &str =
+    "o.id, o.name, o.max_expiry_days, o.storage_quota, o.created_at, o.updated_at";
+const GROUP_SHARE_COLUMNS: &str = "gs.id, gs.group_id, gs.file_id, gs.sender_id, gs.password, gs.expires_at, gs.sign, gs.created_at";
+const INVITATION_COLUMNS: &str = "i.id, i.email, i.file_id, i.sender_id, i.file_name, i.password, i.expires_at, i.sign, i.created_at";
+const WEBHOOK_COLUMNS: &str = "w.id, w.user_id, w.url, w.secret, w.events, w.created_at";
+
+const WEBHOOK_DELIVERY_COLUMNS: &str = "d.id, d.webhook_id, d.event, d.payload, d.status, d.attempts, d.next_attempt_at, d.last_attempt_at, d.response_status, d.last_error, d.delivered_at, d.created_at";
+
+const NOTIFICATION_COLUMNS: &str = "n.id, n.user_id, n.kind, n.actor_id, n.share_id, n.file_id, n.collection_id, n.name, n.read_at, n.created_at";
+const AUDIT_EVENT_COLUMNS: &str = "e.id, e.sequence, e.prev_hash, e.hash, e.action, e.actor_id, e.ip, e.user_agent, e.subject_user_id, e.file_id, e.share_id, e.collection_id, e.detail, e.created_at";
+const SHARE_LINK_COLUMNS: &str = "s.id, s.recipient_user_id, s.file_id, s.password, s.expires_at, s.created_at, s.encrypted_aes_key, s.revoked_at, s.declined_at, s.archived_at, s.can_reshare, s.parent_share_id, s.signature, s.first_opened_at, s.last_opened_at, s.open_count, s.failed_password_attempts, s.organization_id";
+// The share bound to $1 and every share re-shared from it, however deep
+const SHARE_CHAIN: &str = "WITH RECURSIVE chain (id) AS ( \
+     SELECT id FROM share_links WHERE id = $1 \
+     UNION ALL \
+     SELECT s.id FROM share_links s JOIN chain c ON s.parent_share_id = c.id \
+ )";
+
+// Rows younger than this may belong to a write that is still in flight
+const RECONCILE_GRACE_PERIOD_MS: i64 = 10 * 60 * 1000;
+
+// Relational backend for SQLite and PostgreSQL deployments
+pub struct SqlDatabase {
+    pool: AnyPool,
+}
+
+impl SqlDatabase {
+    pub async fn init(db_url: String, backend: DatabaseBackend) -> Self {
+        install_default_drivers();
+
+        let pool = AnyPoolOptions::new()
+            .max_connections(10)
+            .connect(&db_url)
+            .await
+            .expect("Failed to connect to the database");
+
+        let migration_result = match backend {
+            DatabaseBackend::Sqlite => sqlx::migrate!("./migrations/sqlite").run(&pool).await,
+            DatabaseBackend::Postgres => sqlx::migrate!("./migrations/postgres").run(&pool).await,
+            DatabaseBackend::Mongo => panic!("MongoDB is not a relational backend"),
+        };
+        migration_result.expect("Failed to run database migrations");
+
+        SqlDatabase { pool }
+    }
+}
+
+enum SqlValue {
+    Text(String),
+    Int(i64),
+    Null,
+}
+
+// Builds listing queries with numbered placeholders, which both SQLite and
+// PostgreSQL accept through the Any driver
+struct ListingQuery {
+    from: &'static str,
+    conditions: Vec<String>,
+    values: Vec<SqlValue>,
+}
+
+impl ListingQuery {
+    fn new(from: &'static str) -> Self {
+        ListingQuery {
+            from,
+            conditions: Vec::new(),
+            values: Vec::new(),
+        }
+    }
+
+    fn bind(&mut self, value: SqlValue) -> String {
+        self.values.push(value);
+        format!("${}", self.values.len())
+    }
+
+    fn condition(&mut self, condition: String) {
+        self.conditions.push(condition);
+    }
+
+    fn where_clause(&self) -> String {
+        if self.conditions.is_empty() {
+            String::new()
+        } else {
+            format!(" WHERE {}", self.conditions.join(" AND "))
+        }
+    }
+
+    fn apply_filters(&mut self, options: &FileListOptions) {
+        if let Some(email) = &options.counterpart_email {
+            let param = self.bind(SqlValue::Text(like_pattern(email)));
+            self.condition(format!("LOWER(u.email) LIKE {} ESCAPE '\\'", param));
+        }
+        if let Some(name) = &options.name {
+            let param = self.bind(SqlValue::Text(like_pattern(name)));
+            self.condition(format!("LOWER(f.file_name) LIKE {} ESCAPE '\\'", param));
+        }
+        if let Some(min_size) = options.min_size {
+            let param = self.bind(SqlValue::Int(min_size));
+            self.condition(format!("f.file_size >= {}", param));
+        }
+        if let Some(max_size) = options.max_size {
+            let param = self.bind(SqlValue::Int(max_size));
+            self.condition(format!("f.file_size <= {}", param));
+        }
+        match options.status {
+            ShareStatus::All => {}
+            ShareStatus::Active => {
+                let param = self.bind(SqlValue::Int(DateTime::now().timestamp_millis()));
+                self.condition(format!(
+                    "s.expires_at >= {} AND s.revoked_at IS NULL AND s.declined_at IS NULL",
+                    param
+                ));
+            }
+            ShareStatus::Expired => {
+                let param = self.bind(SqlValue::Int(DateTime::now().timestamp_millis()));
+                self.condition(format!("s.expires_at < {}", param));
+            }
+            ShareStatus::Revoked => {
+                self.condition("s.revoked_at IS NOT NULL".to_string());
+            }
+            ShareStatus::Declined => {
+                self.condition("s.declined_at IS NOT NULL".to_string());
+            }
+        }
+    }
+
+    fn apply_cursor(&mut self, options: &FileListOptions, column: &str, comparison: &str) {
+        if let Some(cursor) = &options.after {
+            let value = match &cursor.value {
+                CursorValue::Text(text) => SqlValue::Text(text.clone()),
+                CursorValue::Int(number) => SqlValue::Int(*number),
+            };
+            let value_param = self.bind(value);
+            let share_param = self.bind(SqlValue::Text(cursor.share_id.clone()));
+            self.condition(format!(
+                "({column} {comparison} {value} OR ({column} = {value} AND s.id {comparison} {share}))",
+                column = column,
+                comparison = comparison,
+                value = value_param,
+                share = share_param,
+            ));
+        }
+    }
+
+    fn build<'q>(&'q self, sql: &'q str) -> sqlx::query::Query<'q, Any, AnyArguments<'q>> {
+        let mut query = sqlx::query(sql);
+        for value in &self.values {
+            query = match value {
+                SqlValue::Text(text) => query.bind(text.as_str()),
+                SqlValue::Int(number) => query.bind(*number),
+                SqlValue::Null => query.bind(None::<i64>),
+            };
+        }
+        query
+    }
+}
+
+fn query_error(context: &'static str) -> impl Fn(sqlx::Error) -> Error {
+    move |e| actix_web::error::ErrorServiceUnavailable(format!("{}: {}", context, e))
+}
+
+fn parse_object_id(id: &str) -> Result<ObjectId, Error> {
+    ObjectId::parse_str(id).map_err(|e| {
+        actix_web::error::ErrorBadRequest(format!("Failed to convert to objectid: {}", e))
+    })
+}
+
+fn bson_object_id(id: Bson) -> Result<ObjectId, Error> {
+    match id {
+        Bson::ObjectId(oid) => Ok(oid),
+        Bson::String(id) => parse_object_id(&id),
+        _ => Err(actix_web::error::ErrorBadRequest(
+            "Failed to convert bson to objectId",
+        )),
+    }
+}
+
+fn not_found(message: &'static str) -> Error {
+    Error::from(std::io::Error::new(std::io::ErrorKind::NotFound, message))
+}
+
+// Escape LIKE wildcards so the search text is matched literally
+fn like_pattern(text: &str) -> String {
+    let escaped = text
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{}%", escaped.to_lowercase())
+}
+
+fn optional_date(row: &AnyRow, column: &str) -> Result<Option<DateTime>, sqlx::Error> {
+    Ok(row
+        .try_get::<Option<i64>, _>(column)?
+        .map(DateTime::from_millis))
+}
+
+fn optional_object_id(row: &AnyRow, column: &str) -> Result<Option<ObjectId>, Error> {
+    row.try_get::<Option<String>, _>(column)
+        .map_err(query_error("Failed to decode row"))?
+        .map(|id| parse_object_id(&id))
+        .transpose()
+}
+
+fn user_from_row(row: &AnyRow) -> Result<User, Error> {
+    let decode = query_error("Failed to decode user");
+    Ok(User {
+        _id: parse_object_id(&row.try_get::<String, _>("id").map_err(&decode)?)?,
+        username: row.try_get("username").map_err(&decode)?,
+        email: row.try_get("email").map_err(&decode)?,
+        password: row.try_get("password").map_err(&decode)?,
+        public_key: row.try_get("public_key").map_err(&decode)?,
+        storage_used: row.try_get("storage_used").map_err(&decode)?,
+        organization_id: optional_object_id(row, "organization_id")?,
+        email_verified_at: optional_date(row, "email_verified_at").map_err(&decode)?,
+        email_verification_token: row.try_get("email_verification_token").map_err(&decode)?,
+        email_verification_expires_at: optional_date(row, "email_verification_expires_at")
+            .map_err(&decode)?,
+        created_at: DateTime::from_millis(row.try_get("created_at").map_err(&decode)?),
+        updated_at: DateTime::from_millis(row.try_get("updated_at").map_err(&decode)?),
+    })
+}
+
+fn file_from_row(row: &AnyRow) -> Result<File, Error> {
+    let decode = query_error("Failed to decode file");
+    Ok(File {
+        _id: parse_object_id(&row.try_get::<String, _>("id").map_err(&decode)?)?,
+        user_id: parse_object_id(&row.try_get::<String, _>("user_id").map_err(&decode)?)?,
+        file_name: row.try_get("file_name").map_err(&decode)?,
+        file_size: row.try_get("file_size").map_err(&decode)?,
+        content_type: row.try_get("content_type").map_err(&decode)?,
+        encrypted_aes_key: row.try_get("encrypted_aes_key").map_err(&decode)?,
+        encrypted_file: row.try_get("encrypted_file").map_err(&decode)?,
+        iv: row.try_get("iv").map_err(&decode)?,
+        sha256: row.try_get("sha256").map_err(&decode)?,
+        created_at: DateTime::from_millis(row.try_get("created_at").map_err(&decode)?),
+        updated_at: DateTime::from_millis(row.try_get("updated_at").map_err(&decode)?),
+        collection_id: optional_object_id(row, "collection_id")?,
+        version: row.try_get("version").map_err(&decode)?,
+        scan_status: ScanStatus::parse(&row.try_get::<String, _>("scan_status").map_err(&decode)?),
+        compression: Compression::parse(&row.try_get::<String, _>("compression").map_err(&decode)?),
+        chunk_ids: split_chunk_ids(row.try_get("chunk_ids").map_err(&decode)?),
+        organization_id: optional_object_id(row, "organization_id")?,
+        history_size: row.try_get("history_size").map_err(&decode)?,
+    })
+}
+
+fn file_version_from_row(row: &AnyRow) -> Result<FileVersion, Error> {
+    let decode = query_error("Failed to decode file version");
+    Ok(FileVersion {
+        _id: parse_object_id(&row.try_get::<String, _>("id").map_err(&decode)?)?,
+        file_id: parse_object_id(&row.try_get::<String, _>("file_id").map_err(&decode)?)?,
+        version: row.try_get("version").map_err(&decode)?,
+        file_name: row.try_get("file_name").map_err(&decode)?,
+        file_size: row.try_get("file_size").map_err(&decode)?,
+        content_type: row.try_get("content_type").map_err(&decode)?,
+        encrypted_file: row.try_get("encrypted_file").map_err(&decode)?,
+        iv: row.try_get("iv").map_err(&decode)?,
+        sha256: row.try_get("sha256").map_err(&decode)?,
+        created_at: DateTime::from_millis(row.try_get("created_at").map_err(&decode)?),
+        compression: Compression::parse(&row.try_get::<String, _>("compression").map_err(&decode)?),
+        chunk_ids: split_chunk_ids(row.try_get("chunk_ids").map_err(&decode)?),
+    })
+}
+
+fn file_version_summary_from_row(row: &AnyRow) -> Result<FileVersionSummary, Error> {
+    let decode = query_error("Failed to decode file version");
+    Ok(FileVersionSummary {
+        version: row.try_get("version").map_err(&decode)?,
+        file_name: row.try_get("file_name").map_err(&decode)?,
+        file_size: row.try_get("file_size").map_err(&decode)?,
+        content_type: row.try_get("content_type").map_err(&decode)?,
+        sha256: row.try_get("sha256").map_err(&decode)?,
+        created_at: DateTime::from_millis(row.try_get("created_at").map_err(&decode)?),
+    })
+}
+
+fn collection_from_row(row: &AnyRow) -> Result<Collection, Error> {
+    let decode = query_error("Failed to decode collection");
+    Ok(Collection {
+        _id: parse_object_id(&row.try_get::<String, _>("id").map_err(&decode)?)?,
+        user_id: parse_object_id(&row.try_get::<String, _>("user_id").map_err(&decode)?)?,
+        name: row.try_get("name").map_err(&decode)?,
+        created_at: DateTime::from_millis(row.try_get("created_at").map_err(&decode)?),
+    })
+}
+
+fn shared_collection_from_row(row: &AnyRow) -> Result<SharedCollection, Error> {
+    let decode = query_error("Failed to decode collection");
+    Ok(SharedCollection {
+        _id: parse_object_id(&row.try_get::<String, _>("id").map_err(&decode)?)?,
+        name: row.try_get("name").map_err(&decode)?,
+        file_count: row.try_get("file_count").map_err(&decode)?,
+        total_size: row.try_get("total_size").map_err(&decode)?,
+        created_at: DateTime::from_millis(row.try_get("created_at").map_err(&decode)?),
+        expires_at: DateTime::from_millis(row.try_get("expires_at").map_err(&decode)?),
+        counterpart_email: row.try_get("counterpart_email").map_err(&decode)?,
+    })
+}
+
+fn insert_file(file: File) -> sqlx::query::Query<'static, Any, AnyArguments<'static>> {
+    sqlx::query(
+        "INSERT INTO files (id, user_id, file_name, file_size, content_type, encrypted_aes_key, encrypted_file, iv, sha256, created_at, updated_at, collection_id, version, scan_status, compression, chunk_ids, organization_id, history_size) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18)",
+    )
+    .bind(file._id.to_hex())
+    .bind(file.user_id.to_hex())
+    .bind(file.file_name)
+    .bind(file.file_size)
+    .bind(file.content_type)
+    .bind(file.encrypted_aes_key)
+    .bind(file.encrypted_file)
+    .bind(file.iv)
+    .bind(file.sha256)
+    .bind(file.created_at.timestamp_millis())
+    .bind(file.updated_at.timestamp_millis())
+    .bind(file.collection_id.map(|id| id.to_hex()))
+    .bind(file.version)
+    .bind(file.scan_status.as_str())
+    .bind(file.compression.as_str())
+    .bind(join_chunk_ids(&file.chunk_ids))
+    .bind(file.organization_id.map(|id| id.to_hex()))
+    .bind(file.history_size)
+}
+
+fn insert_share_link(
+    share_link: ShareLink,
+) -> sqlx::query::Query<'static, Any, AnyArguments<'static>> {
+    sqlx::query(
+        "INSERT INTO share_links (id, recipient_user_id, file_id, password, expires_at, created_at, encrypted_aes_key, revoked_at, can_reshare, parent_share_id, signature, organization_id) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)",
+    )
+    .bind(share_link._id.to_hex())
+    .bind(share_link.recipient_user_id.to_hex())
+    .bind(share_link.file_id.to_hex())
+    .bind(share_link.password)
+    .bind(share_link.expires_at.timestamp_millis())
+    .bind(share_link.created_at.timestamp_millis())
+    .bind(share_link.encrypted_aes_key)
+    .bind(share_link.revoked_at.map(|revoked_at| revoked_at.timestamp_millis()))
+    .bind(share_link.can_reshare as i64)
+    .bind(share_link.parent_share_id.map(|id| id.to_hex()))
+    .bind(share_link.signature)
+    .bind(share_link.organization_id.map(|id| id.to_hex()))
+}
+
+fn insert_audit_event(
+    event: AuditEvent,
+) -> sqlx::query::Query<'static, Any, AnyArguments<'static>> {
+    sqlx::query(
+        "INSERT INTO audit_events (id, sequence, prev_hash, hash, action, actor_id, ip, user_agent, subject_user_id, file_id, share_id, collection_id, detail, created_at) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)",
+    )
+    .bind(event._id.to_hex())
+    .bind(event.sequence)
+    .bind(event.prev_hash)
+    .bind(event.hash)
+    .bind(event.action.as_str())
+    .bind(event.actor_id.map(|id| id.to_hex()))
+    .bind(event.ip)
+    .bind(event.user_agent)
+    .bind(event.subject_user_id.map(|id| id.to_hex()))
+    .bind(event.file_id.map(|id| id.to_hex()))
+    .bind(event.share_id.map(|id| id.to_hex()))
+    .bind(event.collection_id.map(|id| id.to_hex()))
+    .bind(event.detail)
+    .bind(event.created_at.timestamp_millis())
+}
+
+fn audit_event_from_row(row: &AnyRow) -> Result<AuditEvent, Error> {
+    let decode = query_error("Failed to decode audit event");
+    let action: String = row.try_get("action").map_err(&decode)?;
+    Ok(AuditEvent {
+        _id: parse_object_id(&row.try_get::<String, _>("id").map_err(&decode)?)?,
+        sequence: row
+            .try_get::<Option<i64>, _>("sequence")
+            .map_err(&decode)?
+            .unwrap_or_default(),
+        prev_hash: row
+            .try_get::<Option<String>, _>("prev_hash")
+            .map_err(&decode)?
+            .unwrap_or_default(),
+        hash: row
+            .try_get::<Option<String>, _>("hash")
+            .map_err(&decode)?
+            .unwrap_or_default(),
+        action: AuditAction::parse(&action).ok_or_else(|| {
+            actix_web::error::ErrorServiceUnavailable(format!("Unknown audit action: {}", action))
+        })?,
+        actor_id: optional_object_id(row, "actor_id")?,
+        ip: row.try_get("ip").map_err(&decode)?,
+        user_agent: row.try_get("user_agent").map_err(&decode)?,
+        subject_user_id: optional_object_id(row, "subject_user_id")?,
+        file_id: optional_object_id(row, "file_id")?,
+        share_id: optional_object_id(row, "share_id")?,
+        collection_id: optional_object_id(row, "collection_id")?,
+        detail: row.try_get("detail").map_err(&decode)?,
+        created_at: DateTime::from_millis(row.try_get("created_at").map_err(&decode)?),
+    })
+}
+
+fn notification_from_row(row: &AnyRow) -> Result<Notification, Error> {
+    let decode = query_error("Failed to decode notification");
+    let kind: String = row.try_get("kind").map_err(&decode)?;
+    Ok(Notification {
+        _id: parse_object_id(&row.try_get::<String, _>("id").map_err(&decode)?)?,
+        user_id: parse_object_id(&row.try_get::<String, _>("user_id").map_err(&decode)?)?,
+        kind: NotificationKind::parse(&kind).ok_or_else(|| {
+            actix_web::error::ErrorInternalServerError(format!(
+                "Unknown notification kind: {}",
+                kind
+            ))
+        })?,
+        actor_id: optional_object_id(row, "actor_id")?,
+        share_id: optional_object_id(row, "share_id")?,
+        file_id: optional_object_id(row, "file_id")?,
+        collection_id: optional_object_id(row, "collection_id")?,
+        name: row.try_get("name").map_err(&decode)?,
+        read_at: optional_date(row, "read_at").map_err(&decode)?,
+        created_at: DateTime::from_millis(row.try_get("created_at").map_err(&decode)?),
+    })
+}
+
+fn invitation_from_row(row: &AnyRow) -> Result<Invitation, Error> {
+    let decode = query_error("Failed to decode invitation");
+    Ok(Invitation {
+        _id: parse_object_id(&row.try_get::<String, _>("id").map_err(&decode)?)?,
+        email: row.try_get("email").map_err(&decode)?,
+        file_id: parse_object_id(&row.try_get::<String, _>("file_id").map_err(&decode)?)?,
+        sender_id: parse_object_id(&row.try_get::<String, _>("sender_id").map_err(&decode)?)?,
+        file_name: row.try_get("file_name").map_err(&decode)?,
+        password: row.try_get("password").map_err(&decode)?,
+        expires_at: DateTime::from_millis(row.try_get("expires_at").map_err(&decode)?),
+        sign: row.try_get::<i64, _>("sign").map_err(&decode)? != 0,
+        created_at: DateTime::from_millis(row.try_get("created_at").map_err(&decode)?),
+    })
+}
+
+// Without its members, which live in group_members
+fn group_from_row(row: &AnyRow) -> Result<Group, Error> {
+    let decode = query_error("Failed to decode group");
+    Ok(Group {
+        _id: parse_object_id(&row.try_get::<String, _>("id").map_err(&decode)?)?,
+        name: row.try_get("name").map_err(&decode)?,
+        owner_id: parse_object_id(&row.try_get::<String, _>("owner_id").map_err(&decode)?)?,
+        members: Vec::new(),
+        admins: Vec::new(),
+        created_at: DateTime::from_millis(row.try_get("created_at").map_err(&decode)?),
+        updated_at: DateTime::from_millis(row.try_get("updated_at").map_err(&decode)?),
+    })
+}
+
+fn organization_from_row(row: &AnyRow) -> Result<Organization, Error> {
+    let decode = query_error("Failed to decode organization");
+    Ok(Organization {
+        _id: parse_object_id(&row.try_get::<String, _>("id").map_err(&decode)?)?,
+        name: row.try_get("name").map_err(&decode)?,
+        domains: Vec::new(),
+        max_expiry_days: row.try_get("max_expiry_days").map_err(&decode)?,
+        storage_quota: row.try_get("storage_quota").map_err(&decode)?,
+        admins: Vec::new(),
+        created_at: DateTime::from_millis(row.try_get("created_at").map_err(&decode)?),
+        updated_at: DateTime::from_millis(row.try_get("updated_at").map_err(&decode)?),
+    })
+}
+
+fn group_share_from_row(row: &AnyRow) -> Result<GroupShare, Error> {
+    let decode = query_error("Failed to decode group share");
+    Ok(GroupShare {
+        _id: parse_object_id(&row.try_get::<String, _>("id").map_err(&decode)?)?,
+        group_id: parse_object_id(&row.try_get::<String, _>("group_id").map_err(&decode)?)?,
+        file_id: parse_object_id(&row.try_get::<String, _>("file_id").map_err(&decode)?)?,
+        sender_id: parse_object_id(&row.try_get::<String, _>("sender_id").map_err(&decode)?)?,
+        password: row.try_get("password").map_err(&decode)?,
+        expires_at: DateTime::from_millis(row.try_get("expires_at").map_err(&decode)?),
+        sign: row.try_get::<i64, _>("sign").map_err(&decode)? != 0,
+        created_at: DateTime::from_millis(row.try_get("created_at").map_err(&decode)?),
+    })
+}
+
+fn webhook_from_row(row: &AnyRow) -> Result<Webhook, Error> {
+    let decode = query_error("Failed to decode webhook");
+    let events: String = row.try_get("events").map_err(&decode)?;
+    Ok(Webhook {
+        _id: parse_object_id(&row.try_get::<String, _>("id").map_err(&decode)?)?,
+        user_id: optional_object_id(row, "user_id")?,
+        url: row.try_get("url").map_err(&decode)?,
+        secret: row.try_get("secret").map_err(&decode)?,
+        events: events.split(',').filter_map(WebhookEvent::parse).collect(),
+        created_at: DateTime::from_millis(row.try_get("created_at").map_err(&decode)?),
+    })
+}
+
+fn webhook_delivery_from_row(row: &AnyRow) -> Result<WebhookDelivery, Error> {
+    let decode = query_error("Failed to decode webhook delivery");
+    let event: String = row.try_get("event").map_err(&decode)?;
+    let status: String = row.try_get("status").map_err(&decode)?;
+    Ok(WebhookDelivery {
+        _id: parse_object_id(&row.try_get::<String, _>("id").map_err(&decode)?)?,
+        webhook_id: parse_object_id(&row.try_get::<String, _>("webhook_id").map_err(&decode)?)?,
+        event: WebhookEvent::parse(&event).ok_or_else(|| {
+            actix_web::error::ErrorInternalServerError(format!("Unknown webhook event: {}", event))
+        })?,
+        payload: row.try_get("payload").map_err(&decode)?,
+        status: DeliveryStatus::parse(&status).ok_or_else(|| {
+            actix_web::error::ErrorInternalServerError(format!(
+                "Unknown delivery status: {}",
+                status
+            ))
+        })?,
+        attempts: row.try_get("attempts").map_err(&decode)?,
+        next_attempt_at: DateTime::from_millis(row.try_get("next_attempt_at").map_err(&decode)?),
+        last_attempt_at: optional_date(row, "last_attempt_at").map_err(&decode)?,
+        response_status: row.try_get("response_status").map_err(&decode)?,
+        last_error: row.try_get("last_error").map_err(&decode)?,
+        delivered_at: optional_date(row, "delivered_at").map_err(&decode)?,
+        created_at: DateTime::from_millis(row.try_get("created_at").map_err(&decode)?),
+    })
+}
+
+fn audit_checkpoint_from_row(row: &AnyRow) -> Result<AuditCheckpoint, Error> {
+    let decode = query_error("Failed to decode audit checkpoint");
+    Ok(AuditCheckpoint {
+        _id: parse_object_id(&row.try_get::<String, _>("id").map_err(&decode)?)?,
+        sequence: row.try_get("sequence").map_err(&decode)?,
+        hash: row.try_get("hash").map_err(&decode)?,
+        signature: row.try_get("signature").map_err(&decode)?,
+        created_at: DateTime::from_millis(row.try_get("created_at").map_err(&decode)?),
+    })
+}
+
+// Chains the event onto the current head. Moving the head row forward comes
+// first, so the row stays locked against every other appender, in this
+// process or another, until the caller's transaction ends
+async fn append_audit_event(conn: &mut AnyConnection, mut event: AuditEvent) -> Result<(), Error> {
+    let head = sqlx::query(
+        "UPDATE audit_head SET sequence = sequence + 1 WHERE id = 1 RETURNING sequence, hash",
+    )
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(query_error("Failed to fetch audit chain head"))?;
+
+    let decode = query_error("Failed to decode audit chain head");
+    let sequence: i64 = head.try_get("sequence").map_err(&decode)?;
+    let hash: String = head.try_get("hash").map_err(&decode)?;
+    chain_event(&mut event, sequence - 1, &hash);
+
+    sqlx::query("UPDATE audit_head SET hash = $1 WHERE id = 1")
+        .bind(&event.hash)
+        .execute(&mut *conn)
+        .await
+        .map_err(query_error("Failed to update audit chain head"))?;
+    insert_audit_event(event)
+        .execute(&mut *conn)
+        .await
+        .map_err(query_error("Failed to record audit event"))?;
+
+    Ok(())
+}
+
+fn share_link_from_row(row: &AnyRow) -> Result<ShareLink, Error> {
+    let decode = query_error("Failed to decode shared link");
+    Ok(ShareLink {
+        _id: parse_object_id(&row.try_get::<String, _>("id").map_err(&decode)?)?,
+        recipient_user_id: parse_object_id(
+            &row.try_get::<String, _>("recipient_user_id")
+                .map_err(&decode)?,
+        )?,
+        file_id: parse_object_id(&row.try_get::<String, _>("file_id").map_err(&decode)?)?,
+        password: row.try_get("password").map_err(&decode)?,
+        expires_at: DateTime::from_millis(row.try_get("expires_at").map_err(&decode)?),
+        created_at: DateTime::from_millis(row.try_get("created_at").map_err(&decode)?),
+        encrypted_aes_key: row.try_get("encrypted_aes_key").map_err(&decode)?,
+        revoked_at: optional_date(row, "revoked_at").map_err(&decode)?,
+        declined_at: optional_date(row, "declined_at").map_err(&decode)?,
+        archived_at: optional_date(row, "archived_at").map_err(&decode)?,
+        can_reshare: row.try_get::<i64, _>("can_reshare").map_err(&decode)? != 0,
+        parent_share_id: optional_object_id(row, "parent_share_id")?,
+        signature: row.try_get("signature").map_err(&decode)?,
+        first_opened_at: optional_date(row, "first_opened_at").map_err(&decode)?,
+        last_opened_at: optional_date(row, "last_opened_at").map_err(&decode)?,
+        open_count: row.try_get("open_count").map_err(&decode)?,
+        failed_password_attempts: row.try_get("failed_password_attempts").map_err(&decode)?,
+        organization_id: optional_object_id(row, "organization_id")?,
+    })
+}
+
+fn shared_file_from_row(row: &AnyRow) -> Result<SharedFile, Error> {
+    let decode = query_error("Failed to decode file");
+    Ok(SharedFile {
+        _id: parse_object_id(&row.try_get::<String, _>("id").map_err(&decode)?)?,
+        file_name: row.try_get("file_name").map_err(&decode)?,
+        file_size: row.try_get("file_size").map_err(&decode)?,
+        content_type: row.try_get("content_type").map_err(&decode)?,
+        version: row.try_get("version").map_err(&decode)?,
+        sha256: row.try_get("sha256").map_err(&decode)?,
+        scan_status: ScanStatus::parse(&row.try_get::<String, _>("scan_status").map_err(&decode)?),
+        created_at: DateTime::from_millis(row.try_get("created_at").map_err(&decode)?),
+        expires_at: DateTime::from_millis(row.try_get("expires_at").map_err(&decode)?),
+        revoked_at: optional_date(row, "revoked_at").map_err(&decode)?,
+        declined_at: optional_date(row, "declined_at").map_err(&decode)?,
+        archived_at: optional_date(row, "archived_at").map_err(&decode)?,
+        can_reshare: row.try_get::<i64, _>("can_reshare").map_err(&decode)? != 0,
+        parent_share_id: optional_object_id(row, "parent_share_id")?,
+        signature: row.try_get("signature").map_err(&decode)?,
+        first_opened_at: optional_date(row, "first_opened_at").map_err(&decode)?,
+        last_opened_at: optional_date(row, "last_opened_at").map_err(&decode)?,
+        open_count: row.try_get("open_count").map_err(&decode)?,
+        failed_password_attempts: row.try_get("failed_password_attempts").map_err(&decode)?,
+        share_id: parse_object_id(&row.try_get::<String, _>("share_id").map_err(&decode)?)?,
+        counterpart_email: row.try_get("counterpart_email").map_err(&decode)?,
+    })
+}
+
+// Chunk id lists are stored comma-separated; inline content has none
+fn split_chunk_ids(chunk_ids: Option<String>) -> Vec<String> {
+    chunk_ids
+        .filter(|chunk_ids| !chunk_ids.is_empty())
+        .map(|chunk_ids| chunk_ids.split(',').map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
+fn join_chunk_ids(chunk_ids: &[String]) -> Option<String> {
+    if chunk_ids.is_empty() {
+        None
+    } else {
+        Some(chunk_ids.join(","))
+    }
+}
+
+// Stores chunks not seen before and adds references to those already stored
+async fn retain_chunks(conn: &mut AnyConnection, chunks: Vec<Chunk>) -> Result<(), Error> {
+    for chunk in chunks {
+        sqlx::query(
+            "INSERT INTO chunks (id, data, refcount, created_at) VALUES ($1, $2, $3, $4) \
+             ON CONFLICT (id) DO UPDATE SET refcount = chunks.refcount + excluded.refcount",
+        )
+        .bind(chunk._id)
+        .bind(chunk.data)
+        .bind(chunk.refcount)
+        .bind(chunk.created_at.timestamp_millis())
+        .execute(&mut *conn)
+        .await
+        .map_err(query_error("Failed to store chunk"))?;
+    }
+
+    Ok(())
+}
+
+// Bumps updated_at after a change to the group's members
+fn revoked_shares_from_rows(rows: &[AnyRow]) -> Result<Vec<RevokedShare>, Error> {
+    let decode = query_error("Failed to decode shared link");
+    rows.iter()
+        .map(|row| {
+            Ok(RevokedShare {
+                share_id: parse_object_id(&row.try_get::<String, _>("id").map_err(&decode)?)?,
+                recipient_user_id: parse_object_id(
+                    &row.try_get::<String, _>("recipient_user_id")
+                        .map_err(&decode)?,
+                )?,
+            })
+        })
+        .collect()
+}
+
+// Revokes every live share re-shared from this one, however deep, but not
+// the share itself
+async fn revoke_reshares(
+    conn: &mut AnyConnection,
+    share_id: ObjectId,
+) -> Result<Vec<RevokedShare>, Error> {
+    let rows = sqlx::query(&format!(
+        "{} UPDATE share_links SET revoked_at = $2 \
+         WHERE id IN (SELECT id FROM chain) AND id <> $1 AND revoked_at IS NULL \
+         RETURNING id, recipient_user_id",
+        SHARE_CHAIN
+    ))
+    .bind(share_id.to_hex())
+    .bind(DateTime::now().timestamp_millis())
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(query_error("Failed to revoke re-shared links"))?;
+
+    revoked_shares_from_rows(&rows)
+}
+
+async fn touch_group(conn: &mut AnyConnection, group_id: ObjectId) -> Result<(), Error> {
+    sqlx::query("UPDATE user_groups SET updated_at = $1 WHERE id = $2")
+        .bind(DateTime::now().timestamp_millis())
+        .bind(group_id.to_hex())
+        .execute(&mut *conn)
+        .await
+        .map_err(query_error("Failed to update group"))?;
+    Ok(())
+}
+
+// Bumps updated_at after a change to the organization's members
+async fn touch_organization(
+    conn: &mut AnyConnection,
+    organization_id: ObjectId,
+) -> Result<(), Error> {
+    sqlx::query("UPDATE organizations SET updated_at = $1 WHERE id = $2")
+        .bind(DateTime::now().timestamp_millis())
+        .bind(organization_id.to_hex())
+        .execute(&mut *conn)
+        .await
+        .map_err(query_error("Failed to update organization"))?;
+    Ok(())
+}
+
+// Replaces the organization's domains
+async fn save_organization_domains(
+    conn: &mut AnyConnection,
+    organization_id: ObjectId,
+    domains: &[String],
+) -> Result<(), Error> {
+    sqlx::query("DELETE FROM organization_domains WHERE organization_id = $1")
+        .bind(organization_id.to_hex())
+        .execute(&mut *conn)
+        .await
+        .map_err(query_error("Failed to update organization domains"))?;
+
+    for domain in domains {
+        sqlx::query("INSERT INTO organization_domains (domain, organization_id) VALUES ($1, $2)")
+            .bind(domain.clone())
+            .bind(organization_id.to_hex())
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| match e {
+                sqlx::Error::Database(ref db_error) if db_error.is_unique_violation() => {
+                    actix_web::error::ErrorConflict(format!(
+                        "{} belongs to another organization",
+                        domain
+                    ))
+                }
+                _ => actix_web::error::ErrorBadRequest(format!(
+                    "Failed to save the organization domains: {}",
+                    e
+                )),
+            })?;
+    }
+
+    Ok(())
+}
+
+// Upper bound on the chunk ids looked up by a single query
+const CHUNK_FETCH_BATCH: usize = 500;
+
+// Drops the references held by deleted files or versions, each row carrying
+// their chunk_ids, and deletes chunks nothing references any more
+async fn release_chunks(conn: &mut AnyConnection, rows: &[AnyRow]) -> Result<(), Error> {
+    let mut references: HashMap<String, i64> = HashMap::new();
+    for row in rows {
+        let chunk_ids = row
+            .try_get("chunk_ids")
+            .map_err(query_error("Failed to decode chunk ids"))?;
+        for chunk_id in split_chunk_ids(chunk_ids) {
+            *references.entry(chunk_id).or_default() += 1;
+        }
+    }
+
+    for (chunk_id, count) in references {
+        sqlx::query("UPDATE chunks SET refcount = refcount - $1 WHERE id = $2")
+            .bind(count)
+            .bind(&chunk_id)
+            .execute(&mut *conn)
+            .await
+            .map_err(query_error("Failed to release chunk"))?;
+        sqlx::query("DELETE FROM chunks WHERE id = $1 AND refcount <= 0")
+            .bind(chunk_id)
+            .execute(&mut *conn)
+            .await
+            .map_err(query_error("Failed to delete chunk"))?;
+    }
+
+    Ok(())
+}
+
+// Gives the space of deleted files and their version history back to their
+// owners and drops their chunk references; each row carries the file's
+// user_id, file_size, history_size and chunk_ids
+async fn release_deleted_files(conn: &mut AnyConnection, rows: &[AnyRow]) -> Result<(), Error> {
+    release_chunks(&mut *conn, rows).await?;
+
+    let decode = query_error("Failed to decode file");
+    for row in rows {
+        let user_id: String = row.try_get("user_id").map_err(&decode)?;
+        let file_size: i64 = row.try_get("file_size").map_err(&decode)?;
+        let history_size: i64 = row.try_get("history_size").map_err(&decode)?;
+
+        sqlx::query("UPDATE users SET storage_used = storage_used - $1 WHERE id = $2")
+            .bind(file_size + history_size)
+            .bind(user_id)
+            .execute(&mut *conn)
+            .await
+            .map_err(query_error("Failed to update storage usage"))?;
+    }
+
+    Ok(())
+}
+
+impl SqlDatabase {
+    // Fills in a group's members and admins, earliest member first
+    async fn load_group_members(&self, mut group: Group) -> Result<Group, Error> {
+        let rows = sqlx::query(
+            "SELECT user_id, admin FROM group_members WHERE group_id = $1 \
+             ORDER BY created_at, user_id",
+        )
+        .bind(group._id.to_hex())
+        .fetch_all(&self.pool)
+        .await
+        .map_err(query_error("Failed to fetch group members"))?;
+
+        let decode = query_error("Failed to decode group member");
+        for row in &rows {
+            let user_id = parse_object_id(&row.try_get::<String, _>("user_id").map_err(&decode)?)?;
+            if row.try_get::<i64, _>("admin").map_err(&decode)? != 0 {
+                group.admins.push(user_id);
+            }
+            group.members.push(user_id);
+        }
+
+        Ok(group)
+    }
+
+    async fn load_organization_details(
+        &self,
+        mut organization: Organization,
+    ) -> Result<Organization, Error> {
+        let decode = query_error("Failed to decode organization");
+
+        let rows = sqlx::query(
+            "SELECT domain FROM organization_domains WHERE organization_id = $1 ORDER BY domain",
+        )
+        .bind(organization._id.to_hex())
+        .fetch_all(&self.pool)
+        .await
+        .map_err(query_error("Failed to fetch organization domains"))?;
+        for row in &rows {
+            organization
+                .domains
+                .push(row.try_get("domain").map_err(&decode)?);
+        }
+
+        let rows = sqlx::query(
+            "SELECT user_id FROM organization_admins WHERE organization_id = $1 ORDER BY user_id",
+        )
+        .bind(organization._id.to_hex())
+        .fetch_all(&self.pool)
+        .await
+        .map_err(query_error("Failed to fetch organization admins"))?;
+        for row in &rows {
+            organization.admins.push(parse_object_id(
+                &row.try_get::<String, _>("user_id").map_err(&decode)?,
+            )?);
+        }
+
+        Ok(organization)
+    }
+
+    // Sets one column on a share link, scoped to its recipient when one is given
+    async fn update_share_link(
+        &self,
+        share_id: ObjectId,
+        recipient_user_id: Option<ObjectId>,
+        column: &'static str,
+        value: SqlValue,
+    ) -> Result<(), Error> {
+        let mut update = ListingQuery::new("");
+        let value_param = update.bind(value);
+        let share_param = update.bind(SqlValue::Text(share_id.to_hex()));
+        update.condition(format!("id = {}", share_param));
+        if let Some(recipient_user_id) = recipient_user_id {
+            let recipient_param = update.bind(SqlValue::Text(recipient_user_id.to_hex()));
+            update.condition(format!("recipient_user_id = {}", recipient_param));
+        }
+
+        let query = format!(
+            "UPDATE share_links SET {} = {}{}",
+            column,
+            value_param,
+            update.where_clause()
+        );
+        let result = update
+            .build(&query)
+            .execute(&self.pool)
+            .await
+            .map_err(query_error("Failed to update shared link"))?;
+
+        if result.rows_affected() == 0 {
+            return Err(actix_web::error::ErrorNotFound("Shared link not found"));
+        }
+
+        Ok(())
+    }
+
+    async fn fetch_page(
+        &self,
+        mut listing: ListingQuery,
+        options: &FileListOptions,
+    ) -> Result<SharedFilePage, Error> {
+        listing.apply_filters(options);
+
+        let count_query = format!("SELECT COUNT(*) {}{}", listing.from, listing.where_clause());
+        let total: i64 = listing
+            .build(&count_query)
+            .fetch_one(&self.pool)
+            .await
+            .map_err(query_error("Unable to fetch files"))?
+            .try_get(0)
+            .map_err(query_error("Unable to fetch files"))?;
+
+        let column = match options.sort {
+            SortField::Name => "f.file_name",
+            SortField::Size => "f.file_size",
+            SortField::SharedAt => "s.created_at",
+            SortField::ExpiresAt => "s.expires_at",
+        };
+        let (direction, comparison) = match options.order {
+            SortOrder::Asc => ("ASC", ">"),
+            SortOrder::Desc => ("DESC", "<"),
+        };
+        listing.apply_cursor(options, column, comparison);
+
+        let page_query = format!(
+            "SELECT {}, u.email AS counterpart_email {}{} \
+             ORDER BY {} {}, s.id {} LIMIT {}",
+            SHARED_FILE_COLUMNS,
+            listing.from,
+            listing.where_clause(),
+            column,
+            direction,
+            direction,
+            options.limit + 1
+        );
+        let rows = listing
+            .build(&page_query)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(query_error("Unable to fetch files"))?;
+
+        let mut files = rows
+            .iter()
+            .map(shared_file_from_row)
+            .collect::<Result<Vec<SharedFile>, Error>>()?;
+        let has_more = files.len() > options.limit;
+        files.truncate(options.limit);
+
+        Ok(SharedFilePage {
+            files,
+            total: total as u64,
+            has_more,
+        })
+    }
+}
+
+#[async_trait]
+impl Database for SqlDatabase {
+    async fn create_user(
+        &self,
+        name: String,
+        email: String,
+        password: String,
+    ) -> Result<ObjectId, Error> {
+        let user_id = ObjectId::new();
+        let now = DateTime::now().timestamp_millis();
+
+        sqlx::query(
+            "INSERT INTO users (id, username, email, password, public_key, created_at, updated_at) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7)",
+        )
+        .bind(user_id.to_hex())
+        .bind(name)
+        .bind(normalize_email(&email))
+        .bind(password)
+        .bind(String::new())
+        .bind(now)
+        .bind(now)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(ref db_error) if db_error.is_unique_violation() => {
+                actix_web::error::ErrorConflict("User already exists")
+            }
+            _ => actix_web::error::ErrorBadRequest(format!("Error creating user: {}", e)),
+        })?;
+
+        Ok(user_id)
+    }
+
+    async fn get_user(&self, email: String) -> Result<User, Error> {
+        let query = format!("SELECT {} FROM users u WHERE u.email = $1", USER_COLUMNS);
+        let row = sqlx::query(&query)
+            .bind(normalize_email(&email))
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(query_error("Error fetching data"))?;
+
+        match row {
+            Some(row) => user_from_row(&row),
+            None => Err(not_found("User not found")),
+        }
+    }
+
+    async fn set_email_verification(
+        &self,
+        user_id: ObjectId,
+        token_hash: String,
+        expires_at: DateTime,
+    ) -> Result<(), Error> {
+        let result = sqlx::query(
+            "UPDATE users SET email_verification_token = $1, email_verification_expires_at = $2, updated_at = $3 \
+             WHERE id = $4",
+        )
+        .bind(token_hash)
+        .bind(expires_at.timestamp_millis())
+        .bind(DateTime::now().timestamp_millis())
+        .bind(user_id.to_hex())
+        .execute(&self.pool)
+        .await
+        .map_err(query_error("Failed to store verification token"))?;
+
+        if result.rows_affected() == 0 {
+            return Err(not_found("User not found"));
+        }
+
+        Ok(())
+    }
+
+    async fn verify_email(&self, token_hash: String) -> Result<User, Error> {
+        let now = DateTime::now().timestamp_millis();
+        let row = sqlx::query(
+            "UPDATE users SET email_verified_at = COALESCE(email_verified_at, $1), \
+             email_verification_token = NULL, email_verification_expires_at = NULL, updated_at = $1 \
+             WHERE email_verification_token = $2 AND email_verification_expires_at > $1 \
+             RETURNING id",
+        )
+        .bind(now)
+        .bind(token_hash)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(query_error("Failed to verify email"))?;
+
+        match row {
+            Some(row) => {
+                let user_id: String = row
+                    .try_get("id")
+                    .map_err(query_error("Failed to decode user"))?;
+                self.get_user_by_id(Bson::String(user_id)).await
+            }
+            None => Err(actix_web::error::ErrorBadRequest(
+                "Invalid or expired verification token",
+            )),
+        }
+    }
+
+    async fn update_public_key(&self, id: Bson, public_key: String) -> Result<(), Error> {
+        let user_id = bson_object_id(id)?;
+
+        sqlx::query("UPDATE users SET public_key = $1, updated_at = $2 WHERE id = $3")
+            .bind(public_key)
+            .bind(DateTime::now().timestamp_millis())
+            .bind(user_id.to_hex())
+            .execute(&self.pool)
+            .await
+            .map_err(query_error("Error updating user"))?;
+
+        Ok(())
+    }
+
+    async fn get_user_by_id(&self, id: Bson) -> Result<User, Error> {
+        let user_id = bson_object_id(id)?;
+        let query = format!("SELECT {} FROM users u WHERE u.id = $1", USER_COLUMNS);
+        let row = sqlx::query(&query)
+            .bind(user_id.to_hex())
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(query_error("Error while fetching user"))?;
+
+        match row {
+            Some(row) => user_from_row(&row),
+            None => Err(not_found("User not found")),
+        }
+    }
+
+    async fn reserve_storage(
+        &self,
+        user_id: ObjectId,
+        bytes: i64,
+        quota: i64,
+    ) -> Result<(), Error> {
+        // Checked and incremented in one statement so concurrent uploads cannot overshoot
+        let result = sqlx::query(
+            "UPDATE users SET storage_used = storage_used + $1 WHERE id = $2 AND storage_used + $1 <= $3",
+        )
+        .bind(bytes)
+        .bind(user_id.to_hex())
+        .bind(quota)
+        .execute(&self.pool)
+        .await
+        .map_err(query_error("Failed to update storage usage"))?;
+
+        if result.rows_affected() == 0 {
+            return Err(actix_web::error::ErrorPayloadTooLarge(
+                "Storage quota exceeded",
+            ));
+        }
+
+        Ok(())
+    }
+
+    async fn release_storage(&self, user_id: ObjectId, bytes: i64) -> Result<(), Error> {
+        sqlx::query("UPDATE users SET storage_used = storage_used - $1 WHERE id = $2")
+            .bind(bytes)
+            .bind(user_id.to_hex())
+            .execute(&self.pool)
+            .await
+            .map_err(query_error("Failed to update storage usage"))?;
+
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn save_file(
+        &self,
+        file_name: String,
+        file_size: i64,
+        file_data: Vec<u8>,
+        iv: Vec<u8>,
+        aes_key: Vec<u8>,
+        reciepient_user_id: String,
+        user_id: ObjectId,
+        password: String,
+        expiration_date: DateTime,
+        content_type: String,
+        sha256: String,
+        signature: Option<Vec<u8>>,
+        scan_status: ScanStatus,
+        compression: Compression,
+        chunk_ids: Vec<String>,
+        chunks: Vec<Chunk>,
+        organization_id: Option<ObjectId>,
+    ) -> Result<ObjectId, Error> {
+        let reciepient_user_id = parse_object_id(&reciepient_user_id)?;
+        let file_id = ObjectId::new();
+        let now = DateTime::now().timestamp_millis();
+
+        // The file and its share link are written atomically
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(query_error("Failed to start transaction"))?;
+
+        sqlx::query(
+            "INSERT INTO files (id, user_id, file_name, file_size, content_type, encrypted_aes_key, encrypted_file, iv, sha256, created_at, updated_at, scan_status, compression, chunk_ids, organization_id) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15)",
+        )
+        .bind(file_id.to_hex())
+        .bind(user_id.to_hex())
+        .bind(file_name)
+        .bind(file_size)
+        .bind(content_type)
+        .bind(aes_key)
+        .bind(file_data)
+        .bind(iv)
+        .bind(sha256)
+        .bind(now)
+        .bind(now)
+        .bind(scan_status.as_str())
+        .bind(compression.as_str())
+        .bind(join_chunk_ids(&chunk_ids))
+        .bind(organization_id.map(|id| id.to_hex()))
+        .execute(&mut *tx)
+        .await
+        .map_err(query_error("Failed to insert file in database"))?;
+        retain_chunks(&mut tx, chunks).await?;
+
+        sqlx::query(
+            "INSERT INTO share_links (id, recipient_user_id, file_id, password, expires_at, created_at, signature, organization_id) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+        )
+        .bind(ObjectId::new().to_hex())
+        .bind(reciepient_user_id.to_hex())
+        .bind(file_id.to_hex())
+        .bind(password)
+        .bind(expiration_date.timestamp_millis())
+        .bind(now)
+        .bind(signature)
+        .bind(organization_id.map(|id| id.to_hex()))
+        .execute(&mut *tx)
+        .await
+        .map_err(query_error("Failed to save the share document"))?;
+
+        tx.commit()
+            .await
+            .map_err(query_error("Failed to commit transaction"))?;
+
+        Ok(file_id)
+    }
+
+    async fn get_shared(&self, share_id: ObjectId, user_id: ObjectId) -> Result<ShareLink, Error> {
+        let query = format!(
+            "SELECT {} FROM share_links s \
+             WHERE s.recipient_user_id = $1 AND s.id = $2 \
+             AND s.revoked_at IS NULL AND s.declined_at IS NULL",
+            SHARE_LINK_COLUMNS
+        );
+        let row = sqlx::query(&query)
+            .bind(user_id.to_hex())
+            .bind(share_id.to_hex())
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(query_error("Couldn't find the shared_file"))?;
+
+        match row {
+            Some(row) => share_link_from_row(&row),
+            None => Err(not_found("Shared file not found")),
+        }
+    }
+
+    async fn get_file(&self, file_id: Bson) -> Result<File, Error> {
+        let file_id = bson_object_id(file_id)?;
+        let query = format!("SELECT {} FROM files f WHERE f.id = $1", FILE_COLUMNS);
+        let row = sqlx::query(&query)
+            .bind(file_id.to_hex())
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(query_error("Error while fetching file"))?;
+
+        match row {
+            Some(row) => file_from_row(&row),
+            None => Err(not_found("File not found")),
+        }
+    }
+
+    async fn get_sent_files(
+        &self,
+        user_id: String,
+        options: &FileListOptions,
+    ) -> Result<SharedFilePage, Error> {
+        let user_id = parse_object_id(&user_id)?;
+
+        let mut listing = ListingQuery::new(
+            "FROM files f \
+             JOIN share_links s ON s.file_id = f.id \
+             JOIN users u ON u.id = s.recipient_user_id",
+        );
+        let user_param = listing.bind(SqlValue::Text(user_id.to_hex()));
+        listing.condition(format!("f.user_id = {}", user_param));
+
+        self.fetch_page(listing, options).await
+    }
+
+    async fn get_recieve_files(
+        &self,
+        user_id: String,
+        options: &FileListOptions,
+    ) -> Result<SharedFilePage, Error> {
+        let user_id = parse_object_id(&user_id)?;
+
+        let mut listing = ListingQuery::new(
+            "FROM share_links s \
+             JOIN files f ON f.id = s.file_id \
+             JOIN users u ON u.id = f.user_id",
+        );
+        let user_param = listing.bind(SqlValue::Text(user_id.to_hex()));
+        listing.condition(format!("s.recipient_user_id = {}", user_param));
+        listing.condition("s.revoked_at IS NULL AND s.declined_at IS NULL".to_string());
+        listing.condition("f.scan_status <> 'quarantined'".to_string());
+        if options.archived {
+            listing.condition("s.archived_at IS NOT NULL".to_string());
+        } else {
+            listing.condition("s.archived_at IS NULL".to_string());
+        }
+
+        self.fetch_page(listing, options).await
+    }
+
+    async fn delete_file_by_share_id(&self, share_id: String) -> Result<bool, Error> {
+        let share_id = parse_object_id(&share_id)?;
+
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(query_error("Failed to start transaction"))?;
+
+        let file_id: Option<String> =
+            sqlx::query_scalar("DELETE FROM share_links WHERE id = $1 RETURNING file_id")
+                .bind(share_id.to_hex())
+                .fetch_optional(&mut *tx)
+                .await
+                .map_err(query_error("Failed to delete shared link"))?;
+
+        let file_id = match file_id {
+            Some(file_id) => file_id,
+            None => {
+                return Err(actix_web::error::ErrorBadRequest(
+                    "Failed to delete shared link",
+                ));
+            }
+        };
+
+        // Deleting the file ends every other recipient's share of it too
+        sqlx::query("DELETE FROM share_links WHERE file_id = $1")
+            .bind(&file_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(query_error("Failed to delete shared links"))?;
+        sqlx::query("DELETE FROM group_shares WHERE file_id = $1")
+            .bind(&file_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(query_error("Failed to delete group shares"))?;
+
+        let versions =
+            sqlx::query("DELETE FROM file_versions WHERE file_id = $1 RETURNING chunk_ids")
+                .bind(&file_id)
+                .fetch_all(&mut *tx)
+                .await
+                .map_err(query_error("Failed to delete file versions"))?;
+        release_chunks(&mut tx, &versions).await?;
+
+        let deleted =
+            sqlx::query("DELETE FROM files WHERE id = $1 RETURNING user_id, file_size, history_size, chunk_ids")
+                .bind(file_id)
+                .fetch_all(&mut *tx)
+                .await
+                .map_err(query_error("Failed to delete file"))?;
+
+        if deleted.is_empty() {
+            return Err(actix_web::error::ErrorBadRequest("Failed to delete file"));
+        }
+        release_deleted_files(&mut tx, &deleted).await?;
+
+        tx.commit()
+            .await
+            .map_err(query_error("Failed to commit transaction"))?;
+
+        Ok(true)
+    }
+
+    async fn get_share_link_doc(&self, share_id: String) -> Result<File, Error> {
+        let share_id = parse_object_id(&share_id)?;
+        let query = format!(
+            "SELECT {} FROM share_links s JOIN files f ON f.id = s.file_id WHERE s.id = $1",
+            FILE_COLUMNS
+        );
+        let row = sqlx::query(&query)
+            .bind(share_id.to_hex())
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(query_error("Failed to fetch shared link"))?;
+
+        match row {
+            Some(row) => file_from_row(&row),
+            None => Err(actix_web::error::ErrorBadRequest(
+                "Failed to fetch shared link",
+            )),
+        }
+    }
+
+    async fn search_user(
+        &self,
+        email_text: String,
+        organization_id: Option<ObjectId>,
+    ) -> Result<Vec<User>, Error> {
+        let tenant = match organization_id {
+            Some(_) => "u.organization_id = $2",
+            None => "u.organization_id IS NULL",
+        };
+        let query = format!(
+            "SELECT {} FROM users u WHERE LOWER(u.email) LIKE $1 ESCAPE '\\' AND {}",
+            USER_COLUMNS, tenant
+        );
+        let mut search = sqlx::query(&query).bind(like_pattern(&email_text));
+        if let Some(organization_id) = organization_id {
+            search = search.bind(organization_id.to_hex());
+        }
+        let rows = search.fetch_all(&self.pool).await.map_err(|e| {
+            actix_web::error::ErrorBadRequest(format!("Failed to fetch users: {}", e))
+        })?;
+
+        rows.iter().map(user_from_row).collect()
+    }
+
+    async fn delete_expired_files(&self) -> Result<Vec<ExpiredShare>, Error> {
+        let now = DateTime::now().timestamp_millis();
+
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(query_error("Failed to start transaction"))?;
+
+        let expired = sqlx::query(
+            "DELETE FROM share_links WHERE expires_at < $1 RETURNING id, file_id, recipient_user_id",
+        )
+        .bind(now)
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(query_error("Failed to delete the shared links"))?;
+
+        let decode = query_error("Failed to decode shared link");
+        let mut file_ids: Vec<String> = Vec::with_capacity(expired.len());
+        let mut expired_shares: Vec<ExpiredShare> = Vec::with_capacity(expired.len());
+        for row in &expired {
+            let file_id: String = row.try_get("file_id").map_err(&decode)?;
+            let expired_share = ExpiredShare {
+                share_id: parse_object_id(&row.try_get::<String, _>("id").map_err(&decode)?)?,
+                file_id: parse_object_id(&file_id)?,
+                recipient_user_id: parse_object_id(
+                    &row.try_get::<String, _>("recipient_user_id")
+                        .map_err(&decode)?,
+                )?,
+                owner_id: None,
+                file_name: None,
+            };
+            let event = AuditEvent {
+                share_id: Some(expired_share.share_id),
+                file_id: Some(expired_share.file_id),
+                subject_user_id: Some(expired_share.recipient_user_id),
+                ..system_event(AuditAction::Expire)
+            };
+            append_audit_event(&mut tx, event).await?;
+
+            let file = sqlx::query("SELECT user_id, file_name FROM files WHERE id = $1")
+                .bind(&file_id)
+                .fetch_optional(&mut *tx)
+                .await
+                .map_err(query_error("Failed to fetch file"))?;
+            let expired_share = match file {
+                Some(file) => ExpiredShare {
+                    owner_id: optional_object_id(&file, "user_id")?,
+                    file_name: file
+                        .try_get("file_name")
+                        .map_err(query_error("Failed to decode file"))?,
+                    ..expired_share
+                },
+                None => expired_share,
+            };
+            expired_shares.push(expired_share);
+            file_ids.push(file_id);
+        }
+
+        // Invitations nobody registered for in time
+        let expired_invitations =
+            sqlx::query("DELETE FROM invitations WHERE expires_at < $1 RETURNING email, file_id")
+                .bind(now)
+                .fetch_all(&mut *tx)
+                .await
+                .map_err(query_error("Failed to delete the invitations"))?;
+        for row in &expired_invitations {
+            let decode = query_error("Failed to decode invitation");
+            let file_id: String = row.try_get("file_id").map_err(&decode)?;
+            let email: String = row.try_get("email").map_err(&decode)?;
+            let event = AuditEvent {
+                file_id: Some(parse_object_id(&file_id)?),
+                detail: Some(format!("Invitation to {}", email)),
+                ..system_event(AuditAction::Expire)
+            };
+            append_audit_event(&mut tx, event).await?;
+            file_ids.push(file_id);
+        }
+
+        let mut deleted_files: u64 = 0;
+        for file_id in &file_ids {
+            // A file stays while any other recipient's share of it is still live
+            let deleted = sqlx::query(
+                "DELETE FROM files WHERE id = $1 \
+                 AND NOT EXISTS (SELECT 1 FROM share_links s WHERE s.file_id = files.id) \
+                 AND NOT EXISTS (SELECT 1 FROM invitations i WHERE i.file_id = files.id) \
+                 RETURNING user_id, file_size, history_size, chunk_ids",
+            )
+            .bind(file_id)
+            .fetch_all(&mut *tx)
+            .await
+            .map_err(query_error("Failed to delete files"))?;
+            release_deleted_files(&mut tx, &deleted).await?;
+            deleted_files += deleted.len() as u64;
+        }
+
+        // Expired group shares and those of the files deleted above
+        sqlx::query(
+            "DELETE FROM group_shares WHERE expires_at < $1 \
+             OR NOT EXISTS (SELECT 1 FROM files f WHERE f.id = group_shares.file_id)",
+        )
+        .bind(now)
+        .execute(&mut *tx)
+        .await
+        .map_err(query_error("Failed to delete the group shares"))?;
+
+        let versions = sqlx::query(
+            "DELETE FROM file_versions \
+             WHERE NOT EXISTS (SELECT 1 FROM files f WHERE f.id = file_versions.file_id) \
+             RETURNING chunk_ids",
+        )
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(query_error("Failed to delete file versions"))?;
+        release_chunks(&mut tx, &versions).await?;
+
+        tx.commit()
+            .await
+            .map_err(query_error("Failed to commit transaction"))?;
+
+        println!(
+            "Successfully deleted {} expired shared links.",
+            expired.len()
+        );
+        println!("Successfully deleted {} expired files.", deleted_files);
+
+        Ok(expired_shares)
+    }
+
+    async fn get_share_link(&self, share_id: ObjectId) -> Result<ShareLink, Error> {
+        let query = format!(
+            "SELECT {} FROM share_links s WHERE s.id = $1",
+            SHARE_LINK_COLUMNS
+        );
+        let row = sqlx::query(&query)
+            .bind(share_id.to_hex())
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(query_error("Failed to fetch shared link"))?;
+
+        match row {
+            Some(row) => share_link_from_row(&row),
+            None => Err(not_found("Shared link not found")),
+        }
+    }
+
+    async fn create_share_link(&self, share_link: ShareLink) -> Result<ObjectId, Error> {
+        // A revoked or declined link to the same recipient is brought back
+        // under its own id; a live one is left alone and nothing is returned
+        let row = sqlx::query(
+            "INSERT INTO share_links (id, recipient_user_id, file_id, password, expires_at, created_at, encrypted_aes_key, revoked_at, can_reshare, parent_share_id, signature, organization_id) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12) \
+             ON CONFLICT (file_id, recipient_user_id) DO UPDATE SET \
+             password = excluded.password, expires_at = excluded.expires_at, created_at = excluded.created_at, \
+             encrypted_aes_key = excluded.encrypted_aes_key, revoked_at = excluded.revoked_at, \
+             can_reshare = excluded.can_reshare, parent_share_id = excluded.parent_share_id, \
+             signature = excluded.signature, organization_id = excluded.organization_id, \
+             declined_at = NULL, archived_at = NULL, first_opened_at = NULL, last_opened_at = NULL, \
+             open_count = 0, failed_password_attempts = 0 \
+             WHERE share_links.revoked_at IS NOT NULL OR share_links.declined_at IS NOT NULL \
+             RETURNING id",
+        )
+        .bind(share_link._id.to_hex())
+        .bind(share_link.recipient_user_id.to_hex())
+        .bind(share_link.file_id.to_hex())
+        .bind(share_link.password)
+        .bind(share_link.expires_at.timestamp_millis())
+        .bind(share_link.created_at.timestamp_millis())
+        .bind(share_link.encrypted_aes_key)
+        .bind(share_link.revoked_at.map(|revoked_at| revoked_at.timestamp_millis()))
+        .bind(share_link.can_reshare as i64)
+        .bind(share_link.parent_share_id.map(|id| id.to_hex()))
+        .bind(share_link.signature)
+        .bind(share_link.organization_id.map(|id| id.to_hex()))
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(query_error("Failed to save the share document"))?
+        .ok_or_else(|| actix_web::error::ErrorConflict("File is already shared with this user"))?;
+
+        parse_object_id(
+            &row.try_get::<String, _>("id")
+                .map_err(query_error("Failed to decode share link"))?,
+        )
+    }
+
+    async fn save_invited_file(
+        &self,
+        file: File,
+        chunks: Vec<Chunk>,
+        invitation: Invitation,
+    ) -> Result<ObjectId, Error> {
+        let file_id = file._id;
+
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(query_error("Failed to start transaction"))?;
+
+        insert_file(file)
+            .execute(&mut *tx)
+            .await
+            .map_err(query_error("Failed to insert file in database"))?;
+        retain_chunks(&mut tx, chunks).await?;
+
+        sqlx::query(
+            "INSERT INTO invitations (id, email, file_id, sender_id, file_name, password, expires_at, sign, created_at) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+        )
+        .bind(invitation._id.to_hex())
+        .bind(normalize_email(&invitation.email))
+        .bind(invitation.file_id.to_hex())
+        .bind(invitation.sender_id.to_hex())
+        .bind(invitation.file_name)
+        .bind(invitation.password)
+        .bind(invitation.expires_at.timestamp_millis())
+        .bind(invitation.sign as i64)
+        .bind(invitation.created_at.timestamp_millis())
+        .execute(&mut *tx)
+        .await
+        .map_err(query_error("Failed to save the invitation"))?;
+
+        tx.commit()
+            .await
+            .map_err(query_error("Failed to commit transaction"))?;
+
+        Ok(file_id)
+    }
+
+    async fn get_invitations(&self, email: String) -> Result<Vec<Invitation>, Error> {
+        let query = format!(
+            "SELECT {} FROM invitations i WHERE i.email = $1 ORDER BY i.created_at, i.id",
+            INVITATION_COLUMNS
+        );
+        let rows = sqlx::query(&query)
+            .bind(normalize_email(&email))
+            .fetch_all(&self.pool)
+            .await
+            .map_err(query_error("Failed to fetch invitations"))?;
+
+        rows.iter().map(invitation_from_row).collect()
+    }
+
+    async fn get_sent_invitations(&self, sender_id: ObjectId) -> Result<Vec<Invitation>, Error> {
+        let query = format!(
+            "SELECT {} FROM invitations i WHERE i.sender_id = $1 \
+             ORDER BY i.created_at DESC, i.id DESC",
+            INVITATION_COLUMNS
+        );
+        let rows = sqlx::query(&query)
+            .bind(sender_id.to_hex())
+            .fetch_all(&self.pool)
+            .await
+            .map_err(query_error("Failed to fetch invitations"))?;
+
+        rows.iter().map(invitation_from_row).collect()
+    }
+
+    async fn get_invitation(&self, invitation_id: ObjectId) -> Result<Invitation, Error> {
+        let query = format!(
+            "SELECT {} FROM invitations i WHERE i.id = $1",
+            INVITATION_COLUMNS
+        );
+        let row = sqlx::query(&query)
+            .bind(invitation_id.to_hex())
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(query_error("Failed to fetch invitation"))?;
+
+        match row {
+            Some(row) => invitation_from_row(&row),
+            None => Err(actix_web::error::ErrorNotFound("Invitation not found")),
+        }
+    }
+
+    async fn accept_invitation(
+        &self,
+        invitation_id: ObjectId,
+        encrypted_aes_key: Vec<u8>,
+        share_link: ShareLink,
+    ) -> Result<(), Error> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(query_error("Failed to start transaction"))?;
+
+        // Deleted first, so an invitation accepted twice fails the second time
+        let result = sqlx::query("DELETE FROM invitations WHERE id = $1")
+            .bind(invitation_id.to_hex())
+            .execute(&mut *tx)
+            .await
+            .map_err(query_error("Failed to delete invitation"))?;
+        if result.rows_affected() == 0 {
+            return Err(actix_web::error::ErrorNotFound("Invitation not found"));
+        }
+
+        sqlx::query("UPDATE files SET encrypted_aes_key = $1, updated_at = $2 WHERE id = $3")
+            .bind(encrypted_aes_key)
+            .bind(DateTime::now().timestamp_millis())
+            .bind(share_link.file_id.to_hex())
+            .execute(&mut *tx)
+            .await
+            .map_err(query_error("Failed to update file"))?;
+
+        insert_share_link(share_link)
+            .execute(&mut *tx)
+            .await
+            .map_err(query_error("Failed to save the share document"))?;
+
+        tx.commit()
+            .await
+            .map_err(query_error("Failed to commit transaction"))?;
+
+        Ok(())
+    }
+
+    async fn delete_invitation(&self, invitation_id: ObjectId) -> Result<(), Error> {
+        let result = sqlx::query("DELETE FROM invitations WHERE id = $1")
+            .bind(invitation_id.to_hex())
+            .execute(&self.pool)
+            .await
+            .map_err(query_error("Failed to delete invitation"))?;
+
+        if result.rows_affected() == 0 {
+            return Err(actix_web::error::ErrorNotFound("Invitation not found"));
+        }
+
+        Ok(())
+    }
+
+    async fn create_group(&self, group: Group) -> Result<ObjectId, Error> {
+        let group_id = group._id;
+
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(query_error("Failed to start transaction"))?;
+
+        sqlx::query(
+            "INSERT INTO user_groups (id, name, owner_id, created_at, updated_at) \
+             VALUES ($1, $2, $3, $4, $5)",
+        )
+        .bind(group._id.to_hex())
+        .bind(group.name)
+        .bind(group.owner_id.to_hex())
+        .bind(group.created_at.timestamp_millis())
+        .bind(group.updated_at.timestamp_millis())
+        .execute(&mut *tx)
+        .await
+        .map_err(query_error("Failed to save the group"))?;
+
+        for member_id in &group.members {
+            sqlx::query(
+                "INSERT INTO group_members (group_id, user_id, admin, created_at) \
+                 VALUES ($1, $2, $3, $4)",
+            )
+            .bind(group._id.to_hex())
+            .bind(member_id.to_hex())
+            .bind(group.admins.contains(member_id) as i64)
+            .bind(group.created_at.timestamp_millis())
+            .execute(&mut *tx)
+            .await
+            .map_err(query_error("Failed to save the group members"))?;
+        }
+
+        tx.commit()
+            .await
+            .map_err(query_error("Failed to commit transaction"))?;
+
+        Ok(group_id)
+    }
+
+    async fn get_group(&self, group_id: ObjectId) -> Result<Group, Error> {
+        let query = format!("SELECT {} FROM user_groups g WHERE g.id = $1", GROUP_COLUMNS);
+        let row = sqlx::query(&query)
+            .bind(group_id.to_hex())
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(query_error("Failed to fetch group"))?;
+
+        let group = match row {
+            Some(row) => group_from_row(&row)?,
+            None => return Err(actix_web::error::ErrorNotFound("Group not found")),
+        };
+        self.load_group_members(group).await
+    }
+
+    async fn get_user_groups(&self, user_id: ObjectId) -> Result<Vec<Group>, Error> {
+        let query = format!(
+            "SELECT {} FROM user_groups g JOIN group_members m ON m.group_id = g.id \
+             WHERE m.user_id = $1 ORDER BY g.name, g.id",
+            GROUP_COLUMNS
+        );
+        let rows = sqlx::query(&query)
+            .bind(user_id.to_hex())
+            .fetch_all(&self.pool)
+            .await
+            .map_err(query_error("Failed to fetch groups"))?;
+
+        let mut groups = Vec::with_capacity(rows.len());
+        for row in &rows {
+            let group = group_from_row(row)?;
+            groups.push(self.load_group_members(group).await?);
+        }
+        Ok(groups)
+    }
+
+    async fn get_group_members(&self, group_id: ObjectId) -> Result<Vec<User>, Error> {
+        let query = format!(
+            "SELECT {} FROM group_members m JOIN users u ON u.id = m.user_id \
+             WHERE m.group_id = $1 ORDER BY u.email",
+            USER_COLUMNS
+        );
+        let rows = sqlx::query(&query)
+            .bind(group_id.to_hex())
+            .fetch_all(&self.pool)
+            .await
+            .map_err(query_error("Failed to fetch group members"))?;
+
+        rows.iter().map(user_from_row).collect()
+    }
+
+    async fn rename_group(&self, group_id: ObjectId, name: String) -> Result<(), Error> {
+        let result = sqlx::query("UPDATE user_groups SET name = $1, updated_at = $2 WHERE id = $3")
+            .bind(name)
+            .bind(DateTime::now().timestamp_millis())
+            .bind(group_id.to_hex())
+            .execute(&self.pool)
+            .await
+            .map_err(query_error("Failed to update group"))?;
+
+        if result.rows_affected() == 0 {
+            return Err(actix_web::error::ErrorNotFound("Group not found"));
+        }
+
+        Ok(())
+    }
+
+    async fn delete_group(&self, group_id: ObjectId) -> Result<(), Error> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(query_error("Failed to start transaction"))?;
+
+        sqlx::query("DELETE FROM group_shares WHERE group_id = $1")
+            .bind(group_id.to_hex())
+            .execute(&mut *tx)
+            .await
+            .map_err(query_error("Failed to delete group shares"))?;
+        sqlx::query("DELETE FROM group_members WHERE group_id = $1")
+            .bind(group_id.to_hex())
+            .execute(&mut *tx)
+            .await
+            .map_err(query_error("Failed to delete group members"))?;
+
+        let result = sqlx::query("DELETE FROM user_groups WHERE id = $1")
+            .bind(group_id.to_hex())
+            .execute(&mut *tx)
+            .await
+            .map_err(query_error("Failed to delete group"))?;
+        if result.rows_affected() == 0 {
+            return Err(actix_web::error::ErrorNotFound("Group not found"));
+        }
+
+        tx.commit()
+            .await
+            .map_err(query_error("Failed to commit transaction"))?;
+
+        Ok(())
+    }
+
+    async fn add_group_member(
+        &self,
+        group_id: ObjectId,
+        user_id: ObjectId,
+        admin: bool,
+    ) -> Result<(), Error> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(query_error("Failed to start transaction"))?;
+
+        sqlx::query(
+            "INSERT INTO group_members (group_id, user_id, admin, created_at) \
+             VALUES ($1, $2, $3, $4)",
+        )
+        .bind(group_id.to_hex())
+        .bind(user_id.to_hex())
+        .bind(admin as i64)
+        .bind(DateTime::now().timestamp_millis())
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(ref db_error) if db_error.is_unique_violation() => {
+                actix_web::error::ErrorConflict("User is already a member of this group")
+            }
+            _ => actix_web::error::ErrorBadRequest(format!(
+                "Failed to save the group member: {}",
+                e
+            )),
+        })?;
+        touch_group(&mut tx, group_id).await?;
+
+        tx.commit()
+            .await
+            .map_err(query_error("Failed to commit transaction"))?;
+
+        Ok(())
+    }
+
+    async fn remove_group_member(
+        &self,
+        group_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<(), Error> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(query_error("Failed to start transaction"))?;
+
+        let result = sqlx::query("DELETE FROM group_members WHERE group_id = $1 AND user_id = $2")
+            .bind(group_id.to_hex())
+            .bind(user_id.to_hex())
+            .execute(&mut *tx)
+            .await
+            .map_err(query_error("Failed to delete group member"))?;
+        if result.rows_affected() == 0 {
+            return Err(actix_web::error::ErrorNotFound(
+                "User is not a member of this group",
+            ));
+        }
+        touch_group(&mut tx, group_id).await?;
+
+        tx.commit()
+            .await
+            .map_err(query_error("Failed to commit transaction"))?;
+
+        Ok(())
+    }
+
+    async fn set_group_admin(
+        &self,
+        group_id: ObjectId,
+        user_id: ObjectId,
+        admin: bool,
+    ) -> Result<(), Error> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(query_error("Failed to start transaction"))?;
+
+        let result = sqlx::query(
+            "UPDATE group_members SET admin = $1 WHERE group_id = $2 AND user_id = $3",
+        )
+        .bind(admin as i64)
+        .bind(group_id.to_hex())
+        .bind(user_id.to_hex())
+        .execute(&mut *tx)
+        .await
+        .map_err(query_error("Failed to update group member"))?;
+        if result.rows_affected() == 0 {
+            return Err(actix_web::error::ErrorNotFound(
+                "User is not a member of this group",
+            ));
+        }
+        touch_group(&mut tx, group_id).await?;
+
+        tx.commit()
+            .await
+            .map_err(query_error("Failed to commit transaction"))?;
+
+        Ok(())
+    }
+
+    async fn save_group_file(
+        &self,
+        file: File,
+        chunks: Vec<Chunk>,
+        share_links: Vec<ShareLink>,
+        group_share: Option<GroupShare>,
+    ) -> Result<ObjectId, Error> {
+        let file_id = file._id;
+
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(query_error("Failed to start transaction"))?;
+
+        insert_file(file)
+            .execute(&mut *tx)
+            .await
+            .map_err(query_error("Failed to insert file in database"))?;
+        retain_chunks(&mut tx, chunks).await?;
+
+        for share_link in share_links {
+            insert_share_link(share_link)
+                .execute(&mut *tx)
+                .await
+                .map_err(query_error("Failed to save the share document"))?;
+        }
+
+        if let Some(group_share) = group_share {
+            sqlx::query(
+                "INSERT INTO group_shares (id, group_id, file_id, sender_id, password, expires_at, sign, created_at) \
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+            )
+            .bind(group_share._id.to_hex())
+            .bind(group_share.group_id.to_hex())
+            .bind(group_share.file_id.to_hex())
+            .bind(group_share.sender_id.to_hex())
+            .bind(group_share.password)
+            .bind(group_share.expires_at.timestamp_millis())
+            .bind(group_share.sign as i64)
+            .bind(group_share.created_at.timestamp_millis())
+            .execute(&mut *tx)
+            .await
+            .map_err(query_error("Failed to save the group share"))?;
+        }
+
+        tx.commit()
+            .await
+            .map_err(query_error("Failed to commit transaction"))?;
+
+        Ok(file_id)
+    }
+
+    async fn get_group_shares(&self, group_id: ObjectId) -> Result<Vec<GroupShare>, Error> {
+        let query = format!(
+            "SELECT {} FROM group_shares gs WHERE gs.group_id = $1 AND gs.expires_at > $2 \
+             ORDER BY gs.created_at, gs.id",
+            GROUP_SHARE_COLUMNS
+        );
+        let rows = sqlx::query(&query)
+            .bind(group_id.to_hex())
+            .bind(DateTime::now().timestamp_millis())
+            .fetch_all(&self.pool)
+            .await
+            .map_err(query_error("Failed to fetch group shares"))?;
+
+        rows.iter().map(group_share_from_row).collect()
+    }
+
+    async fn create_organization(&self, organization: Organization) -> Result<ObjectId, Error> {
+        let organization_id = organization._id;
+
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(query_error("Failed to start transaction"))?;
+
+        sqlx::query(
+            "INSERT INTO organizations (id, name, max_expiry_days, storage_quota, created_at, updated_at) \
+             VALUES ($1, $2, $3, $4, $5, $6)",
+        )
+        .bind(organization_id.to_hex())
+        .bind(organization.name)
+        .bind(organization.max_expiry_days)
+        .bind(organization.storage_quota)
+        .bind(organization.created_at.timestamp_millis())
+        .bind(organization.updated_at.timestamp_millis())
+        .execute(&mut *tx)
+        .await
+        .map_err(query_error("Failed to save the organization"))?;
+        save_organization_domains(&mut tx, organization_id, &organization.domains).await?;
+
+        for admin_id in &organization.admins {
+            let joined = sqlx::query(
+                "UPDATE users SET organization_id = $1, updated_at = $2 \
+                 WHERE id = $3 AND organization_id IS NULL",
+            )
+            .bind(organization_id.to_hex())
+            .bind(organization.created_at.timestamp_millis())
+            .bind(admin_id.to_hex())
+            .execute(&mut *tx)
+            .await
+            .map_err(query_error("Failed to update user"))?;
+            if joined.rows_affected() == 0 {
+                return Err(actix_web::error::ErrorConflict(
+                    "User already belongs to an organization",
+                ));
+            }
+
+            sqlx::query(
+                "INSERT INTO organization_admins (organization_id, user_id) VALUES ($1, $2)",
+            )
+            .bind(organization_id.to_hex())
+            .bind(admin_id.to_hex())
+            .execute(&mut *tx)
+            .await
+            .map_err(query_error("Failed to save the organization admins"))?;
+        }
+
+        tx.commit()
+            .await
+            .map_err(query_error("Failed to commit transaction"))?;
+
+        Ok(organization_id)
+    }
+
+    async fn get_organization(&self, organization_id: ObjectId) -> Result<Organization, Error> {
+        let query = format!(
+            "SELECT {} FROM organizations o WHERE o.id = $1",
+            ORGANIZATION_COLUMNS
+        );
+        let row = sqlx::query(&query)
+            .bind(organization_id.to_hex())
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(query_error("Failed to fetch organization"))?;
+
+        let organization = match row {
+            Some(row) => organization_from_row(&row)?,
+            None => return Err(actix_web::error::ErrorNotFound("Organization not found")),
+        };
+        self.load_organization_details(organization).await
+    }
+
+    async fn get_organizations(&self) -> Result<Vec<Organization>, Error> {
+        let query = format!(
+            "SELECT {} FROM organizations o ORDER BY o.name, o.id",
+            ORGANIZATION_COLUMNS
+        );
+        let rows = sqlx::query(&query)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(query_error("Failed to fetch organizations"))?;
+
+        let mut organizations = Vec::with_capacity(rows.len());
+        for row in &rows {
+            let organization = organization_from_row(row)?;
+            organizations.push(self.load_organization_details(organization).await?);
+        }
+        Ok(organizations)
+    }
+
+    async fn get_organization_by_domain(
+        &self,
+        domain: String,
+    ) -> Result<Option<Organization>, Error> {
+        let query = format!(
+            "SELECT {} FROM organizations o JOIN organization_domains d ON d.organization_id = o.id \
+             WHERE d.domain = $1",
+            ORGANIZATION_COLUMNS
+        );
+        let row = sqlx::query(&query)
+            .bind(domain)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(query_error("Failed to fetch organization"))?;
+
+        let organization = match row {
+            Some(row) => organization_from_row(&row)?,
+            None => return Ok(None),
+        };
+        Ok(Some(self.load_organization_details(organization).await?))
+    }
+
+    async fn update_organization(&self, organization: Organization) -> Result<(), Error> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(query_error("Failed to start transaction"))?;
+
+        let result = sqlx::query(
+            "UPDATE organizations SET name = $1, max_expiry_days = $2, storage_quota = $3, updated_at = $4 \
+             WHERE id = $5",
+        )
+        .bind(organization.name)
+        .bind(organization.max_expiry_days)
+        .bind(organization.storage_quota)
+        .bind(DateTime::now().timestamp_millis())
+        .bind(organization._id.to_hex())
+        .execute(&mut *tx)
+        .await
+        .map_err(query_error("Failed to update organization"))?;
+        if result.rows_affected() == 0 {
+            return Err(actix_web::error::ErrorNotFound("Organization not found"));
+        }
+        save_organization_domains(&mut tx, organization._id, &organization.domains).await?;
+
+        tx.commit()
+            .await
+            .map_err(query_error("Failed to commit transaction"))?;
+
+        Ok(())
+    }
+
+    async fn get_organization_members(
+        &self,
+        organization_id: ObjectId,
+    ) -> Result<Vec<User>, Error> {
+        let query = format!(
+            "SELECT {} FROM users u WHERE u.organization_id = $1 ORDER BY u.email",
+            USER_COLUMNS
+        );
+        let rows = sqlx::query(&query)
+            .bind(organization_id.to_hex())
+            .fetch_all(&self.pool)
+            .await
+            .map_err(query_error("Failed to fetch organization members"))?;
+
+        rows.iter().map(user_from_row).collect()
+    }
+
+    async fn join_organization(
+        &self,
+        organization_id: ObjectId,
+        user_id: ObjectId,
+        admin: bool,
+    ) -> Result<(), Error> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(query_error("Failed to start transaction"))?;
+
+        let result = sqlx::query(
+            "UPDATE users SET organization_id = $1, updated_at = $2 \
+             WHERE id = $3 AND organization_id IS NULL",
+        )
+        .bind(organization_id.to_hex())
+        .bind(DateTime::now().timestamp_millis())
+        .bind(user_id.to_hex())
+        .execute(&mut *tx)
+        .await
+        .map_err(query_error("Failed to update user"))?;
+        if result.rows_affected() == 0 {
+            return Err(actix_web::error::ErrorConflict(
+                "User already belongs to an organization",
+            ));
+        }
+        if admin {
+            sqlx::query(
+                "INSERT INTO organization_admins (organization_id, user_id) VALUES ($1, $2) \
+                 ON CONFLICT (organization_id, user_id) DO NOTHING",
+            )
+            .bind(organization_id.to_hex())
+            .bind(user_id.to_hex())
+            .execute(&mut *tx)
+            .await
+            .map_err(query_error("Failed to update organization admins"))?;
+        }
+        touch_organization(&mut tx, organization_id).await?;
+
+        tx.commit()
+            .await
+            .map_err(query_error("Failed to commit transaction"))?;
+
+        Ok(())
+    }
+
+    async fn leave_organization(
+        &self,
+        organization_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<(), Error> {
+        let now = DateTime::now().timestamp_millis();
+
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(query_error("Failed to start transaction"))?;
+
+        let result = sqlx::query(
+            "UPDATE users SET organization_id = NULL, updated_at = $1 \
+             WHERE id = $2 AND organization_id = $3",
+        )
+        .bind(now)
+        .bind(user_id.to_hex())
+        .bind(organization_id.to_hex())
+        .execute(&mut *tx)
+        .await
+        .map_err(query_error("Failed to update user"))?;
+        if result.rows_affected() == 0 {
+            return Err(actix_web::error::ErrorNotFound(
+                "User is not a member of this organization",
+            ));
+        }
+
+        sqlx::query("DELETE FROM organization_admins WHERE organization_id = $1 AND user_id = $2")
+            .bind(organization_id.to_hex())
+            .bind(user_id.to_hex())
+            .execute(&mut *tx)
+            .await
+            .map_err(query_error("Failed to delete organization admin"))?;
+        sqlx::query(
+            "UPDATE share_links SET revoked_at = $1 \
+             WHERE recipient_user_id = $2 AND organization_id = $3 AND revoked_at IS NULL",
+        )
+        .bind(now)
+        .bind(user_id.to_hex())
+        .bind(organization_id.to_hex())
+        .execute(&mut *tx)
+        .await
+        .map_err(query_error("Failed to revoke shares"))?;
+        touch_organization(&mut tx, organization_id).await?;
+
+        tx.commit()
+            .await
+            .map_err(query_error("Failed to commit transaction"))?;
+
+        Ok(())
+    }
+
+    async fn set_organization_admin(
+        &self,
+        organization_id: ObjectId,
+        user_id: ObjectId,
+        admin: bool,
+    ) -> Result<(), Error> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(query_error("Failed to start transaction"))?;
+
+        let member = sqlx::query("SELECT id FROM users WHERE id = $1 AND organization_id = $2")
+            .bind(user_id.to_hex())
+            .bind(organization_id.to_hex())
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(query_error("Failed to fetch user"))?;
+        if member.is_none() {
+            return Err(actix_web::error::ErrorNotFound(
+                "User is not a member of this organization",
+            ));
+        }
+
+        let query = if admin {
+            "INSERT INTO organization_admins (organization_id, user_id) VALUES ($1, $2) \
+             ON CONFLICT (organization_id, user_id) DO NOTHING"
+        } else {
+            "DELETE FROM organization_admins WHERE organization_id = $1 AND user_id = $2"
+        };
+        sqlx::query(query)
+            .bind(organization_id.to_hex())
+            .bind(user_id.to_hex())
+            .execute(&mut *tx)
+            .await
+            .map_err(query_error("Failed to update organization admins"))?;
+        touch_organization(&mut tx, organization_id).await?;
+
+        tx.commit()
+            .await
+            .map_err(query_error("Failed to commit transaction"))?;
+
+        Ok(())
+    }
+
+    async fn revoke_share(&self, share_id: ObjectId) -> Result<Vec<RevokedShare>, Error> {
+        let rows = sqlx::query(&format!(
+            "{} UPDATE share_links SET revoked_at = $2 \
+             WHERE id IN (SELECT id FROM chain) AND revoked_at IS NULL \
+             RETURNING id, recipient_user_id",
+            SHARE_CHAIN
+        ))
+        .bind(share_id.to_hex())
+        .bind(DateTime::now().timestamp_millis())
+        .fetch_all(&self.pool)
+        .await
+        .map_err(query_error("Failed to revoke shared links"))?;
+
+        if rows.is_empty() {
+            return Err(actix_web::error::ErrorNotFound("Shared link not found"));
+        }
+
+        revoked_shares_from_rows(&rows)
+    }
+
+    async fn update_share_permissions(
+        &self,
+        share_id: ObjectId,
+        can_reshare: bool,
+    ) -> Result<(), Error> {
+        self.update_share_link(
+            share_id,
+            None,
+            "can_reshare",
+            SqlValue::Int(can_reshare as i64),
+        )
+        .await
+    }
+
+    async fn update_share_expiry(
+        &self,
+        share_id: ObjectId,
+        expires_at: DateTime,
+    ) -> Result<(), Error> {
+        self.update_share_link(
+            share_id,
+            None,
+            "expires_at",
+            SqlValue::Int(expires_at.timestamp_millis()),
+        )
+        .await
+    }
+
+    async fn update_share_password(
+        &self,
+        share_id: ObjectId,
+        password: String,
+    ) -> Result<(), Error> {
+        self.update_share_link(share_id, None, "password", SqlValue::Text(password))
+            .await
+    }
+
+    async fn remove_share_recipient(
+        &self,
+        file_id: ObjectId,
+        recipient_user_id: ObjectId,
+    ) -> Result<(), Error> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(query_error("Failed to start transaction"))?;
+
+        let share_id: Option<String> = sqlx::query_scalar(
+            "SELECT id FROM share_links WHERE file_id = $1 AND recipient_user_id = $2",
+        )
+        .bind(file_id.to_hex())
+        .bind(recipient_user_id.to_hex())
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(query_error("Failed to fetch shared link"))?;
+        let share_id = share_id
+            .ok_or_else(|| actix_web::error::ErrorNotFound("File is not shared with this user"))?;
+
+        // Revoked and declined links no longer reach anyone, and neither do
+        // the ones re-shared from this one
+        let other_recipients: i64 = sqlx::query_scalar(&format!(
+            "{} SELECT COUNT(*) FROM share_links WHERE file_id = $2 \
+             AND id NOT IN (SELECT id FROM chain) AND revoked_at IS NULL AND declined_at IS NULL",
+            SHARE_CHAIN
+        ))
+        .bind(&share_id)
+        .bind(file_id.to_hex())
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(query_error("Failed to count shared links"))?;
+        if other_recipients == 0 {
+            return Err(actix_web::error::ErrorBadRequest(
+                "Cannot remove the last recipient; delete the file instead",
+            ));
+        }
+
+        // Whoever the recipient re-shared the file with loses it too
+        sqlx::query(&format!(
+            "{} UPDATE share_links SET revoked_at = $2 \
+             WHERE id IN (SELECT id FROM chain) AND revoked_at IS NULL",
+            SHARE_CHAIN
+        ))
+        .bind(&share_id)
+        .bind(DateTime::now().timestamp_millis())
+        .execute(&mut *tx)
+        .await
+        .map_err(query_error("Failed to revoke re-shared links"))?;
+
+        sqlx::query("DELETE FROM share_links WHERE id = $1")
+            .bind(&share_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(query_error("Failed to delete shared link"))?;
+
+        tx.commit()
+            .await
+            .map_err(query_error("Failed to commit transaction"))?;
+
+        Ok(())
+    }
+
+    async fn decline_share(
+        &self,
+        share_id: ObjectId,
+        recipient_user_id: ObjectId,
+    ) -> Result<Vec<RevokedShare>, Error> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(query_error("Failed to start transaction"))?;
+
+        let result = sqlx::query(
+            "UPDATE share_links SET declined_at = $1 WHERE id = $2 AND recipient_user_id = $3",
+        )
+        .bind(DateTime::now().timestamp_millis())
+        .bind(share_id.to_hex())
+        .bind(recipient_user_id.to_hex())
+        .execute(&mut *tx)
+        .await
+        .map_err(query_error("Failed to update shared link"))?;
+        if result.rows_affected() == 0 {
+            return Err(actix_web::error::ErrorNotFound("Shared link not found"));
+        }
+        let revoked_shares = revoke_reshares(&mut tx, share_id).await?;
+
+        tx.commit()
+            .await
+            .map_err(query_error("Failed to commit transaction"))?;
+
+        Ok(revoked_shares)
+    }
+
+    async fn set_share_archived(
+        &self,
+        share_id: ObjectId,
+        recipient_user_id: ObjectId,
+        archived: bool,
+    ) -> Result<(), Error> {
+        let archived_at = if archived {
+            SqlValue::Int(DateTime::now().timestamp_millis())
+        } else {
+            SqlValue::Null
+        };
+
+        self.update_share_link(
+            share_id,
+            Some(recipient_user_id),
+            "archived_at",
+            archived_at,
+        )
+        .await
+    }
+
+    async fn remove_received_share(
+        &self,
+        share_id: ObjectId,
+        recipient_user_id: ObjectId,
+    ) -> Result<Vec<RevokedShare>, Error> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(query_error("Failed to start transaction"))?;
+
+        let query = format!(
+            "SELECT {} FROM share_links s WHERE s.id = $1 AND s.recipient_user_id = $2",
+            SHARE_LINK_COLUMNS
+        );
+        let row = sqlx::query(&query)
+            .bind(share_id.to_hex())
+            .bind(recipient_user_id.to_hex())
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(query_error("Failed to fetch shared link"))?
+            .ok_or_else(|| actix_web::error::ErrorNotFound("Shared link not found"))?;
+        let share_link = share_link_from_row(&row)?;
+
+        // Only live links outside this one's re-shares keep the file reachable
+        let other_recipients: i64 = sqlx::query_scalar(&format!(
+            "{} SELECT COUNT(*) FROM share_links WHERE file_id = $2 \
+             AND id NOT IN (SELECT id FROM chain) AND revoked_at IS NULL AND declined_at IS NULL",
+            SHARE_CHAIN
+        ))
+        .bind(share_id.to_hex())
+        .bind(share_link.file_id.to_hex())
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(query_error("Failed to count shared links"))?;
+
+        let revoked_shares = revoke_reshares(&mut tx, share_id).await?;
+        if other_recipients > 0 {
+            sqlx::query("DELETE FROM share_links WHERE id = $1")
+                .bind(share_id.to_hex())
+                .execute(&mut *tx)
+                .await
+                .map_err(query_error("Failed to delete shared link"))?;
+        } else if share_link.declined_at.is_none() {
+            sqlx::query("UPDATE share_links SET declined_at = $1 WHERE id = $2")
+                .bind(DateTime::now().timestamp_millis())
+                .bind(share_id.to_hex())
+                .execute(&mut *tx)
+                .await
+                .map_err(query_error("Failed to update shared link"))?;
+        }
+
+        tx.commit()
+            .await
+            .map_err(query_error("Failed to commit transaction"))?;
+
+        Ok(revoked_shares)
+    }
+
+    async fn record_share_open(&self, share_id: ObjectId) -> Result<(), Error> {
+        sqlx::query(
+            "UPDATE share_links SET open_count = open_count + 1, \
+             first_opened_at = COALESCE(first_opened_at, $1), last_opened_at = $1 WHERE id = $2",
+        )
+        .bind(DateTime::now().timestamp_millis())
+        .bind(share_id.to_hex())
+        .execute(&self.pool)
+        .await
+        .map_err(query_error("Failed to update shared link"))?;
+
+        Ok(())
+    }
+
+    async fn record_share_password_failure(&self, share_id: ObjectId) -> Result<(), Error> {
+        sqlx::query(
+            "UPDATE share_links SET failed_password_attempts = failed_password_attempts + 1 \
+             WHERE id = $1",
+        )
+        .bind(share_id.to_hex())
+        .execute(&self.pool)
+        .await
+        .map_err(query_error("Failed to update shared link"))?;
+
+        Ok(())
+    }
+
+    async fn save_collection(
+        &self,
+        collection: Collection,
+        files: Vec<File>,
+        share_links: Vec<ShareLink>,
+        chunks: Vec<Chunk>,
+    ) -> Result<ObjectId, Error> {
+        let collection_id = collection._id;
+
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(query_error("Failed to start transaction"))?;
+
+        sqlx::query(
+            "INSERT INTO collections (id, user_id, name, created_at) VALUES ($1, $2, $3, $4)",
+        )
+        .bind(collection._id.to_hex())
+        .bind(collection.user_id.to_hex())
+        .bind(collection.name)
+        .bind(collection.created_at.timestamp_millis())
+        .execute(&mut *tx)
+        .await
+        .map_err(query_error("Failed to insert collection in database"))?;
+
+        for file in files {
+            insert_file(file)
+                .execute(&mut *tx)
+                .await
+                .map_err(query_error("Failed to insert file in database"))?;
+        }
+        retain_chunks(&mut tx, chunks).await?;
+
+        for share_link in share_links {
+            insert_share_link(share_link)
+                .execute(&mut *tx)
+                .await
+                .map_err(query_error("Failed to save the share document"))?;
+        }
+
+        tx.commit()
+            .await
+            .map_err(query_error("Failed to commit transaction"))?;
+
+        Ok(collection_id)
+    }
+
+    async fn get_collection(&self, collection_id: ObjectId) -> Result<Collection, Error> {
+        let query = format!(
+            "SELECT {} FROM collections c WHERE c.id = $1",
+            COLLECTION_COLUMNS
+        );
+        let row = sqlx::query(&query)
+            .bind(collection_id.to_hex())
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(query_error("Failed to fetch collection"))?;
+
+        match row {
+            Some(row) => collection_from_row(&row),
+            None => Err(actix_web::error::ErrorNotFound("Collection not found")),
+        }
+    }
+
+    async fn get_sent_collections(
+        &self,
+        user_id: ObjectId,
+    ) -> Result<Vec<SharedCollection>, Error> {
+        let query = format!(
+            "SELECT {} FROM collections c \
+             JOIN files f ON f.collection_id = c.id \
+             JOIN share_links s ON s.file_id = f.id \
+             JOIN users u ON u.id = s.recipient_user_id \
+             WHERE c.user_id = $1 \
+             GROUP BY c.id, c.name, u.email \
+             ORDER BY created_at DESC, c.id DESC",
+            SHARED_COLLECTION_COLUMNS
+        );
+        let rows = sqlx::query(&query)
+            .bind(user_id.to_hex())
+            .fetch_all(&self.pool)
+            .await
+            .map_err(query_error("Unable to fetch collections"))?;
+
+        rows.iter().map(shared_collection_from_row).collect()
+    }
+
+    async fn get_received_collections(
+        &self,
+        user_id: ObjectId,
+    ) -> Result<Vec<SharedCollection>, Error> {
+        let query = format!(
+            "SELECT {} FROM share_links s \
+             JOIN files f ON f.id = s.file_id \
+             JOIN collections c ON c.id = f.collection_id \
+             JOIN users u ON u.id = c.user_id \
+             WHERE s.recipient_user_id = $1 AND s.revoked_at IS NULL AND s.declined_at IS NULL \
+             AND f.scan_status <> 'quarantined' \
+             GROUP BY c.id, c.name, u.email \
+             ORDER BY created_at DESC, c.id DESC",
+            SHARED_COLLECTION_COLUMNS
+        );
+        let rows = sqlx::query(&query)
+            .bind(user_id.to_hex())
+            .fetch_all(&self.pool)
+            .await
+            .map_err(query_error("Unable to fetch collections"))?;
+
+        rows.iter().map(shared_collection_from_row).collect()
+    }
+
+    async fn get_collection_shares(
+        &self,
+        collection_id: ObjectId,
+        recipient_user_id: ObjectId,
+    ) -> Result<Vec<ShareLink>, Error> {
+        let query = format!(
+            "SELECT {} FROM share_links s \
+             JOIN files f ON f.id = s.file_id \
+             WHERE f.collection_id = $1 AND s.recipient_user_id = $2 \
+             AND s.revoked_at IS NULL AND s.declined_at IS NULL \
+             AND f.scan_status <> 'quarantined' \
+             ORDER BY s.created_at, s.id",
+            SHARE_LINK_COLUMNS
+        );
+        let rows = sqlx::query(&query)
+            .bind(collection_id.to_hex())
+            .bind(recipient_user_id.to_hex())
+            .fetch_all(&self.pool)
+            .await
+            .map_err(query_error("Failed to fetch shared links"))?;
+
+        rows.iter().map(share_link_from_row).collect()
+    }
+
+    async fn get_file_share_links(&self, file_id: ObjectId) -> Result<Vec<ShareLink>, Error> {
+        let query = format!(
+            "SELECT {} FROM share_links s WHERE s.file_id = $1",
+            SHARE_LINK_COLUMNS
+        );
+        let rows = sqlx::query(&query)
+            .bind(file_id.to_hex())
+            .fetch_all(&self.pool)
+            .await
+            .map_err(query_error("Failed to fetch shared links"))?;
+
+        rows.iter().map(share_link_from_row).collect()
+    }
+
+    async fn add_file_version(
+        &self,
+        file_id: ObjectId,
+        file_name: String,
+        file_size: i64,
+        encrypted_file: Vec<u8>,
+        iv: Vec<u8>,
+        content_type: String,
+        sha256: String,
+        signatures: Vec<(ObjectId, Vec<u8>)>,
+        scan_status: ScanStatus,
+        compression: Compression,
+        chunk_ids: Vec<String>,
+        chunks: Vec<Chunk>,
+        history: i64,
+        quota: i64,
+    ) -> Result<i64, Error> {
+        let now = DateTime::now().timestamp_millis();
+
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(query_error("Failed to start transaction"))?;
+
+        let current = sqlx::query("SELECT f.user_id, f.version FROM files f WHERE f.id = $1")
+            .bind(file_id.to_hex())
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(query_error("Failed to fetch file"))?;
+        let current = match current {
+            Some(current) => current,
+            None => return Err(not_found("File not found")),
+        };
+        let owner_id: String = current
+            .try_get("user_id")
+            .map_err(query_error("Failed to decode file"))?;
+        let version = current
+            .try_get::<i64, _>("version")
+            .map_err(query_error("Failed to decode file"))?
+            + 1;
+
+        // A revision was current from the file's last update until now
+        sqlx::query(
+            "INSERT INTO file_versions (id, file_id, version, file_name, file_size, content_type, encrypted_file, iv, sha256, created_at, compression, chunk_ids) \
+             SELECT $1, f.id, f.version, f.file_name, f.file_size, f.content_type, f.encrypted_file, f.iv, f.sha256, f.updated_at, f.compression, f.chunk_ids \
+             FROM files f WHERE f.id = $2",
+        )
+        .bind(ObjectId::new().to_hex())
+        .bind(file_id.to_hex())
+        .execute(&mut *tx)
+        .await
+        .map_err(query_error("Failed to save file version"))?;
+
+        let pruned = sqlx::query(
+            "DELETE FROM file_versions WHERE file_id = $1 AND version < $2 RETURNING file_size, chunk_ids",
+        )
+        .bind(file_id.to_hex())
+        .bind(version - history)
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(query_error("Failed to prune file versions"))?;
+        let mut pruned_size = 0;
+        for row in &pruned {
+            pruned_size += row
+                .try_get::<i64, _>("file_size")
+                .map_err(query_error("Failed to decode file version"))?;
+        }
+        release_chunks(&mut tx, &pruned).await?;
+
+        // The superseded revision stays in the owner's storage until pruned
+        let growth = file_size - pruned_size;
+        let usage = sqlx::query(
+            "UPDATE users SET storage_used = storage_used + $1 \
+             WHERE id = $2 AND ($1 <= 0 OR storage_used + $1 <= $3)",
+        )
+        .bind(growth)
+        .bind(owner_id)
+        .bind(quota)
+        .execute(&mut *tx)
+        .await
+        .map_err(query_error("Failed to update storage usage"))?;
+
+        if usage.rows_affected() == 0 {
+            return Err(actix_web::error::ErrorPayloadTooLarge(
+                "Storage quota exceeded",
+            ));
+        }
+
+        let updated: i64 = sqlx::query_scalar(
+            "UPDATE files SET history_size = history_size + file_size - $1, file_name = $2, file_size = $3, \
+             content_type = $4, encrypted_file = $5, iv = $6, sha256 = $7, updated_at = $8, scan_status = $9, \
+             compression = $10, chunk_ids = $11, version = version + 1 WHERE id = $12 RETURNING version",
+        )
+        .bind(pruned_size)
+        .bind(file_name)
+        .bind(file_size)
+        .bind(content_type)
+        .bind(encrypted_file)
+        .bind(iv)
+        .bind(sha256)
+        .bind(now)
+        .bind(scan_status.as_str())
+        .bind(compression.as_str())
+        .bind(join_chunk_ids(&chunk_ids))
+        .bind(file_id.to_hex())
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(query_error("Failed to update file"))?;
+
+        for (share_id, signature) in signatures {
+            sqlx::query("UPDATE share_links SET signature = $1 WHERE id = $2 AND file_id = $3")
+                .bind(signature)
+                .bind(share_id.to_hex())
+                .bind(file_id.to_hex())
+                .execute(&mut *tx)
+                .await
+                .map_err(query_error("Failed to update share signature"))?;
+        }
+
+        // Concurrent revisions are serialised by the write transaction
+        if updated != version {
+            return Err(actix_web::error::ErrorConflict(
+                "File was updated by another request",
+            ));
+        }
+
+        // The superseded revision keeps its chunk references in the history
+        retain_chunks(&mut tx, chunks).await?;
+
+        tx.commit()
+            .await
+            .map_err(query_error("Failed to commit transaction"))?;
+
+        Ok(version)
+    }
+
+    async fn get_file_versions(&self, file_id: ObjectId) -> Result<Vec<FileVersionSummary>, Error> {
+        let query = format!(
+            "SELECT {} FROM file_versions v WHERE v.file_id = $1 ORDER BY v.version DESC",
+            FILE_VERSION_SUMMARY_COLUMNS
+        );
+        let rows = sqlx::query(&query)
+            .bind(file_id.to_hex())
+            .fetch_all(&self.pool)
+            .await
+            .map_err(query_error("Failed to fetch file versions"))?;
+
+        rows.iter().map(file_version_summary_from_row).collect()
+    }
+
+    async fn get_file_version(
+        &self,
+        file_id: ObjectId,
+        version: i64,
+    ) -> Result<FileVersion, Error> {
+        let query = format!(
+            "SELECT {} FROM file_versions v WHERE v.file_id = $1 AND v.version = $2",
+            FILE_VERSION_COLUMNS
+        );
+        let row = sqlx::query(&query)
+            .bind(file_id.to_hex())
+            .bind(version)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(query_error("Failed to fetch file version"))?;
+
+        match row {
+            Some(row) => file_version_from_row(&row),
+            None => Err(not_found("File version not found")),
+        }
+    }
+
+    async fn get_chunks(&self, chunk_ids: &[String]) -> Result<Vec<Vec<u8>>, Error> {
+        let mut unique: Vec<&String> = chunk_ids.iter().collect();
+        unique.sort();
+        unique.dedup();
+
+        // One query per batch, kept well below the bind parameter limits
+        let mut chunks: HashMap<String, Vec<u8>> = HashMap::with_capacity(unique.len());
+        let decode = query_error("Failed to decode chunk");
+        for batch in unique.chunks(CHUNK_FETCH_BATCH) {
+            let params: Vec<String> = (1..=batch.len()).map(|i| format!("${}", i)).collect();
+            let sql = format!("SELECT id, data FROM chunks WHERE id IN ({})", params.join(", "));
+            let mut query = sqlx::query(&sql);
+            for chunk_id in batch {
+                query = query.bind(*chunk_id);
+            }
+            let rows = query
+                .fetch_all(&self.pool)
+                .await
+                .map_err(query_error("Failed to fetch chunks"))?;
+            for row in &rows {
+                chunks.insert(
+                    row.try_get("id").map_err(&decode)?,
+                    row.try_get("data").map_err(&decode)?,
+                );
+            }
+        }
+
+        // A chunk may appear more than once in the list
+        chunk_ids
+            .iter()
+            .map(|chunk_id| {
+                chunks
+                    .get(chunk_id)
+                    .cloned()
+                    .ok_or_else(|| not_found("Chunk not found"))
+            })
+            .collect()
+    }
+
+    async fn record_audit_event(&self, event: AuditEvent) -> Result<(), Error> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(query_error("Failed to start transaction"))?;
+        append_audit_event(&mut tx, event).await?;
+        tx.commit()
+            .await
+            .map_err(query_error("Failed to commit transaction"))?;
+
+        Ok(())
+    }
+
+    async fn get_audit_events(&self, query: AuditQuery) -> Result<Vec<AuditEvent>, Error> {
+        let mut listing = ListingQuery::new("FROM audit_events e");
+        if let Some(user_id) = query.user_id {
+            let param = listing.bind(SqlValue::Text(user_id.to_hex()));
+            listing.condition(format!(
+                "(e.actor_id = {param} OR e.subject_user_id = {param})",
+                param = param
+            ));
+        }
+        if let Some(share_id) = query.share_id {
+            let param = listing.bind(SqlValue::Text(share_id.to_hex()));
+            listing.condition(format!("e.share_id = {}", param));
+        }
+        if !query.actions.is_empty() {
+            let params: Vec<String> = query
+                .actions
+                .iter()
+                .map(|action| listing.bind(SqlValue::Text(action.as_str().to_string())))
+                .collect();
+            listing.condition(format!("e.action IN ({})", params.join(", ")));
+        }
+        if let Some(before) = query.before {
+            let param = listing.bind(SqlValue::Text(before.to_hex()));
+            listing.condition(format!(
+                "(e.created_at < (SELECT created_at FROM audit_events WHERE id = {param}) \
+                 OR (e.created_at = (SELECT created_at FROM audit_events WHERE id = {param}) AND e.id < {param}))",
+                param = param
+            ));
+        }
+
+        let page_query = format!(
+            "SELECT {} {}{} ORDER BY e.created_at DESC, e.id DESC LIMIT {}",
+            AUDIT_EVENT_COLUMNS,
+            listing.from,
+            listing.where_clause(),
+            query.limit
+        );
+        let rows = listing
+            .build(&page_query)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(query_error("Failed to fetch audit events"))?;
+
+        rows.iter().map(audit_event_from_row).collect()
+    }
+
+    async fn get_audit_head(&self) -> Result<Option<AuditEvent>, Error> {
+        let query = format!(
+            "SELECT {} FROM audit_events e WHERE e.sequence IS NOT NULL \
+             ORDER BY e.sequence DESC LIMIT 1",
+            AUDIT_EVENT_COLUMNS
+        );
+        let row = sqlx::query(&query)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(query_error("Failed to fetch audit chain head"))?;
+
+        row.as_ref().map(audit_event_from_row).transpose()
+    }
+
+    async fn get_audit_chain(
+        &self,
+        after_sequence: i64,
+        limit: usize,
+    ) -> Result<Vec<AuditEvent>, Error> {
+        let query = format!(
+            "SELECT {} FROM audit_events e WHERE e.sequence > $1 ORDER BY e.sequence LIMIT {}",
+            AUDIT_EVENT_COLUMNS, limit
+        );
+        let rows = sqlx::query(&query)
+            .bind(after_sequence)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(query_error("Failed to fetch audit chain"))?;
+
+        rows.iter().map(audit_event_from_row).collect()
+    }
+
+    async fn save_audit_checkpoint(&self, checkpoint: AuditCheckpoint) -> Result<(), Error> {
+        sqlx::query(
+            "INSERT INTO audit_checkpoints (id, sequence, hash, signature, created_at) \
+             VALUES ($1, $2, $3, $4, $5)",
+        )
+        .bind(checkpoint._id.to_hex())
+        .bind(checkpoint.sequence)
+        .bind(checkpoint.hash)
+        .bind(checkpoint.signature)
+        .bind(checkpoint.created_at.timestamp_millis())
+        .execute(&self.pool)
+        .await
+        .map_err(query_error("Failed to save audit checkpoint"))?;
+
+        Ok(())
+    }
+
+    async fn get_audit_checkpoints(&self) -> Result<Vec<AuditCheckpoint>, Error> {
+        let rows = sqlx::query(
+            "SELECT id, sequence, hash, signature, created_at FROM audit_checkpoints \
+             ORDER BY sequence",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(query_error("Failed to fetch audit checkpoints"))?;
+
+        rows.iter().map(audit_checkpoint_from_row).collect()
+    }
+
+    async fn save_notifications(&self, notifications: Vec<Notification>) -> Result<(), Error> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(query_error("Failed to start transaction"))?;
+
+        for notification in notifications {
+            sqlx::query(
+                "INSERT INTO notifications (id, user_id, kind, actor_id, share_id, file_id, collection_id, name, read_at, created_at) \
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)",
+            )
+            .bind(notification._id.to_hex())
+            .bind(notification.user_id.to_hex())
+            .bind(notification.kind.as_str())
+            .bind(notification.actor_id.map(|id| id.to_hex()))
+            .bind(notification.share_id.map(|id| id.to_hex()))
+            .bind(notification.file_id.map(|id| id.to_hex()))
+            .bind(notification.collection_id.map(|id| id.to_hex()))
+            .bind(notification.name)
+            .bind(notification.read_at.map(|date| date.timestamp_millis()))
+            .bind(notification.created_at.timestamp_millis())
+            .execute(&mut *tx)
+            .await
+            .map_err(query_error("Failed to save notification"))?;
+        }
+
+        tx.commit()
+            .await
+            .map_err(query_error("Failed to commit transaction"))?;
+
+        Ok(())
+    }
+
+    async fn get_notifications(
+        &self,
+        query: NotificationQuery,
+    ) -> Result<Vec<Notification>, Error> {
+        let mut listing = ListingQuery::new("FROM notifications n");
+        let param = listing.bind(SqlValue::Text(query.user_id.to_hex()));
+        listing.condition(format!("n.user_id = {}", param));
+        if query.unread_only {
+            listing.condition("n.read_at IS NULL".to_string());
+        }
+        if let Some(before) = query.before {
+            let param = listing.bind(SqlValue::Text(before.to_hex()));
+            listing.condition(format!(
+                "(n.created_at < (SELECT created_at FROM notifications WHERE id = {param}) \
+                 OR (n.created_at = (SELECT created_at FROM notifications WHERE id = {param}) AND n.id < {param}))",
+                param = param
+            ));
+        }
+
+        let page_query = format!(
+            "SELECT {} {}{} ORDER BY n.created_at DESC, n.id DESC LIMIT {}",
+            NOTIFICATION_COLUMNS,
+            listing.from,
+            listing.where_clause(),
+            query.limit
+        );
+        let rows = listing
+            .build(&page_query)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(query_error("Failed to fetch notifications"))?;
+
+        rows.iter().map(notification_from_row).collect()
+    }
+
+    async fn count_unread_notifications(&self, user_id: ObjectId) -> Result<u64, Error> {
+        let count: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM notifications WHERE user_id = $1 AND read_at IS NULL",
+        )
+        .bind(user_id.to_hex())
+        .fetch_one(&self.pool)
+        .await
+        .map_err(query_error("Failed to count notifications"))?;
+
+        Ok(count as u64)
+    }
+
+    async fn mark_notification_read(
+        &self,
+        notification_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<(), Error> {
+        let result = sqlx::query(
+            "UPDATE notifications SET read_at = COALESCE(read_at, $1) WHERE id = $2 AND user_id = $3",
+        )
+        .bind(DateTime::now().timestamp_millis())
+        .bind(notification_id.to_hex())
+        .bind(user_id.to_hex())
+        .execute(&self.pool)
+        .await
+        .map_err(query_error("Failed to update notification"))?;
+
+        if result.rows_affected() == 0 {
+            return Err(actix_web::error::ErrorNotFound("Notification not found"));
+        }
+
+        Ok(())
+    }
+
+    async fn mark_all_notifications_read(&self, user_id: ObjectId) -> Result<u64, Error> {
+        let result = sqlx::query(
+            "UPDATE notifications SET read_at = $1 WHERE user_id = $2 AND read_at IS NULL",
+        )
+        .bind(DateTime::now().timestamp_millis())
+        .bind(user_id.to_hex())
+        .execute(&self.pool)
+        .await
+        .map_err(query_error("Failed to update notifications"))?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn claim_expiring_shares(&self, window_ms: i64) -> Result<Vec<ShareLink>, Error> {
+        let now = DateTime::now().timestamp_millis();
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(query_error("Failed to start transaction"))?;
+
+        // A share whose expiry moved later since the last notice is due another
+        let query = format!(
+            "SELECT {} FROM share_links s \
+             WHERE s.revoked_at IS NULL AND s.declined_at IS NULL \
+             AND s.expires_at > $1 AND s.expires_at <= $2 \
+             AND (s.expiry_notified_at IS NULL OR s.expiry_notified_at < s.expires_at - $3)",
+            SHARE_LINK_COLUMNS
+        );
+        let rows = sqlx::query(&query)
+            .bind(now)
+            .bind(now + window_ms)
+            .bind(window_ms)
+            .fetch_all(&mut *tx)
+            .await
+            .map_err(query_error("Failed to fetch expiring shared links"))?;
+        let share_links: Vec<ShareLink> = rows
+            .iter()
+            .map(share_link_from_row)
+            .collect::<Result<_, _>>()?;
+
+        for share_link in &share_links {
+            sqlx::query("UPDATE share_links SET expiry_notified_at = $1 WHERE id = $2")
+                .bind(now)
+                .bind(share_link._id.to_hex())
+                .execute(&mut *tx)
+                .await
+                .map_err(query_error("Failed to update shared link"))?;
+        }
+
+        tx.commit()
+            .await
+            .map_err(query_error("Failed to commit transaction"))?;
+
+        Ok(share_links)
+    }
+
+    async fn get_email_preferences(&self, user_id: ObjectId) -> Result<EmailPreferences, Error> {
+        let row = sqlx::query(
+            "SELECT share_received, share_expiring, share_downloaded, share_revoked, share_declined \
+             FROM email_preferences WHERE user_id = $1",
+        )
+        .bind(user_id.to_hex())
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(query_error("Failed to fetch email preferences"))?;
+
+        let row = match row {
+            Some(row) => row,
+            None => return Ok(EmailPreferences::new(user_id)),
+        };
+        let decode = query_error("Failed to decode email preferences");
+        let flag = |column: &str| row.try_get::<i64, _>(column).map(|value| value != 0);
+        Ok(EmailPreferences {
+            _id: user_id,
+            share_received: flag("share_received").map_err(&decode)?,
+            share_expiring: flag("share_expiring").map_err(&decode)?,
+            share_downloaded: flag("share_downloaded").map_err(&decode)?,
+            share_revoked: flag("share_revoked").map_err(&decode)?,
+            share_declined: flag("share_declined").map_err(&decode)?,
+        })
+    }
+
+    async fn save_email_preferences(&self, preferences: EmailPreferences) -> Result<(), Error> {
+        sqlx::query(
+            "INSERT INTO email_preferences (user_id, share_received, share_expiring, share_downloaded, share_revoked, share_declined) \
+             VALUES ($1, $2, $3, $4, $5, $6) \
+             ON CONFLICT (user_id) DO UPDATE SET share_received = excluded.share_received, \
+             share_expiring = excluded.share_expiring, share_downloaded = excluded.share_downloaded, \
+             share_revoked = excluded.share_revoked, share_declined = excluded.share_declined",
+        )
+        .bind(preferences._id.to_hex())
+        .bind(preferences.share_received as i64)
+        .bind(preferences.share_expiring as i64)
+        .bind(preferences.share_downloaded as i64)
+        .bind(preferences.share_revoked as i64)
+        .bind(preferences.share_declined as i64)
+        .execute(&self.pool)
+        .await
+        .map_err(query_error("Failed to save email preferences"))?;
+
+        Ok(())
+    }
+
+    async fn create_webhook(&self, webhook: Webhook) -> Result<(), Error> {
+        let events: Vec<&str> = webhook.events.iter().map(|event| event.as_str()).collect();
+        sqlx::query(
+            "INSERT INTO webhooks (id, user_id, url, secret, events, created_at) \
+             VALUES ($1, $2, $3, $4, $5, $6)",
+        )
+        .bind(webhook._id.to_hex())
+        .bind(webhook.user_id.map(|id| id.to_hex()))
+        .bind(webhook.url)
+        .bind(webhook.secret)
+        .bind(events.join(","))
+        .bind(webhook.created_at.timestamp_millis())
+        .execute(&self.pool)
+        .await
+        .map_err(query_error("Failed to save webhook"))?;
+
+        Ok(())
+    }
+
+    async fn get_webhooks(&self, user_id: Option<ObjectId>) -> Result<Vec<Webhook>, Error> {
+        let rows = match user_id {
+            Some(user_id) => {
+                let query = format!(
+                    "SELECT {} FROM webhooks w WHERE w.user_id = $1 ORDER BY w.created_at",
+                    WEBHOOK_COLUMNS
+                );
+                sqlx::query(&query)
+                    .bind(user_id.to_hex())
+                    .fetch_all(&self.pool)
+                    .await
+            }
+            None => {
+                let query = format!(
+                    "SELECT {} FROM webhooks w WHERE w.user_id IS NULL ORDER BY w.created_at",
+                    WEBHOOK_COLUMNS
+                );
+                sqlx::query(&query).fetch_all(&self.pool).await
+            }
+        }
+        .map_err(query_error("Failed to fetch webhooks"))?;
+
+        rows.iter().map(webhook_from_row).collect()
+    }
+
+    async fn get_webhook(&self, webhook_id: ObjectId) -> Result<Webhook, Error> {
+        let query = format!("SELECT {} FROM webhooks w WHERE w.id = $1", WEBHOOK_COLUMNS);
+        let row = sqlx::query(&query)
+            .bind(webhook_id.to_hex())
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(query_error("Failed to fetch webhook"))?;
+
+        match row {
+            Some(row) => webhook_from_row(&row),
+            None => Err(actix_web::error::ErrorNotFound("Webhook not found")),
+        }
+    }
+
+    async fn delete_webhook(&self, webhook_id: ObjectId) -> Result<(), Error> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(query_error("Failed to start transaction"))?;
+
+        sqlx::query("DELETE FROM webhook_deliveries WHERE webhook_id = $1")
+            .bind(webhook_id.to_hex())
+            .execute(&mut *tx)
+            .await
+            .map_err(query_error("Failed to delete webhook deliveries"))?;
+        let result = sqlx::query("DELETE FROM webhooks WHERE id = $1")
+            .bind(webhook_id.to_hex())
+            .execute(&mut *tx)
+            .await
+            .map_err(query_error("Failed to delete webhook"))?;
+        if result.rows_affected() == 0 {
+            return Err(actix_web::error::ErrorNotFound("Webhook not found"));
+        }
+
+        tx.commit()
+            .await
+            .map_err(query_error("Failed to commit transaction"))?;
+
+        Ok(())
+    }
+
+    async fn get_subscribed_webhooks(
+        &self,
+        user_ids: Vec<ObjectId>,
+    ) -> Result<Vec<Webhook>, Error> {
+        let mut listing = ListingQuery::new("FROM webhooks w");
+        let mut owners = vec!["w.user_id IS NULL".to_string()];
+        for user_id in user_ids {
+            let param = listing.bind(SqlValue::Text(user_id.to_hex()));
+            owners.push(format!("w.user_id = {}", param));
+        }
+        listing.condition(format!("({})", owners.join(" OR ")));
+
+        let query = format!(
+            "SELECT {} {}{}",
+            WEBHOOK_COLUMNS,
+            listing.from,
+            listing.where_clause()
+        );
+        let rows = listing
+            .build(&query)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(query_error("Failed to fetch webhooks"))?;
+
+        rows.iter().map(webhook_from_row).collect()
+    }
+
+    async fn save_webhook_deliveries(&self, deliveries: Vec<WebhookDelivery>) -> Result<(), Error> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(query_error("Failed to start transaction"))?;
+
+        for delivery in deliveries {
+            sqlx::query(
+                "INSERT INTO webhook_deliveries (id, webhook_id, event, payload, status, attempts, next_attempt_at, last_attempt_at, response_status, last_error, delivered_at, created_at) \
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)",
+            )
+            .bind(delivery._id.to_hex())
+            .bind(delivery.webhook_id.to_hex())
+            .bind(delivery.event.as_str())
+            .bind(delivery.payload)
+            .bind(delivery.status.as_str())
+            .bind(delivery.attempts)
+            .bind(delivery.next_attempt_at.timestamp_millis())
+            .bind(delivery.last_attempt_at.map(|date| date.timestamp_millis()))
+            .bind(delivery.response_status)
+            .bind(delivery.last_error)
+            .bind(delivery.delivered_at.map(|date| date.timestamp_millis()))
+            .bind(delivery.created_at.timestamp_millis())
+            .execute(&mut *tx)
+            .await
+            .map_err(query_error("Failed to save webhook delivery"))?;
+        }
+
+        tx.commit()
+            .await
+            .map_err(query_error("Failed to commit transaction"))?;
+
+        Ok(())
+    }
+
+    async fn claim_due_deliveries(
+        &self,
+        limit: usize,
+        lease_ms: i64,
+    ) -> Result<Vec<WebhookDelivery>, Error> {
+        let now = DateTime::now().timestamp_millis();
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(query_error("Failed to start transaction"))?;
+
+        let query = format!(
+            "SELECT {} FROM webhook_deliveries d \
+             WHERE d.status = $1 AND d.next_attempt_at <= $2 \
+             ORDER BY d.next_attempt_at, d.id LIMIT {}",
+            WEBHOOK_DELIVERY_COLUMNS, limit
+        );
+        let rows = sqlx::query(&query)
+            .bind(DeliveryStatus::Pending.as_str())
+            .bind(now)
+            .fetch_all(&mut *tx)
+            .await
+            .map_err(query_error("Failed to fetch webhook deliveries"))?;
+        let deliveries: Vec<WebhookDelivery> = rows
+            .iter()
+            .map(webhook_delivery_from_row)
+            .collect::<Result<_, _>>()?;
+
+        // Only claims what is still due, in case another worker got there first
+        let mut claimed = Vec::with_capacity(deliveries.len());
+        for delivery in deliveries {
+            let result = sqlx::query(
+                "UPDATE webhook_deliveries SET next_attempt_at = $1 \
+                 WHERE id = $2 AND next_attempt_at = $3",
+            )
+            .bind(now + lease_ms)
+            .bind(delivery._id.to_hex())
+            .bind(delivery.next_attempt_at.timestamp_millis())
+            .execute(&mut *tx)
+            .await
+            .map_err(query_error("Failed to claim webhook delivery"))?;
+            if result.rows_affected() > 0 {
+                claimed.push(delivery);
+            }
+        }
+
+        tx.commit()
+            .await
+            .map_err(query_error("Failed to commit transaction"))?;
+
+        Ok(claimed)
+    }
+
+    async fn update_webhook_delivery(&self, delivery: WebhookDelivery) -> Result<(), Error> {
+        let result = sqlx::query(
+            "UPDATE webhook_deliveries SET status = $1, attempts = $2, next_attempt_at = $3, \
+             last_attempt_at = $4, response_status = $5, last_error = $6, delivered_at = $7 \
+             WHERE id = $8",
+        )
+        .bind(delivery.status.as_str())
+        .bind(delivery.attempts)
+        .bind(delivery.next_attempt_at.timestamp_millis())
+        .bind(delivery.last_attempt_at.map(|date| date.timestamp_millis()))
+        .bind(delivery.response_status)
+        .bind(delivery.last_error)
+        .bind(delivery.delivered_at.map(|date| date.timestamp_millis()))
+        .bind(delivery._id.to_hex())
+        .execute(&self.pool)
+        .await
+        .map_err(query_error("Failed to update webhook delivery"))?;
+
+        if result.rows_affected() == 0 {
+            return Err(actix_web::error::ErrorNotFound("Webhook delivery not found"));
+        }
+
+        Ok(())
+    }
+
+    async fn get_webhook_deliveries(
+        &self,
+        query: WebhookDeliveryQuery,
+    ) -> Result<Vec<WebhookDelivery>, Error> {
+        let mut listing = ListingQuery::new("FROM webhook_deliveries d");
+        let param = listing.bind(SqlValue::Text(query.webhook_id.to_hex()));
+        listing.condition(format!("d.webhook_id = {}", param));
+        if let Some(status) = query.status {
+            let param = listing.bind(SqlValue::Text(status.as_str().to_string()));
+            listing.condition(format!("d.status = {}", param));
+        }
+        if let Some(before) = query.before {
+            let param = listing.bind(SqlValue::Text(before.to_hex()));
+            listing.condition(format!(
+                "(d.created_at < (SELECT created_at FROM webhook_deliveries WHERE id = {param}) \
+                 OR (d.created_at = (SELECT created_at FROM webhook_deliveries WHERE id = {param}) AND d.id < {param}))",
+                param = param
+            ));
+        }
+
+        let page_query = format!(
+            "SELECT {} {}{} ORDER BY d.created_at DESC, d.id DESC LIMIT {}",
+            WEBHOOK_DELIVERY_COLUMNS,
+            listing.from,
+            listing.where_clause(),
+            query.limit
+        );
+        let rows = listing
+            .build(&page_query)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(query_error("Failed to fetch webhook deliveries"))?;
+
+        rows.iter().map(webhook_delivery_from_row).collect()
+    }
+
+    async fn get_webhook_delivery(
+        &self,
+        delivery_id: ObjectId,
+    ) -> Result<WebhookDelivery, Error> {
+        let query = format!(
+            "SELECT {} FROM webhook_deliveries d WHERE d.id = $1",
+            WEBHOOK_DELIVERY_COLUMNS
+        );
+        let row = sqlx::query(&query)
+            .bind(delivery_id.to_hex())
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(query_error("Failed to fetch webhook delivery"))?;
+
+        match row {
+            Some(row) => webhook_delivery_from_row(&row),
+            None => Err(actix_web::error::ErrorNotFound("Webhook delivery not found")),
+        }
+    }
+
+    async fn reconcile(&self) -> Result<(), Error> {
+        let cutoff = DateTime::now().timestamp_millis() - RECONCILE_GRACE_PERIOD_MS;
+
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(query_error("Failed to start transaction"))?;
+
+        let dangling_shares = sqlx::query(
+            "DELETE FROM share_links WHERE created_at < $1 \
+             AND NOT EXISTS (SELECT 1 FROM files f WHERE f.id = share_links.file_id)",
+        )
+        .bind(cutoff)
+        .execute(&mut *tx)
+        .await
+        .map_err(query_error("Failed to delete dangling shared links"))?;
+
+        // Files still waiting for an invitee to register have no share link yet
+        let orphaned_files = sqlx::query(
+            "DELETE FROM files WHERE created_at < $1 \
+             AND NOT EXISTS (SELECT 1 FROM share_links s WHERE s.file_id = files.id) \
+             AND NOT EXISTS (SELECT 1 FROM invitations i WHERE i.file_id = files.id) \
+             RETURNING user_id, file_size, history_size, chunk_ids",
+        )
+        .bind(cutoff)
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(query_error("Failed to delete orphaned files"))?;
+        release_deleted_files(&mut tx, &orphaned_files).await?;
+
+        let orphaned_versions = sqlx::query(
+            "DELETE FROM file_versions \
+             WHERE NOT EXISTS (SELECT 1 FROM files f WHERE f.id = file_versions.file_id) \
+             RETURNING chunk_ids",
+        )
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(query_error("Failed to delete orphaned file versions"))?;
+        release_chunks(&mut tx, &orphaned_versions).await?;
+
+        // Collections whose files have all expired or been deleted
+        let empty_collections = sqlx::query(
+            "DELETE FROM collections WHERE created_at < $1 \
+             AND NOT EXISTS (SELECT 1 FROM files f WHERE f.collection_id = collections.id)",
+        )
+        .bind(cutoff)
+        .execute(&mut *tx)
+        .await
+        .map_err(query_error("Failed to delete empty collections"))?;
+
+        sqlx::query(
+            "DELETE FROM group_shares WHERE created_at < $1 \
+             AND NOT EXISTS (SELECT 1 FROM files f WHERE f.id = group_shares.file_id)",
+        )
+        .bind(cutoff)
+        .execute(&mut *tx)
+        .await
+        .map_err(query_error("Failed to delete dangling group shares"))?;
+
+        tx.commit()
+            .await
+            .map_err(query_error("Failed to commit transaction"))?;
+
+        println!(
+            "Reconciled {} orphaned files, {} orphaned file versions, {} dangling shared links and {} empty collections.",
+            orphaned_files.len(),
+            orphaned_versions.len(),
+            dangling_shares.rows_affected(),
+            empty_collections.rows_affected()
+        );
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use ed25519_dalek::SigningKey;
+    use futures_util::future::join_all;
+
+    use super::*;
+    use crate::services::{
+        audit,
+        listing::{ListingCursor, SortField, SortOrder},
+    };
+
+    // A SQLite file of its own, removed again once the test is done
+    struct TempDatabase {
+        path: PathBuf,
+    }
+
+    impl TempDatabase {
+        fn new() -> Self {
+            let path =
+                std::env::temp_dir().join(format!("secure-share-{}.db", ObjectId::new().to_hex()));
+            TempDatabase { path }
+        }
+
+        // Each call opens its own pool, like another server instance would
+        async fn open(&self) -> SqlDatabase {
+            let url = format!("sqlite://{}?mode=rwc", self.path.display());
+            SqlDatabase::init(url, DatabaseBackend::Sqlite).await
+        }
+    }
+
+    impl Drop for TempDatabase {
+        fn drop(&mut self) {
+            for suffix in ["", "-wal", "-shm"] {
+                let mut path = self.path.clone().into_os_string();
+                path.push(suffix);
+                let _ = std::fs::remove_file(path);
+            }
+        }
+    }
+
+    async fn user(db: &SqlDatabase, name: &str) -> ObjectId {
+        db.create_user(
+            name.to_string(),
+            format!("{}@example.com", name),
+            "password".to_string(),
+        )
+        .await
+        .unwrap()
+    }
+
+    fn in_days(days: i64) -> DateTime {
+        DateTime::from_millis(DateTime::now().timestamp_millis() + days * 24 * 60 * 60 * 1000)
+    }
+
+    async fn share_file(
+        db: &SqlDatabase,
+        owner: ObjectId,
+        recipient: ObjectId,
+        file_name: &str,
+        expires_at: DateTime,
+    ) -> ShareLink {
+        let file_id = db
+            .save_file(
+                file_name.to_string(),
+                100,
+                vec![0; 16],
+                vec![0; 16],
+                vec![0; 32],
+                recipient.to_hex(),
+                owner,
+                "password".to_string(),
+                expires_at,
+                "text/plain".to_string(),
+                String::new(),
+                None,
+                ScanStatus::Clean,
+                Compression::None,
+                Vec::new(),
+                Vec::new(),
+                None,
+            )
+            .await
+            .unwrap();
+        db.get_file_share_links(file_id).await.unwrap().remove(0)
+    }
+
+    fn share_link(file_id: ObjectId, recipient: ObjectId, parent: Option<ObjectId>) -> ShareLink {
+        ShareLink {
+            _id: ObjectId::new(),
+            recipient_user_id: recipient,
+            file_id,
+            password: "password".to_string(),
+            expires_at: in_days(1),
+            created_at: DateTime::now(),
+            encrypted_aes_key: Some(vec![0; 32]),
+            revoked_at: None,
+            declined_at: None,
+            archived_at: None,
+            can_reshare: false,
+            parent_share_id: parent,
+            signature: None,
+            first_opened_at: None,
+            last_opened_at: None,
+            open_count: 0,
+            failed_password_attempts: 0,
+            organization_id: None,
+        }
+    }
+
+    async fn reshare(db: &SqlDatabase, parent: &ShareLink, recipient: ObjectId) -> ObjectId {
+        db.create_share_link(share_link(parent.file_id, recipient, Some(parent._id)))
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn sent_files_page_with_cursors() {
+        let temp = TempDatabase::new();
+        let db = temp.open().await;
+        let (alice, bob) = (user(&db, "alice").await, user(&db, "bob").await);
+        for name in ["c.txt", "a.txt", "b.txt"] {
+            share_file(&db, alice, bob, name, in_days(1)).await;
+        }
+
+        let mut options = FileListOptions {
+            sort: SortField::Name,
+            order: SortOrder::Asc,
+            limit: 2,
+            ..FileListOptions::default()
+        };
+        let first = db.get_sent_files(alice.to_hex(), &options).await.unwrap();
+        assert_eq!(first.total, 3);
+        assert!(first.has_more);
+        let names: Vec<_> = first
+            .files
+            .iter()
+            .map(|file| file.file_name.as_str())
+            .collect();
+        assert_eq!(names, ["a.txt", "b.txt"]);
+
+        options.after = Some(ListingCursor::after(
+            &first.files[1],
+            options.sort,
+            options.order,
+        ));
+        let second = db.get_sent_files(alice.to_hex(), &options).await.unwrap();
+        assert!(!second.has_more);
+        assert_eq!(second.files.len(), 1);
+        assert_eq!(second.files[0].file_name, "c.txt");
+
+        let received = db.get_recieve_files(bob.to_hex(), &options).await.unwrap();
+        assert_eq!(received.total, 3);
+    }
+
+    #[tokio::test]
+    async fn revoking_a_share_revokes_its_reshares() {
+        let temp = TempDatabase::new();
+        let db = temp.open().await;
+        let (alice, bob, carol, dave) = (
+            user(&db, "alice").await,
+            user(&db, "bob").await,
+            user(&db, "carol").await,
+            user(&db, "dave").await,
+        );
+        let share = share_file(&db, alice, bob, "report.pdf", in_days(1)).await;
+        let carol_share = reshare(&db, &share, carol).await;
+        let carol_link = db.get_share_link(carol_share).await.unwrap();
+        reshare(&db, &carol_link, dave).await;
+
+        let revoked = db.revoke_share(share._id).await.unwrap();
+
+        let mut recipients: Vec<_> = revoked
+            .iter()
+            .map(|revoked| revoked.recipient_user_id)
+            .collect();
+        recipients.sort();
+        let mut expected = vec![bob, carol, dave];
+        expected.sort();
+        assert_eq!(recipients, expected);
+        let links = db.get_file_share_links(share.file_id).await.unwrap();
+        assert!(links.iter().all(|link| link.revoked_at.is_some()));
+    }
+
+    #[tokio::test]
+    async fn declining_revokes_reshares() {
+        let temp = TempDatabase::new();
+        let db = temp.open().await;
+        let (alice, bob, carol) = (
+            user(&db, "alice").await,
+            user(&db, "bob").await,
+            user(&db, "carol").await,
+        );
+        let share = share_file(&db, alice, bob, "report.pdf", in_days(1)).await;
+        let carol_share = reshare(&db, &share, carol).await;
+
+        let revoked = db.decline_share(share._id, bob).await.unwrap();
+
+        assert_eq!(revoked.len(), 1);
+        assert_eq!(revoked[0].share_id, carol_share);
+        assert!(db
+            .get_share_link(share._id)
+            .await
+            .unwrap()
+            .declined_at
+            .is_some());
+        assert!(db.decline_share(share._id, carol).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn removing_the_last_live_share_declines_it() {
+        let temp = TempDatabase::new();
+        let db = temp.open().await;
+        let (alice, bob, carol) = (
+            user(&db, "alice").await,
+            user(&db, "bob").await,
+            user(&db, "carol").await,
+        );
+        let share = share_file(&db, alice, bob, "report.pdf", in_days(1)).await;
+        let carol_share = reshare(&db, &share, carol).await;
+
+        // Carol's re-share hangs off Bob's, so it does not keep the file reachable
+        let revoked = db.remove_received_share(share._id, bob).await.unwrap();
+
+        assert_eq!(revoked.len(), 1);
+        assert!(db
+            .get_share_link(share._id)
+            .await
+            .unwrap()
+            .declined_at
+            .is_some());
+        assert!(db
+            .get_share_link(carol_share)
+            .await
+            .unwrap()
+            .revoked_at
+            .is_some());
+    }
+
+    #[tokio::test]
+    async fn readding_a_revoked_recipient_reuses_their_link() {
+        let temp = TempDatabase::new();
+        let db = temp.open().await;
+        let (alice, bob) = (user(&db, "alice").await, user(&db, "bob").await);
+        let share = share_file(&db, alice, bob, "report.pdf", in_days(1)).await;
+
+        let readded = || share_link(share.file_id, bob, None);
+        assert!(db.create_share_link(readded()).await.is_err());
+        db.revoke_share(share._id).await.unwrap();
+        let share_id = db.create_share_link(readded()).await.unwrap();
+
+        assert_eq!(share_id, share._id);
+        assert!(db
+            .get_share_link(share_id)
+            .await
+            .unwrap()
+            .revoked_at
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn expired_shares_take_their_files_and_storage_with_them() {
+        let temp = TempDatabase::new();
+        let db = temp.open().await;
+        let (alice, bob) = (user(&db, "alice").await, user(&db, "bob").await);
+        db.reserve_storage(alice, 100, 1000).await.unwrap();
+        let share = share_file(&db, alice, bob, "report.pdf", in_days(-1)).await;
+        share_file(&db, alice, bob, "live.pdf", in_days(1)).await;
+
+        let expired = db.delete_expired_files().await.unwrap();
+
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].share_id, share._id);
+        assert_eq!(expired[0].file_name.as_deref(), Some("report.pdf"));
+        assert!(db.get_file(Bson::ObjectId(share.file_id)).await.is_err());
+        let owner = db.get_user_by_id(Bson::ObjectId(alice)).await.unwrap();
+        assert_eq!(owner.storage_used, 0);
+        let head = db.get_audit_head().await.unwrap().unwrap();
+        assert_eq!(head.action, AuditAction::Expire);
+        assert_eq!(head.share_id, Some(share._id));
+    }
+
+    #[tokio::test]
+    async fn storage_reservations_stop_at_the_quota() {
+        let temp = TempDatabase::new();
+        let db = temp.open().await;
+        let alice = user(&db, "alice").await;
+
+        db.reserve_storage(alice, 60, 100).await.unwrap();
+        assert!(db.reserve_storage(alice, 60, 100).await.is_err());
+        db.release_storage(alice, 60).await.unwrap();
+        db.reserve_storage(alice, 60, 100).await.unwrap();
+
+        let user = db.get_user_by_id(Bson::ObjectId(alice)).await.unwrap();
+        assert_eq!(user.storage_used, 60);
+    }
+
+    #[tokio::test]
+    async fn reconcile_removes_old_files_without_shares() {
+        let temp = TempDatabase::new();
+        let db = temp.open().await;
+        let (alice, bob) = (user(&db, "alice").await, user(&db, "bob").await);
+        db.reserve_storage(alice, 200, 1000).await.unwrap();
+        let orphan = share_file(&db, alice, bob, "orphan.pdf", in_days(1)).await;
+        let recent = share_file(&db, alice, bob, "recent.pdf", in_days(1)).await;
+        sqlx::query("DELETE FROM share_links")
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        // Only rows older than the grace period are touched
+        sqlx::query("UPDATE files SET created_at = 0 WHERE id = $1")
+            .bind(orphan.file_id.to_hex())
+            .execute(&db.pool)
+            .await
+            .unwrap();
+
+        db.reconcile().await.unwrap();
+
+        assert!(db.get_file(Bson::ObjectId(orphan.file_id)).await.is_err());
+        assert!(db.get_file(Bson::ObjectId(recent.file_id)).await.is_ok());
+        let owner = db.get_user_by_id(Bson::ObjectId(alice)).await.unwrap();
+        assert_eq!(owner.storage_used, 100);
+    }
+
+    #[tokio::test]
+    async fn concurrent_instances_extend_one_chain() {
+        let temp = TempDatabase::new();
+        let first = temp.open().await;
+        let second = temp.open().await;
+
+        let appends = (0..20).map(|i| {
+            let db = if i % 2 == 0 { &first } else { &second };
+            db.record_audit_event(system_event(AuditAction::Login))
+        });
+        for result in join_all(appends).await {
+            result.unwrap();
+        }
+
+        let verifying_key = SigningKey::generate(&mut rand::rngs::OsRng).verifying_key();
+        let verification = audit::verify_chain(&first, &verifying_key).await.unwrap();
+        assert!(verification.valid, "{:?}", verification.problems);
+        assert_eq!(verification.events_checked, 20);
+        assert_eq!(verification.head_sequence, 20);
+    }
+}
@@ -0,0 +1,56 @@
+use actix_web::Error;
+use mongodb::bson::DateTime;
+use rand::Rng;
+
+use crate::{
+    models::user_model::User,
+    services::{db::Database, mail_templates::verification_email, notifications::Notifier},
+    utils::file::integrity::sha256_hex,
+};
+
+const VERIFICATION_TOKEN_TTL_MS: i64 = 24 * 60 * 60 * 1000;
+
+// Emails the user a new single-use token proving they own their address;
+// only its hash is stored, so a leaked database cannot verify anyone
+pub async fn send_verification(
+    db: &dyn Database,
+    notifier: &Notifier,
+    user: &User,
+) -> Result<(), Error> {
+    let token = generate_token();
+    let expires_at =
+        DateTime::from_millis(DateTime::now().timestamp_millis() + VERIFICATION_TOKEN_TTL_MS);
+    db.set_email_verification(user._id, sha256_hex(token.as_bytes()), expires_at)
+        .await?;
+    notifier.send_emails(vec![verification_email(
+        user.email.clone(),
+        &token,
+        expires_at,
+    )]);
+
+    Ok(())
+}
+
+// Consumes the token and returns the now verified user
+pub async fn verify(db: &dyn Database, token: &str) -> Result<User, Error> {
+    db.verify_email(sha256_hex(token.trim().as_bytes())).await
+}
+
+fn generate_token() -> String {
+    let mut token = [0u8; 32];
+    rand::thread_rng().fill(&mut token);
+    token.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// Files only go to accounts whose owner proved the address, or whoever
+// registered someone else's email would receive what was meant for them
+pub fn ensure_verified(recipient: &User) -> Result<(), Error> {
+    if recipient.email_verified_at.is_none() {
+        return Err(actix_web::error::ErrorForbidden(format!(
+            "{} has not verified their email yet",
+            recipient.email
+        )));
+    }
+
+    Ok(())
+}
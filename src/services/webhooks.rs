@@ -0,0 +1,587 @@
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    sync::Arc,
+    time::Duration,
+};
+
+use actix_web::{web::Data, Error};
+use hmac::{Hmac, Mac};
+use mongodb::bson::{oid::ObjectId, DateTime};
+use rand::Rng;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use sha2::Sha256;
+use tokio::{net::lookup_host, time};
+
+use crate::{
+    models::{
+        share_link_model::ExpiredShare,
+        webhook_model::{DeliveryStatus, Webhook, WebhookDelivery, WebhookEvent},
+    },
+    services::db::Database,
+};
+
+pub const DEFAULT_PAGE_SIZE: usize = 50;
+pub const MAX_PAGE_SIZE: usize = 200;
+
+// A delivery still failing after this many attempts becomes a dead letter
+pub const MAX_ATTEMPTS: i64 = 8;
+// Doubled after every failed attempt: 30s, 1m, 2m, ... about an hour for the last
+const RETRY_BASE_MS: i64 = 30 * 1000;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+const DELIVERY_BATCH_SIZE: usize = 20;
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+// Claimed deliveries are not due again until this passes, so a worker that
+// dies mid-attempt only delays them
+const CLAIM_LEASE_MS: i64 = 5 * 60 * 1000;
+
+pub const SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+pub const TIMESTAMP_HEADER: &str = "X-Webhook-Timestamp";
+pub const EVENT_HEADER: &str = "X-Webhook-Event";
+pub const DELIVERY_HEADER: &str = "X-Webhook-Delivery";
+
+#[derive(Debug, Clone)]
+pub struct WebhookDeliveryQuery {
+    pub webhook_id: ObjectId,
+    pub status: Option<DeliveryStatus>,
+    // Id of the last delivery of the previous page, newest first
+    pub before: Option<ObjectId>,
+    pub limit: usize,
+}
+
+// Something that happened to a file, reported to the webhooks of its owner and
+// recipient and to every admin webhook
+#[derive(Debug, Clone)]
+pub struct FileEvent {
+    pub event: WebhookEvent,
+    pub file_id: ObjectId,
+    pub file_name: Option<String>,
+    pub owner_id: Option<ObjectId>,
+    pub share_id: Option<ObjectId>,
+    pub recipient_id: Option<ObjectId>,
+    // None for events the server raises itself, such as expirations
+    pub actor_id: Option<ObjectId>,
+}
+
+pub fn file_event(event: WebhookEvent, file_id: ObjectId) -> FileEvent {
+    FileEvent {
+        event,
+        file_id,
+        file_name: None,
+        owner_id: None,
+        share_id: None,
+        recipient_id: None,
+        actor_id: None,
+    }
+}
+
+pub fn expired_events(expired_shares: Vec<ExpiredShare>) -> Vec<FileEvent> {
+    expired_shares
+        .into_iter()
+        .map(|expired_share| FileEvent {
+            file_name: expired_share.file_name,
+            owner_id: expired_share.owner_id,
+            share_id: Some(expired_share.share_id),
+            recipient_id: Some(expired_share.recipient_user_id),
+            ..file_event(WebhookEvent::Expired, expired_share.file_id)
+        })
+        .collect()
+}
+
+fn generate_secret() -> String {
+    let mut secret = [0u8; 32];
+    rand::thread_rng().fill(&mut secret);
+    secret.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// HMAC-SHA256 over "<timestamp>.<body>", hex encoded. Receivers recompute it
+// and reject stale timestamps to stop replays
+pub fn sign(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body.as_bytes());
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn payload(delivery_id: ObjectId, event: &FileEvent, created_at: DateTime) -> String {
+    let hex = |id: Option<ObjectId>| id.map(|id| id.to_hex());
+    serde_json::json!({
+        "id": delivery_id.to_hex(),
+        "event": event.event,
+        "created_at": created_at.try_to_rfc3339_string().unwrap_or_default(),
+        "data": {
+            "file_id": event.file_id.to_hex(),
+            "file_name": event.file_name,
+            "owner_id": hex(event.owner_id),
+            "share_id": hex(event.share_id),
+            "recipient_id": hex(event.recipient_id),
+            "actor_id": hex(event.actor_id),
+        },
+    })
+    .to_string()
+}
+
+// Queues a delivery to every webhook subscribed to each event. Deliveries
+// that cannot be queued are logged and not retried
+pub async fn dispatch(db: &dyn Database, events: Vec<FileEvent>) {
+    let mut deliveries = Vec::new();
+    for event in &events {
+        let mut user_ids: Vec<ObjectId> = event.owner_id.into_iter().collect();
+        user_ids.extend(event.recipient_id.filter(|id| Some(*id) != event.owner_id));
+
+        let webhooks = match db.get_subscribed_webhooks(user_ids).await {
+            Ok(webhooks) => webhooks,
+            Err(e) => {
+                eprintln!(
+                    "Failed to fetch webhooks for {}: {}",
+                    event.event.as_str(),
+                    e
+                );
+                continue;
+            }
+        };
+
+        let now = DateTime::now();
+        for webhook in webhooks
+            .iter()
+            .filter(|webhook| webhook.events.contains(&event.event))
+        {
+            let delivery_id = ObjectId::new();
+            deliveries.push(WebhookDelivery {
+                _id: delivery_id,
+                webhook_id: webhook._id,
+                event: event.event,
+                payload: payload(delivery_id, event, now),
+                status: DeliveryStatus::Pending,
+                attempts: 0,
+                next_attempt_at: now,
+                last_attempt_at: None,
+                response_status: None,
+                last_error: None,
+                delivered_at: None,
+                created_at: now,
+            });
+        }
+    }
+
+    if deliveries.is_empty() {
+        return;
+    }
+    if let Err(e) = db.save_webhook_deliveries(deliveries).await {
+        eprintln!("Failed to queue webhook deliveries: {}", e);
+    }
+}
+
+// Sends due deliveries until the server stops
+pub async fn run_delivery_worker(db: Data<dyn Database>) {
+    let client = delivery_client();
+
+    let mut interval = time::interval(POLL_INTERVAL);
+    loop {
+        interval.tick().await;
+        // Actix errors are not Send, so none may be held across an await here
+        let deliveries = db
+            .claim_due_deliveries(DELIVERY_BATCH_SIZE, CLAIM_LEASE_MS)
+            .await
+            .map_err(|e| eprintln!("Failed to claim webhook deliveries: {}", e))
+            .unwrap_or_default();
+
+        for delivery in deliveries {
+            let webhook = db.get_webhook(delivery.webhook_id).await.ok();
+            let delivery = attempt(&client, webhook.as_ref(), delivery).await;
+            if let Err(e) = db
+                .update_webhook_delivery(delivery)
+                .await
+                .map_err(|e| e.to_string())
+            {
+                eprintln!("Failed to record webhook delivery: {}", e);
+            }
+        }
+    }
+}
+
+// Redirects and proxies could lead a request past the address checks
+fn delivery_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .timeout(DELIVERY_TIMEOUT)
+        .redirect(reqwest::redirect::Policy::none())
+        .no_proxy()
+        .dns_resolver(Arc::new(PublicResolver))
+        .build()
+        .expect("Failed to build webhook client")
+}
+
+async fn attempt(
+    client: &reqwest::Client,
+    webhook: Option<&Webhook>,
+    mut delivery: WebhookDelivery,
+) -> WebhookDelivery {
+    let now = DateTime::now();
+    delivery.attempts += 1;
+    delivery.last_attempt_at = Some(now);
+
+    let outcome = match webhook {
+        Some(webhook) => send(client, webhook, &delivery, now).await,
+        None => Err((None, "Webhook no longer exists".to_string())),
+    };
+
+    match outcome {
+        Ok(status) => {
+            delivery.status = DeliveryStatus::Delivered;
+            delivery.response_status = Some(status);
+            delivery.last_error = None;
+            delivery.delivered_at = Some(now);
+        }
+        Err((status, error)) => {
+            delivery.response_status = status;
+            delivery.last_error = Some(error);
+            if delivery.attempts >= MAX_ATTEMPTS || webhook.is_none() {
+                delivery.status = DeliveryStatus::Dead;
+            } else {
+                let backoff = RETRY_BASE_MS << (delivery.attempts - 1);
+                delivery.next_attempt_at = DateTime::from_millis(now.timestamp_millis() + backoff);
+            }
+        }
+    }
+
+    delivery
+}
+
+// Any 2xx response counts as delivered
+async fn send(
+    client: &reqwest::Client,
+    webhook: &Webhook,
+    delivery: &WebhookDelivery,
+    now: DateTime,
+) -> Result<i64, (Option<i64>, String)> {
+    // Hostnames are checked by the client's resolver as they are connected to
+    if let Some(ip) = literal_ip(&webhook.url) {
+        if !is_public(ip) {
+            return Err((None, format!("Refusing to connect to {}", ip)));
+        }
+    }
+
+    let timestamp = now.timestamp_millis() / 1000;
+    let response = client
+        .post(&webhook.url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(EVENT_HEADER, delivery.event.as_str())
+        .header(DELIVERY_HEADER, delivery._id.to_hex())
+        .header(TIMESTAMP_HEADER, timestamp)
+        .header(
+            SIGNATURE_HEADER,
+            format!(
+                "sha256={}",
+                sign(&webhook.secret, timestamp, &delivery.payload)
+            ),
+        )
+        .body(delivery.payload.clone())
+        .send()
+        .await
+        .map_err(|e| (None, format!("Request failed: {}", e)))?;
+
+    // The body is never read, so a webhook cannot be used to fetch a page
+    // through the server
+    let status = response.status();
+    if status.is_success() {
+        return Ok(status.as_u16() as i64);
+    }
+    Err((
+        Some(status.as_u16() as i64),
+        format!("Endpoint responded with {}", status),
+    ))
+}
+
+// Puts a dead letter back in the queue with a fresh set of attempts
+pub fn requeue(mut delivery: WebhookDelivery) -> WebhookDelivery {
+    delivery.status = DeliveryStatus::Pending;
+    delivery.attempts = 0;
+    delivery.next_attempt_at = DateTime::now();
+    delivery
+}
+
+// Stores a new webhook with a fresh secret; `user_id` is None for an admin webhook
+pub async fn register(
+    db: &dyn Database,
+    user_id: Option<ObjectId>,
+    url: String,
+    events: Vec<WebhookEvent>,
+) -> Result<Webhook, Error> {
+    let parsed = match reqwest::Url::parse(&url) {
+        Ok(parsed) if matches!(parsed.scheme(), "http" | "https") && parsed.has_host() => parsed,
+        _ => {
+            return Err(actix_web::error::ErrorBadRequest(
+                "Webhook url must be an http or https URL",
+            ))
+        }
+    };
+    check_destination(&parsed).await?;
+
+    let webhook = Webhook {
+        _id: ObjectId::new(),
+        user_id,
+        url,
+        secret: generate_secret(),
+        events,
+        created_at: DateTime::now(),
+    };
+    db.create_webhook(webhook.clone()).await?;
+
+    Ok(webhook)
+}
+
+// Webhook urls are chosen by users, so they must not reach the server's own
+// network: loopback, private, link-local (cloud metadata) and other
+// non-routable addresses are refused
+async fn check_destination(url: &reqwest::Url) -> Result<(), Error> {
+    let refused = |ip: IpAddr| {
+        actix_web::error::ErrorBadRequest(format!(
+            "Webhook url resolves to a non-public address: {}",
+            ip
+        ))
+    };
+
+    if let Some(ip) = literal_ip(url.as_str()) {
+        if !is_public(ip) {
+            return Err(refused(ip));
+        }
+        return Ok(());
+    }
+
+    let host = url.host_str().unwrap_or_default();
+    let port = url.port_or_known_default().unwrap_or(443);
+    let addrs = lookup_host((host, port)).await.map_err(|e| {
+        actix_web::error::ErrorBadRequest(format!("Failed to resolve webhook host: {}", e))
+    })?;
+    for addr in addrs {
+        if !is_public(addr.ip()) {
+            return Err(refused(addr.ip()));
+        }
+    }
+
+    Ok(())
+}
+
+// URL parsing already normalises forms like http://2130706433/ to dotted quads
+fn literal_ip(url: &str) -> Option<IpAddr> {
+    let url = reqwest::Url::parse(url).ok()?;
+    url.host_str()?
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse()
+        .ok()
+}
+
+// Resolves like the system resolver but fails when any address is not public,
+// so a name re-pointed after registration is still refused
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_string();
+        Box::pin(async move {
+            let addrs: Vec<_> = lookup_host((host.as_str(), 0)).await?.collect();
+            if let Some(addr) = addrs.iter().find(|addr| !is_public(addr.ip())) {
+                return Err(format!("Refusing to connect to {} ({})", host, addr.ip()).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => is_public_v6(ip),
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        // 0.0.0.0/8 "this network"
+        || a == 0
+        // 100.64.0.0/10 shared address space, which some clouds use for metadata
+        || (a == 100 && (b & 0xc0) == 64)
+        // 192.0.0.0/24 protocol assignments
+        || (a == 192 && b == 0 && c == 0)
+        // 198.18.0.0/15 benchmarking
+        || (a == 198 && (b & 0xfe) == 18)
+        // 240.0.0.0/4 reserved
+        || a >= 240)
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    // IPv4-mapped, IPv4-compatible and NAT64 addresses reach IPv4 hosts
+    if let Some(v4) = ip.to_ipv4_mapped() {
+        return is_public_v4(v4);
+    }
+    let segments = ip.segments();
+    if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] || (segments[..6] == [0; 6] && segments[6] != 0)
+    {
+        let [a, b] = segments[6].to_be_bytes();
+        let [c, d] = segments[7].to_be_bytes();
+        return is_public_v4(Ipv4Addr::new(a, b, c, d));
+    }
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        // fc00::/7 unique local
+        || (segments[0] & 0xfe00) == 0xfc00
+        // fe80::/10 link-local
+        || (segments[0] & 0xffc0) == 0xfe80
+        // 2001:db8::/32 documentation
+        || (segments[0] == 0x2001 && segments[1] == 0x0db8))
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    fn webhook(url: &str) -> Webhook {
+        Webhook {
+            _id: ObjectId::new(),
+            user_id: None,
+            url: url.to_string(),
+            secret: generate_secret(),
+            events: vec![WebhookEvent::Uploaded],
+            created_at: DateTime::now(),
+        }
+    }
+
+    fn delivery(webhook: &Webhook) -> WebhookDelivery {
+        let now = DateTime::now();
+        WebhookDelivery {
+            _id: ObjectId::new(),
+            webhook_id: webhook._id,
+            event: WebhookEvent::Uploaded,
+            payload: "{}".to_string(),
+            status: DeliveryStatus::Pending,
+            attempts: 0,
+            next_attempt_at: now,
+            last_attempt_at: None,
+            response_status: None,
+            last_error: None,
+            delivered_at: None,
+            created_at: now,
+        }
+    }
+
+    #[test]
+    fn refuses_internal_addresses() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.100.100.200",
+            "0.0.0.0",
+            "255.255.255.255",
+            "::1",
+            "::",
+            "fd00:ec2::254",
+            "fe80::1",
+            "::ffff:10.0.0.1",
+            "::127.0.0.1",
+            "64:ff9b::a9fe:a9fe",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{} should be refused", ip);
+        }
+    }
+
+    #[test]
+    fn signs_timestamp_and_body() {
+        let signature = sign("secret", 1700000000, r#"{"id":1}"#);
+
+        assert_eq!(
+            signature,
+            "3dd1b9aef568d75f6790a84bd2e5dfa1f44409eef3cbdbd3f10b837376100c11"
+        );
+        assert_ne!(sign("secret", 1700000001, r#"{"id":1}"#), signature);
+        assert_ne!(sign("other", 1700000000, r#"{"id":1}"#), signature);
+    }
+
+    #[test]
+    fn allows_public_addresses() {
+        for ip in [
+            "1.1.1.1",
+            "8.8.8.8",
+            "2606:4700:4700::1111",
+            "::ffff:1.1.1.1",
+        ] {
+            assert!(is_public(ip.parse().unwrap()), "{} should be allowed", ip);
+        }
+    }
+
+    #[test]
+    fn reads_literal_hosts() {
+        assert_eq!(
+            literal_ip("http://2130706433/"),
+            Some("127.0.0.1".parse().unwrap())
+        );
+        assert_eq!(
+            literal_ip("http://[::1]:8080/hook"),
+            Some("::1".parse().unwrap())
+        );
+        assert_eq!(literal_ip("https://example.com/hook"), None);
+    }
+
+    #[tokio::test]
+    async fn refuses_internal_urls_at_registration() {
+        for url in [
+            "http://127.0.0.1/hook",
+            "http://0x7f.1/hook",
+            "http://[::ffff:169.254.169.254]/latest/meta-data",
+            "http://localhost:8080/hook",
+        ] {
+            let url = reqwest::Url::parse(url).unwrap();
+            assert!(
+                check_destination(&url).await.is_err(),
+                "{} should be refused",
+                url
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn resolver_refuses_internal_names() {
+        let name: Name = "localhost".parse().unwrap();
+        assert!(PublicResolver.resolve(name).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn never_connects_to_internal_endpoints() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let client = delivery_client();
+
+        for url in [
+            format!("http://127.0.0.1:{}/hook", port),
+            format!("http://localhost:{}/hook", port),
+        ] {
+            let webhook = webhook(&url);
+            let delivery = attempt(&client, Some(&webhook), delivery(&webhook)).await;
+            assert_eq!(delivery.status, DeliveryStatus::Pending);
+            assert_eq!(delivery.response_status, None);
+            assert!(delivery.last_error.is_some());
+        }
+
+        let accepted = time::timeout(Duration::from_millis(100), listener.accept()).await;
+        assert!(accepted.is_err(), "no connection should have been made");
+    }
+}
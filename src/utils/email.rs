@@ -0,0 +1,4 @@
+// Emails are matched case-insensitively, so they are stored lowercased
+pub fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
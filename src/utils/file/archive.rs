@@ -0,0 +1,91 @@
+use std::{cell::RefCell, collections::HashSet, io::Write, rc::Rc};
+
+use actix_web::{error, web::Bytes, Error};
+use zip::{
+    write::{SimpleFileOptions, StreamWriter},
+    CompressionMethod, ZipWriter,
+};
+
+#[derive(Clone, Default)]
+struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+// Builds a zip one entry at a time, handing back whatever has been written so
+// far so the archive can be streamed instead of held in memory
+pub struct ZipStream {
+    writer: ZipWriter<StreamWriter<SharedBuffer>>,
+    buffer: SharedBuffer,
+    names: HashSet<String>,
+}
+
+impl ZipStream {
+    pub fn new() -> Self {
+        let buffer = SharedBuffer::default();
+        ZipStream {
+            writer: ZipWriter::new_stream(buffer.clone()),
+            buffer,
+            names: HashSet::new(),
+        }
+    }
+
+    pub fn add_file(&mut self, file_name: &str, data: &[u8]) -> Result<Bytes, Error> {
+        let entry_name = self.unique_name(file_name);
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+        self.writer.start_file(entry_name, options).map_err(|e| {
+            error::ErrorInternalServerError(format!("Failed to write archive: {}", e))
+        })?;
+        self.writer.write_all(data).map_err(|e| {
+            error::ErrorInternalServerError(format!("Failed to write archive: {}", e))
+        })?;
+
+        Ok(self.take())
+    }
+
+    pub fn finish(self) -> Result<Bytes, Error> {
+        let buffer = self.buffer.clone();
+        self.writer.finish().map_err(|e| {
+            error::ErrorInternalServerError(format!("Failed to write archive: {}", e))
+        })?;
+
+        let remaining = buffer.0.take();
+        Ok(Bytes::from(remaining))
+    }
+
+    fn take(&self) -> Bytes {
+        Bytes::from(self.buffer.0.take())
+    }
+
+    // Files in one collection may share a name; later ones get a numeric suffix
+    fn unique_name(&mut self, file_name: &str) -> String {
+        let mut candidate = file_name.to_string();
+        let mut counter = 1;
+        while self.names.contains(&candidate) {
+            candidate = match file_name.rsplit_once('.') {
+                Some((stem, extension)) if !stem.is_empty() => {
+                    format!("{} ({}).{}", stem, counter, extension)
+                }
+                _ => format!("{} ({})", file_name, counter),
+            };
+            counter += 1;
+        }
+        self.names.insert(candidate.clone());
+        candidate
+    }
+}
+
+impl Default for ZipStream {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,60 @@
+use actix_web::{error, Error};
+
+use crate::models::file_model::Compression;
+
+const ZSTD_LEVEL: i32 = 3;
+
+// Compresses with zstd, keeping the original when that saves nothing
+pub fn compress_upload(data: Vec<u8>, skip: bool) -> Result<(Vec<u8>, Compression), Error> {
+    if skip || data.is_empty() {
+        return Ok((data, Compression::None));
+    }
+
+    let compressed = zstd::bulk::compress(&data, ZSTD_LEVEL)
+        .map_err(|e| error::ErrorInternalServerError(format!("Failed to compress file: {}", e)))?;
+
+    if compressed.len() < data.len() {
+        Ok((compressed, Compression::Zstd))
+    } else {
+        Ok((data, Compression::None))
+    }
+}
+
+pub fn decompress(data: Vec<u8>, compression: Compression) -> Result<Vec<u8>, Error> {
+    match compression {
+        Compression::None => Ok(data),
+        Compression::Zstd => zstd::stream::decode_all(data.as_slice()).map_err(|e| {
+            error::ErrorConflict(format!("Error occured while decompressing file: {}", e))
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_compressible_data() {
+        let data = b"secure share ".repeat(1000);
+        let (compressed, compression) = compress_upload(data.clone(), false).unwrap();
+
+        assert_eq!(compression, Compression::Zstd);
+        assert!(compressed.len() < data.len());
+        assert_eq!(decompress(compressed, compression).unwrap(), data);
+    }
+
+    #[test]
+    fn keeps_data_that_does_not_shrink() {
+        let data: Vec<u8> = (0..64).map(|_| rand::random::<u8>()).collect();
+
+        let (stored, compression) = compress_upload(data.clone(), false).unwrap();
+        assert_eq!(compression, Compression::None);
+        assert_eq!(stored, data);
+
+        let text = b"skip me ".repeat(100);
+        assert_eq!(
+            compress_upload(text.clone(), true).unwrap(),
+            (text, Compression::None)
+        );
+    }
+}
@@ -0,0 +1,94 @@
+use actix_web::{error, Error};
+
+// Detects a MIME type from the file's magic bytes; content without a known
+// signature is plain text when it decodes as UTF-8 and opaque bytes otherwise
+pub fn sniff_content_type(data: &[u8]) -> String {
+    match infer::get(data) {
+        Some(kind) => kind.mime_type().to_string(),
+        None if std::str::from_utf8(data).is_ok() => "text/plain".to_string(),
+        None => "application/octet-stream".to_string(),
+    }
+}
+
+// Which uploads are accepted, by MIME type (`image/*` matches a whole family)
+// and by file extension. Empty allow lists accept everything not denied
+#[derive(Debug, Clone, Default)]
+pub struct UploadPolicy {
+    pub allowed_types: Vec<String>,
+    pub denied_types: Vec<String>,
+    pub allowed_extensions: Vec<String>,
+    pub denied_extensions: Vec<String>,
+}
+
+impl UploadPolicy {
+    pub fn check(&self, file_name: &str, content_type: &str) -> Result<(), Error> {
+        let matches_type = |pattern: &String| match pattern.strip_suffix("/*") {
+            Some(family) => content_type.split('/').next() == Some(family),
+            None => pattern == content_type,
+        };
+
+        if self.denied_types.iter().any(matches_type)
+            || (!self.allowed_types.is_empty() && !self.allowed_types.iter().any(matches_type))
+        {
+            return Err(error::ErrorUnsupportedMediaType(format!(
+                "Files of type {} are not accepted",
+                content_type
+            )));
+        }
+
+        let extension = match file_name.rsplit_once('.') {
+            Some((_, extension)) => extension.to_lowercase(),
+            None => String::new(),
+        };
+
+        if self.denied_extensions.contains(&extension)
+            || (!self.allowed_extensions.is_empty()
+                && !self.allowed_extensions.contains(&extension))
+        {
+            return Err(error::ErrorUnsupportedMediaType(format!(
+                "Files named {} are not accepted",
+                file_name
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sniffs_magic_bytes_before_text() {
+        assert_eq!(sniff_content_type(b"%PDF-1.7\n"), "application/pdf");
+        assert_eq!(sniff_content_type("héllo".as_bytes()), "text/plain");
+        assert_eq!(
+            sniff_content_type(&[0xff, 0xfe, 0x00, 0x81]),
+            "application/octet-stream"
+        );
+    }
+
+    #[test]
+    fn matches_type_families_and_extensions() {
+        let policy = UploadPolicy {
+            allowed_types: vec!["image/*".to_string(), "application/pdf".to_string()],
+            denied_types: vec!["image/svg+xml".to_string()],
+            allowed_extensions: Vec::new(),
+            denied_extensions: vec!["exe".to_string()],
+        };
+
+        assert!(policy.check("photo.png", "image/png").is_ok());
+        assert!(policy.check("report.pdf", "application/pdf").is_ok());
+        assert!(policy.check("logo.svg", "image/svg+xml").is_err());
+        assert!(policy.check("notes.txt", "text/plain").is_err());
+        assert!(policy.check("SETUP.EXE", "image/png").is_err());
+    }
+
+    #[test]
+    fn empty_policy_accepts_everything() {
+        let policy = UploadPolicy::default();
+
+        assert!(policy.check("anything", "application/octet-stream").is_ok());
+    }
+}
@@ -3,10 +3,19 @@ use aes::Aes256;
 use block_modes::{block_padding::Pkcs7, BlockMode, Cbc};
 use rsa::{Pkcs1v15Encrypt, RsaPrivateKey};
 
+use crate::{
+    models::file_model::Compression,
+    utils::file::{compress::decompress, dedup::join_chunks},
+};
+
+// Returns the original plaintext, undoing any compression applied before
+// encryption. Deduplicated content decrypts to a manifest of its chunks
 pub async fn decrypt_file(
     encrypted_aes_key: Vec<u8>,
     encrypted_file: Vec<u8>,
     iv: Vec<u8>,
+    compression: Compression,
+    chunks: Vec<Vec<u8>>,
     user_private_key: &RsaPrivateKey,
 ) -> Result<Vec<u8>, Error> {
     let aes_key = user_private_key
@@ -36,5 +45,9 @@ pub async fn decrypt_file(
         ))
     })?;
 
-    Ok(decrypted_data)
+    if chunks.is_empty() {
+        decompress(decrypted_data, compression)
+    } else {
+        decompress(join_chunks(&decrypted_data, chunks)?, compression)
+    }
 }
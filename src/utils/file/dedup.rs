@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+
+use actix_web::{error, Error};
+use aes::Aes256;
+use block_modes::{block_padding::Pkcs7, BlockMode, Cbc};
+use hmac::{Hmac, Mac};
+use mongodb::bson::DateTime;
+use sha2::{Digest, Sha256};
+
+use crate::{models::chunk_model::Chunk, utils::file::integrity::sha256_hex};
+
+const CHUNK_SIZE: usize = 1024 * 1024;
+const CHUNK_KEY_LEN: usize = 32;
+
+// Content split into deduplicated chunks
+pub struct ChunkedContent {
+    // What gets encrypted under the file's own AES key: each chunk's key in
+    // order, or the content itself when it is not chunked
+    pub data: Vec<u8>,
+    pub chunk_ids: Vec<String>,
+    // One entry per distinct chunk, its refcount being how often this content uses it
+    pub chunks: Vec<Chunk>,
+}
+
+// Convergent encryption keyed by the server: a chunk's key is a keyed hash of
+// its plaintext, so identical chunks encrypt identically and are stored once,
+// while nobody without the server key can confirm a guessed plaintext
+pub fn split_chunks(dedup_key: &str, data: &[u8]) -> Result<ChunkedContent, Error> {
+    let mut manifest = Vec::new();
+    let mut chunk_ids = Vec::new();
+    let mut chunks: Vec<Chunk> = Vec::new();
+    let mut positions: HashMap<String, usize> = HashMap::new();
+
+    for plaintext in data.chunks(CHUNK_SIZE) {
+        let mut mac = Hmac::<Sha256>::new_from_slice(dedup_key.as_bytes())
+            .map_err(|e| error::ErrorInternalServerError(format!("Invalid dedup key: {}", e)))?;
+        mac.update(plaintext);
+        let chunk_key = mac.finalize().into_bytes();
+
+        // The id is a hash of the key, so it reveals nothing about the key itself
+        let chunk_id = sha256_hex(&chunk_key);
+        manifest.extend_from_slice(&chunk_key);
+        chunk_ids.push(chunk_id.clone());
+
+        match positions.get(&chunk_id) {
+            Some(&position) => chunks[position].refcount += 1,
+            None => {
+                positions.insert(chunk_id.clone(), chunks.len());
+                chunks.push(Chunk {
+                    _id: chunk_id,
+                    data: chunk_cipher(&chunk_key)?.encrypt_vec(plaintext),
+                    refcount: 1,
+                    created_at: DateTime::now(),
+                });
+            }
+        }
+    }
+
+    Ok(ChunkedContent {
+        data: manifest,
+        chunk_ids,
+        chunks,
+    })
+}
+
+// Chunks the content when deduplication is configured
+pub fn dedup_upload(dedup_key: Option<&str>, data: Vec<u8>) -> Result<ChunkedContent, Error> {
+    match dedup_key {
+        Some(dedup_key) => split_chunks(dedup_key, &data),
+        None => Ok(ChunkedContent {
+            data,
+            chunk_ids: Vec::new(),
+            chunks: Vec::new(),
+        }),
+    }
+}
+
+// Decrypts chunks listed in manifest order back into the content
+pub fn join_chunks(manifest: &[u8], chunks: Vec<Vec<u8>>) -> Result<Vec<u8>, Error> {
+    if manifest.len() != chunks.len() * CHUNK_KEY_LEN {
+        return Err(error::ErrorConflict(
+            "Chunk manifest does not match the file",
+        ));
+    }
+
+    let mut data = Vec::new();
+    for (chunk_key, chunk) in manifest.chunks(CHUNK_KEY_LEN).zip(chunks) {
+        let plaintext = chunk_cipher(chunk_key)?.decrypt_vec(&chunk).map_err(|e| {
+            error::ErrorConflict(format!("Error occured while decrypting chunk: {}", e))
+        })?;
+        data.extend_from_slice(&plaintext);
+    }
+
+    Ok(data)
+}
+
+// The IV is derived from the key as well, keeping encryption deterministic
+fn chunk_cipher(chunk_key: &[u8]) -> Result<Cbc<Aes256, Pkcs7>, Error> {
+    let iv = Sha256::new()
+        .chain_update(b"chunk-iv")
+        .chain_update(chunk_key)
+        .finalize();
+
+    Cbc::<Aes256, Pkcs7>::new_from_slices(chunk_key, &iv[..16]).map_err(|e| {
+        error::ErrorConflict(format!("Error occured while creating cipher text: {}", e))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identical_chunks_are_stored_once() {
+        let block = vec![7u8; CHUNK_SIZE];
+        let data = [block.clone(), block, vec![1u8; 10]].concat();
+        let content = split_chunks("dedup-key", &data).unwrap();
+
+        assert_eq!(content.chunk_ids.len(), 3);
+        assert_eq!(content.chunk_ids[0], content.chunk_ids[1]);
+        assert_eq!(content.chunks.len(), 2);
+        assert_eq!(content.chunks[0].refcount, 2);
+        assert_eq!(content.chunks[1].refcount, 1);
+
+        let chunks = content
+            .chunk_ids
+            .iter()
+            .map(|id| {
+                content
+                    .chunks
+                    .iter()
+                    .find(|chunk| &chunk._id == id)
+                    .unwrap()
+                    .data
+                    .clone()
+            })
+            .collect();
+        assert_eq!(join_chunks(&content.data, chunks).unwrap(), data);
+    }
+
+    #[test]
+    fn chunk_ids_depend_on_the_server_key() {
+        let first = split_chunks("first-key", b"content").unwrap();
+        let again = split_chunks("first-key", b"content").unwrap();
+        let other = split_chunks("other-key", b"content").unwrap();
+
+        assert_eq!(first.chunk_ids, again.chunk_ids);
+        assert_eq!(first.chunks[0].data, again.chunks[0].data);
+        assert_ne!(first.chunk_ids, other.chunk_ids);
+    }
+
+    #[test]
+    fn rejects_manifests_that_do_not_match() {
+        let content = split_chunks("dedup-key", b"content").unwrap();
+
+        assert!(join_chunks(&content.data, Vec::new()).is_err());
+    }
+}
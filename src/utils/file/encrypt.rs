@@ -2,7 +2,7 @@ use actix_web::{error, Error};
 use aes::Aes256;
 use block_modes::{block_padding::Pkcs7, BlockMode, Cbc};
 use rand::Rng;
-use rsa::{Pkcs1v15Encrypt, RsaPublicKey};
+use rsa::{Pkcs1v15Encrypt, RsaPrivateKey, RsaPublicKey};
 
 pub async fn encrypt_file(
     file_data: Vec<u8>,
@@ -35,3 +35,26 @@ pub async fn encrypt_file(
 
     Ok((encrypt_aes_key, encrypted_data, iv.to_vec()))
 }
+
+// Encrypts a new revision under a file's existing AES key with a fresh IV, so
+// every share's copy of the key keeps working
+pub async fn encrypt_revision(
+    file_data: Vec<u8>,
+    encrypted_aes_key: &[u8],
+    holder_private_key: &RsaPrivateKey,
+) -> Result<(Vec<u8>, Vec<u8>), Error> {
+    let aes_key = holder_private_key
+        .decrypt(Pkcs1v15Encrypt, encrypted_aes_key)
+        .map_err(|e| {
+            error::ErrorConflict(format!("Error occured while decrypting aes key: {}", e))
+        })?;
+
+    let mut iv = [0u8; 16];
+    rand::thread_rng().fill(&mut iv);
+
+    let cipher = Cbc::<Aes256, Pkcs7>::new_from_slices(&aes_key, &iv).map_err(|e| {
+        error::ErrorConflict(format!("Error occured while creating cipher text: {}", e))
+    })?;
+
+    Ok((cipher.encrypt_vec(&file_data), iv.to_vec()))
+}
@@ -0,0 +1,125 @@
+use actix_web::{error, Error};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use mongodb::bson::oid::ObjectId;
+use sha2::{Digest, Sha256};
+
+pub fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+// The signed fields as a JSON array, so no field can run into the next
+fn signed_message(sha256: &str, file_name: &str, recipient_user_id: ObjectId) -> Vec<u8> {
+    serde_json::to_vec(&(sha256, file_name, recipient_user_id.to_hex())).unwrap_or_default()
+}
+
+pub fn sign_upload(
+    signing_key: &SigningKey,
+    sha256: &str,
+    file_name: &str,
+    recipient_user_id: ObjectId,
+) -> Vec<u8> {
+    signing_key
+        .sign(&signed_message(sha256, file_name, recipient_user_id))
+        .to_bytes()
+        .to_vec()
+}
+
+// Hashes decrypted content and checks it against the hash recorded at upload
+pub fn verify_checksum(data: &[u8], expected: Option<&str>) -> Result<String, Error> {
+    let sha256 = sha256_hex(data);
+
+    match expected {
+        Some(expected) if expected != sha256 => Err(error::ErrorConflict(
+            "File content does not match its checksum",
+        )),
+        _ => Ok(sha256),
+    }
+}
+
+pub fn verify_signature(
+    verifying_key: &VerifyingKey,
+    signature: &[u8],
+    sha256: &str,
+    file_name: &str,
+    recipient_user_id: ObjectId,
+) -> Result<(), Error> {
+    let signature = Signature::from_slice(signature)
+        .map_err(|e| error::ErrorConflict(format!("Invalid sender signature: {}", e)))?;
+
+    verifying_key
+        .verify(
+            &signed_message(sha256, file_name, recipient_user_id),
+            &signature,
+        )
+        .map_err(|_| error::ErrorConflict("Sender signature does not match the file"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashes_as_lowercase_hex() {
+        assert_eq!(
+            sha256_hex(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn rejects_mismatched_checksums() {
+        let sha256 = sha256_hex(b"content");
+
+        assert_eq!(verify_checksum(b"content", None).unwrap(), sha256);
+        assert!(verify_checksum(b"content", Some(&sha256)).is_ok());
+        assert!(verify_checksum(b"tampered", Some(&sha256)).is_err());
+    }
+
+    #[test]
+    fn signatures_cover_hash_name_and_recipient() {
+        let signing_key = SigningKey::generate(&mut rand::rngs::OsRng);
+        let verifying_key = signing_key.verifying_key();
+        let recipient = ObjectId::new();
+        let sha256 = sha256_hex(b"content");
+        let signature = sign_upload(&signing_key, &sha256, "report.pdf", recipient);
+
+        assert!(
+            verify_signature(&verifying_key, &signature, &sha256, "report.pdf", recipient).is_ok()
+        );
+        assert!(verify_signature(
+            &verifying_key,
+            &signature,
+            &sha256_hex(b"other"),
+            "report.pdf",
+            recipient
+        )
+        .is_err());
+        assert!(verify_signature(
+            &verifying_key,
+            &signature,
+            &sha256,
+            "invoice.pdf",
+            recipient
+        )
+        .is_err());
+        assert!(verify_signature(
+            &verifying_key,
+            &signature,
+            &sha256,
+            "report.pdf",
+            ObjectId::new()
+        )
+        .is_err());
+        assert!(verify_signature(
+            &verifying_key,
+            &signature[1..],
+            &sha256,
+            "report.pdf",
+            recipient
+        )
+        .is_err());
+    }
+}
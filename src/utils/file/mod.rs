@@ -1,2 +1,9 @@
 pub mod encrypt;
-pub mod decrypt;
\ No newline at end of file
+pub mod decrypt;
+pub mod rewrap;
+pub mod archive;
+pub mod integrity;
+pub mod content_type;
+pub mod upload;
+pub mod compress;
+pub mod dedup;
\ No newline at end of file
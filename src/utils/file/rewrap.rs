@@ -0,0 +1,20 @@
+use actix_web::{error, Error};
+use rsa::{Pkcs1v15Encrypt, RsaPrivateKey, RsaPublicKey};
+
+// Re-encrypts a file's AES key from an existing recipient's key pair to a new
+// recipient's public key without touching the file
+pub async fn rewrap_aes_key(
+    encrypted_aes_key: &[u8],
+    holder_private_key: &RsaPrivateKey,
+    recipient_public_key: &RsaPublicKey,
+) -> Result<Vec<u8>, Error> {
+    let aes_key = holder_private_key
+        .decrypt(Pkcs1v15Encrypt, encrypted_aes_key)
+        .map_err(|e| {
+            error::ErrorConflict(format!("Error occured while decrypting aes key: {}", e))
+        })?;
+
+    recipient_public_key
+        .encrypt(&mut rand::thread_rng(), Pkcs1v15Encrypt, &aes_key)
+        .map_err(|e| error::ErrorConflict(format!("Error occured while encrypting aes key: {}", e)))
+}
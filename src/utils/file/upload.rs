@@ -0,0 +1,21 @@
+use actix_multipart::Field;
+use actix_web::{error, Error};
+use futures_util::stream::StreamExt;
+
+// Collects a multipart field, failing as soon as it grows past `limit` bytes
+// rather than after the whole body has been buffered
+pub async fn read_field_limited(field: &mut Field, limit: i64) -> Result<Vec<u8>, Error> {
+    let mut data = Vec::new();
+
+    while let Some(chunk) = field.next().await {
+        let chunk = chunk?;
+        if (data.len() + chunk.len()) as i64 > limit {
+            return Err(error::ErrorPayloadTooLarge(
+                "Upload exceeds the maximum size",
+            ));
+        }
+        data.extend_from_slice(&chunk);
+    }
+
+    Ok(data)
+}
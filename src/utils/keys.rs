@@ -1,20 +1,30 @@
 use std::{
-    fs::{self, File},
-    io::Write,
+    fs::{self, File, OpenOptions},
+    io::{ErrorKind, Write},
 };
 
-use actix_web::web::Data;
+use actix_web::{web::Data, Error};
 use base64::{engine::general_purpose::STANDARD, Engine};
-use mongodb::bson::Bson;
+use ed25519_dalek::{
+    pkcs8::{DecodePrivateKey, EncodePrivateKey},
+    SigningKey, VerifyingKey,
+};
+use mongodb::bson::{oid::ObjectId, Bson};
 use rand::rngs::OsRng;
 use rsa::{
-    pkcs1::{EncodeRsaPrivateKey, EncodeRsaPublicKey},
+    pkcs1::{DecodeRsaPrivateKey, DecodeRsaPublicKey, EncodeRsaPrivateKey, EncodeRsaPublicKey},
     RsaPrivateKey, RsaPublicKey,
 };
 
-use crate::services::db::Database;
+use crate::{models::user_model::User, services::db::Database};
+
+const PRIVATE_KEYS_DIR: &str = "assets/private_keys";
+const SIGNING_KEYS_DIR: &str = "assets/signing_keys";
+// Stored alongside the users' keys, whose names are object ids
+const AUDIT_SIGNING_KEY: &str = "audit";
+const INVITATION_KEY: &str = "invitations";
 
-pub async fn generate_key(db: Data<Database>, user_id: Bson) -> Result<String, String> {
+pub async fn generate_key(db: Data<dyn Database>, user_id: Bson) -> Result<String, String> {
     let mut rng = OsRng;
 
     let private_key = RsaPrivateKey::new(&mut rng, 2048)
@@ -36,8 +46,8 @@ pub async fn generate_key(db: Data<Database>, user_id: Bson) -> Result<String, S
         .await
         .map_err(|e| format!("Error while updating user: {}", e))?;
 
-    let private_keys_dir = "assets/private_keys";
-    fs::create_dir_all(&private_keys_dir)
+    let private_keys_dir = PRIVATE_KEYS_DIR;
+    fs::create_dir_all(private_keys_dir)
         .map_err(|e| format!("Error while saving private key: {}", e))?;
 
     let user_id = if let Bson::ObjectId(id) = user_id {
@@ -56,3 +66,145 @@ pub async fn generate_key(db: Data<Database>, user_id: Bson) -> Result<String, S
 
     Ok("true".to_string())
 }
+
+pub fn load_public_key(user: &User) -> Result<RsaPublicKey, Error> {
+    let public_key_bytes = STANDARD.decode(&user.public_key).map_err(|e| {
+        actix_web::error::ErrorBadRequest(format!("Failed to get public key: {}", e))
+    })?;
+
+    let public_key = String::from_utf8(public_key_bytes).map_err(|e| {
+        actix_web::error::ErrorBadRequest(format!("Failed to get public key: {}", e))
+    })?;
+
+    RsaPublicKey::from_pkcs1_pem(&public_key)
+        .map_err(|e| actix_web::error::ErrorBadRequest(format!("Failed to get public key: {}", e)))
+}
+
+pub fn load_private_key(user_id: ObjectId) -> Result<RsaPrivateKey, Error> {
+    let path = format!("{}/{}.pem", PRIVATE_KEYS_DIR, user_id.to_hex());
+
+    let private_key = fs::read_to_string(&path).map_err(|e| {
+        actix_web::error::ErrorBadRequest(format!("Failed to collect private key: {}", e))
+    })?;
+
+    RsaPrivateKey::from_pkcs1_pem(&private_key).map_err(|e| {
+        actix_web::error::ErrorBadRequest(format!("Failed to decode rsa private key: {}", e))
+    })
+}
+
+// RSA key pair the server holds the AES keys of pending invitations under,
+// created on first use
+pub fn load_invitation_key() -> Result<RsaPrivateKey, Error> {
+    let path = format!("{}/{}.pem", PRIVATE_KEYS_DIR, INVITATION_KEY);
+
+    match fs::read_to_string(&path) {
+        Ok(pem) => RsaPrivateKey::from_pkcs1_pem(&pem).map_err(|e| {
+            actix_web::error::ErrorInternalServerError(format!(
+                "Failed to decode invitation key: {}",
+                e
+            ))
+        }),
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            let save_error = |e: String| {
+                actix_web::error::ErrorInternalServerError(format!(
+                    "Error while saving invitation key: {}",
+                    e
+                ))
+            };
+            fs::create_dir_all(PRIVATE_KEYS_DIR).map_err(|e| save_error(e.to_string()))?;
+
+            let private_key =
+                RsaPrivateKey::new(&mut OsRng, 2048).map_err(|e| save_error(e.to_string()))?;
+            let pem = private_key
+                .to_pkcs1_pem(rsa::pkcs1::LineEnding::LF)
+                .map_err(|e| save_error(e.to_string()))?;
+
+            // A concurrent request may have created the key first; use that one
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    file.write_all(pem.as_bytes())
+                        .map_err(|e| save_error(e.to_string()))?;
+                    Ok(private_key)
+                }
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => load_invitation_key(),
+                Err(e) => Err(save_error(e.to_string())),
+            }
+        }
+        Err(e) => Err(actix_web::error::ErrorInternalServerError(format!(
+            "Failed to collect invitation key: {}",
+            e
+        ))),
+    }
+}
+
+// Ed25519 key a user signs uploads with, created the first time they sign
+pub fn load_signing_key(user_id: ObjectId) -> Result<SigningKey, Error> {
+    load_or_create_signing_key(&format!("{}/{}.pem", SIGNING_KEYS_DIR, user_id.to_hex()))
+}
+
+// Ed25519 key the server signs audit checkpoints with, created on first use
+pub fn load_audit_signing_key() -> Result<SigningKey, Error> {
+    load_or_create_signing_key(&format!("{}/{}.pem", SIGNING_KEYS_DIR, AUDIT_SIGNING_KEY))
+}
+
+fn load_or_create_signing_key(path: &str) -> Result<SigningKey, Error> {
+    match fs::read_to_string(path) {
+        Ok(pem) => decode_signing_key(&pem),
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            fs::create_dir_all(SIGNING_KEYS_DIR).map_err(|e| {
+                actix_web::error::ErrorInternalServerError(format!(
+                    "Error while saving signing key: {}",
+                    e
+                ))
+            })?;
+
+            let signing_key = SigningKey::generate(&mut OsRng);
+            let pem = signing_key
+                .to_pkcs8_pem(ed25519_dalek::pkcs8::spki::der::pem::LineEnding::LF)
+                .map_err(|e| {
+                    actix_web::error::ErrorInternalServerError(format!(
+                        "Error while encoding signing key: {}",
+                        e
+                    ))
+                })?;
+
+            // A concurrent request may have created the key first; sign with that one
+            match OpenOptions::new().write(true).create_new(true).open(path) {
+                Ok(mut file) => {
+                    file.write_all(pem.as_bytes()).map_err(|e| {
+                        actix_web::error::ErrorInternalServerError(format!(
+                            "Error while saving signing key: {}",
+                            e
+                        ))
+                    })?;
+                    Ok(signing_key)
+                }
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => load_or_create_signing_key(path),
+                Err(e) => Err(actix_web::error::ErrorInternalServerError(format!(
+                    "Error while saving signing key: {}",
+                    e
+                ))),
+            }
+        }
+        Err(e) => Err(actix_web::error::ErrorInternalServerError(format!(
+            "Failed to collect signing key: {}",
+            e
+        ))),
+    }
+}
+
+pub fn load_verifying_key(user_id: ObjectId) -> Result<VerifyingKey, Error> {
+    let path = format!("{}/{}.pem", SIGNING_KEYS_DIR, user_id.to_hex());
+
+    let pem = fs::read_to_string(&path).map_err(|e| {
+        actix_web::error::ErrorConflict(format!("Failed to collect signing key: {}", e))
+    })?;
+
+    Ok(decode_signing_key(&pem)?.verifying_key())
+}
+
+fn decode_signing_key(pem: &str) -> Result<SigningKey, Error> {
+    SigningKey::from_pkcs8_pem(pem).map_err(|e| {
+        actix_web::error::ErrorInternalServerError(format!("Failed to decode signing key: {}", e))
+    })
+}
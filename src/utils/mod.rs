@@ -1,4 +1,5 @@
 pub mod password;
 pub mod token;
 pub mod keys;
+pub mod email;
 pub mod file;
\ No newline at end of file